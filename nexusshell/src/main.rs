@@ -2,7 +2,7 @@ mod shell;
 
 use colored::*;
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::{Cmd, ConditionalEventHandler, Editor, EventContext, EventHandler, KeyCode, KeyEvent, Modifiers, Movement};
 use rustyline::completion::{Completer, Pair};
 use rustyline::hint::Hinter;
 use rustyline::highlight::Highlighter;
@@ -10,10 +10,11 @@ use rustyline::validate::Validator;
 use shell::Shell;
 use std::borrow::Cow;
 use std::path::PathBuf;
-use tokio;
 
+/// Completes from the running shell's completer, so plugins see the shell's state (such as
+/// scheduled jobs) rather than a copy's.
 struct ShellHelper {
-    shell: Shell,
+    completer: shell::Completer,
 }
 
 impl Completer for ShellHelper {
@@ -25,7 +26,8 @@ impl Completer for ShellHelper {
         pos: usize,
         _ctx: &rustyline::Context<'_>,
     ) -> rustyline::Result<(usize, Vec<Pair>)> {
-        let completions = self.shell.complete(&line[..pos]);
+        let start = word_start(&line[..pos]);
+        let completions = shell::order_candidates(self.completer.complete(&line[..pos]), &line[start..pos]);
         let pairs: Vec<Pair> = completions
             .into_iter()
             .map(|s| Pair {
@@ -33,10 +35,14 @@ impl Completer for ShellHelper {
                 replacement: s,
             })
             .collect();
-        Ok((0, pairs))
+        Ok((start, pairs))
     }
 }
 
+fn word_start(line: &str) -> usize {
+    line.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0)
+}
+
 impl Hinter for ShellHelper {
     type Hint = String;
 
@@ -45,8 +51,12 @@ impl Hinter for ShellHelper {
             return None;
         }
 
-        let completions = self.shell.complete(line);
-        completions.first().map(|s| s[pos..].to_string().dimmed().to_string())
+        let typed = pos - word_start(line);
+        let completions = self.completer.complete(line);
+        completions
+            .first()
+            .and_then(|s| s.get(typed..))
+            .map(|s| s.dimmed().to_string())
     }
 }
 
@@ -80,25 +90,128 @@ impl Highlighter for ShellHelper {
     }
 }
 
+/// Tab when the completion menu is on: several candidates open an arrow-key menu, otherwise
+/// the editor completes as usual.
+struct MenuHandler {
+    completer: shell::Completer,
+}
+
+impl ConditionalEventHandler for MenuHandler {
+    fn handle(&self, _evt: &rustyline::Event, _n: usize, _positive: bool, ctx: &EventContext) -> Option<Cmd> {
+        let line = &ctx.line()[..ctx.pos()];
+        let partial = &line[word_start(line)..];
+        let candidates = shell::order_candidates(self.completer.complete(line), partial);
+        if candidates.len() < 2 {
+            return None;
+        }
+
+        // Inserting keeps the cursor after the completion; replacing (needed when only the
+        // case matches) leaves it at the start of the word
+        match shell::menu::select(candidates) {
+            Ok(Some(choice)) => match choice.strip_prefix(partial) {
+                Some(rest) => Some(Cmd::Insert(1, rest.to_string())),
+                None => Some(Cmd::Replace(Movement::BackwardChar(partial.chars().count()), Some(choice))),
+            },
+            _ => Some(Cmd::Noop),
+        }
+    }
+}
+
 impl Validator for ShellHelper {}
 
 impl rustyline::Helper for ShellHelper {}
 
+enum Mode {
+    Interactive,
+    Script(PathBuf),
+    Inline(String),
+}
+
+fn parse_args(args: &[String]) -> anyhow::Result<Mode> {
+    match args.first().map(|s| s.as_str()) {
+        None => Ok(Mode::Interactive),
+        Some("-c") => args
+            .get(1)
+            .map(|commands| Mode::Inline(commands.clone()))
+            .ok_or_else(|| anyhow::anyhow!("-c requires a command string")),
+        Some(path) => Ok(Mode::Script(PathBuf::from(path))),
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    shell::color::init_from_env();
+    if let Some(index) = args.iter().position(|arg| arg == "--no-color") {
+        args.remove(index);
+        shell::color::set_enabled(false);
+    }
+    let mut quiet = false;
+    if let Some(index) = args.iter().position(|arg| arg == "--quiet" || arg == "-q") {
+        args.remove(index);
+        quiet = true;
+    }
+    let mut interactive = false;
+    if let Some(index) = args.iter().position(|arg| arg == "--interactive") {
+        args.remove(index);
+        interactive = true;
+    }
+
+    match parse_args(&args)? {
+        Mode::Interactive => {}
+        Mode::Script(path) => {
+            let source = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+            let mut shell = Shell::new();
+            // Scripts never echo their commands
+            shell.set_quiet(true);
+            shell.start().await;
+            let status = shell.run_lines(&source).await;
+            shell.shutdown().await;
+            std::process::exit(shell.exit_requested().unwrap_or(status));
+        }
+        Mode::Inline(commands) => {
+            let mut shell = Shell::new();
+            shell.set_quiet(true);
+            shell.start().await;
+            let status = shell.run_lines(&commands).await;
+            shell.shutdown().await;
+            std::process::exit(shell.exit_requested().unwrap_or(status));
+        }
+    }
+
     println!("{}", "Welcome to NexusShell - Next Generation CLI".bright_green());
     println!("{}", "Type 'help' for available commands or 'exit' to quit\n".bright_blue());
 
     let mut shell = Shell::new();
-    let helper = ShellHelper { shell: Shell::new() };
-    let mut rl = Editor::new()?;
+    if quiet {
+        shell.set_quiet(true);
+    }
+    shell.start().await;
+    shell.load_rc().await;
+    shell.load_profile();
+    shell.run_startup_hooks().await;
+    let helper = ShellHelper { completer: shell.completer().clone() };
+    let history_file = shell.config().history_file.clone();
+    let editor_config = rustyline::Config::builder()
+        .max_history_size(shell.config().history_size)?
+        .build();
+    let mut rl = Editor::with_config(editor_config)?;
     rl.set_helper(Some(helper));
+    if interactive || shell.config().completion.menu {
+        let menu = MenuHandler { completer: shell.completer().clone() };
+        rl.bind_sequence(KeyEvent(KeyCode::Tab, Modifiers::NONE), EventHandler::Conditional(Box::new(menu)));
+    }
 
-    if let Err(err) = rl.load_history("history.txt") {
+    if let Some(parent) = history_file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if let Err(err) = rl.load_history(&history_file) {
         println!("No previous history: {}", err);
     }
 
-    loop {
+    // An `exit` in the rc file or a startup hook skips the prompt altogether
+    while shell.exit_requested().is_none() {
         let prompt = format!(
             "{}:{} {} ",
             "nexus".bright_green(),
@@ -108,7 +221,9 @@ async fn main() -> anyhow::Result<()> {
 
         match rl.readline(&prompt) {
             Ok(line) => {
-                rl.add_history_entry(line.as_str());
+                if shell.should_record(&line).await {
+                    let _ = rl.add_history_entry(line.as_str());
+                }
                 
                 match shell.run_command(&line).await {
                     Ok(output) => {
@@ -120,12 +235,25 @@ async fn main() -> anyhow::Result<()> {
                         eprintln!("{}: {}", "Error".bright_red(), e);
                     }
                 }
+
+                // Mirror `history clear`/`history delete` into the editor so the removed
+                // entries aren't written back to the history file on exit
+                if shell.history_rewritten().await {
+                    let _ = rl.clear_history();
+                    for entry in shell.get_history().await {
+                        let _ = rl.add_history_entry(entry.as_str());
+                    }
+                }
             }
             Err(ReadlineError::Interrupted) => {
                 println!("^C");
                 continue;
             }
             Err(ReadlineError::Eof) => {
+                if let Err(warning) = shell.request_exit(false) {
+                    println!("{}", warning);
+                    continue;
+                }
                 println!("exit");
                 break;
             }
@@ -136,6 +264,8 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    rl.save_history("history.txt")?;
-    Ok(())
+    shell.shutdown().await;
+    rl.save_history(&history_file)?;
+    // Like a bare `exit`, Ctrl-D ends with the last command's status
+    std::process::exit(shell.exit_requested().unwrap_or(shell.last_status()))
 }
@@ -0,0 +1,89 @@
+use std::env;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Disables color when `NO_COLOR` is set to a non-empty value.
+pub fn init_from_env() {
+    if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        set_enabled(false);
+    }
+}
+
+pub fn set_enabled(enabled: bool) {
+    COLOR_ENABLED.store(enabled, Ordering::Relaxed);
+    colored::control::set_override(enabled);
+}
+
+pub fn enabled() -> bool {
+    COLOR_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Restores the previous color setting when dropped; see `scoped`.
+#[cfg(test)]
+pub struct ScopedColor {
+    previous: bool,
+    _lock: std::sync::MutexGuard<'static, ()>,
+}
+
+/// Sets color for as long as the returned guard lives. Tests run in parallel and the setting
+/// is global, so guards are also held one at a time.
+#[cfg(test)]
+pub fn scoped(enabled: bool) -> ScopedColor {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let lock = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let previous = self::enabled();
+    set_enabled(enabled);
+    ScopedColor { previous, _lock: lock }
+}
+
+#[cfg(test)]
+impl Drop for ScopedColor {
+    fn drop(&mut self) {
+        set_enabled(self.previous);
+    }
+}
+
+/// A per-command `--color=auto|always|never` choice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" | "tty" | "if-tty" => Some(ColorChoice::Auto),
+            "always" | "yes" | "force" => Some(ColorChoice::Always),
+            "never" | "no" | "none" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    /// `always`/`never` override `NO_COLOR` and `--no-color`; `auto` honours them and
+    /// additionally requires stdout to be a terminal.
+    pub fn should_color(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => enabled() && std::io::stdout().is_terminal(),
+        }
+    }
+}
+
+/// Wraps `text` in the given SGR code, or returns it untouched when color is off.
+pub fn paint(code: &str, text: &str) -> String {
+    paint_if(enabled(), code, text)
+}
+
+/// Like `paint`, but for a caller that has already decided whether to color.
+pub fn paint_if(enabled: bool, code: &str, text: &str) -> String {
+    if enabled {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
@@ -6,6 +6,10 @@ pub struct Command {
     pub args: Vec<String>,
     pub flags: HashMap<String, Option<String>>,
     pub raw_input: String,
+    pub stdin: Option<String>,
+    pub assignments: Vec<(String, String)>,
+    /// Set on the last stage of a pipeline written with a trailing `&`.
+    pub background: bool,
 }
 
 impl Command {
@@ -15,17 +19,63 @@ impl Command {
             args,
             flags,
             raw_input,
+            stdin: None,
+            assignments: Vec::new(),
+            background: false,
         }
     }
 
+    /// A bare `NAME=VALUE ...` line with no command, which sets session variables.
+    pub fn is_assignment_only(&self) -> bool {
+        self.name.is_empty() && !self.assignments.is_empty()
+    }
+
     pub fn is_builtin(&self) -> bool {
         matches!(
             self.name.as_str(),
-            "cd" | "exit" | "history" | "help" | "clear" | "pwd" | "echo"
+            "cd" | "pushd" | "popd" | "dirs" | "exit" | "history" | "help" | "clear" | "reset" | "pwd" | "echo" | "grep" | "open"
         )
     }
 
     pub fn is_exit(&self) -> bool {
         self.name == "exit"
     }
+
+    /// The arguments as typed, flags included and quoted words kept together, for commands
+    /// that hand their arguments on (such as `git`) rather than interpreting flags.
+    pub fn raw_args(&self) -> Vec<String> {
+        split_words(&self.raw_input).into_iter().skip(self.assignments.len() + 1).collect()
+    }
+}
+
+/// Splits on whitespace, keeping single- or double-quoted text together without its quotes.
+pub fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if c == '\'' || c == '"' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            None => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
 }
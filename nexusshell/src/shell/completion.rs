@@ -1,14 +1,36 @@
 use std::path::{Path, PathBuf};
 use std::fs;
-use super::Environment;
+use std::sync::Arc;
+use super::{Environment, PluginManager, ShellError};
 
+/// Commands completed at the start of a line, alongside plugins and executables on PATH.
+const BUILTIN_COMMANDS: &[&str] = &[
+    "cd", "pushd", "popd", "dirs", "pwd", "ls", "clear", "reset", "exit", "help", "history",
+    "cat", "dedup", "echo", "grep", "find", "ps", "kill", "mkdir", "rmdir",
+    "rm", "cp", "mv", "touch", "stat", "chmod", "chown", "df",
+    "du", "free", "top", "htop", "ping", "curl", "wget", "completion", "open",
+];
+
+/// Options of the `nexusshell` binary itself, offered by the generated completion scripts.
+const OPTIONS: &[&str] = &["-c", "--quiet", "--no-color", "--interactive"];
+
+#[derive(Clone)]
 pub struct Completer {
     environment: Environment,
+    plugin_manager: Option<Arc<PluginManager>>,
 }
 
 impl Completer {
     pub fn new(environment: Environment) -> Self {
-        Completer { environment }
+        Completer {
+            environment,
+            plugin_manager: None,
+        }
+    }
+
+    pub fn with_plugins(mut self, plugin_manager: Arc<PluginManager>) -> Self {
+        self.plugin_manager = Some(plugin_manager);
+        self
     }
 
     pub fn complete(&self, line: &str) -> Vec<String> {
@@ -18,6 +40,14 @@ impl Completer {
             return self.get_executables();
         }
 
+        // Let the owning plugin complete its own subcommand arguments
+        if words.len() > 1 || line.ends_with(char::is_whitespace) {
+            let plugin_completions = self.complete_plugin_args(line, &words);
+            if !plugin_completions.is_empty() {
+                return plugin_completions;
+            }
+        }
+
         if words.len() == 1 {
             return self.complete_command(words[0]);
         }
@@ -32,22 +62,31 @@ impl Completer {
         Vec::new()
     }
 
+    fn complete_plugin_args(&self, line: &str, words: &[&str]) -> Vec<String> {
+        let plugin = match self.plugin_manager.as_ref().and_then(|pm| pm.get_plugin(words[0])) {
+            Some(plugin) => plugin,
+            None => return Vec::new(),
+        };
+
+        let (args, partial) = if line.ends_with(char::is_whitespace) {
+            (&words[1..], "")
+        } else {
+            (&words[1..words.len() - 1], words[words.len() - 1])
+        };
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+
+        plugin.complete(&args, partial)
+    }
+
     fn complete_command(&self, partial: &str) -> Vec<String> {
         let mut completions = Vec::new();
 
         // Built-in commands
-        let builtins = vec![
-            "cd", "pwd", "ls", "clear", "exit", "help", "history",
-            "cat", "echo", "grep", "find", "ps", "kill", "mkdir",
-            "rm", "cp", "mv", "touch", "chmod", "chown", "df",
-            "du", "free", "top", "htop", "ping", "curl", "wget",
-        ];
-
         completions.extend(
-            builtins
-                .into_iter()
+            BUILTIN_COMMANDS
+                .iter()
                 .filter(|cmd| cmd.starts_with(partial))
-                .map(String::from),
+                .map(|cmd| cmd.to_string()),
         );
 
         // Executables from PATH
@@ -58,6 +97,121 @@ impl Completer {
         completions
     }
 
+    /// Every command known without looking at PATH, each with the words that can follow it:
+    /// builtins, then plugins with what their `complete` offers for an empty argument list.
+    pub fn static_commands(&self) -> Vec<(String, Vec<String>)> {
+        let mut commands: Vec<(String, Vec<String>)> = BUILTIN_COMMANDS
+            .iter()
+            .map(|name| (name.to_string(), Vec::new()))
+            .collect();
+
+        if let Some(plugin_manager) = &self.plugin_manager {
+            for name in plugin_manager.commands() {
+                if let Some(plugin) = plugin_manager.get_plugin(&name) {
+                    let mut words = plugin.complete(&[], "");
+                    words.sort();
+                    words.dedup();
+                    commands.push((name, words));
+                }
+            }
+        }
+
+        // Only plain words can be embedded in the generated scripts unquoted
+        let plain = |word: &String| word.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c));
+        commands.retain(|(name, _)| plain(name));
+        for (_, words) in &mut commands {
+            words.retain(plain);
+        }
+        commands.sort_by(|a, b| a.0.cmp(&b.0));
+        commands.dedup_by(|a, b| a.0 == b.0);
+        commands
+    }
+
+    /// A completion script for `nexusshell <command> ...` in another shell.
+    pub fn generate_script(&self, shell: &str) -> anyhow::Result<String> {
+        let commands = self.static_commands();
+        let names: Vec<&str> = commands.iter().map(|(name, _)| name.as_str()).collect();
+        let with_words: Vec<&(String, Vec<String>)> = commands.iter().filter(|(_, words)| !words.is_empty()).collect();
+
+        // Commands run as `nexusshell -c '<command>'`, so each script completes the words of
+        // that one (usually quoted) argument as a NexusShell command line
+        let mut script = String::new();
+        match shell {
+            "bash" => {
+                script.push_str("# nexusshell completion for bash\n");
+                script.push_str("_nexusshell() {\n");
+                script.push_str("    local cur=\"${COMP_WORDS[COMP_CWORD]}\"\n");
+                script.push_str("    if [[ $COMP_CWORD -eq 1 ]]; then\n");
+                script.push_str(&format!("        mapfile -t COMPREPLY < <(compgen -W \"{}\" -- \"$cur\")\n", OPTIONS.join(" ")));
+                script.push_str("        return\n");
+                script.push_str("    fi\n");
+                script.push_str("    if [[ \"${COMP_WORDS[COMP_CWORD-1]}\" != \"-c\" ]]; then\n");
+                script.push_str("        mapfile -t COMPREPLY < <(compgen -f -- \"$cur\")\n");
+                script.push_str("        return\n");
+                script.push_str("    fi\n");
+                script.push_str("    # Complete the last word of the command, keeping the words before it\n");
+                script.push_str("    local line=\"${cur#[\\\"\\']}\" prefix=\"\" last\n");
+                script.push_str("    [[ \"$line\" == *\" \"* ]] && prefix=\"${line% *} \"\n");
+                script.push_str("    last=\"${line##* }\"\n");
+                script.push_str("    local words\n");
+                script.push_str("    case \"${prefix%% *}\" in\n");
+                script.push_str(&format!("        \"\") words=\"{}\" ;;\n", names.join(" ")));
+                for (name, words) in &with_words {
+                    script.push_str(&format!("        {}) words=\"{}\" ;;\n", name, words.join(" ")));
+                }
+                script.push_str("        *) mapfile -t COMPREPLY < <(compgen -P \"$prefix\" -f -- \"$last\"); return ;;\n");
+                script.push_str("    esac\n");
+                script.push_str("    mapfile -t COMPREPLY < <(compgen -P \"$prefix\" -W \"$words\" -- \"$last\")\n");
+                script.push_str("}\n");
+                script.push_str("complete -F _nexusshell nexusshell\n");
+            }
+            "zsh" => {
+                script.push_str("#compdef nexusshell\n");
+                script.push_str("_nexusshell() {\n");
+                script.push_str(&format!("    local -a commands\n    commands=({})\n", names.join(" ")));
+                script.push_str("    if [[ $words[CURRENT-1] != -c ]]; then\n");
+                script.push_str(&format!("        (( CURRENT == 2 )) && compadd -- {} || _files\n", OPTIONS.join(" ")));
+                script.push_str("        return\n");
+                script.push_str("    fi\n");
+                script.push_str("    # Inside the quotes, complete as if the command were the whole line\n");
+                script.push_str("    if ! compset -q || (( CURRENT == 1 )); then\n");
+                script.push_str("        compadd -a commands\n");
+                script.push_str("        return\n");
+                script.push_str("    fi\n");
+                script.push_str("    case \"$words[1]\" in\n");
+                for (name, words) in &with_words {
+                    script.push_str(&format!("        {}) compadd {} ;;\n", name, words.join(" ")));
+                }
+                script.push_str("        *) _files ;;\n");
+                script.push_str("    esac\n");
+                script.push_str("}\n");
+                script.push_str("compdef _nexusshell nexusshell\n");
+            }
+            "fish" => {
+                script.push_str("# nexusshell completion for fish\n");
+                script.push_str("function __nexusshell_command\n");
+                script.push_str("    set -l words (string split ' ' -- (string trim -l -c '\\'\"' -- (commandline -ct)))\n");
+                script.push_str("    set -l prefix (string join ' ' -- $words[1..-2])\n");
+                script.push_str("    if test (count $words) -le 1\n");
+                script.push_str(&format!("        printf '%s\\n' {}\n", names.join(" ")));
+                script.push_str("        return\n");
+                script.push_str("    end\n");
+                script.push_str("    switch $words[1]\n");
+                for (name, words) in &with_words {
+                    script.push_str(&format!("        case {}\n", name));
+                    script.push_str(&format!("            printf \"$prefix %s\\n\" {}\n", words.join(" ")));
+                }
+                script.push_str("    end\n");
+                script.push_str("end\n");
+                script.push_str("complete -c nexusshell -s c -x -d 'Run a command' -a '(__nexusshell_command)'\n");
+            }
+            other => {
+                return Err(ShellError::InvalidUsage(format!("completion generate bash|zsh|fish (not '{}')", other)).into())
+            }
+        }
+        Ok(script)
+    }
+
     fn complete_path(&self, partial: &str) -> Vec<String> {
         let path = PathBuf::from(partial);
         let (dir, prefix) = if partial.ends_with('/') || partial.ends_with('\\') {
@@ -94,6 +248,17 @@ impl Completer {
         }
     }
 
+    /// Whether `name` is a builtin, a plugin (when the completer has them) or an executable on
+    /// PATH. A name containing a path separator must be an existing file instead.
+    pub fn is_command(&self, name: &str) -> bool {
+        if name.contains(std::path::MAIN_SEPARATOR) || name.contains('/') {
+            return Path::new(name).is_file();
+        }
+        BUILTIN_COMMANDS.contains(&name)
+            || self.plugin_manager.as_ref().is_some_and(|plugins| plugins.get_plugin(name).is_some())
+            || self.get_executables().iter().any(|executable| executable == name)
+    }
+
     fn get_executables(&self) -> Vec<String> {
         let mut executables = Vec::new();
         if let Some(path_var) = self.environment.get_var("PATH") {
@@ -123,3 +288,85 @@ impl Completer {
             .collect()
     }
 }
+
+/// Orders completion candidates for the REPL's list or menu: exact-case prefix matches of
+/// `partial` first, then case-insensitive ones, then the rest, each group alphabetical
+/// ignoring case. Duplicates are dropped.
+pub fn order_candidates(candidates: Vec<String>, partial: &str) -> Vec<String> {
+    let lower = partial.to_lowercase();
+    let rank = |candidate: &str| {
+        if candidate.starts_with(partial) {
+            0
+        } else if candidate.to_lowercase().starts_with(&lower) {
+            1
+        } else {
+            2
+        }
+    };
+
+    let mut ordered = candidates;
+    ordered.sort_by(|a, b| {
+        rank(a).cmp(&rank(b))
+            .then_with(|| a.to_lowercase().cmp(&b.to_lowercase()))
+            .then_with(|| a.cmp(b))
+    });
+    ordered.dedup();
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_candidates() {
+        let candidates = ["Makefile", "main.rs", "README.md", "match/", "main.rs", "docs/", "Main.java"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            order_candidates(candidates, "ma"),
+            vec!["main.rs", "match/", "Main.java", "Makefile", "docs/", "README.md"]
+        );
+        assert_eq!(order_candidates(vec!["b".to_string(), "A".to_string(), "a".to_string()], ""), vec!["A", "a", "b"]);
+    }
+
+    #[test]
+    fn test_generate_bash_script() {
+        let completer = Completer::new(Environment::new()).with_plugins(PluginManager::new());
+        let script = completer.generate_script("bash").unwrap();
+
+        assert!(script.contains("complete -F _nexusshell nexusshell"));
+        let first_words = script.lines().find(|line| line.contains("\"\") words=")).unwrap();
+        for command in ["ls", "cd", "checksum", "completion"] {
+            assert!(first_words.split(['"', ' ']).any(|word| word == command));
+        }
+        assert!(script.contains("checksum) words=\"sha1 sha256 sha384 sha512\""));
+
+        // Structurally balanced
+        assert_eq!(script.matches("case ").count(), script.matches("esac").count());
+        assert_eq!(script.matches('{').count(), script.matches('}').count());
+
+        // Completes the command given to `nexusshell -c`, quoted or not
+        #[cfg(unix)]
+        {
+            use std::process::Command;
+            let complete = |words: &str| {
+                let probe = format!(
+                    "{}\nCOMP_WORDS=({}); COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1)); _nexusshell; printf '%s\\n' \"${{COMPREPLY[@]}}\"",
+                    script, words
+                );
+                Command::new("bash").arg("-c").arg(probe).output().ok()
+                    .filter(|output| output.status.success())
+                    .map(|output| String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect::<Vec<_>>())
+            };
+            if let Some(replies) = complete("nexusshell -c \"'checksum sha3\"") {
+                assert_eq!(replies, vec!["checksum sha384"]);
+                assert_eq!(complete("nexusshell -c chec").unwrap(), vec!["checksum"]);
+                assert!(complete("nexusshell -").unwrap().contains(&"-c".to_string()));
+            }
+        }
+
+        assert!(completer.generate_script("tcsh").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Shell settings read from `~/.nexusshell/config.json`. Missing keys take their defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Where the interactive prompt persists its history
+    pub history_file: PathBuf,
+    /// How many entries the history keeps before dropping the oldest
+    pub history_size: usize,
+    /// Commands never written to history, matched as prefixes with `*` wildcards
+    pub history_ignore: Vec<String>,
+    /// Don't record lines that start with a space (also turned on by `HISTCONTROL=ignorespace`)
+    pub history_ignore_space: bool,
+    /// Page every long result, as if `--page` were given
+    pub pager: bool,
+    /// Don't echo each command back before running it, as if `--quiet` were given
+    pub quiet: bool,
+    /// Load `.env` from each directory `cd`, `pushd` or `popd` enters
+    pub auto_env: bool,
+    pub completion: CompletionConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompletionConfig {
+    /// Pick among several candidates from an arrow-key menu on Tab, as if `--interactive` were given
+    pub menu: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut history_file = dirs::home_dir().unwrap_or_default();
+        history_file.push(".nexusshell");
+        history_file.push("history");
+
+        Config {
+            history_file,
+            history_size: 1000,
+            history_ignore: vec![
+                "security credential add".to_string(),
+                "export *PASSWORD*".to_string(),
+                "export *SECRET*".to_string(),
+                "export *TOKEN*".to_string(),
+            ],
+            history_ignore_space: true,
+            pager: false,
+            quiet: false,
+            auto_env: false,
+            completion: CompletionConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the user's config. Nothing is written: without a config file the defaults apply.
+    pub fn load() -> Self {
+        let mut config_path = dirs::home_dir().unwrap_or_default();
+        config_path.push(".nexusshell");
+        config_path.push("config.json");
+        Self::load_from(&config_path).unwrap_or_default()
+    }
+
+    pub fn load_from(config_path: &Path) -> anyhow::Result<Self> {
+        if !config_path.exists() {
+            return Ok(Config::default());
+        }
+        let file = File::open(config_path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_config_is_not_created() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join(".nexusshell").join("config.json");
+
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.history_size, 1000);
+        assert!(!path.exists());
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, r#"{"history_size": 50}"#).unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.history_size, 50);
+        assert!(config.history_ignore_space);
+    }
+}
@@ -1,10 +1,25 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
+#[derive(Debug, Clone)]
 pub struct Environment {
     vars: HashMap<String, String>,
     current_dir: PathBuf,
+    /// Names set during the session, as opposed to inherited from the process
+    user_set: HashSet<String>,
+    /// Variables set by plugins, which only see `&Environment`. Clones share the list, so
+    /// the shell picks them up with `apply_exports` once the command returns.
+    exports: Arc<Mutex<Vec<(String, String)>>>,
+    /// Directories saved by `pushd`, most recent first; the current directory is not included
+    dir_stack: Vec<PathBuf>,
+    previous_dir: Option<PathBuf>,
+    /// Shell functions by name, with their unexpanded bodies
+    functions: BTreeMap<String, String>,
 }
 
 impl Environment {
@@ -19,6 +34,11 @@ impl Environment {
         Environment {
             vars,
             current_dir,
+            user_set: HashSet::new(),
+            exports: Arc::new(Mutex::new(Vec::new())),
+            dir_stack: Vec::new(),
+            previous_dir: None,
+            functions: BTreeMap::new(),
         }
     }
 
@@ -27,9 +47,103 @@ impl Environment {
     }
 
     pub fn set_var(&mut self, name: String, value: String) {
+        self.user_set.insert(name.clone());
         self.vars.insert(name, value);
     }
 
+    pub fn vars(&self) -> &HashMap<String, String> {
+        &self.vars
+    }
+
+    /// Whether `name` can be assigned from a script: an identifier, which rules out
+    /// special parameters such as `?`.
+    pub fn is_settable(name: &str) -> bool {
+        let mut chars = name.chars();
+        matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    /// Queues a variable for the session that owns this environment.
+    pub fn export_var(&self, name: String, value: String) {
+        if let Ok(mut exports) = self.exports.lock() {
+            exports.push((name, value));
+        }
+    }
+
+    /// Sets every variable queued through `export_var`, in order.
+    pub fn apply_exports(&mut self) {
+        let exports = match self.exports.lock() {
+            Ok(mut exports) => std::mem::take(&mut *exports),
+            Err(_) => return,
+        };
+        for (name, value) in exports {
+            self.set_var(name, value);
+        }
+    }
+
+    pub fn define_function(&mut self, name: String, body: String) {
+        self.functions.insert(name, body);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&String> {
+        self.functions.get(name)
+    }
+
+    pub fn functions(&self) -> &BTreeMap<String, String> {
+        &self.functions
+    }
+
+    /// Writes the variables set during this session, the functions defined and the working
+    /// directory to `path`.
+    pub fn save_profile(&self, path: &Path) -> io::Result<()> {
+        let profile = Profile {
+            vars: self.user_set
+                .iter()
+                .filter_map(|name| self.vars.get(name).map(|value| (name.clone(), value.clone())))
+                .collect(),
+            current_dir: Some(self.current_dir.clone()),
+            functions: self.functions.clone(),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(&profile)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, json)
+    }
+
+    /// Restores a profile written by `save_profile`. A missing file is not an error, and a
+    /// saved directory that no longer exists is skipped.
+    pub fn load_profile(&mut self, path: &Path) -> io::Result<()> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let profile: Profile = serde_json::from_str(&content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        for (name, value) in profile.vars {
+            self.set_var(name, value);
+        }
+        self.functions.extend(profile.functions);
+        if let Some(dir) = profile.current_dir {
+            let _ = self.change_directory(dir);
+        }
+        Ok(())
+    }
+
+    /// Sets every variable in a dotenv-style file. Returns how many were set.
+    pub fn load_env_file(&mut self, path: &Path) -> anyhow::Result<usize> {
+        let content = fs::read_to_string(path)?;
+        let vars = parse_env_file(&content)?;
+        let count = vars.len();
+        for (name, value) in vars {
+            self.set_var(name, value);
+        }
+        Ok(count)
+    }
+
     pub fn get_current_dir(&self) -> PathBuf {
         self.current_dir.clone()
     }
@@ -50,10 +164,73 @@ impl Environment {
         }
 
         env::set_current_dir(&new_path)?;
-        self.current_dir = new_path;
+        let previous = std::mem::replace(&mut self.current_dir, new_path);
+        // Tracked like inherited variables, so they aren't saved with the profile
+        self.vars.insert("OLDPWD".to_string(), previous.to_string_lossy().to_string());
+        self.vars.insert("PWD".to_string(), self.current_dir.to_string_lossy().to_string());
+        self.previous_dir = Some(previous);
         Ok(())
     }
 
+    /// The directory `cd -` returns to.
+    pub fn previous_dir(&self) -> Option<&PathBuf> {
+        self.previous_dir.as_ref()
+    }
+
+    /// The directory stack as `dirs` lists it: the current directory, then the saved ones.
+    pub fn dir_stack(&self) -> Vec<PathBuf> {
+        std::iter::once(self.current_dir.clone())
+            .chain(self.dir_stack.iter().cloned())
+            .collect()
+    }
+
+    /// The `dir_stack` position named by `+N` or `~N` (from the top) or `-N` (from the
+    /// bottom), or `None` if `spec` isn't a stack reference.
+    pub fn stack_index(&self, spec: &str) -> Option<anyhow::Result<usize>> {
+        let (from_top, digits) = match spec.chars().next()? {
+            '+' | '~' => (true, &spec[1..]),
+            '-' => (false, &spec[1..]),
+            _ => return None,
+        };
+        let n: usize = digits.parse().ok()?;
+        let len = self.dir_stack.len() + 1;
+        if n >= len {
+            return Some(Err(anyhow::anyhow!("{}: directory stack index out of range", spec)));
+        }
+        Some(Ok(if from_top { n } else { len - 1 - n }))
+    }
+
+    /// Changes to the `dir_stack` entry at `index`, leaving the directory it came from in
+    /// that entry's place so nothing drops off the stack.
+    pub fn change_to_stack_entry(&mut self, index: usize) -> anyhow::Result<PathBuf> {
+        if index == 0 {
+            return Ok(self.current_dir.clone());
+        }
+        let target = self.dir_stack.get(index - 1).cloned()
+            .ok_or_else(|| anyhow::anyhow!("directory stack index out of range"))?;
+        let previous = self.current_dir.clone();
+        self.change_directory(target.clone())?;
+        self.dir_stack[index - 1] = previous;
+        Ok(target)
+    }
+
+    /// Saves the current directory on the stack and changes to `path`.
+    pub fn push_directory(&mut self, path: PathBuf) -> anyhow::Result<()> {
+        let previous = self.current_dir.clone();
+        self.change_directory(path)?;
+        self.dir_stack.insert(0, previous);
+        Ok(())
+    }
+
+    /// Changes to the most recently saved directory and drops it from the stack.
+    pub fn pop_directory(&mut self) -> anyhow::Result<PathBuf> {
+        let target = self.dir_stack.first().cloned()
+            .ok_or_else(|| anyhow::anyhow!("directory stack empty"))?;
+        self.change_directory(target.clone())?;
+        self.dir_stack.remove(0);
+        Ok(target)
+    }
+
     pub fn expand_path(&self, path: &str) -> PathBuf {
         let path = if path.starts_with('~') {
             if let Some(home) = self.get_var("HOME") {
@@ -75,4 +252,224 @@ impl Environment {
             self.current_dir.join(path)
         }
     }
+
+    /// Directories listed in `CDPATH`; empty entries stand for the current directory.
+    pub fn cdpath(&self) -> Vec<PathBuf> {
+        match self.get_var("CDPATH") {
+            Some(cdpath) => env::split_paths(cdpath)
+                .map(|dir| if dir.as_os_str().is_empty() { self.current_dir.clone() } else { dir })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Resolves a `cd` target, falling back to `CDPATH` for bare relative names that
+    /// don't exist under the current directory. The flag reports a `CDPATH` match.
+    pub fn resolve_cd_target(&self, target: &str) -> (PathBuf, bool) {
+        let local = self.expand_path(target);
+        let searchable = !(target.starts_with('/')
+            || target.starts_with('~')
+            || target == "."
+            || target == ".."
+            || target.starts_with("./")
+            || target.starts_with("../"));
+
+        if local.is_dir() || !searchable {
+            return (local, false);
+        }
+
+        self.cdpath()
+            .into_iter()
+            .map(|dir| dir.join(target))
+            .find(|candidate| candidate.is_dir())
+            .map(|found| (found, true))
+            .unwrap_or((local, false))
+    }
+}
+
+/// What `save_profile` persists between sessions.
+#[derive(Serialize, Deserialize)]
+struct Profile {
+    vars: BTreeMap<String, String>,
+    current_dir: Option<PathBuf>,
+    /// Absent from profiles saved before functions existed
+    #[serde(default)]
+    functions: BTreeMap<String, String>,
+}
+
+/// Parses `KEY=VALUE` lines as found in `.env` files. Blank lines and `#` comments are
+/// skipped and a leading `export ` is allowed. Values may be single-quoted (taken literally),
+/// double-quoted (with `\n`, `\t`, `\"` and `\\` escapes) or bare, where a ` #` starts a comment.
+pub fn parse_env_file(content: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").map(str::trim_start).unwrap_or(line);
+        let invalid = |reason: &str| anyhow::anyhow!("line {}: {}", number + 1, reason);
+
+        let (name, raw) = line.split_once('=').ok_or_else(|| invalid("expected KEY=VALUE"))?;
+        let name = name.trim();
+        if !Environment::is_settable(name) {
+            return Err(invalid(&format!("invalid variable name '{}'", name)));
+        }
+        let raw = raw.trim_start();
+
+        let value = if let Some(rest) = raw.strip_prefix('\'') {
+            let end = rest.find('\'').ok_or_else(|| invalid("unterminated single quote"))?;
+            rest[..end].to_string()
+        } else if let Some(rest) = raw.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = rest.chars();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some(c @ ('"' | '\\' | '$')) => value.push(c),
+                        Some(c) => {
+                            value.push('\\');
+                            value.push(c);
+                        }
+                        None => return Err(invalid("unterminated double quote")),
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err(invalid("unterminated double quote")),
+                }
+            }
+            value
+        } else {
+            let end = raw.find(" #").or_else(|| raw.find("\t#")).unwrap_or(raw.len());
+            raw[..end].trim_end().to_string()
+        };
+        vars.push((name.to_string(), value));
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cd_via_cdpath() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::create_dir(temp_dir.path().join("projects")).unwrap();
+
+        let mut env = Environment::new();
+        env.set_var("CDPATH".to_string(), temp_dir.path().to_string_lossy().to_string());
+
+        let (path, via_cdpath) = env.resolve_cd_target("projects");
+        assert_eq!(path, temp_dir.path().join("projects"));
+        assert!(via_cdpath);
+
+        let (path, via_cdpath) = env.resolve_cd_target("no-such-dir-anywhere");
+        assert_eq!(path, env.get_current_dir().join("no-such-dir-anywhere"));
+        assert!(!via_cdpath);
+    }
+
+    #[test]
+    fn test_parse_env_file() {
+        let content = r#"
+# Database settings
+DB_HOST=localhost
+DB_PORT = 5432   # inline comment
+export API_KEY="abc 123 # not a comment"
+GREETING="line one\nline \"two\""
+RAW='$HOME stays \n literal'
+EMPTY=
+"#;
+        let vars = parse_env_file(content).unwrap();
+        assert_eq!(vars, vec![
+            ("DB_HOST".to_string(), "localhost".to_string()),
+            ("DB_PORT".to_string(), "5432".to_string()),
+            ("API_KEY".to_string(), "abc 123 # not a comment".to_string()),
+            ("GREETING".to_string(), "line one\nline \"two\"".to_string()),
+            ("RAW".to_string(), "$HOME stays \\n literal".to_string()),
+            ("EMPTY".to_string(), String::new()),
+        ]);
+
+        assert!(parse_env_file("NOT VALID").unwrap_err().to_string().starts_with("line 1"));
+        assert!(parse_env_file("A=1\nB=\"open").unwrap_err().to_string().contains("line 2"));
+        assert!(parse_env_file("1ABC=x").is_err());
+
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join(".env");
+        std::fs::write(&path, content).unwrap();
+        let mut env = Environment::new();
+        assert_eq!(env.load_env_file(&path).unwrap(), 6);
+        assert_eq!(env.get_var("API_KEY").map(String::as_str), Some("abc 123 # not a comment"));
+    }
+
+    #[test]
+    fn test_profile_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let profile = temp_dir.path().join("profile.json");
+
+        let mut env = Environment::new();
+        env.set_var("PROJECT".to_string(), "nexus".to_string());
+        env.export_var("TARGET".to_string(), "release".to_string());
+        env.apply_exports();
+        env.define_function("greet".to_string(), "echo hello $1".to_string());
+        env.save_profile(&profile).unwrap();
+
+        let saved = std::fs::read_to_string(&profile).unwrap();
+        assert!(saved.contains("PROJECT"));
+        assert!(!saved.contains("\"PATH\""));
+
+        std::env::remove_var("PROJECT");
+        let mut restored = Environment::new();
+        restored.load_profile(&profile).unwrap();
+        assert_eq!(restored.get_var("PROJECT").map(|s| s.as_str()), Some("nexus"));
+        assert_eq!(restored.get_var("TARGET").map(|s| s.as_str()), Some("release"));
+        assert_eq!(restored.get_current_dir(), env.get_current_dir());
+        assert_eq!(restored.function("greet").map(String::as_str), Some("echo hello $1"));
+
+        restored.load_profile(&temp_dir.path().join("missing.json")).unwrap();
+    }
+
+    #[test]
+    fn test_directory_stack_indices() {
+        let temp_dir = tempdir().unwrap();
+        let original = std::env::current_dir().unwrap();
+        let [a, b, c] = ["a", "b", "c"].map(|name| {
+            let dir = temp_dir.path().join(name);
+            std::fs::create_dir(&dir).unwrap();
+            dir
+        });
+
+        let mut env = Environment::new();
+        let start = env.get_current_dir();
+        env.push_directory(a.clone()).unwrap();
+        env.push_directory(b.clone()).unwrap();
+        env.push_directory(c.clone()).unwrap();
+        assert_eq!(env.dir_stack(), vec![c.clone(), b.clone(), a.clone(), start.clone()]);
+        assert_eq!(env.previous_dir(), Some(&b));
+
+        // +N counts from the top, -N from the bottom, ~N like +N
+        assert_eq!(env.stack_index("+2").unwrap().unwrap(), 2);
+        assert_eq!(env.stack_index("-0").unwrap().unwrap(), 3);
+        assert_eq!(env.stack_index("~1").unwrap().unwrap(), 1);
+        assert!(env.stack_index("+4").unwrap().is_err());
+        assert!(env.stack_index("other").is_none());
+
+        let index = env.stack_index("+2").unwrap().unwrap();
+        assert_eq!(env.change_to_stack_entry(index).unwrap(), a);
+        assert_eq!(env.dir_stack(), vec![a.clone(), b.clone(), c.clone(), start.clone()]);
+
+        let index = env.stack_index("-1").unwrap().unwrap();
+        assert_eq!(env.change_to_stack_entry(index).unwrap(), c);
+        assert_eq!(env.dir_stack(), vec![c.clone(), b.clone(), a.clone(), start.clone()]);
+        assert_eq!(env.previous_dir(), Some(&a));
+        assert_eq!(env.get_var("OLDPWD").map(PathBuf::from), Some(a.clone()));
+
+        assert_eq!(env.pop_directory().unwrap(), b);
+        assert_eq!(env.dir_stack(), vec![b, a, start]);
+
+        std::env::set_current_dir(original).unwrap();
+    }
 }
@@ -0,0 +1,65 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ShellError {
+    NotFound(String),
+    InvalidUsage(String),
+    PermissionDenied(String),
+    Io(io::Error),
+    External(String),
+    /// The command would overwrite something and needs the user's go-ahead; holds the question.
+    ConfirmationRequired(String),
+}
+
+impl ShellError {
+    /// Maps an I/O failure on `path` to the most specific variant.
+    pub fn from_io(path: &Path, err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => ShellError::NotFound(path.display().to_string()),
+            io::ErrorKind::PermissionDenied => ShellError::PermissionDenied(path.display().to_string()),
+            io::ErrorKind::AlreadyExists => ShellError::External(format!("{} already exists", path.display())),
+            _ => ShellError::Io(err),
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ShellError::InvalidUsage(_) => 2,
+            ShellError::PermissionDenied(_) => 126,
+            ShellError::NotFound(_)
+            | ShellError::Io(_)
+            | ShellError::External(_)
+            | ShellError::ConfirmationRequired(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShellError::NotFound(what) => write!(f, "{} not found", what),
+            ShellError::InvalidUsage(usage) => write!(f, "Usage: {}", usage),
+            ShellError::PermissionDenied(what) => write!(f, "Permission denied: {}", what),
+            ShellError::Io(err) => write!(f, "I/O error: {}", err),
+            ShellError::External(message) => write!(f, "{}", message),
+            ShellError::ConfirmationRequired(question) => write!(f, "{}", question),
+        }
+    }
+}
+
+impl std::error::Error for ShellError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShellError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ShellError {
+    fn from(err: io::Error) -> Self {
+        ShellError::Io(err)
+    }
+}
@@ -1,6 +1,7 @@
-use super::{Command, Environment, PluginManager};
+use super::{Command, Environment, PluginManager, ShellError};
 use std::process::Stdio;
 use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command as TokioCommand;
 
 pub struct Executor {
@@ -12,56 +13,154 @@ impl Executor {
         Executor { plugin_manager }
     }
 
+    /// Exit status for a failed command; untyped errors map to 1.
+    pub fn exit_code(error: &anyhow::Error) -> i32 {
+        error
+            .downcast_ref::<ShellError>()
+            .map(ShellError::exit_code)
+            .unwrap_or(1)
+    }
+
     pub async fn execute(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        // `NAME=VALUE cmd` only affects this one command
+        let scoped;
+        let env = if command.assignments.is_empty() {
+            env
+        } else {
+            let mut vars = env.clone();
+            for (name, value) in &command.assignments {
+                vars.set_var(name.clone(), value.clone());
+            }
+            scoped = vars;
+            &scoped
+        };
+
         if command.is_builtin() {
             self.execute_builtin(command, env).await
         } else if let Some(plugin) = self.plugin_manager.get_plugin(&command.name) {
             plugin.execute(command, env).await
         } else {
-            self.execute_system_command(command).await
+            self.execute_system_command(command, env).await
         }
     }
 
+    pub async fn execute_pipeline(&self, commands: &[Command], env: &Environment) -> anyhow::Result<String> {
+        if commands.last().is_some_and(|command| command.background) {
+            return self.spawn_background(commands, env);
+        }
+
+        let mut output: Option<String> = None;
+        for command in commands {
+            let mut stage = command.clone();
+            stage.stdin = output.take();
+            output = Some(self.execute(&stage, env).await?);
+        }
+        Ok(output.unwrap_or_default())
+    }
+
     async fn execute_builtin(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
         match command.name.as_str() {
             "cd" => {
-                let path = if command.args.is_empty() {
-                    env.get_var("HOME")
-                        .ok_or_else(|| anyhow::anyhow!("HOME environment variable not set"))?
-                        .clone()
+                let (path, via_cdpath) = if command.args.is_empty() {
+                    let home = env.get_var("HOME")
+                        .ok_or_else(|| anyhow::anyhow!("HOME environment variable not set"))?;
+                    (env.expand_path(home), false)
                 } else {
-                    command.args[0].clone()
+                    env.resolve_cd_target(&command.args[0])
                 };
-                
-                let path = env.expand_path(&path);
+
                 std::env::set_current_dir(&path)?;
-                Ok("".to_string())
+                if via_cdpath {
+                    // Like bash, echo the directory when it was found through CDPATH
+                    Ok(path.to_string_lossy().to_string())
+                } else {
+                    Ok("".to_string())
+                }
             }
             "pwd" => Ok(env.get_current_dir().to_string_lossy().to_string()),
+            // The session handles these on their own; in a pipeline the stack is read-only
+            "dirs" => Ok(env.dir_stack().iter().map(|dir| dir.to_string_lossy()).collect::<Vec<_>>().join(" ")),
+            "pushd" | "popd" => Err(ShellError::External(format!("{}: cannot change directory inside a pipeline", command.name)).into()),
             "echo" => Ok(command.args.join(" ")),
-            "clear" => {
-                print!("\x1B[2J\x1B[1;1H");
+            "open" => super::open::run(command, env).await,
+            "clear" | "reset" => {
+                print!("{}", terminal_sequence(command));
+                std::io::Write::flush(&mut std::io::stdout())?;
                 Ok("".to_string())
             }
-            _ => Err(anyhow::anyhow!("Unknown builtin command")),
+            "grep" => {
+                let pattern = command.args.first()
+                    .ok_or_else(|| ShellError::InvalidUsage("grep <pattern> [file...]".to_string()))?;
+
+                let mut input = command.stdin.clone().unwrap_or_default();
+                for file in command.args.iter().skip(1) {
+                    input.push_str(&tokio::fs::read_to_string(env.expand_path(file)).await?);
+                }
+
+                let matches: Vec<&str> = input
+                    .lines()
+                    .filter(|line| line.contains(pattern.as_str()))
+                    .collect();
+                Ok(matches.join("\n"))
+            }
+            _ => Err(ShellError::NotFound(format!("Builtin '{}'", command.name)).into()),
         }
     }
 
-    async fn execute_system_command(&self, command: &Command) -> anyhow::Result<String> {
+    /// Starts `cmd &` without waiting for it and records it in the job table. Only a single
+    /// external command can be backgrounded; builtins and plugins run inside the shell.
+    fn spawn_background(&self, commands: &[Command], env: &Environment) -> anyhow::Result<String> {
+        let command = match commands {
+            [command] if !command.is_builtin() && self.plugin_manager.get_plugin(&command.name).is_none() => command,
+            _ => {
+                return Err(ShellError::InvalidUsage(
+                    "only a single external command can run in the background".to_string(),
+                ).into())
+            }
+        };
+
+        let mut vars = env.vars().clone();
+        vars.extend(command.assignments.iter().cloned());
+        let child = TokioCommand::new(&command.name)
+            .args(&command.args)
+            .envs(&vars)
+            .current_dir(env.get_current_dir())
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| ShellError::from_io(std::path::Path::new(&command.name), e))?;
+
+        let pid = self.plugin_manager.jobs().add(command.raw_input.clone(), child)
+            .ok_or_else(|| ShellError::External(format!("{}: exited before it could be tracked", command.name)))?;
+        Ok(format!("[{}] {}", pid, command.raw_input))
+    }
+
+    async fn execute_system_command(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
         let mut cmd = if cfg!(target_os = "windows") {
             let mut cmd = TokioCommand::new("cmd");
-            cmd.args(&["/C", &command.name]);
+            cmd.args(["/C", &command.name]);
             cmd
         } else {
             TokioCommand::new(&command.name)
         };
 
         cmd.args(&command.args)
-            .stdin(Stdio::inherit())
+            .envs(env.vars())
+            .stdin(if command.stdin.is_some() { Stdio::piped() } else { Stdio::inherit() })
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
 
-        let output = cmd.output().await?;
+        let mut child = cmd.spawn()?;
+        // Feed stdin while the output is drained, or a child that fills its stdout pipe
+        // before reading all of its input would never finish
+        let stdin = child.stdin.take();
+        let feed = async {
+            if let (Some(input), Some(mut stdin)) = (&command.stdin, stdin) {
+                // A child that exits without reading everything (`head`) isn't an error
+                let _ = stdin.write_all(input.as_bytes()).await;
+            }
+        };
+        let ((), output) = tokio::join!(feed, child.wait_with_output());
+        let output = output?;
 
         let mut result = String::new();
         if !output.stdout.is_empty() {
@@ -74,3 +173,111 @@ impl Executor {
         Ok(result)
     }
 }
+
+/// Erases the screen and homes the cursor.
+const CLEAR_SCREEN: &str = "\x1B[2J\x1B[1;1H";
+/// Erases the terminal's scrollback buffer.
+const CLEAR_SCROLLBACK: &str = "\x1B[3J";
+/// Full reset (RIS): modes, character sets, colors and tab stops go back to defaults.
+const RESET_TERMINAL: &str = "\x1Bc";
+
+/// What `clear` or `reset` writes to the terminal.
+fn terminal_sequence(command: &Command) -> String {
+    match command.name.as_str() {
+        "reset" => format!("{}{}{}", RESET_TERMINAL, CLEAR_SCREEN, CLEAR_SCROLLBACK),
+        _ if command.flags.contains_key("scrollback") => format!("{}{}", CLEAR_SCREEN, CLEAR_SCROLLBACK),
+        _ => CLEAR_SCREEN.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::{color, Parser};
+
+    #[tokio::test]
+    async fn test_pipe_into_native_grep() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fruit = temp_dir.path().join("fruit.txt");
+        std::fs::write(&fruit, "apple\nbanana\ncherry\nmango\n").unwrap();
+        let executor = Executor::new(PluginManager::new());
+        let env = Environment::new();
+        let commands = Parser::new().parse_pipeline(&format!("cat {} | grep an", fruit.display())).unwrap();
+        assert_eq!(commands.len(), 2);
+
+        let output = executor.execute_pipeline(&commands, &env).await.unwrap();
+        assert_eq!(output, "banana\nmango");
+
+        // A quoted `|` is part of the argument, not a stage boundary
+        let commands = Parser::new().parse_pipeline("echo \"a|b\" | grep a").unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].args, vec!["a|b".to_string()]);
+        assert_eq!(executor.execute_pipeline(&commands, &env).await.unwrap(), "a|b");
+    }
+
+    #[tokio::test]
+    async fn test_pipe_ps_into_native_grep() {
+        // The test binary is named after the crate, so it shows up in its own listing
+        let _color = color::scoped(false);
+        let executor = Executor::new(PluginManager::new());
+        let commands = Parser::new().parse_pipeline("ps | grep nexusshell").unwrap();
+        assert_eq!(commands[1].name, "grep");
+
+        let output = executor.execute_pipeline(&commands, &Environment::new()).await.unwrap();
+        let pid = std::process::id().to_string();
+        assert!(output.lines().any(|line| line.split_whitespace().any(|word| word == pid)), "{}", output);
+        assert!(output.lines().all(|line| line.contains("nexusshell")));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_large_input_piped_into_a_system_command() {
+        // More than a pipe buffer each way; writing it all before reading would deadlock
+        let temp_dir = tempfile::tempdir().unwrap();
+        let big = temp_dir.path().join("big.txt");
+        std::fs::write(&big, "0123456789\n".repeat(256 * 1024)).unwrap();
+        let executor = Executor::new(PluginManager::new());
+        let commands = Parser::new().parse_pipeline(&format!("cat {} | tr 1 x", big.display())).unwrap();
+        let env = Environment::new();
+
+        let run = executor.execute_pipeline(&commands, &env);
+        let output = tokio::time::timeout(std::time::Duration::from_secs(30), run).await
+            .expect("pipeline deadlocked")
+            .unwrap();
+        assert_eq!(output.len(), 11 * 256 * 1024);
+        assert!(output.starts_with("0x23456789\n"));
+    }
+
+    #[test]
+    fn test_clear_and_reset_sequences() {
+        let parser = Parser::new();
+        let sequence = |line: &str| terminal_sequence(&parser.parse_pipeline(line).unwrap()[0]);
+
+        assert_eq!(sequence("clear"), "\x1B[2J\x1B[1;1H");
+        assert_eq!(sequence("clear --scrollback"), "\x1B[2J\x1B[1;1H\x1B[3J");
+        assert_eq!(sequence("reset"), "\x1Bc\x1B[2J\x1B[1;1H\x1B[3J");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_background_command_returns_job_id() {
+        let plugin_manager = PluginManager::new();
+        let executor = Executor::new(plugin_manager.clone());
+        let commands = Parser::new().parse_pipeline("sleep 30 &").unwrap();
+        assert!(commands[0].background);
+
+        // Had the executor waited, the job would be gone by now
+        let output = executor.execute_pipeline(&commands, &Environment::new()).await.unwrap();
+        let running = plugin_manager.jobs().running();
+        assert_eq!(running.len(), 1);
+        assert_eq!(output, format!("[{}] sleep 30", running[0].0));
+
+        // Finished jobs drop out of the table
+        std::process::Command::new("kill").arg(running[0].0.to_string()).status().unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while !plugin_manager.jobs().running().is_empty() {
+            assert!(std::time::Instant::now() < deadline, "killed job is still listed");
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+    }
+}
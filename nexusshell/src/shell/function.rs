@@ -0,0 +1,205 @@
+use super::{Environment, ShellError};
+
+const USAGE: &str = "function name() { commands; }";
+
+/// Parses `function name() { body }`, `function name { body }` or `name() { body }`.
+/// Returns `None` when `line` isn't a definition at all, and an error when it starts like one
+/// but is malformed. The body is kept unexpanded, like an alias value.
+pub fn parse_definition(line: &str) -> Option<anyhow::Result<(String, String)>> {
+    let line = line.trim();
+    let (rest, keyword) = match line.strip_prefix("function") {
+        Some(rest) if rest.starts_with(char::is_whitespace) => (rest.trim_start(), true),
+        _ => (line, false),
+    };
+
+    let name_len = rest.find(|c: char| c == '(' || c == '{' || c.is_whitespace()).unwrap_or(rest.len());
+    let (name, after_name) = rest.split_at(name_len);
+    let after_name = after_name.trim_start();
+    let (has_parens, after_parens) = match after_name.strip_prefix("()") {
+        Some(after) => (true, after.trim_start()),
+        None => (false, after_name),
+    };
+    // Without the keyword only `name()` marks a definition
+    if !keyword && (!has_parens || !is_name(name)) {
+        return None;
+    }
+
+    Some(match (is_name(name), after_parens.strip_prefix('{').and_then(|body| body.trim_end().strip_suffix('}'))) {
+        (true, Some(body)) => {
+            let body = body.trim().trim_end_matches(';').trim_end();
+            if body.is_empty() {
+                Err(ShellError::InvalidUsage(format!("{}: the body is empty", USAGE)).into())
+            } else {
+                Ok((name.to_string(), body.to_string()))
+            }
+        }
+        _ => Err(ShellError::InvalidUsage(USAGE.to_string()).into()),
+    })
+}
+
+/// Function names follow variable names, plus `-`.
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// `function name() { body }` lines for every defined function, as they can be re-entered.
+pub fn list(env: &Environment) -> String {
+    env.functions()
+        .iter()
+        .map(|(name, body)| format!("function {}() {{ {}; }}", name, body))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Splits a script into statements at `;` and newlines, except inside quotes or a `{ ... }`
+/// block, so a function definition can span lines.
+pub fn split_statements(source: &str) -> Vec<String> {
+    split(source, false).into_iter().map(|(_, statement)| statement).collect()
+}
+
+/// Splits a function body into commands. Each comes with whether it only runs when the one
+/// before it succeeded (it followed `&&`) rather than unconditionally (`;` or a newline).
+pub fn split_sequence(body: &str) -> Vec<(bool, String)> {
+    split(body, true)
+}
+
+fn split(source: &str, and_then: bool) -> Vec<(bool, String)> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut conditional = false;
+    let mut quote = None;
+    let mut depth = 0usize;
+    let mut chars = source.chars().peekable();
+
+    let mut finish = |current: &mut String, conditional: bool| {
+        let statement = current.trim();
+        if !statement.is_empty() {
+            statements.push((conditional, statement.to_string()));
+        }
+        current.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            // A comment runs to the end of the line, quotes and all
+            (None, '#') if current.trim().is_empty() => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+                continue;
+            }
+            (None, '{') => depth += 1,
+            (None, '}') => depth = depth.saturating_sub(1),
+            (None, ';' | '\n') if depth == 0 => {
+                finish(&mut current, conditional);
+                conditional = false;
+                continue;
+            }
+            (None, '&') if and_then && depth == 0 && chars.peek() == Some(&'&') => {
+                chars.next();
+                finish(&mut current, conditional);
+                conditional = true;
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    finish(&mut current, conditional);
+    statements
+}
+
+/// Replaces `$1`..`$9`, `${N}`, `$#` and `$@` with the call's arguments. Missing arguments
+/// become empty; single-quoted text is left alone, but an apostrophe inside double quotes
+/// doesn't start a quote.
+pub fn substitute_args(body: &str, args: &[String]) -> String {
+    let chars: Vec<char> = body.chars().collect();
+    let mut output = String::new();
+    let mut in_single_quotes = false;
+    let mut in_double_quotes = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\'' if !in_double_quotes => in_single_quotes = !in_single_quotes,
+            '"' if !in_single_quotes => in_double_quotes = !in_double_quotes,
+            _ => {}
+        }
+        if c != '$' || in_single_quotes {
+            output.push(c);
+            i += 1;
+            continue;
+        }
+
+        let (value, len) = match chars.get(i + 1) {
+            Some('#') => (args.len().to_string(), 2),
+            Some('@') => (args.join(" "), 2),
+            Some(d) if d.is_ascii_digit() && *d != '0' => (argument(args, &d.to_string()), 2),
+            Some('{') => match chars[i + 2..].iter().position(|&c| c == '}') {
+                Some(end) if end > 0 && chars[i + 2..i + 2 + end].iter().all(char::is_ascii_digit) => {
+                    let index: String = chars[i + 2..i + 2 + end].iter().collect();
+                    (argument(args, &index), end + 3)
+                }
+                _ => (c.to_string(), 1),
+            },
+            _ => (c.to_string(), 1),
+        };
+        output.push_str(&value);
+        i += len;
+    }
+    output
+}
+
+fn argument(args: &[String], index: &str) -> String {
+    index.parse::<usize>()
+        .ok()
+        .and_then(|index| index.checked_sub(1))
+        .and_then(|index| args.get(index))
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_definition() {
+        let parsed = |line: &str| parse_definition(line).map(|result| result.unwrap());
+        let deploy = Some(("deploy".to_string(), "git push && schedule run-now 3".to_string()));
+        assert_eq!(parsed("function deploy() { git push && schedule run-now 3; }"), deploy);
+        assert_eq!(parsed("deploy() { git push && schedule run-now 3 }"), deploy);
+        assert_eq!(parsed("function deploy {\n  git push && schedule run-now 3\n}"), deploy);
+
+        assert_eq!(parsed("ls -la"), None);
+        assert_eq!(parsed("echo deploy() { x; }"), None);
+        assert_eq!(parsed("functions"), None);
+        for bad in ["function deploy() { git push", "function () { ls; }", "function 9lives() { ls; }", "f() { }"] {
+            assert!(parse_definition(bad).unwrap().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_split_sequence() {
+        assert_eq!(split_sequence("git push && schedule run-now 3; echo done\nls & pwd"), vec![
+            (false, "git push".to_string()),
+            (true, "schedule run-now 3".to_string()),
+            (false, "echo done".to_string()),
+            (false, "ls & pwd".to_string()),
+        ]);
+        assert_eq!(split_statements("a; f() { b; c\n}\necho 'x;y'"), vec!["a", "f() { b; c\n}", "echo 'x;y'"]);
+        assert_eq!(split_statements("# it's a comment\nls # trailing"), vec!["ls # trailing"]);
+    }
+
+    #[test]
+    fn test_substitute_args() {
+        let args = vec!["prod".to_string(), "v2".to_string()];
+        assert_eq!(substitute_args("deploy $1 ${2} $3", &args), "deploy prod v2 ");
+        assert_eq!(substitute_args("echo $# $@ $HOME '$1'", &args), "echo 2 prod v2 $HOME '$1'");
+        assert_eq!(substitute_args("echo \"it's $1\" '$2'", &args), "echo \"it's prod\" '$2'");
+    }
+}
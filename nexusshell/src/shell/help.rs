@@ -20,28 +20,42 @@ impl HelpSystem {
         help.push_str(&format!("{}\n\n", "=================".bright_green()));
 
         help.push_str(&format!("{}\n", "File Operations:".yellow()));
-        help.push_str("  ls      - List directory contents\n");
-        help.push_str("  cd      - Change directory\n");
+        help.push_str("  ls      - List directory contents (--color=auto|always|never)\n");
+        help.push_str("  cd      - Change directory (- for the previous one, +N/-N/~N for a stack entry)\n");
+        help.push_str("  pushd   - Save the current directory on the stack and change directory\n");
+        help.push_str("  popd    - Return to the most recently pushed directory\n");
+        help.push_str("  dirs    - Show the directory stack (-v numbered)\n");
         help.push_str("  pwd     - Print working directory\n");
-        help.push_str("  cp      - Copy files or directories\n");
-        help.push_str("  mv      - Move files or directories\n");
+        help.push_str("  cp      - Copy files or directories (-n no-clobber, -i confirm)\n");
+        help.push_str("  mv      - Move files or directories (-n no-clobber, -i confirm)\n");
         help.push_str("  rm      - Remove files or directories\n");
-        help.push_str("  mkdir   - Create directory\n");
-        help.push_str("  touch   - Create empty file\n");
-        help.push_str("  cat     - Display file contents\n\n");
+        help.push_str("  mkdir   - Create directories (-p to create parents, -m <mode> for permissions)\n");
+        help.push_str("  rmdir   - Remove empty directories (-p to remove empty parents)\n");
+        help.push_str("  stat    - Show file metadata (--format with %n %s %F %a %A %u %g %i %x %y %w)\n");
+        help.push_str("  touch   - Create files or update timestamps (-t/--date <time>)\n");
+        help.push_str("  cat     - Display file contents\n");
+        help.push_str("  diff    - Show differences between two files (--stat, --context <n>)\n");
+        help.push_str("  find    - Find files (--name <glob>, --type f|d|l, -L to follow symlinks)\n");
+        help.push_str("  dedup   - Find duplicate files in a directory (--delete keeps the first of each group)\n");
+        help.push_str("  checksum - Compute or --check sha1/sha256/sha384/sha512 digests\n");
+        help.push_str("  manifest - SHA-256 manifest of a tree (create <dir> [file], verify <dir> <file>, --key <keyfile> to sign)\n\n");
 
         help.push_str(&format!("{}\n", "Process Management:".yellow()));
-        help.push_str("  ps      - List processes\n");
-        help.push_str("  kill    - Terminate process\n");
-        help.push_str("  top     - Show system resources\n");
-        help.push_str("  bg      - Run process in background\n");
+        help.push_str("  ps      - List processes (-o table|json|yaml, --csv)\n");
+        help.push_str("  kill    - Terminate process (SIGTERM; --force, --timeout <secs>)\n");
+        help.push_str("  top     - Show top processes by CPU (-o table|json|yaml, --csv)\n");
+        help.push_str("  df      - Show filesystem usage (-h human-readable, optional path)\n");
+        help.push_str("  free    - Show memory and swap usage (-h, --watch <secs>)\n");
+        help.push_str("  bg      - Run process in background (or end a command with &)\n");
+        help.push_str("  jobs    - List running background jobs\n");
         help.push_str("  fg      - Bring process to foreground\n\n");
 
         help.push_str(&format!("{}\n", "Network Operations:".yellow()));
         help.push_str("  ping    - Test network connectivity\n");
         help.push_str("  curl    - Transfer data from/to server\n");
         help.push_str("  wget    - Download files\n");
-        help.push_str("  netstat - Network statistics\n\n");
+        help.push_str("  netstat - Network statistics\n");
+        help.push_str("  net     - Echo server for testing (listen <port> [--once] [--bind <addr>], loopback by default), local addresses (interfaces [--json])\n\n");
 
         help.push_str(&format!("{}\n", "Git Commands:".yellow()));
         help.push_str("  git status   - Show working tree status\n");
@@ -51,9 +65,18 @@ impl HelpSystem {
 
         help.push_str(&format!("{}\n", "Shell Control:".yellow()));
         help.push_str("  help    - Show this help\n");
-        help.push_str("  clear   - Clear screen\n");
-        help.push_str("  exit    - Exit shell\n");
-        help.push_str("  history - Show command history\n\n");
+        help.push_str("  clear   - Clear screen (--scrollback to clear scrollback too)\n");
+        help.push_str("  reset   - Reset a garbled terminal and clear it\n");
+        help.push_str("  open    - Open a file or URL with the default application (--reveal to show it in its folder)\n");
+        help.push_str("  exit    - Exit shell with [status] or the last command's (--force to skip the running-jobs warning)\n");
+        help.push_str("  alias   - Define or list aliases (alias name='value'), unalias to remove\n");
+        help.push_str("  function - Define a function (function name() { cmd $1 && cmd2; }) or list them\n");
+        help.push_str("  source  - Run commands from a file (~/.nexusshellrc runs at startup)\n");
+        help.push_str("  on-exit - Run a command when the shell exits (on-exit 'schedule cleanup 7'); on-startup in ~/.nexusshellrc runs one after startup\n");
+        help.push_str("  history - Show numbered command history (run <n>, clear [--force], delete [--regex] <pattern>, export/import <path>)\n");
+        help.push_str("  env load - Set variables from a dotenv file (auto_env in config.json loads .env on cd)\n");
+        help.push_str("  completion - Print a completion script for another shell (completion generate bash|zsh|fish)\n");
+        help.push_str("  --page  - Add to any command (or -P) to page long output through $PAGER or the built-in pager\n\n");
 
         help.push_str(&format!("{}\n", "For detailed help on any command, type:".bright_blue()));
         help.push_str(&format!("{}\n", "  help <command>".bright_blue()));
@@ -68,17 +91,17 @@ impl HelpSystem {
                 "=========================".bright_green(),
                 "Usage:".yellow()),
 
-            "cd" => format!("{}\n{}\n\n{}\n  cd              Change to home directory\n  cd <dir>         Change to specified directory\n  cd ..           Move up one directory\n  cd -            Change to previous directory",
+            "cd" => format!("{}\n{}\n\n{}\n  cd              Change to home directory\n  cd <dir>         Change to specified directory\n  cd ..           Move up one directory\n  cd -            Change to previous directory\n  cd +N / cd ~N   Change to entry N of the directory stack (see dirs -v)\n  cd -N           Change to entry N counting from the bottom of the stack",
                 "cd - Change directory".bright_green(),
                 "====================".bright_green(),
                 "Usage:".yellow()),
 
-            "git" => format!("{}\n{}\n\n{}\n  git status      Show working tree status\n  git log         Show commit logs\n  git diff        Show changes\n  git branch      List branches\n  git checkout    Switch branches\n  git commit      Record changes\n  git push        Update remote refs",
+            "git" => format!("{}\n{}\n\n{}\n  git status      Show working tree status\n  git status --porcelain\n                  Stable `XY path` lines for scripts\n  git log         Show commit logs\n  git log --preset <oneline|full|graph>\n                  Show commit logs in a preset format\n  git log --raw   Pass log arguments through untouched\n  git diff        Show changes\n  git branch      List branches\n  git checkout    Switch branches\n  git commit      Record changes\n  git push        Update remote refs",
                 "git - Version control operations".bright_green(),
                 "=============================".bright_green(),
                 "Usage:".yellow()),
 
-            "ps" => format!("{}\n{}\n\n{}\n  ps              List processes\n  ps -e           List all processes\n  ps -f           Full format listing\n  ps aux          BSD style listing",
+            "ps" => format!("{}\n{}\n\n{}\n  ps              List processes\n  ps -e           List all processes\n  ps -f           Full format listing\n  ps aux          BSD style listing\n  ps -o json      Output as table, json or yaml",
                 "ps - List processes".bright_green(),
                 "==================".bright_green(),
                 "Usage:".yellow()),
@@ -1,20 +1,86 @@
+use regex::Regex;
 use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
-const MAX_HISTORY_SIZE: usize = 1000;
+const DEFAULT_HISTORY_SIZE: usize = 1000;
 
 pub struct History {
     commands: VecDeque<String>,
+    max_size: usize,
+    file: Option<PathBuf>,
+    rewritten: bool,
+    /// Lines these match are never recorded
+    ignore: Vec<Regex>,
+    /// Skip lines that start with a space, like bash's `HISTCONTROL=ignorespace`
+    ignore_space: bool,
 }
 
 impl History {
     pub fn new() -> Self {
         History {
-            commands: VecDeque::with_capacity(MAX_HISTORY_SIZE),
+            commands: VecDeque::new(),
+            max_size: DEFAULT_HISTORY_SIZE,
+            file: None,
+            rewritten: false,
+            ignore: Vec::new(),
+            ignore_space: false,
         }
     }
 
+    /// Never records lines that start with one of `patterns`. Matching ignores case and
+    /// repeated whitespace, and `*` matches anything (`export *PASSWORD*`).
+    pub fn with_ignore_patterns(mut self, patterns: &[String]) -> Self {
+        self.ignore = patterns.iter()
+            .filter_map(|pattern| {
+                let words: Vec<String> = pattern.split_whitespace()
+                    .map(|word| word.split('*').map(regex::escape).collect::<Vec<_>>().join(".*"))
+                    .collect();
+                Regex::new(&format!("(?i)^{}", words.join(" "))).ok()
+            })
+            .collect();
+        self
+    }
+
+    pub fn set_ignore_space(&mut self, ignore_space: bool) {
+        self.ignore_space = ignore_space;
+    }
+
+    /// Whether `line` may be kept, given the ignore patterns and the leading-space rule.
+    pub fn should_record(&self, line: &str) -> bool {
+        if self.ignore_space && line.starts_with(' ') {
+            return false;
+        }
+        let normalized = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        !self.ignore.iter().any(|pattern| pattern.is_match(&normalized))
+    }
+
+    /// Keeps at most `max_size` entries, dropping the oldest first.
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        while self.commands.len() > max_size {
+            self.commands.pop_front();
+        }
+        self
+    }
+
+    /// Associates the history with its persisted file, loading any entries already in it
+    /// so that `clear` and `delete` rewrite the file as a whole.
+    pub fn with_file(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        if path.exists() {
+            let _ = self.import(&path);
+        }
+        self.file = Some(path);
+        self
+    }
+
     pub fn add(&mut self, command: String) {
-        if self.commands.len() >= MAX_HISTORY_SIZE {
+        if self.max_size == 0 || !self.should_record(&command) {
+            return;
+        }
+        if self.commands.len() >= self.max_size {
             self.commands.pop_front();
         }
         self.commands.push_back(command);
@@ -24,19 +90,143 @@ impl History {
         self.commands.iter().cloned().collect()
     }
 
-    pub fn clear(&mut self) {
+    pub fn clear(&mut self) -> io::Result<()> {
         self.commands.clear();
+        self.rewritten = true;
+        self.persist()
+    }
+
+    /// Removes every entry for which `matches` returns true, returning how many were removed.
+    pub fn delete_matching(&mut self, matches: impl Fn(&str) -> bool) -> io::Result<usize> {
+        let before = self.commands.len();
+        self.commands.retain(|command| !matches(command));
+        let removed = before - self.commands.len();
+        if removed > 0 {
+            self.rewritten = true;
+            self.persist()?;
+        }
+        Ok(removed)
+    }
+
+    /// Reports (once) that entries were removed, so callers mirroring the history can resync.
+    pub fn take_rewritten(&mut self) -> bool {
+        std::mem::take(&mut self.rewritten)
+    }
+
+    fn persist(&self) -> io::Result<()> {
+        match &self.file {
+            Some(file) => self.export(file).map(|_| ()),
+            None => Ok(()),
+        }
     }
 
-    pub fn get_last(&self, n: usize) -> Vec<String> {
-        self.commands
-            .iter()
-            .rev()
-            .take(n)
-            .cloned()
-            .collect::<Vec<_>>()
-            .into_iter()
-            .rev()
-            .collect()
+    /// Writes every entry to `path`, one per line, returning the number written.
+    pub fn export(&self, path: &Path) -> io::Result<usize> {
+        let mut content = String::new();
+        for command in &self.commands {
+            content.push_str(command);
+            content.push('\n');
+        }
+        fs::write(path, content)?;
+        Ok(self.commands.len())
+    }
+
+    /// Appends the entries stored in `path`, returning the number read.
+    pub fn import(&mut self, path: &Path) -> io::Result<usize> {
+        let content = fs::read_to_string(path)?;
+        let mut count = 0;
+        // Skip the version header rustyline writes at the top of its history files
+        for line in content.lines().filter(|line| !line.trim().is_empty() && *line != "#V2") {
+            self.add(line.to_string());
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_export_then_import() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("exported.txt");
+
+        let mut history = History::new();
+        history.add("ls -la".to_string());
+        history.add("git status".to_string());
+        assert_eq!(history.export(&path).unwrap(), 2);
+
+        let mut fresh = History::new();
+        assert_eq!(fresh.import(&path).unwrap(), 2);
+        assert_eq!(fresh.get_all(), vec!["ls -la".to_string(), "git status".to_string()]);
+    }
+
+    #[test]
+    fn test_clear_wipes_persisted_file() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("history.txt");
+        fs::write(&path, "echo old\n").unwrap();
+
+        let mut history = History::new().with_file(&path);
+        history.add("echo new".to_string());
+        history.clear().unwrap();
+
+        assert!(history.get_all().is_empty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[test]
+    fn test_delete_matching_substring() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("history.txt");
+
+        let mut history = History::new().with_file(&path);
+        history.add("mysql -p hunter2".to_string());
+        history.add("ls".to_string());
+        history.add("echo hunter2".to_string());
+        history.add("git status".to_string());
+
+        assert_eq!(history.delete_matching(|entry| entry.contains("hunter2")).unwrap(), 2);
+        assert_eq!(history.get_all(), vec!["ls".to_string(), "git status".to_string()]);
+        assert!(history.take_rewritten());
+        assert!(!fs::read_to_string(&path).unwrap().contains("hunter2"));
+    }
+
+    #[test]
+    fn test_sensitive_commands_not_recorded() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("history.txt");
+        let patterns = vec!["security credential add".to_string(), "export *PASSWORD*".to_string()];
+
+        let mut history = History::new().with_ignore_patterns(&patterns).with_file(&path);
+        history.set_ignore_space(true);
+        history.add("security  credential add db admin hunter2".to_string());
+        history.add("export DB_Password=hunter2".to_string());
+        history.add(" echo hunter2".to_string());
+        history.add("export EDITOR=vim".to_string());
+        history.add("security credential list".to_string());
+
+        assert_eq!(history.get_all(), vec!["export EDITOR=vim", "security credential list"]);
+        history.export(&path).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("export EDITOR=vim"));
+        assert!(!saved.contains("hunter2"));
+        history.clear().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        history.set_ignore_space(false);
+        assert!(history.should_record(" echo hunter2"));
+    }
+
+    #[test]
+    fn test_max_size_drops_oldest() {
+        let mut history = History::new().with_max_size(3);
+        for n in 1..=5 {
+            history.add(format!("echo {}", n));
+        }
+        assert_eq!(history.get_all(), vec!["echo 3", "echo 4", "echo 5"]);
     }
 }
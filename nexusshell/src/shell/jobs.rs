@@ -0,0 +1,39 @@
+use std::sync::Mutex;
+use tokio::process::Child;
+
+struct BackgroundJob {
+    pid: u32,
+    command: String,
+    child: Child,
+}
+
+/// Background processes started from the shell, tracked so `exit` can warn about them.
+pub struct JobTable {
+    jobs: Mutex<Vec<BackgroundJob>>,
+}
+
+impl JobTable {
+    pub fn new() -> Self {
+        JobTable {
+            jobs: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn add(&self, command: String, child: Child) -> Option<u32> {
+        let pid = child.id()?;
+        if let Ok(mut jobs) = self.jobs.lock() {
+            jobs.push(BackgroundJob { pid, command, child });
+        }
+        Some(pid)
+    }
+
+    /// Jobs that are still running as `(pid, command)`; finished ones are dropped.
+    pub fn running(&self) -> Vec<(u32, String)> {
+        let mut jobs = match self.jobs.lock() {
+            Ok(jobs) => jobs,
+            Err(_) => return Vec::new(),
+        };
+        jobs.retain_mut(|job| matches!(job.child.try_wait(), Ok(None)));
+        jobs.iter().map(|job| (job.pid, job.command.clone())).collect()
+    }
+}
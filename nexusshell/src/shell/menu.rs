@@ -0,0 +1,137 @@
+use crossterm::cursor;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Print, Stylize};
+use crossterm::terminal::{self, ClearType};
+use crossterm::queue;
+use std::io::{self, Write};
+
+/// How many candidates the menu shows at once; the window scrolls to follow the selection.
+const VISIBLE: usize = 10;
+
+/// Arrow-key selection among completion candidates, in the order they were given.
+pub struct Menu {
+    items: Vec<String>,
+    selected: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    Continue,
+    Accept(String),
+    Cancel,
+}
+
+impl Menu {
+    pub fn new(items: Vec<String>) -> Self {
+        Menu { items, selected: 0 }
+    }
+
+    /// Up/Down (or Shift-Tab/Tab, Ctrl-P/Ctrl-N) move the selection and wrap around, Enter
+    /// picks it and Esc or Ctrl-C closes the menu.
+    pub fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Step {
+        let len = self.items.len();
+        match code {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Step::Cancel,
+            KeyCode::Char('p') if modifiers.contains(KeyModifiers::CONTROL) => self.selected = (self.selected + len - 1) % len,
+            KeyCode::Char('n') if modifiers.contains(KeyModifiers::CONTROL) => self.selected = (self.selected + 1) % len,
+            KeyCode::Up | KeyCode::BackTab => self.selected = (self.selected + len - 1) % len,
+            KeyCode::Down | KeyCode::Tab => self.selected = (self.selected + 1) % len,
+            KeyCode::Enter => return Step::Accept(self.items[self.selected].clone()),
+            KeyCode::Esc => return Step::Cancel,
+            _ => {}
+        }
+        Step::Continue
+    }
+
+    /// The visible rows as `(index, item)`, keeping the selection inside the window.
+    pub fn window(&self) -> impl Iterator<Item = (usize, &String)> {
+        let top = (self.selected + 1).saturating_sub(VISIBLE);
+        self.items.iter().enumerate().skip(top).take(VISIBLE)
+    }
+
+    fn rows(&self) -> usize {
+        self.items.len().min(VISIBLE)
+    }
+}
+
+/// Shows `items` below the cursor until one is picked or the menu is closed, then erases
+/// it and leaves the cursor where it was. Expects the terminal to be in raw mode already,
+/// as it is while the line editor reads a line.
+pub fn select(items: Vec<String>) -> io::Result<Option<String>> {
+    let mut menu = Menu::new(items);
+    let mut stdout = io::stdout();
+    let (column, _) = cursor::position()?;
+
+    let result = loop {
+        draw(&menu, &mut stdout, column)?;
+        let key = match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => continue,
+        };
+        match menu.key(key.code, key.modifiers) {
+            Step::Continue => {}
+            Step::Accept(item) => break Some(item),
+            Step::Cancel => break None,
+        }
+    };
+
+    queue!(stdout, cursor::MoveToNextLine(1), terminal::Clear(ClearType::FromCursorDown))?;
+    queue!(stdout, cursor::MoveToPreviousLine(1), cursor::MoveToColumn(column))?;
+    stdout.flush()?;
+    Ok(result)
+}
+
+fn draw(menu: &Menu, stdout: &mut io::Stdout, column: u16) -> io::Result<()> {
+    for (index, item) in menu.window() {
+        queue!(stdout, Print("\r\n"), terminal::Clear(ClearType::CurrentLine))?;
+        if index == menu.selected {
+            queue!(stdout, Print(format!("> {}", item).reverse()))?;
+        } else {
+            queue!(stdout, Print(format!("  {}", item)))?;
+        }
+    }
+    queue!(stdout, cursor::MoveToPreviousLine(menu.rows() as u16), cursor::MoveToColumn(column))?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn menu(count: usize) -> Menu {
+        Menu::new((0..count).map(|i| format!("item{}", i)).collect())
+    }
+
+    #[test]
+    fn test_keys_move_and_wrap() {
+        let mut menu = menu(3);
+        assert_eq!(menu.key(KeyCode::Up, KeyModifiers::NONE), Step::Continue);
+        assert_eq!(menu.key(KeyCode::Enter, KeyModifiers::NONE), Step::Accept("item2".to_string()));
+
+        menu.key(KeyCode::Down, KeyModifiers::NONE);
+        menu.key(KeyCode::Char('n'), KeyModifiers::CONTROL);
+        assert_eq!(menu.key(KeyCode::Enter, KeyModifiers::NONE), Step::Accept("item1".to_string()));
+
+        assert_eq!(menu.key(KeyCode::Char('x'), KeyModifiers::NONE), Step::Continue);
+        assert_eq!(menu.key(KeyCode::Esc, KeyModifiers::NONE), Step::Cancel);
+        assert_eq!(menu.key(KeyCode::Char('c'), KeyModifiers::CONTROL), Step::Cancel);
+    }
+
+    #[test]
+    fn test_window_follows_the_selection() {
+        let mut menu = menu(25);
+        let first = |menu: &Menu| menu.window().next().unwrap().0;
+        assert_eq!(menu.window().count(), VISIBLE);
+        assert_eq!(first(&menu), 0);
+
+        for _ in 0..12 {
+            menu.key(KeyCode::Down, KeyModifiers::NONE);
+        }
+        assert_eq!(first(&menu), 3);
+        assert_eq!(menu.window().last().unwrap().0, 12);
+
+        menu.key(KeyCode::Up, KeyModifiers::NONE);
+        menu.key(KeyCode::Up, KeyModifiers::NONE);
+        assert_eq!(first(&menu), 1);
+    }
+}
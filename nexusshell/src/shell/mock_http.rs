@@ -0,0 +1,53 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// A canned response from a `serve` handler.
+pub struct Reply {
+    status: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Reply {
+    pub fn new(status: &'static str, body: impl Into<String>) -> Self {
+        Reply { status, headers: Vec::new(), body: body.into() }
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+}
+
+/// Serves HTTP on a local port until the test runtime shuts down, answering each request
+/// with whatever `respond` returns for its request line (`GET /path HTTP/1.1`). Returns the
+/// base URL. Every connection carries a single request.
+pub async fn serve<F>(mut respond: F) -> String
+where
+    F: FnMut(&str) -> Reply + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut buf = [0u8; 4096];
+            let read = socket.read(&mut buf).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..read]);
+            let reply = respond(request.lines().next().unwrap_or_default());
+
+            let mut response = format!("HTTP/1.1 {}\r\n", reply.status);
+            for (name, value) in &reply.headers {
+                response.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            response.push_str(&format!(
+                "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                reply.body.len(),
+                reply.body
+            ));
+            let _ = socket.write_all(response.as_bytes()).await;
+        }
+    });
+
+    base
+}
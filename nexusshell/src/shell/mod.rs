@@ -7,108 +7,1079 @@ mod environment;
 mod completion;
 mod syntax;
 mod help;
+mod error;
+mod jobs;
+mod preprocessor;
+mod config;
+mod pager;
+mod util;
+mod render;
+mod table;
+mod timespec;
+mod function;
+mod tty;
+mod open;
+pub mod color;
+pub mod menu;
+#[cfg(test)]
+mod mock_http;
 
-pub use command::Command;
+pub use command::{split_words, Command};
 pub use history::History;
 pub use parser::Parser;
-pub use plugins::PluginManager;
+pub use plugins::{Plugin, PluginManager};
 pub use executor::Executor;
 pub use environment::Environment;
-pub use completion::Completer;
+pub use completion::{order_candidates, Completer};
 pub use syntax::SyntaxHighlighter;
 pub use help::HelpSystem;
+pub use error::ShellError;
+pub use jobs::JobTable;
+pub use preprocessor::Preprocessor;
+pub use config::Config;
+pub use table::Table;
 
+use colored::*;
+use std::io::IsTerminal;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use std::path::PathBuf;
 
+/// Startup file run before the first prompt of an interactive session.
+pub const RC_FILE: &str = ".nexusshellrc";
+/// Session profile, relative to the home directory, written on exit when
+/// `NEXUSSHELL_SAVE_PROFILE` is set.
+pub const PROFILE_FILE: &str = ".nexusshell/profile.json";
+/// How deeply `source` may nest before it is assumed to be looping.
+const MAX_SOURCE_DEPTH: usize = 16;
+/// How deeply shell functions may call each other (or themselves).
+const MAX_CALL_DEPTH: usize = 32;
+
 pub struct Shell {
     history: Arc<Mutex<History>>,
     plugin_manager: Arc<PluginManager>,
-    parser: Parser,
+    preprocessor: Preprocessor,
     executor: Executor,
     environment: Environment,
     completer: Completer,
     syntax_highlighter: SyntaxHighlighter,
     help_system: HelpSystem,
+    config: Config,
+    exit_warned: bool,
+    /// Status of the most recent command, used by a bare `exit`
+    last_status: i32,
+    /// Set by an `exit` that went through; the caller shuts down and exits with it
+    exit_requested: Option<i32>,
+    source_depth: usize,
+    call_depth: usize,
+    /// Nesting of `run_lines`: rc files, scripts, sourced files and hooks aren't recorded
+    script_depth: usize,
+    /// Skip echoing the highlighted command before running it
+    quiet: bool,
+    /// Commands registered with `on-startup`, run once the rc file and profile are loaded
+    startup_hooks: Vec<String>,
+    /// Commands registered with `on-exit`, run at shutdown before anything is saved
+    exit_hooks: Vec<String>,
 }
 
 impl Shell {
     pub fn new() -> Self {
+        let config = Config::load();
         let environment = Environment::new();
-        let history = Arc::new(Mutex::new(History::new()));
-        let plugin_manager = Arc::new(PluginManager::new());
-        let parser = Parser::new();
+        let history = History::new()
+            .with_max_size(config.history_size)
+            .with_ignore_patterns(&config.history_ignore)
+            .with_file(&config.history_file);
+        let history = Arc::new(Mutex::new(history));
+        let plugin_manager = PluginManager::new();
+        let preprocessor = Preprocessor::new();
         let executor = Executor::new(plugin_manager.clone());
-        let completer = Completer::new(environment.clone());
+        let completer = Completer::new(environment.clone()).with_plugins(plugin_manager.clone());
         let syntax_highlighter = SyntaxHighlighter::new();
         let help_system = HelpSystem::new();
 
         Shell {
             history,
             plugin_manager,
-            parser,
+            preprocessor,
             executor,
             environment,
             completer,
             syntax_highlighter,
             help_system,
+            exit_warned: false,
+            last_status: 0,
+            exit_requested: None,
+            source_depth: 0,
+            call_depth: 0,
+            script_depth: 0,
+            quiet: config.quiet,
+            startup_hooks: Vec::new(),
+            exit_hooks: Vec::new(),
+            config,
+        }
+    }
+
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    /// The highlighted command to echo before running `input`, unless quiet.
+    fn echo_line(&self, input: &str) -> Option<String> {
+        if self.quiet {
+            None
+        } else {
+            Some(self.syntax_highlighter.highlight_command(input))
         }
     }
 
+    /// Runs one line, remembering its status for a bare `exit`.
     pub async fn run_command(&mut self, input: &str) -> anyhow::Result<String> {
-        // Highlight the command
-        let highlighted_input = self.syntax_highlighter.highlight_command(input);
-        println!("{}", highlighted_input);
+        let result = self.dispatch(input).await;
+        self.last_status = match &result {
+            Ok(_) => 0,
+            Err(e) => Executor::exit_code(e),
+        };
+        result
+    }
+
+    /// The status of the last command run, as `exit` without an argument uses it.
+    pub fn last_status(&self) -> i32 {
+        self.last_status
+    }
+
+    /// The status to exit with once `exit` has run. Scripts, functions and hooks stop at
+    /// that point; the interactive loop ends so history can be saved before exiting.
+    pub fn exit_requested(&self) -> Option<i32> {
+        self.exit_requested
+    }
+
+    async fn dispatch(&mut self, input: &str) -> anyhow::Result<String> {
+        // Statements of a function body belong to the call, which was already echoed and recorded
+        let in_function = self.call_depth > 0;
+        if let Some(echo) = self.echo_line(input).filter(|_| !in_function) {
+            println!("{}", echo);
+        }
+        let record = !in_function && self.script_depth == 0 && self.should_record(input).await;
+
+        // Function definitions keep their body unexpanded until the function is called
+        if let Some(definition) = function::parse_definition(input) {
+            if record {
+                self.history.lock().await.add(input.to_string());
+            }
+            let (name, body) = definition?;
+            self.environment.define_function(name, body);
+            return Ok(String::new());
+        }
+        if input.trim() == "function" {
+            return Ok(function::list(&self.environment));
+        }
 
-        // Add command to history
-        self.history.lock().await.add(input.to_string());
+        // These take the raw line; alias values and sourced files are expanded when used
+        let mut words = input.split_whitespace();
+        if let Some(builtin @ ("alias" | "unalias" | "source" | "." | "on-startup" | "on-exit")) = words.next() {
+            if record {
+                self.history.lock().await.add(input.to_string());
+            }
+            let rest = input.trim_start()[builtin.len()..].trim();
+            return match builtin {
+                "alias" => self.run_alias(rest),
+                "unalias" => self.run_unalias(rest),
+                "on-startup" => Ok(add_hook(&mut self.startup_hooks, builtin, rest)),
+                "on-exit" => Ok(add_hook(&mut self.exit_hooks, builtin, rest)),
+                _ => self.source(rest).await,
+            };
+        }
+
+        // Record the line with history references resolved but before variables and command
+        // substitutions are expanded, so their values (often secrets) stay out of the history
+        let history = self.history.lock().await.get_all();
+        let line = self.preprocessor.expand_history(input, &history)?;
+        // `history run <n>` stands for the entry it names, which is recorded and run in its place
+        let line = resolve_history_run(&line, &history)?.unwrap_or(line);
+        if record {
+            self.history.lock().await.add(line.clone());
+        }
+        let line = self.preprocessor.expand_commands(&line, &self.environment, &self.executor).await?;
 
         // Handle help command
-        if input.starts_with("help") {
-            let args: Vec<&str> = input.split_whitespace().collect();
+        if line.starts_with("help") {
+            let args: Vec<&str> = line.split_whitespace().collect();
             return Ok(self.help_system.get_help(args.get(1).copied()));
         }
 
-        // Parse the command
-        let command = self.parser.parse(input)?;
+        // Parse the command into pipeline stages
+        let mut commands = self.preprocessor.tokenize(&line)?;
+        let page_requested = commands.last_mut().is_some_and(take_page_flag);
+
+        // A bare `NAME=VALUE` line sets session variables
+        if commands.len() == 1 && commands[0].is_assignment_only() {
+            for (name, value) in &commands[0].assignments {
+                self.environment.set_var(name.clone(), value.clone());
+            }
+            return Ok(String::new());
+        }
+
+        if commands.len() == 1 && commands[0].name == "history" {
+            return self.run_history(&commands[0]).await;
+        }
+
+        if commands.len() == 1 {
+            if let Some(body) = self.environment.function(&commands[0].name).cloned() {
+                // Positional arguments are the words as typed, flags included
+                let args: Vec<String> = line.split_whitespace().skip(1).map(String::from).collect();
+                return self.call_function(&commands[0].name, &body, &args).await;
+            }
+        }
+
+        if commands.len() == 1 && matches!(commands[0].name.as_str(), "cd" | "pushd" | "popd" | "dirs") {
+            let before = self.environment.get_current_dir();
+            let output = self.run_directory_builtin(&commands[0])?;
+            if self.config.auto_env && self.environment.get_current_dir() != before {
+                self.load_dir_env_file();
+            }
+            return Ok(output);
+        }
+
+        if commands.len() == 1 && commands[0].name == "env" && commands[0].args.first().map(String::as_str) == Some("load") {
+            let file = commands[0].args.get(1)
+                .ok_or_else(|| ShellError::InvalidUsage("env load <file>".to_string()))?;
+            let path = self.environment.expand_path(file);
+            let count = self.environment.load_env_file(&path)
+                .map_err(|e| ShellError::External(format!("env load: {}: {}", path.display(), e)))?;
+            return Ok(format!("Loaded {} variables from {}", count, path.display()));
+        }
+
+        // On a terminal, `cat <file>` is highlighted line by line rather than read whole
+        if commands.len() == 1 && commands[0].name == "cat" && commands[0].args.len() == 1
+            && std::io::stdout().is_terminal()
+        {
+            return self.cat_highlighted(&commands[0].args[0], page_requested || self.config.pager);
+        }
+
+        if commands.len() == 1 && commands[0].name == "completion" {
+            return match commands[0].args.as_slice() {
+                [action, shell] if action == "generate" => self.completer.generate_script(shell),
+                _ => Err(ShellError::InvalidUsage("completion generate bash|zsh|fish".to_string()).into()),
+            };
+        }
 
         // Check for exit command
-        if command.is_exit() {
-            std::process::exit(0);
+        if commands.len() == 1 && commands[0].is_exit() {
+            let status = exit_status(&commands[0], self.last_status)?;
+            match self.request_exit(commands[0].flags.contains_key("force")) {
+                Ok(()) => {
+                    self.exit_requested = Some(status);
+                    return Ok(String::new());
+                }
+                Err(warning) => return Ok(warning),
+            }
+        }
+        self.exit_warned = false;
+
+        // Execute the pipeline, then keep any variables the commands exported
+        let result = match self.executor.execute_pipeline(&commands, &self.environment).await {
+            Err(e) if commands.len() == 1 => match e.downcast_ref::<ShellError>() {
+                // `-i`: ask, then run again without the flag if the user agrees
+                Some(ShellError::ConfirmationRequired(question)) => {
+                    if !confirm(question)? {
+                        return Ok("Skipped".to_string());
+                    }
+                    let mut command = commands[0].clone();
+                    command.flags.remove("i");
+                    command.flags.remove("interactive");
+                    self.executor.execute(&command, &self.environment).await
+                }
+                _ => Err(e),
+            },
+            result => result,
+        };
+        self.environment.apply_exports();
+
+        let output = result?;
+        if page_requested || self.config.pager {
+            let height = crossterm::terminal::size().ok().map(|(_, rows)| rows);
+            if pager::should_page(&output, std::io::stdout().is_terminal(), height) {
+                pager::page(&output, &self.environment)?;
+                return Ok(String::new());
+            }
+        }
+        Ok(output)
+    }
+
+    /// Runs newline/`;`-separated commands as a script, printing each result. Returns the
+    /// status of the last command, or the one given to `exit`, which ends the script.
+    pub async fn run_lines(&mut self, source: &str) -> i32 {
+        self.script_depth += 1;
+        let status = self.run_script_lines(source).await;
+        self.script_depth -= 1;
+        status
+    }
+
+    async fn run_script_lines(&mut self, source: &str) -> i32 {
+        let mut status = 0;
+
+        for line in function::split_statements(source) {
+            let line = line.as_str();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(code) = self.exit_requested {
+                return code;
+            }
+
+            status = match self.run_command(line).await {
+                Ok(output) => {
+                    if !output.is_empty() {
+                        println!("{}", output);
+                    }
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{}: {}", "Error".bright_red(), e);
+                    Executor::exit_code(&e)
+                }
+            };
+        }
+
+        self.exit_requested.unwrap_or(status)
+    }
+
+    /// Runs `~/.nexusshellrc` if it exists.
+    pub async fn load_rc(&mut self) {
+        let path = match dirs::home_dir() {
+            Some(home) => home.join(RC_FILE),
+            None => return,
+        };
+        if let Ok(content) = std::fs::read_to_string(&path) {
+            self.run_lines(&content).await;
+        }
+    }
+
+    /// Runs the `on-startup` hooks the rc file registered. Call after `load_rc` and
+    /// `load_profile`, so hooks see the restored directory and variables.
+    pub async fn run_startup_hooks(&mut self) {
+        let hooks = self.startup_hooks.clone();
+        self.run_hooks(&hooks).await;
+    }
+
+    /// Runs hook commands like script lines. `last_status` is left as it was, so a hook can't
+    /// change the status the shell exits with.
+    async fn run_hooks(&mut self, hooks: &[String]) {
+        let status = self.last_status;
+        for hook in hooks {
+            if self.exit_requested.is_some() {
+                break;
+            }
+            Box::pin(self.run_lines(hook)).await;
+        }
+        self.last_status = status;
+    }
+
+    /// Restores variables and the working directory from the last saved profile. Runs after
+    /// the rc file so it can opt in to saving.
+    pub fn load_profile(&mut self) {
+        if let Some(home) = dirs::home_dir() {
+            if let Err(e) = self.environment.load_profile(&home.join(PROFILE_FILE)) {
+                eprintln!("{}: profile: {}", "Warning".yellow(), e);
+            }
+        }
+    }
+
+    /// Runs the plugins' startup hooks. Call once before the first command.
+    pub async fn start(&self) {
+        for (name, e) in self.plugin_manager.startup().await {
+            eprintln!("{}: {}: {}", "Warning".yellow(), name, e);
+        }
+    }
+
+    /// Runs the `on-exit` hooks, then the plugins' shutdown hooks, and saves the session
+    /// profile. Exit hooks run once, even if one of them exits the shell.
+    pub async fn shutdown(&mut self) {
+        let hooks = std::mem::take(&mut self.exit_hooks);
+        self.run_hooks(&hooks).await;
+
+        for (name, e) in self.plugin_manager.shutdown().await {
+            eprintln!("{}: {}: {}", "Warning".yellow(), name, e);
+        }
+        self.save_profile();
+    }
+
+    /// Saves the session's variables and working directory if the user opted in.
+    pub fn save_profile(&self) {
+        let enabled = self.environment.get_var("NEXUSSHELL_SAVE_PROFILE")
+            .is_some_and(|value| !value.is_empty() && value != "0");
+        if let (true, Some(home)) = (enabled, dirs::home_dir()) {
+            if let Err(e) = self.environment.save_profile(&home.join(PROFILE_FILE)) {
+                eprintln!("{}: profile: {}", "Warning".yellow(), e);
+            }
+        }
+    }
+
+    async fn source(&mut self, path: &str) -> anyhow::Result<String> {
+        if path.is_empty() {
+            return Err(ShellError::InvalidUsage("source <file>".to_string()).into());
+        }
+        if self.source_depth >= MAX_SOURCE_DEPTH {
+            return Err(ShellError::External(format!("source: {} is nested too deeply", path)).into());
+        }
+
+        let path = self.environment.expand_path(path);
+        let content = std::fs::read_to_string(&path).map_err(|e| ShellError::from_io(&path, e))?;
+
+        self.source_depth += 1;
+        let status = Box::pin(self.run_lines(&content)).await;
+        self.source_depth -= 1;
+
+        if status != 0 {
+            return Err(ShellError::External(format!("{}: last command exited with status {}", path.display(), status)).into());
+        }
+        Ok(String::new())
+    }
+
+    /// Runs a function body with `$1..$N` set from `args`. Commands after `&&` are skipped once
+    /// one fails; a failure followed by `;` is reported and the body carries on. The call fails
+    /// if the last command that ran did.
+    async fn call_function(&mut self, name: &str, body: &str, args: &[String]) -> anyhow::Result<String> {
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(ShellError::External(format!("{}: functions are nested too deeply", name)).into());
+        }
+
+        let mut output = Vec::new();
+        let mut failure: Option<anyhow::Error> = None;
+        self.call_depth += 1;
+        for (and_then, statement) in function::split_sequence(&function::substitute_args(body, args)) {
+            if self.exit_requested.is_some() {
+                break;
+            }
+            if and_then && failure.is_some() {
+                continue;
+            }
+            if let Some(e) = failure.take() {
+                eprintln!("{}: {}", "Error".bright_red(), e);
+            }
+            match Box::pin(self.run_command(&statement)).await {
+                Ok(result) if result.is_empty() => {}
+                Ok(result) => output.push(result),
+                Err(e) => failure = Some(e),
+            }
+        }
+        self.call_depth -= 1;
+
+        match failure {
+            Some(e) => {
+                if !output.is_empty() {
+                    println!("{}", output.join("\n"));
+                }
+                Err(e)
+            }
+            None => Ok(output.join("\n")),
+        }
+    }
+
+    fn run_alias(&mut self, spec: &str) -> anyhow::Result<String> {
+        if spec.is_empty() {
+            return Ok(self.preprocessor
+                .aliases()
+                .iter()
+                .map(|(name, value)| format!("alias {}='{}'", name, value))
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+
+        let (name, value) = match spec.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => {
+                return self.preprocessor
+                    .aliases()
+                    .into_iter()
+                    .find(|(name, _)| name == spec)
+                    .map(|(name, value)| format!("alias {}='{}'", name, value))
+                    .ok_or_else(|| ShellError::NotFound(format!("alias {}", spec)).into());
+            }
+        };
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(ShellError::InvalidUsage("alias [name[=value]]".to_string()).into());
+        }
+
+        let value = ["'", "\""]
+            .iter()
+            .find_map(|quote| value.strip_prefix(quote).and_then(|v| v.strip_suffix(quote)))
+            .unwrap_or(value);
+        self.preprocessor.set_alias(name.to_string(), value.to_string());
+        Ok(String::new())
+    }
+
+    fn run_unalias(&mut self, name: &str) -> anyhow::Result<String> {
+        if name.is_empty() {
+            return Err(ShellError::InvalidUsage("unalias <name>".to_string()).into());
+        }
+        if !self.preprocessor.remove_alias(name) {
+            return Err(ShellError::NotFound(format!("alias {}", name)).into());
+        }
+        Ok(String::new())
+    }
+
+    /// `cd`, `pushd`, `popd` and `dirs`, which change the session's directory and its stack.
+    fn run_directory_builtin(&mut self, command: &Command) -> anyhow::Result<String> {
+        // `cd -` and `cd -N` parse as flags, so turn them back into the operand
+        let operand = command.args.first().cloned().or_else(|| {
+            command.flags.keys()
+                .find(|flag| flag.chars().all(|c| c.is_ascii_digit()))
+                .map(|flag| format!("-{}", flag))
+        });
+        let failed = |name: &str, target: &str, e: anyhow::Error| ShellError::External(format!("{}: {}: {}", name, target, e));
+
+        match (command.name.as_str(), operand.as_deref()) {
+            ("cd", None) => {
+                let home = self.environment.get_var("HOME").cloned()
+                    .ok_or_else(|| ShellError::External("cd: HOME not set".to_string()))?;
+                self.environment.change_directory(PathBuf::from(&home)).map_err(|e| failed("cd", &home, e))?;
+                Ok(String::new())
+            }
+            ("cd", Some("-")) => {
+                let previous = self.environment.previous_dir().cloned()
+                    .ok_or_else(|| ShellError::External("cd: OLDPWD not set".to_string()))?;
+                self.environment.change_directory(previous.clone())
+                    .map_err(|e| failed("cd", &previous.to_string_lossy(), e))?;
+                // Like bash, `cd -` echoes where it went
+                Ok(previous.to_string_lossy().to_string())
+            }
+            ("cd", Some(target)) => {
+                if let Some(index) = self.environment.stack_index(target) {
+                    let index = index.map_err(|e| ShellError::External(format!("cd: {}", e)))?;
+                    self.environment.change_to_stack_entry(index).map_err(|e| failed("cd", target, e))?;
+                    return Ok(String::new());
+                }
+                let (path, via_cdpath) = self.environment.resolve_cd_target(target);
+                self.environment.change_directory(path.clone()).map_err(|e| failed("cd", target, e))?;
+                // Like bash, echo the directory when it was found through CDPATH
+                Ok(if via_cdpath { path.to_string_lossy().to_string() } else { String::new() })
+            }
+            ("pushd", None) => {
+                if self.environment.dir_stack().len() < 2 {
+                    return Err(ShellError::External("pushd: no other directory".to_string()).into());
+                }
+                self.environment.change_to_stack_entry(1).map_err(|e| failed("pushd", "+1", e))?;
+                Ok(self.format_dir_stack(false))
+            }
+            ("pushd", Some(target)) => {
+                let (path, _) = self.environment.resolve_cd_target(target);
+                self.environment.push_directory(path).map_err(|e| failed("pushd", target, e))?;
+                Ok(self.format_dir_stack(false))
+            }
+            ("popd", _) => {
+                self.environment.pop_directory().map_err(|e| ShellError::External(format!("popd: {}", e)))?;
+                Ok(self.format_dir_stack(false))
+            }
+            _ => Ok(self.format_dir_stack(command.flags.contains_key("v"))),
+        }
+    }
+
+    /// Loads `.env` from the current directory, if there is one.
+    fn load_dir_env_file(&mut self) {
+        let path = self.environment.get_current_dir().join(".env");
+        if path.is_file() {
+            if let Err(e) = self.environment.load_env_file(&path) {
+                eprintln!("{}: {}: {}", "Warning".yellow(), path.display(), e);
+            }
+        }
+    }
+
+    /// Writes `file` to the terminal highlighted for its extension, streaming it so large
+    /// files are never held in memory. With paging on, output too long for the screen is
+    /// highlighted into the pager instead.
+    fn cat_highlighted(&self, file: &str, page: bool) -> anyhow::Result<String> {
+        let path = self.environment.expand_path(file);
+        let reader = std::fs::File::open(&path)
+            .map(std::io::BufReader::new)
+            .map_err(|e| ShellError::from_io(&path, e))?;
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("txt");
+        if !page {
+            self.syntax_highlighter.highlight_reader(reader, extension, std::io::stdout().lock())?;
+            return Ok(String::new());
+        }
+
+        // The pager needs the whole text anyway
+        let content = std::io::read_to_string(reader).map_err(|e| ShellError::from_io(&path, e))?;
+        let output = self.syntax_highlighter.highlight_file(&content, extension);
+        let height = crossterm::terminal::size().ok().map(|(_, rows)| rows);
+        if pager::should_page(&output, true, height) {
+            pager::page(&output, &self.environment)?;
+        } else {
+            print!("{}", output);
+        }
+        Ok(String::new())
+    }
+
+    /// The directory stack on one line, or numbered one per line for `dirs -v`, with the
+    /// home directory shown as `~`.
+    fn format_dir_stack(&self, numbered: bool) -> String {
+        let home = self.environment.get_var("HOME").map(PathBuf::from);
+        let dirs = self.environment.dir_stack().into_iter().map(|dir| {
+            match home.as_ref().and_then(|home| dir.strip_prefix(home).ok()) {
+                Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+                Some(rest) => format!("~/{}", rest.to_string_lossy()),
+                None => dir.to_string_lossy().to_string(),
+            }
+        });
+        if numbered {
+            dirs.enumerate().map(|(i, dir)| format!("{:>2}  {}", i, dir)).collect::<Vec<_>>().join("\n")
+        } else {
+            dirs.collect::<Vec<_>>().join(" ")
+        }
+    }
+
+    /// Decides whether the shell may exit. While background jobs are still running the first
+    /// attempt is refused with a warning; a second attempt or `exit --force` goes through.
+    pub fn request_exit(&mut self, force: bool) -> Result<(), String> {
+        let running = self.plugin_manager.jobs().running();
+        if force || running.is_empty() || self.exit_warned {
+            return Ok(());
         }
 
-        // Execute the command
-        let result = self.executor.execute(&command, &self.environment).await?;
+        self.exit_warned = true;
+        let mut warning = format!("There are {} running background jobs:\n", running.len());
+        for (pid, command) in running {
+            warning.push_str(&format!("  [{}] {}\n", pid, command));
+        }
+        warning.push_str("Run exit again or exit --force to quit anyway");
+        Err(warning)
+    }
+
+    async fn run_history(&mut self, command: &Command) -> anyhow::Result<String> {
+        let mut history = self.history.lock().await;
+        match command.args.first().map(|s| s.as_str()) {
+            None => Ok(history
+                .get_all()
+                .iter()
+                .enumerate()
+                .map(|(i, entry)| format!("{:>5}  {}", i + 1, entry))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            Some("clear") => {
+                if !command.flags.contains_key("force") && !confirm("Clear all command history?")? {
+                    return Ok("History not cleared".to_string());
+                }
+                history.clear()?;
+                Ok("History cleared".to_string())
+            }
+            Some("delete") => {
+                let pattern = command.args.get(1)
+                    .or_else(|| command.flags.get("regex").and_then(|value| value.as_ref()))
+                    .ok_or_else(|| ShellError::InvalidUsage("history delete [--regex] <pattern>".to_string()))?;
+                let removed = if command.flags.contains_key("regex") {
+                    let regex = regex::Regex::new(pattern)
+                        .map_err(|e| ShellError::InvalidUsage(format!("history delete --regex <pattern>: {}", e)))?;
+                    history.delete_matching(|entry| regex.is_match(entry))?
+                } else {
+                    history.delete_matching(|entry| entry.contains(pattern.as_str()))?
+                };
+                Ok(format!("Deleted {} history entries", removed))
+            }
+            Some("export") => {
+                let path = command.args.get(1)
+                    .ok_or_else(|| ShellError::InvalidUsage("history export <path>".to_string()))?;
+                let path = self.environment.expand_path(path);
+                let count = history.export(&path).map_err(|e| ShellError::from_io(&path, e))?;
+                Ok(format!("Exported {} entries to {}", count, path.display()))
+            }
+            Some("import") => {
+                let path = command.args.get(1)
+                    .ok_or_else(|| ShellError::InvalidUsage("history import <path>".to_string()))?;
+                let path = self.environment.expand_path(path);
+                let count = history.import(&path).map_err(|e| ShellError::from_io(&path, e))?;
+                Ok(format!("Imported {} entries from {}", count, path.display()))
+            }
+            // Only reached when `history run` isn't the whole line, e.g. inside a pipeline
+            Some("run") => Err(ShellError::InvalidUsage("history run <n> must be the whole command".to_string()).into()),
+            Some(_) => Err(ShellError::InvalidUsage("history [run <n>|clear [--force]|delete [--regex] <pattern>|export <path>|import <path>]".to_string()).into()),
+        }
+    }
+
+    /// Whether the history was cleared or had entries deleted since the last call.
+    pub async fn history_rewritten(&self) -> bool {
+        self.history.lock().await.take_rewritten()
+    }
 
-        Ok(result)
+    /// Whether `line` belongs in the history: not space-prefixed (with `HISTCONTROL=ignorespace`
+    /// or `history_ignore_space`) and not matching `history_ignore`.
+    pub async fn should_record(&self, line: &str) -> bool {
+        let histcontrol = self.environment.get_var("HISTCONTROL").is_some_and(|value| {
+            value.split(':').any(|option| option == "ignorespace" || option == "ignoreboth")
+        });
+        let mut history = self.history.lock().await;
+        history.set_ignore_space(self.config.history_ignore_space || histcontrol);
+        history.should_record(line)
     }
 
     pub async fn get_history(&self) -> Vec<String> {
         self.history.lock().await.get_all()
     }
 
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     pub fn get_current_dir(&self) -> PathBuf {
         self.environment.get_current_dir()
     }
 
-    pub fn change_directory(&mut self, path: PathBuf) -> anyhow::Result<()> {
-        self.environment.change_directory(path)
+    pub fn completer(&self) -> &Completer {
+        &self.completer
+    }
+}
+
+/// Removes `--page`/`-P` from a command, handing back any operand the flag swallowed.
+/// `--page <number>` is left alone: it selects a page of a `--limit`ed listing.
+fn take_page_flag(command: &mut Command) -> bool {
+    let mut requested = false;
+    for flag in ["page", "P"] {
+        let page_number = flag == "page" && matches!(
+            command.flags.get(flag),
+            Some(Some(value)) if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+        );
+        if page_number {
+            continue;
+        }
+        if let Some(value) = command.flags.remove(flag) {
+            requested = true;
+            if let Some(operand) = value {
+                command.args.insert(0, operand);
+            }
+        }
+    }
+    requested
+}
+
+/// `on-startup`/`on-exit <command>` registers a hook, its command unexpanded like an alias
+/// value; without a command, lists the hooks registered so far.
+fn add_hook(hooks: &mut Vec<String>, builtin: &str, command: &str) -> String {
+    if command.is_empty() {
+        return hooks.iter().map(|hook| format!("{} '{}'", builtin, hook)).collect::<Vec<_>>().join("\n");
+    }
+    let command = ["'", "\""]
+        .iter()
+        .find_map(|quote| command.strip_prefix(quote).and_then(|c| c.strip_suffix(quote)))
+        .unwrap_or(command);
+    hooks.push(command.to_string());
+    String::new()
+}
+
+/// The status `exit` should end the process with: its argument, or `last_status` without
+/// one.
+fn exit_status(command: &Command, last_status: i32) -> anyhow::Result<i32> {
+    if let Some(flag) = command.flags.keys().find(|flag| flag.as_str() != "force") {
+        return Err(ShellError::InvalidUsage(format!("exit [0-255]: '-{}' is not a valid status", flag)).into());
+    }
+    match command.args.as_slice() {
+        [] => Ok(last_status.clamp(0, 255)),
+        [code] => code.parse::<u8>()
+            .map(i32::from)
+            .map_err(|_| ShellError::InvalidUsage(format!("exit [0-255]: '{}' is not a valid status", code)).into()),
+        _ => Err(ShellError::InvalidUsage("exit [0-255]: too many arguments".to_string()).into()),
+    }
+}
+
+fn confirm(question: &str) -> std::io::Result<bool> {
+    use std::io::Write;
+
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// The history entry a `history run <n>` line names, using the numbers `history` lists
+/// (from 1). `None` when the line is something else.
+fn resolve_history_run(line: &str, history: &[String]) -> anyhow::Result<Option<String>> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if words.len() < 2 || words[0] != "history" || words[1] != "run" {
+        return Ok(None);
+    }
+    let index = match words[2..] {
+        [index] => index.parse::<usize>()
+            .map_err(|_| ShellError::InvalidUsage(format!("history run <n>: '{}' is not a number", index)))?,
+        _ => return Err(ShellError::InvalidUsage("history run <n>".to_string()).into()),
+    };
+    let entry = index.checked_sub(1).and_then(|i| history.get(i)).ok_or_else(|| {
+        ShellError::InvalidUsage(format!("history run: no entry {} (history has {} entries)", index, history.len()))
+    })?;
+    Ok(Some(entry.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Puts the process back in the directory it started in, even when the test fails.
+    struct RestoreCwd(PathBuf);
+
+    impl RestoreCwd {
+        fn new() -> Self {
+            RestoreCwd(std::env::current_dir().unwrap())
+        }
+    }
+
+    impl Drop for RestoreCwd {
+        fn drop(&mut self) {
+            let _ = std::env::set_current_dir(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_exit_status() {
+        let parser = Parser::new();
+        let status = |input: &str, last: i32| exit_status(&parser.parse(input).unwrap(), last);
+
+        assert_eq!(status("exit", 0).unwrap(), 0);
+        assert_eq!(status("exit", 127).unwrap(), 127);
+        assert_eq!(status("exit 3", 127).unwrap(), 3);
+        assert_eq!(status("exit 255", 0).unwrap(), 255);
+        assert_eq!(status("exit --force 4", 0).unwrap(), 4);
+        assert_eq!(status("exit 4 --force", 0).unwrap(), 4);
+
+        for input in ["exit 256", "exit -1", "exit abc", "exit 1 2"] {
+            let err = status(input, 0).unwrap_err();
+            assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::InvalidUsage(_))), "{}", input);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_last_status_follows_commands() {
+        let mut shell = Shell::new();
+        shell.set_quiet(true);
+        assert!(shell.run_command("cd /definitely/not/here").await.is_err());
+        assert_ne!(shell.last_status(), 0);
+        shell.run_command("pwd").await.unwrap();
+        assert_eq!(shell.last_status(), 0);
     }
 
-    pub fn complete(&self, line: &str) -> Vec<String> {
-        self.completer.complete(line)
+    #[tokio::test]
+    async fn test_rm_runs_through_the_fileops_plugin() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("notes.txt");
+        std::fs::write(&file, "x").unwrap();
+        let mut shell = Shell::new();
+        shell.set_quiet(true);
+        shell.history = Arc::new(Mutex::new(History::new()));
+
+        let output = shell.run_command(&format!("rm {}", file.display())).await.unwrap();
+        assert_eq!(output, format!("Removed {}", file.display()));
+        assert!(!file.exists());
+
+        // The system rm would only print to stderr; the plugin reports a typed error
+        let err = shell.run_command(&format!("rm {}", file.display())).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_history_lists_numbered_entries() {
+        let mut shell = Shell::new();
+        shell.set_quiet(true);
+        shell.history = Arc::new(Mutex::new(History::new()));
+        shell.run_command("pwd").await.unwrap();
+        shell.run_command("echo one").await.unwrap();
+
+        let listing = shell.run_command("history").await.unwrap();
+        assert_eq!(listing.lines().take(2).collect::<Vec<_>>(), vec!["    1  pwd", "    2  echo one"]);
+    }
+
+    #[tokio::test]
+    async fn test_history_keeps_the_line_as_typed() {
+        let mut shell = Shell::new();
+        shell.set_quiet(true);
+        shell.history = Arc::new(Mutex::new(History::new()));
+        shell.environment.set_var("TOKEN".to_string(), "s3cret".to_string());
+
+        assert_eq!(shell.run_command("echo $TOKEN $(echo inner)").await.unwrap(), "s3cret inner");
+        assert_eq!(shell.run_command("!!").await.unwrap(), "s3cret inner");
+        // History references are resolved; variables and substitutions are not
+        assert_eq!(shell.get_history().await, vec!["echo $TOKEN $(echo inner)"; 2]);
+
+        // Script lines, like those of an rc file, aren't recorded
+        assert_eq!(shell.run_lines("echo from-a-script").await, 0);
+        assert_eq!(shell.get_history().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_history_run_reruns_entry() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let target = temp_dir.path().canonicalize().unwrap();
+        let _cwd = RestoreCwd::new();
+        let mut shell = Shell::new();
+        shell.set_quiet(true);
+        shell.history = Arc::new(Mutex::new(History::new()));
+        let start = shell.environment.get_current_dir();
+
+        shell.run_command(&format!("cd {}", target.display())).await.unwrap();
+        shell.run_command(&format!("cd {}", start.display())).await.unwrap();
+        shell.run_command("history run 1").await.unwrap();
+        assert_eq!(shell.environment.get_current_dir(), target);
+        // The resolved command is recorded, not `history run 1`
+        assert_eq!(shell.get_history().await.last(), Some(&format!("cd {}", target.display())));
+
+        let err = shell.run_command("history run 9").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::InvalidUsage(_))));
+        assert!(err.to_string().contains("no entry 9 (history has 3 entries)"));
+        assert!(shell.run_command("history run 0").await.is_err());
+        assert!(shell.run_command("history run last").await.is_err());
     }
 
-    pub fn highlight_file(&self, content: &str, extension: &str) -> String {
-        self.syntax_highlighter.highlight_file(content, extension)
+    #[tokio::test]
+    async fn test_function_chains_commands_with_arguments() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let target = temp_dir.path().canonicalize().unwrap();
+        let _cwd = RestoreCwd::new();
+        let mut shell = Shell::new();
+        shell.set_quiet(true);
+        shell.history = Arc::new(Mutex::new(History::new()));
+
+        shell.run_command("function enter() { echo entering $1 && cd $1 && pwd; }").await.unwrap();
+        let output = shell.run_command(&format!("enter {}", target.display())).await.unwrap();
+        assert_eq!(output, format!("entering {}\n{}", target.display(), target.display()));
+        assert_eq!(shell.environment.get_current_dir(), target);
+        assert!(function::list(&shell.environment).contains("function enter() {"));
+        // Only the call is recorded, not the statements it ran
+        assert_eq!(shell.get_history().await.last(), Some(&format!("enter {}", target.display())));
+
+        // A failing command stops the rest of the `&&` chain
+        let missing = target.join("missing");
+        assert!(shell.run_command(&format!("enter {}", missing.display())).await.is_err());
+        assert_eq!(shell.environment.get_current_dir(), target);
+
+        shell.run_command("forever() { forever; }").await.unwrap();
+        let err = shell.run_command("forever").await.unwrap_err();
+        assert!(err.to_string().contains("nested too deeply"));
     }
 
-    pub fn highlight_help(&self, content: &str) -> String {
-        self.syntax_highlighter.highlight_help(content)
+    #[test]
+    fn test_page_flag_keeps_page_numbers() {
+        let parser = Parser::new();
+
+        let mut command = parser.parse("cat --page notes.txt").unwrap();
+        assert!(take_page_flag(&mut command));
+        assert_eq!(command.args, vec!["notes.txt".to_string()]);
+
+        let mut command = parser.parse("schedule list --limit 10 --page 2").unwrap();
+        assert!(!take_page_flag(&mut command));
+        assert_eq!(command.flags.get("page"), Some(&Some("2".to_string())));
     }
 
-    pub fn get_plugin_list(&self) -> Vec<(String, String)> {
-        self.plugin_manager.list_plugins()
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_exit_warns_about_background_jobs() {
+        let mut shell = Shell::new();
+        assert!(shell.request_exit(false).is_ok());
+
+        let child = tokio::process::Command::new("sleep").arg("30").kill_on_drop(true).spawn().unwrap();
+        shell.plugin_manager.jobs().add("sleep 30".to_string(), child);
+
+        let warning = shell.request_exit(false).unwrap_err();
+        assert!(warning.contains("sleep 30"));
+        assert!(shell.request_exit(false).is_ok());
+
+        shell.exit_warned = false;
+        assert!(shell.request_exit(true).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_exit_stops_the_script_without_exiting_the_process() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let sentinel = temp_dir.path().join("after-exit");
+        let mut shell = Shell::new();
+        shell.set_quiet(true);
+        shell.history = Arc::new(Mutex::new(History::new()));
+
+        let status = shell.run_lines(&format!("echo before; exit 3; touch {}", sentinel.display())).await;
+        assert_eq!(status, 3);
+        assert_eq!(shell.exit_requested(), Some(3));
+        assert!(!sentinel.exists());
+    }
+
+    #[test]
+    fn test_quiet_suppresses_echo() {
+        let mut shell = Shell::new();
+        shell.set_quiet(false);
+        assert!(shell.echo_line("ls -la").unwrap().contains("ls"));
+
+        shell.set_quiet(true);
+        assert_eq!(shell.echo_line("ls -la"), None);
+    }
+
+    #[tokio::test]
+    async fn test_exit_hook_runs_at_shutdown() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let sentinel = temp_dir.path().join("exited");
+        let mut shell = Shell::new();
+        shell.set_quiet(true);
+        shell.history = Arc::new(Mutex::new(History::new()));
+
+        shell.run_command(&format!("on-exit \"touch {}\"", sentinel.display())).await.unwrap();
+        assert_eq!(shell.run_command("on-exit").await.unwrap(), format!("on-exit 'touch {}'", sentinel.display()));
+        assert!(!sentinel.exists());
+
+        // The status the shell exits with is the last command's, not the hook's
+        assert!(shell.run_command("cd /no/such/dir").await.is_err());
+        let status = shell.last_status();
+        shell.shutdown().await;
+        assert!(sentinel.exists());
+        assert_eq!(shell.last_status(), status);
+
+        // Hooks run only once
+        std::fs::remove_file(&sentinel).unwrap();
+        shell.shutdown().await;
+        assert!(!sentinel.exists());
+    }
+
+    struct LifecyclePlugin {
+        calls: Arc<std::sync::Mutex<Vec<&'static str>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl plugins::Plugin for LifecyclePlugin {
+        fn name(&self) -> &str {
+            "lifecycle"
+        }
+
+        fn description(&self) -> &str {
+            "Records lifecycle hook calls"
+        }
+
+        async fn execute(&self, _command: &Command, _env: &Environment) -> anyhow::Result<String> {
+            self.calls.lock().unwrap().push("execute");
+            Ok(String::new())
+        }
+
+        async fn on_startup(&self) -> anyhow::Result<()> {
+            self.calls.lock().unwrap().push("startup");
+            Ok(())
+        }
+
+        async fn on_shutdown(&self) -> anyhow::Result<()> {
+            self.calls.lock().unwrap().push("shutdown");
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plugin_lifecycle_hooks() {
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut shell = Shell::new();
+        shell.set_quiet(true);
+        shell.plugin_manager.register_plugin(Box::new(LifecyclePlugin { calls: calls.clone() })).unwrap();
+        assert!(calls.lock().unwrap().is_empty());
+
+        shell.start().await;
+        assert_eq!(*calls.lock().unwrap(), vec!["startup"]);
+
+        // Already started, so starting again doesn't repeat the hook
+        shell.start().await;
+        shell.run_command("lifecycle").await.unwrap();
+        assert_eq!(*calls.lock().unwrap(), vec!["startup", "execute"]);
+
+        shell.shutdown().await;
+        assert_eq!(*calls.lock().unwrap(), vec!["startup", "execute", "shutdown"]);
     }
 }
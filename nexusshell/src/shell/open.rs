@@ -0,0 +1,109 @@
+use super::{Command, Environment, ShellError};
+use std::path::Path;
+
+const USAGE: &str = "open [--reveal] <path-or-url>";
+
+/// `open <path-or-url>` hands the target to the desktop's default handler; `--reveal` shows
+/// the file in its folder instead. Paths are checked before anything is launched.
+pub async fn run(command: &Command, env: &Environment) -> anyhow::Result<String> {
+    let reveal = command.flags.contains_key("reveal");
+    let target = command.args.first().ok_or_else(|| ShellError::InvalidUsage(USAGE.to_string()))?;
+
+    let target = if is_url(target) {
+        if reveal {
+            return Err(ShellError::InvalidUsage(format!("{}: --reveal needs a file, not a URL", USAGE)).into());
+        }
+        target.clone()
+    } else {
+        let path = env.expand_path(target);
+        if !path.exists() {
+            return Err(ShellError::NotFound(path.display().to_string()).into());
+        }
+        path.to_string_lossy().to_string()
+    };
+
+    let (program, args) = launcher(std::env::consts::OS, &target, reveal)
+        .ok_or_else(|| ShellError::External(format!("open: no default handler is known for {}", std::env::consts::OS)))?;
+    // Handlers such as xdg-open may stay in the foreground until the viewer closes, so don't
+    // wait on them
+    tokio::process::Command::new(program)
+        .args(&args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ShellError::External(format!("open: no handler available ({} is not installed)", program)),
+            _ => ShellError::Io(e),
+        })?;
+    Ok(String::new())
+}
+
+/// The program and arguments that open `target` on `os` (as in `std::env::consts::OS`), or
+/// with `reveal`, show it selected in its folder where the platform can. `None` for
+/// platforms without a known handler.
+pub fn launcher(os: &str, target: &str, reveal: bool) -> Option<(&'static str, Vec<String>)> {
+    let target = target.to_string();
+    Some(match os {
+        "macos" if reveal => ("open", vec!["-R".to_string(), target]),
+        "macos" => ("open", vec![target]),
+        "windows" if reveal => ("explorer", vec![format!("/select,{}", target)]),
+        // Not `cmd /C start`, which would reinterpret `&`, `|` and `^` in the target
+        "windows" => ("rundll32", vec!["url.dll,FileProtocolHandler".to_string(), target]),
+        "linux" | "freebsd" | "openbsd" | "netbsd" | "dragonfly" => {
+            // xdg-open can't select a file, so open the folder it is in
+            let target = if reveal {
+                Path::new(&target).parent().map_or(target.clone(), |dir| dir.to_string_lossy().to_string())
+            } else {
+                target
+            };
+            ("xdg-open", vec![target])
+        }
+        _ => return None,
+    })
+}
+
+/// `scheme://...` or `mailto:`, rather than a path.
+fn is_url(target: &str) -> bool {
+    target.starts_with("mailto:")
+        || target.split_once("://").is_some_and(|(scheme, _)| {
+            scheme.len() > 1 && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Parser;
+
+    #[test]
+    fn test_launcher_per_platform() {
+        let launch = |os: &str, reveal: bool| launcher(os, "/home/me/report.pdf", reveal);
+        assert_eq!(launch("linux", false), Some(("xdg-open", vec!["/home/me/report.pdf".to_string()])));
+        assert_eq!(launch("freebsd", true), Some(("xdg-open", vec!["/home/me".to_string()])));
+        assert_eq!(launch("macos", false), Some(("open", vec!["/home/me/report.pdf".to_string()])));
+        assert_eq!(launch("macos", true), Some(("open", vec!["-R".to_string(), "/home/me/report.pdf".to_string()])));
+        assert_eq!(launch("windows", true), Some(("explorer", vec!["/select,/home/me/report.pdf".to_string()])));
+        let handler = vec!["url.dll,FileProtocolHandler".to_string(), "/home/me/report.pdf".to_string()];
+        assert_eq!(launch("windows", false), Some(("rundll32", handler)));
+        assert_eq!(launch("haiku", false), None);
+
+        assert!(is_url("https://example.com/a?b=c"));
+        assert!(is_url("mailto:me@example.com"));
+        assert!(!is_url("C:\\Users\\me\\notes.txt"));
+        assert!(!is_url("notes.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_path_is_not_launched() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let missing = temp_dir.path().join("missing.txt");
+        let env = Environment::new();
+        for line in [format!("open {}", missing.display()), format!("open --reveal {}", missing.display())] {
+            let err = run(&Parser::new().parse(&line).unwrap(), &env).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::NotFound(_))), "{}", line);
+        }
+        let err = run(&Parser::new().parse("open --reveal https://example.com").unwrap(), &env).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::InvalidUsage(_))));
+    }
+}
@@ -0,0 +1,117 @@
+use super::Environment;
+use crossterm::cursor;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Print, Stylize};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{execute, queue};
+use std::io::{self, Write};
+use std::process::Stdio;
+
+/// Whether `output` should go through the pager: only on a terminal, and only when it
+/// wouldn't fit above the next prompt.
+pub fn should_page(output: &str, is_tty: bool, terminal_height: Option<u16>) -> bool {
+    match (is_tty, terminal_height) {
+        (true, Some(height)) => output.lines().count() >= height as usize,
+        _ => false,
+    }
+}
+
+/// Shows `output` through `$PAGER` if set, otherwise through the built-in pager.
+pub fn page(output: &str, env: &Environment) -> io::Result<()> {
+    match env.get_var("PAGER").filter(|pager| !pager.trim().is_empty()) {
+        Some(pager) => external(pager, output, env),
+        None => internal(output),
+    }
+}
+
+fn external(pager: &str, output: &str, env: &Environment) -> io::Result<()> {
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or("less");
+    let mut child = std::process::Command::new(program)
+        .args(words)
+        .envs(env.vars())
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager early closes the pipe; that's not an error
+        match stdin.write_all(output.as_bytes()) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+            result => result?,
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
+
+fn internal(output: &str) -> io::Result<()> {
+    let lines: Vec<&str> = output.lines().collect();
+    let mut stdout = io::stdout();
+
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, terminal::DisableLineWrap, cursor::Hide)?;
+    let result = browse(&lines, &mut stdout);
+    execute!(stdout, cursor::Show, terminal::EnableLineWrap, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+/// Draws a screenful starting at the current line and scrolls on key presses until `q`.
+fn browse(lines: &[&str], stdout: &mut io::Stdout) -> io::Result<()> {
+    let mut top = 0;
+    loop {
+        let (_, rows) = terminal::size()?;
+        let height = rows.saturating_sub(1).max(1) as usize;
+        let last_top = lines.len().saturating_sub(height);
+        top = top.min(last_top);
+        let bottom = (top + height).min(lines.len());
+
+        queue!(stdout, terminal::Clear(ClearType::All))?;
+        for (row, line) in lines[top..bottom].iter().enumerate() {
+            queue!(stdout, cursor::MoveTo(0, row as u16), Print(line))?;
+        }
+        let status = format!(
+            " lines {}-{} of {} (space/b: page, j/k: line, q: quit) ",
+            top + 1,
+            bottom,
+            lines.len()
+        );
+        queue!(stdout, cursor::MoveTo(0, rows.saturating_sub(1)), Print(status.reverse()))?;
+        stdout.flush()?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            // Anything else, including a resize, just redraws
+            _ => continue,
+        };
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char(' ') | KeyCode::PageDown => top += height,
+            KeyCode::Char('b') | KeyCode::PageUp => top = top.saturating_sub(height),
+            KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => top += 1,
+            KeyCode::Char('k') | KeyCode::Up => top = top.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => top = 0,
+            KeyCode::Char('G') | KeyCode::End => top = last_top,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_page() {
+        let long = "line\n".repeat(40);
+        let short = "line\n".repeat(5);
+
+        assert!(should_page(&long, true, Some(24)));
+        assert!(!should_page(&short, true, Some(24)));
+        assert!(!should_page(&long, false, Some(24)));
+        assert!(!should_page(&long, true, None));
+        // A full screen leaves no room for the prompt
+        assert!(should_page(&"line\n".repeat(24), true, Some(24)));
+    }
+}
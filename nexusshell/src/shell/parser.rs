@@ -1,4 +1,4 @@
-use super::Command;
+use super::{split_words, Command};
 use std::collections::HashMap;
 
 pub struct Parser;
@@ -14,28 +14,60 @@ impl Parser {
             return Err(anyhow::anyhow!("Empty command"));
         }
 
-        let mut parts = input.split_whitespace();
+        // Quoted text stays one word, without its quotes
+        let words = split_words(input);
+        let mut parts = words.iter().map(String::as_str).peekable();
+        let mut assignments = Vec::new();
+        while let Some(assignment) = parts.peek().and_then(|part| parse_assignment(part)) {
+            assignments.push(assignment);
+            parts.next();
+        }
+
         let name = parts.next().unwrap_or("").to_string();
-        
+
         let mut args = Vec::new();
         let mut flags = HashMap::new();
-        let mut current_arg = None;
+        let mut current_arg: Option<String> = None;
+        let mut end_of_flags = false;
 
         for part in parts {
-            if part.starts_with("--") {
-                if let Some(flag_name) = current_arg {
-                    flags.insert(flag_name, None);
+            if end_of_flags || !part.starts_with('-') {
+                match current_arg.take() {
+                    Some(flag_name) => {
+                        flags.insert(flag_name, Some(part.to_string()));
+                    }
+                    None => args.push(part.to_string()),
                 }
-                current_arg = Some(part[2..].to_string());
-            } else if part.starts_with('-') {
-                if let Some(flag_name) = current_arg {
-                    flags.insert(flag_name, None);
+                continue;
+            }
+
+            if let Some(flag_name) = current_arg.take() {
+                flags.insert(flag_name, None);
+            }
+            // After `--` every word is an operand, even one starting with `-`
+            if part == "--" {
+                end_of_flags = true;
+            } else if let Some(flag) = part.strip_prefix("--") {
+                match flag.split_once('=') {
+                    Some((flag, value)) => {
+                        flags.insert(flag.to_string(), Some(value.to_string()));
+                    }
+                    None if is_switch(&name, flag) => {
+                        flags.insert(flag.to_string(), None);
+                    }
+                    None => current_arg = Some(flag.to_string()),
                 }
-                current_arg = Some(part[1..].to_string());
-            } else if let Some(flag_name) = current_arg.take() {
-                flags.insert(flag_name, Some(part.to_string()));
             } else {
-                args.push(part.to_string());
+                let letters = &part[1..];
+                // `-rf` is two switches only when every letter is one; otherwise (`-rfoo`)
+                // it stays a single flag, so an odd file name doesn't turn on `-r`
+                let combined = !letters.is_empty()
+                    && letters.chars().all(|c| is_switch(&name, c.encode_utf8(&mut [0; 4])));
+                if combined {
+                    flags.extend(letters.chars().map(|c| (c.to_string(), None)));
+                } else {
+                    current_arg = Some(letters.to_string());
+                }
             }
         }
 
@@ -43,6 +75,157 @@ impl Parser {
             flags.insert(flag_name, None);
         }
 
-        Ok(Command::new(name, args, flags, input.to_string()))
+        let mut command = Command::new(name, args, flags, input.to_string());
+        command.assignments = assignments;
+        Ok(command)
+    }
+
+    pub fn parse_pipeline(&self, input: &str) -> anyhow::Result<Vec<Command>> {
+        let (input, background) = split_background(input);
+        let mut commands = Vec::new();
+        for stage in split_stages(input) {
+            if stage.trim().is_empty() {
+                return Err(anyhow::anyhow!("Empty pipeline stage"));
+            }
+            commands.push(self.parse(stage)?);
+        }
+        if let Some(last) = commands.last_mut() {
+            last.background = background;
+        }
+        Ok(commands)
+    }
+}
+
+/// Flags that never take a value, per command. Any other flag reads the word after it as
+/// its value, so without an entry here `rm -r dir` would take `dir` as the value of `-r`.
+const SWITCHES: &[(&str, &[&str])] = &[
+    ("aws", &["delete"]),
+    ("cp", &["n", "no-clobber", "i", "interactive"]),
+    ("dedup", &["delete"]),
+    ("dev", &["outdated", "deps-tree", "recursive"]),
+    ("df", &["h", "human-readable"]),
+    ("diff", &["stat"]),
+    ("docker", &["a", "all"]),
+    ("exit", &["force"]),
+    ("find", &["L", "follow-symlinks"]),
+    ("free", &["h", "human-readable"]),
+    ("kill", &["9", "force"]),
+    ("kubectl", &["f", "follow", "timestamps", "w", "watch"]),
+    ("ls", &["color"]),
+    ("mkdir", &["p", "parents"]),
+    ("mv", &["n", "no-clobber", "i", "interactive"]),
+    ("net", &["once"]),
+    ("open", &["reveal"]),
+    ("rm", &["r", "recursive", "f", "force"]),
+    ("rmdir", &["p", "parents"]),
+    ("schedule", &["dry-run"]),
+    ("security", &["dry-run"]),
+    ("sftp", &["R", "recursive", "resume", "verify"]),
+    ("ssh", &["verify"]),
+];
+
+/// Switches every command accepts.
+const COMMON_SWITCHES: &[&str] = &["json"];
+
+/// Whether `flag` is a switch of `command`.
+fn is_switch(command: &str, flag: &str) -> bool {
+    COMMON_SWITCHES.contains(&flag)
+        || SWITCHES.iter().any(|(name, switches)| *name == command && switches.contains(&flag))
+}
+
+/// Splits a line into pipeline stages at each `|` outside quotes.
+pub fn split_stages(input: &str) -> Vec<&str> {
+    let mut stages = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    for (i, c) in input.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '|' => {
+                stages.push(&input[start..i]);
+                start = i + 1;
+            }
+            None => {}
+        }
+    }
+    stages.push(&input[start..]);
+    stages
+}
+
+/// Strips a trailing `&` (but not `&&`), reporting whether it was there.
+fn split_background(input: &str) -> (&str, bool) {
+    let trimmed = input.trim_end();
+    match trimmed.strip_suffix('&') {
+        Some(rest) if !rest.ends_with('&') => (rest, true),
+        _ => (input, false),
+    }
+}
+
+/// Splits a `NAME=VALUE` token, where NAME is a valid shell identifier.
+fn parse_assignment(token: &str) -> Option<(String, String)> {
+    let (name, value) = token.split_once('=')?;
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then(|| (name.to_string(), value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flag<'a>(command: &'a Command, name: &str) -> Option<Option<&'a str>> {
+        command.flags.get(name).map(|value| value.as_deref())
+    }
+
+    #[test]
+    fn test_switches_never_take_a_value() {
+        let parser = Parser::new();
+
+        let command = parser.parse("rm -rf build").unwrap();
+        assert_eq!(command.args, ["build"]);
+        assert_eq!((flag(&command, "r"), flag(&command, "f")), (Some(None), Some(None)));
+
+        let command = parser.parse("cp a -n b").unwrap();
+        assert_eq!(command.args, ["a", "b"]);
+        assert_eq!(flag(&command, "n"), Some(None));
+
+        // Other flags still take the next word, and `--json` is a switch everywhere
+        let command = parser.parse("mkdir --mode 700 -p logs").unwrap();
+        assert_eq!(command.args, ["logs"]);
+        assert_eq!((flag(&command, "mode"), flag(&command, "p")), (Some(Some("700")), Some(None)));
+        let command = parser.parse("touch -t 202401011200 --json notes.txt").unwrap();
+        assert_eq!(command.args, ["notes.txt"]);
+        assert_eq!((flag(&command, "t"), flag(&command, "json")), (Some(Some("202401011200")), Some(None)));
+    }
+
+    #[test]
+    fn test_combined_switches_and_end_of_flags() {
+        let parser = Parser::new();
+
+        // Not every letter is a switch, so this is one flag and doesn't turn on `-r`
+        let command = parser.parse("rm -rfoo").unwrap();
+        assert!(command.args.is_empty());
+        assert_eq!(flag(&command, "rfoo"), Some(None));
+        assert_eq!(flag(&command, "r"), None);
+
+        let command = parser.parse("rm -f -- -rfoo --force").unwrap();
+        assert_eq!(command.args, ["-rfoo", "--force"]);
+        assert_eq!(command.flags.len(), 1);
+        assert_eq!(flag(&command, "f"), Some(None));
+    }
+
+    #[test]
+    fn test_long_flag_with_inline_value() {
+        let command = Parser::new().parse("kubectl scale deployment web --replicas=3").unwrap();
+        assert_eq!(command.args, ["scale", "deployment", "web"]);
+        assert_eq!(flag(&command, "replicas"), Some(Some("3")));
+
+        let command = Parser::new().parse("ls --color=never --color-scheme x").unwrap();
+        assert!(command.args.is_empty());
+        assert_eq!(flag(&command, "color"), Some(Some("never")));
+        assert_eq!(flag(&command, "color-scheme"), Some(Some("x")));
     }
 }
@@ -0,0 +1,197 @@
+use async_trait::async_trait;
+use super::super::{Command, Environment, Plugin, ShellError};
+use ring::digest;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub struct ChecksumPlugin;
+
+#[async_trait]
+impl Plugin for ChecksumPlugin {
+    fn name(&self) -> &str {
+        "checksum"
+    }
+
+    fn description(&self) -> &str {
+        "File checksums (sha1, sha256, sha384, sha512)"
+    }
+
+    async fn execute(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        if let Some(manifest) = command.flags.get("check") {
+            let manifest = manifest.as_ref()
+                .ok_or_else(|| ShellError::InvalidUsage("checksum [algorithm] --check <manifest>".to_string()))?;
+            let algorithm = match command.args.first() {
+                Some(name) => Some(algorithm(name)?),
+                None => None,
+            };
+            return self.check(algorithm, manifest, env).await;
+        }
+
+        if command.args.len() < 2 {
+            return Err(ShellError::InvalidUsage("checksum <algorithm> <file...>".to_string()).into());
+        }
+
+        let algorithm = algorithm(&command.args[0])?;
+        let mut lines = Vec::new();
+        for file in &command.args[1..] {
+            let hex = hash_file(algorithm, &env.expand_path(file)).await?;
+            lines.push(format!("{}  {}", hex, file));
+        }
+        Ok(lines.join("\n"))
+    }
+
+    fn complete(&self, args: &[String], partial: &str) -> Vec<String> {
+        if !args.is_empty() {
+            return Vec::new();
+        }
+        ["sha1", "sha256", "sha384", "sha512"]
+            .iter()
+            .filter(|name| name.starts_with(partial))
+            .map(|name| name.to_string())
+            .collect()
+    }
+}
+
+impl ChecksumPlugin {
+    pub fn new() -> Self {
+        ChecksumPlugin
+    }
+
+    async fn check(
+        &self,
+        algorithm: Option<&'static digest::Algorithm>,
+        manifest: &str,
+        env: &Environment,
+    ) -> anyhow::Result<String> {
+        let manifest_path = env.expand_path(manifest);
+        let content = tokio::fs::read_to_string(&manifest_path).await
+            .map_err(|e| ShellError::from_io(&manifest_path, e))?;
+
+        let mut lines = Vec::new();
+        let mut failed = 0;
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let (expected, file) = line.split_once("  ")
+                .ok_or_else(|| ShellError::External(format!("Malformed checksum line: {}", line)))?;
+            let algorithm = match algorithm {
+                Some(algorithm) => algorithm,
+                None => algorithm_for_length(expected.len())
+                    .ok_or_else(|| ShellError::External(format!("Cannot infer algorithm for: {}", line)))?,
+            };
+
+            let matches = hash_file(algorithm, &env.expand_path(file)).await
+                .map(|actual| actual.eq_ignore_ascii_case(expected))
+                .unwrap_or(false);
+            if matches {
+                lines.push(format!("{}: OK", file));
+            } else {
+                failed += 1;
+                lines.push(format!("{}: FAILED", file));
+            }
+        }
+
+        if failed > 0 {
+            return Err(ShellError::External(format!(
+                "{}\nWARNING: {} computed checksum(s) did NOT match",
+                lines.join("\n"),
+                failed
+            )).into());
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+fn algorithm(name: &str) -> anyhow::Result<&'static digest::Algorithm> {
+    match name.trim_end_matches("sum") {
+        "sha1" => Ok(&digest::SHA1_FOR_LEGACY_USE_ONLY),
+        "sha256" => Ok(&digest::SHA256),
+        "sha384" => Ok(&digest::SHA384),
+        "sha512" => Ok(&digest::SHA512),
+        _ => Err(ShellError::NotFound(format!("Checksum algorithm '{}'", name)).into()),
+    }
+}
+
+fn algorithm_for_length(hex_len: usize) -> Option<&'static digest::Algorithm> {
+    match hex_len {
+        40 => Some(&digest::SHA1_FOR_LEGACY_USE_ONLY),
+        64 => Some(&digest::SHA256),
+        96 => Some(&digest::SHA384),
+        128 => Some(&digest::SHA512),
+        _ => None,
+    }
+}
+
+pub(super) async fn hash_file(algorithm: &'static digest::Algorithm, path: &Path) -> anyhow::Result<String> {
+    let mut file = File::open(path).await.map_err(|e| ShellError::from_io(path, e))?;
+    let mut context = digest::Context::new(algorithm);
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buffer).await.map_err(|e| ShellError::from_io(path, e))?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+    }
+
+    Ok(context.finish().as_ref().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::tempdir;
+
+    const HELLO_SHA256: &str = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+    const HELLO_SHA1: &str = "f572d396fae9206628714fb2ce00f72e94f2258f";
+
+    fn command(args: &[&str], flags: &[(&str, &str)]) -> Command {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let flags: HashMap<String, Option<String>> = flags
+            .iter()
+            .map(|(name, value)| (name.to_string(), Some(value.to_string())))
+            .collect();
+        Command::new("checksum".to_string(), args.clone(), flags, format!("checksum {}", args.join(" ")))
+    }
+
+    #[tokio::test]
+    async fn test_known_digests() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("hello.txt");
+        fs::write(&file, "hello\n").unwrap();
+        let file = file.to_string_lossy().to_string();
+        let env = Environment::new();
+
+        let output = ChecksumPlugin.execute(&command(&["sha256", &file], &[]), &env).await.unwrap();
+        assert_eq!(output, format!("{}  {}", HELLO_SHA256, file));
+
+        let output = ChecksumPlugin.execute(&command(&["sha1sum", &file], &[]), &env).await.unwrap();
+        assert_eq!(output, format!("{}  {}", HELLO_SHA1, file));
+
+        let err = ChecksumPlugin.execute(&command(&["md5", &file], &[]), &env).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_check_manifest() {
+        let temp_dir = tempdir().unwrap();
+        let good = temp_dir.path().join("good.txt");
+        let bad = temp_dir.path().join("bad.txt");
+        fs::write(&good, "hello\n").unwrap();
+        fs::write(&bad, "tampered\n").unwrap();
+        let env = Environment::new();
+
+        let manifest = temp_dir.path().join("SHA256SUMS");
+        fs::write(&manifest, format!("{}  {}\n", HELLO_SHA256, good.display())).unwrap();
+        let manifest = manifest.to_string_lossy().to_string();
+        let output = ChecksumPlugin.execute(&command(&[], &[("check", &manifest)]), &env).await.unwrap();
+        assert!(output.ends_with(": OK"));
+
+        fs::write(&manifest, format!("{}  {}\n", HELLO_SHA256, bad.display())).unwrap();
+        let err = ChecksumPlugin.execute(&command(&["sha256"], &[("check", &manifest)]), &env).await.unwrap_err();
+        assert!(err.to_string().contains("FAILED"));
+    }
+}
@@ -1,23 +1,42 @@
 use async_trait::async_trait;
 use super::super::super::{Command, Environment, Plugin};
-use aws_sdk_ec2::{Client as EC2Client, Region};
+use super::super::super::util::{aws_retryable, retry, RetryPolicy};
+use super::CloudStorageProvider;
+use super::sync::{self, FileState, SyncPlan};
+use aws_sdk_ec2::Client as EC2Client;
 use aws_sdk_s3::{Client as S3Client};
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::{ByteStream, DateTimeFormat};
 use aws_sdk_iam::{Client as IAMClient};
+use aws_sdk_lambda::{Client as LambdaClient, primitives::Blob};
 use aws_config::meta::region::RegionProviderChain;
 use aws_types::region::Region as AwsRegion;
-use aws_config::BehaviorVersion;
 use anyhow::{Result, Context};
 use tokio::fs;
 use serde::{Serialize, Deserialize};
-use std::path::PathBuf;
-use indicatif::{ProgressBar, ProgressStyle};
+use std::path::{Path, PathBuf};
+use futures::StreamExt;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// SigV4 presigned URLs are valid for at most seven days.
+const MAX_PRESIGN_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const DEFAULT_PRESIGN_EXPIRY: Duration = Duration::from_secs(60 * 60);
+/// How many objects `s3 sync` transfers at once.
+const SYNC_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct AWSConfig {
     region: String,
     profile: Option<String>,
     output_format: String,
+    #[serde(default)]
+    endpoint_url: Option<String>,
+    /// Items requested per page when listing; `None` uses the service default.
+    #[serde(default)]
+    page_size: Option<i32>,
 }
 
 impl Default for AWSConfig {
@@ -26,25 +45,31 @@ impl Default for AWSConfig {
             region: "us-west-2".to_string(),
             profile: None,
             output_format: "json".to_string(),
+            endpoint_url: None,
+            page_size: None,
         }
     }
 }
 
+struct AWSClients {
+    ec2: EC2Client,
+    s3: S3Client,
+    iam: IAMClient,
+    lambda: LambdaClient,
+}
+
 pub struct AWSPlugin {
-    config: AWSConfig,
-    ec2_client: Option<EC2Client>,
-    s3_client: Option<S3Client>,
-    iam_client: Option<IAMClient>,
+    config: RwLock<AWSConfig>,
+    /// Created on first use, and again after `configure` changes the region or profile
+    clients: Mutex<Option<Arc<AWSClients>>>,
 }
 
 impl AWSPlugin {
     pub async fn new() -> Self {
-        let config = Self::load_config().unwrap_or_default();
+        let config = Self::load_config().await.unwrap_or_default();
         AWSPlugin {
-            config,
-            ec2_client: None,
-            s3_client: None,
-            iam_client: None,
+            config: RwLock::new(config),
+            clients: Mutex::new(None),
         }
     }
 
@@ -64,46 +89,68 @@ impl AWSPlugin {
         }
     }
 
-    async fn init_clients(&mut self) -> Result<()> {
-        let region_provider = RegionProviderChain::first_try(AwsRegion::new(self.config.region.clone()))
-            .or_default_provider()
-            .or_else(Region::new("us-west-2"));
+    /// The service clients for the configured region and profile, created on first use.
+    async fn init_clients(&self) -> Result<Arc<AWSClients>> {
+        if let Some(clients) = self.clients.lock().unwrap().clone() {
+            return Ok(clients);
+        }
 
-        let shared_config = aws_config::defaults(BehaviorVersion::latest())
-            .region(region_provider)
-            .load()
-            .await;
+        let config = self.config.read().unwrap().clone();
+        let region_provider = RegionProviderChain::first_try(AwsRegion::new(config.region))
+            .or_default_provider()
+            .or_else(AwsRegion::new("us-west-2"));
 
-        self.ec2_client = Some(EC2Client::new(&shared_config));
-        self.s3_client = Some(S3Client::new(&shared_config));
-        self.iam_client = Some(IAMClient::new(&shared_config));
+        let mut loader = aws_config::from_env().region(region_provider);
+        if let Some(profile) = &config.profile {
+            loader = loader.profile_name(profile);
+        }
+        if let Some(endpoint_url) = &config.endpoint_url {
+            loader = loader.endpoint_url(endpoint_url);
+        }
+        let shared_config = loader.load().await;
+
+        let clients = Arc::new(AWSClients {
+            ec2: EC2Client::new(&shared_config),
+            s3: S3Client::new(&shared_config),
+            iam: IAMClient::new(&shared_config),
+            lambda: LambdaClient::new(&shared_config),
+        });
+        *self.clients.lock().unwrap() = Some(clients.clone());
+        Ok(clients)
+    }
 
-        Ok(())
+    /// Items requested per page when listing.
+    fn page_size(&self) -> i32 {
+        self.config.read().unwrap().page_size.unwrap_or(1000)
     }
 
     async fn list_instances(&self) -> Result<String> {
-        let client = self.ec2_client.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("EC2 client not initialized"))?;
+        let clients = self.init_clients().await?;
+        let client = &clients.ec2;
 
-        let resp = client.describe_instances()
-            .send()
-            .await?;
+        let mut pages = client.describe_instances()
+            .into_paginator()
+            .page_size(self.page_size())
+            .send();
 
         let mut output = String::from("EC2 Instances:\n");
-        for reservation in resp.reservations().unwrap_or_default() {
-            for instance in reservation.instances().unwrap_or_default() {
-                let instance_id = instance.instance_id().unwrap_or("Unknown");
-                let state = instance.state().map(|s| s.name().as_str()).unwrap_or("Unknown");
-                let instance_type = instance.instance_type().map(|t| t.as_str()).unwrap_or("Unknown");
+        while let Some(page) = pages.next().await {
+            let resp = page?;
+            for reservation in resp.reservations().unwrap_or_default() {
+                for instance in reservation.instances().unwrap_or_default() {
+                    let instance_id = instance.instance_id().unwrap_or("Unknown");
+                    let state = instance.state().and_then(|s| s.name()).map(|name| name.as_str()).unwrap_or("Unknown");
+                    let instance_type = instance.instance_type().map(|t| t.as_str()).unwrap_or("Unknown");
                 
-                output.push_str(&format!("ID: {} | State: {} | Type: {}\n",
-                    instance_id, state, instance_type));
+                    output.push_str(&format!("ID: {} | State: {} | Type: {}\n",
+                        instance_id, state, instance_type));
                 
-                // Add tags if they exist
-                if let Some(tags) = instance.tags() {
-                    for tag in tags {
-                        if let (Some(key), Some(value)) = (tag.key(), tag.value()) {
-                            output.push_str(&format!("  {}: {}\n", key, value));
+                    // Add tags if they exist
+                    if let Some(tags) = instance.tags() {
+                        for tag in tags {
+                            if let (Some(key), Some(value)) = (tag.key(), tag.value()) {
+                                output.push_str(&format!("  {}: {}\n", key, value));
+                            }
                         }
                     }
                 }
@@ -114,18 +161,16 @@ impl AWSPlugin {
     }
 
     async fn list_buckets(&self) -> Result<String> {
-        let client = self.s3_client.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("S3 client not initialized"))?;
+        let clients = self.init_clients().await?;
+        let client = &clients.s3;
 
-        let resp = client.list_buckets()
-            .send()
-            .await?;
+        let resp = retry(&RetryPolicy::default(), aws_retryable, || client.list_buckets().send()).await?;
 
         let mut output = String::from("S3 Buckets:\n");
         for bucket in resp.buckets().unwrap_or_default() {
             let name = bucket.name().unwrap_or("Unknown");
             let created = bucket.creation_date()
-                .map(|d| d.fmt(aws_sdk_s3::types::DateTime::FORMAT))
+                .and_then(|d| d.fmt(DateTimeFormat::DateTime).ok())
                 .unwrap_or_else(|| "Unknown".to_string());
             
             output.push_str(&format!("Name: {} | Created: {}\n", name, created));
@@ -134,17 +179,17 @@ impl AWSPlugin {
         Ok(output)
     }
 
-    async fn upload_to_s3(&self, bucket: &str, key: &str, file_path: &PathBuf) -> Result<String> {
-        let client = self.s3_client.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("S3 client not initialized"))?;
+    async fn upload_to_s3(&self, bucket: &str, key: &str, file_path: &Path) -> Result<String> {
+        let clients = self.init_clients().await?;
+        let client = &clients.s3;
 
         let file_size = fs::metadata(file_path).await?.len();
         let pb = ProgressBar::new(file_size);
         pb.set_style(ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
             .progress_chars("#>-"));
 
-        let body = aws_sdk_s3::types::ByteStream::from_path(file_path).await?;
+        let body = ByteStream::from_path(file_path).await?;
         
         client.put_object()
             .bucket(bucket)
@@ -157,9 +202,9 @@ impl AWSPlugin {
         Ok(format!("Successfully uploaded {} to s3://{}/{}", file_path.display(), bucket, key))
     }
 
-    async fn download_from_s3(&self, bucket: &str, key: &str, file_path: &PathBuf) -> Result<String> {
-        let client = self.s3_client.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("S3 client not initialized"))?;
+    async fn download_from_s3(&self, bucket: &str, key: &str, file_path: &Path) -> Result<String> {
+        let clients = self.init_clients().await?;
+        let client = &clients.s3;
 
         let resp = client.get_object()
             .bucket(bucket)
@@ -170,7 +215,7 @@ impl AWSPlugin {
         let size = resp.content_length() as u64;
         let pb = ProgressBar::new(size);
         pb.set_style(ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
             .progress_chars("#>-"));
 
         let body = resp.body.collect().await?;
@@ -180,28 +225,210 @@ impl AWSPlugin {
         Ok(format!("Successfully downloaded s3://{}/{} to {}", bucket, key, file_path.display()))
     }
 
+    /// Objects under `prefix`, keyed by their path relative to it.
+    async fn remote_files(&self, bucket: &str, prefix: &str) -> Result<BTreeMap<String, FileState>> {
+        let clients = self.init_clients().await?;
+        let client = &clients.s3;
+
+        let mut pages = client.list_objects_v2()
+            .bucket(bucket)
+            .prefix(prefix)
+            .into_paginator()
+            .page_size(self.page_size())
+            .send();
+
+        let mut files = BTreeMap::new();
+        while let Some(page) = pages.next().await {
+            for object in page?.contents().unwrap_or_default() {
+                let relative = match object.key().and_then(|key| key.strip_prefix(prefix)) {
+                    Some(relative) if !relative.is_empty() && !relative.ends_with('/') => relative,
+                    _ => continue,
+                };
+                files.insert(relative.to_string(), FileState::remote(object.size() as u64, object.e_tag()));
+            }
+        }
+        Ok(files)
+    }
+
+    /// `s3 sync <dir> s3://bucket/prefix` or the reverse: copies only files that are missing
+    /// or differ at the destination, and with `delete` removes destination extras.
+    async fn sync(&self, source: &str, destination: &str, delete: bool) -> Result<SyncPlan> {
+        let clients = self.init_clients().await?;
+        let client = &clients.s3;
+
+        let upload = match (source.starts_with("s3://"), destination.starts_with("s3://")) {
+            (false, true) => true,
+            (true, false) => false,
+            _ => return Err(anyhow::anyhow!("s3 sync needs one local directory and one s3:// URL")),
+        };
+        let (local, remote) = if upload { (source, destination) } else { (destination, source) };
+        let local = Path::new(local);
+        let (bucket, prefix) = parse_s3_prefix(remote)?;
+
+        let local_files = sync::local_files(local).await?;
+        let remote_files = self.remote_files(bucket, &prefix).await?;
+        let plan = if upload {
+            sync::plan(&local_files, &remote_files, delete)
+        } else {
+            sync::plan(&remote_files, &local_files, delete)
+        };
+
+        let multi = MultiProgress::new();
+        let total = multi.add(ProgressBar::new((plan.transfer.len() + plan.delete.len()) as u64));
+        total.set_style(ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")?
+            .progress_chars("#>-"));
+        let file_style = ProgressStyle::default_spinner().template("  {spinner:.green} {msg}")?;
+
+        let transfers = futures::stream::iter(plan.transfer.clone())
+            .map(|relative| {
+                let bar = multi.add(ProgressBar::new_spinner());
+                bar.set_style(file_style.clone());
+                bar.enable_steady_tick(Duration::from_millis(100));
+                bar.set_message(relative.clone());
+                let key = format!("{}{}", prefix, relative);
+                let path = local.join(&relative);
+                async move {
+                    let result: Result<()> = async {
+                        if upload {
+                            let body = ByteStream::from_path(&path).await?;
+                            client.put_object().bucket(bucket).key(&key).body(body).send().await?;
+                        } else {
+                            let resp = client.get_object().bucket(bucket).key(&key).send().await?;
+                            if let Some(parent) = path.parent() {
+                                fs::create_dir_all(parent).await?;
+                            }
+                            fs::write(&path, resp.body.collect().await?.into_bytes()).await?;
+                        }
+                        Ok(())
+                    }.await;
+                    bar.finish_and_clear();
+                    result.with_context(|| format!("Failed to sync {}", relative))
+                }
+            })
+            .buffer_unordered(SYNC_CONCURRENCY);
+        futures::pin_mut!(transfers);
+        while let Some(result) = transfers.next().await {
+            result?;
+            total.inc(1);
+        }
+
+        for relative in &plan.delete {
+            if upload {
+                client.delete_object().bucket(bucket).key(format!("{}{}", prefix, relative)).send().await?;
+            } else {
+                fs::remove_file(local.join(relative)).await?;
+            }
+            total.inc(1);
+        }
+
+        total.finish_and_clear();
+        Ok(plan)
+    }
+
+    /// A time-limited URL for downloading (`get`) or uploading (`put`) an object.
+    async fn presign(&self, url: &str, expires: Duration, method: &str) -> Result<String> {
+        let clients = self.init_clients().await?;
+        let client = &clients.s3;
+
+        if expires.is_zero() || expires > MAX_PRESIGN_EXPIRY {
+            return Err(anyhow::anyhow!("Expiry must be between 1 second and 7 days"));
+        }
+        let (bucket, key) = parse_s3_url(url)?;
+        let config = PresigningConfig::expires_in(expires)?;
+
+        let request = match method.to_lowercase().as_str() {
+            "get" => client.get_object().bucket(bucket).key(key).presigned(config).await?,
+            "put" => client.put_object().bucket(bucket).key(key).presigned(config).await?,
+            other => return Err(anyhow::anyhow!("Unsupported presign method '{}', expected get or put", other)),
+        };
+        Ok(request.uri().to_string())
+    }
+
     async fn list_users(&self) -> Result<String> {
-        let client = self.iam_client.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("IAM client not initialized"))?;
+        let clients = self.init_clients().await?;
+        let client = &clients.iam;
+
+        let mut pages = client.list_users()
+            .into_paginator()
+            .page_size(self.page_size())
+            .send();
+
+        let mut output = String::from("IAM Users:\n");
+        while let Some(page) = pages.next().await {
+            let resp = page?;
+            for user in resp.users().unwrap_or_default() {
+                let name = user.user_name().unwrap_or("Unknown");
+                let created = user.create_date()
+                    .and_then(|d| d.fmt(DateTimeFormat::DateTime).ok())
+                    .unwrap_or_else(|| "Unknown".to_string());
+
+                output.push_str(&format!("Username: {} | Created: {}\n", name, created));
+            }
+        }
 
-        let resp = client.list_users()
+        Ok(output)
+    }
+
+    async fn list_functions(&self) -> Result<String> {
+        let clients = self.init_clients().await?;
+        let client = &clients.lambda;
+
+        let resp = client.list_functions()
             .send()
             .await?;
 
-        let mut output = String::from("IAM Users:\n");
-        for user in resp.users().unwrap_or_default() {
-            let name = user.user_name().unwrap_or("Unknown");
-            let created = user.create_date()
-                .map(|d| d.fmt(aws_sdk_iam::types::DateTime::FORMAT))
-                .unwrap_or_else(|| "Unknown".to_string());
-            
-            output.push_str(&format!("Username: {} | Created: {}\n", name, created));
+        let mut output = String::from("Lambda Functions:\n");
+        for function in resp.functions().unwrap_or_default() {
+            let name = function.function_name().unwrap_or("Unknown");
+            let runtime = function.runtime().map(|r| r.as_str()).unwrap_or("Unknown");
+            let modified = function.last_modified().unwrap_or("Unknown");
+
+            output.push_str(&format!("Name: {} | Runtime: {} | Modified: {}\n", name, runtime, modified));
+        }
+
+        Ok(output)
+    }
+
+    async fn invoke_function(&self, name: &str, payload: Option<&str>) -> Result<String> {
+        let clients = self.init_clients().await?;
+        let client = &clients.lambda;
+
+        let mut request = client.invoke().function_name(name);
+        if let Some(payload) = payload {
+            serde_json::from_str::<serde_json::Value>(payload)
+                .context("Payload must be valid JSON")?;
+            request = request.payload(Blob::new(payload.as_bytes().to_vec()));
+        }
+
+        let resp = request.send().await?;
+
+        let mut output = format!("Status: {}\n", resp.status_code());
+        if let Some(error) = resp.function_error() {
+            output.push_str(&format!("Function error: {}\n", error));
+        }
+        if let Some(body) = resp.payload() {
+            output.push_str(&String::from_utf8_lossy(body.as_ref()));
+            output.push('\n');
         }
 
         Ok(output)
     }
 }
 
+#[async_trait]
+impl CloudStorageProvider for AWSPlugin {
+    async fn upload_file(&self, source: &Path, destination: &str) -> Result<String> {
+        let (bucket, key) = parse_s3_url(destination)?;
+        self.upload_to_s3(bucket, key, source).await
+    }
+
+    async fn download_file(&self, source: &str, destination: &Path) -> Result<String> {
+        let (bucket, key) = parse_s3_url(source)?;
+        self.download_from_s3(bucket, key, destination).await
+    }
+}
+
 #[async_trait]
 impl Plugin for AWSPlugin {
     fn name(&self) -> &str {
@@ -221,13 +448,15 @@ impl Plugin for AWSPlugin {
                 let setting = &command.args[1];
                 let value = &command.args[2];
                 
-                match *setting {
+                match setting.as_str() {
                     "region" => {
-                        self.config.region = value.to_string();
+                        self.config.write().unwrap().region = value.to_string();
+                        *self.clients.lock().unwrap() = None;
                         Ok("Region updated successfully".to_string())
                     }
                     "profile" => {
-                        self.config.profile = Some(value.to_string());
+                        self.config.write().unwrap().profile = Some(value.to_string());
+                        *self.clients.lock().unwrap() = None;
                         Ok("Profile updated successfully".to_string())
                     }
                     _ => Err(anyhow::anyhow!("Invalid configuration setting"))
@@ -251,9 +480,9 @@ impl Plugin for AWSPlugin {
                         let source = &command.args[2];
                         let dest = &command.args[3];
 
-                        if source.starts_with("s3://") {
+                        if let Some(url) = source.strip_prefix("s3://") {
                             // Download from S3
-                            let parts: Vec<&str> = source[5..].splitn(2, '/').collect();
+                            let parts: Vec<&str> = url.splitn(2, '/').collect();
                             if parts.len() != 2 {
                                 return Err(anyhow::anyhow!("Invalid S3 URL"));
                             }
@@ -267,7 +496,28 @@ impl Plugin for AWSPlugin {
                             self.upload_to_s3(parts[0], parts[1], &PathBuf::from(source)).await
                         }
                     }
-                    _ => Ok("Available S3 commands: ls, cp".to_string()),
+                    Some("presign") => {
+                        let url = match command.args.get(2) {
+                            Some(url) => url,
+                            None => return Ok("Usage: aws s3 presign s3://<bucket>/<key> [--expires <duration>] [--method get|put]".to_string()),
+                        };
+                        let expires = match command.flags.get("expires").and_then(|e| e.as_deref()) {
+                            Some(value) => parse_expiry(value)?,
+                            None => DEFAULT_PRESIGN_EXPIRY,
+                        };
+                        let method = command.flags.get("method").and_then(|m| m.as_deref()).unwrap_or("get");
+                        self.presign(url, expires, method).await
+                    }
+                    Some("sync") => {
+                        let (source, destination) = match sync_operands(command) {
+                            Some(operands) => operands,
+                            None => return Ok("Usage: aws s3 sync <dir> s3://<bucket>[/<prefix>] [--delete] (or the reverse)".to_string()),
+                        };
+                        let plan = self.sync(&source, &destination, command.flags.contains_key("delete")).await?;
+                        Ok(format!("Transferred {}, deleted {}, {} unchanged",
+                            plan.transfer.len(), plan.delete.len(), plan.unchanged))
+                    }
+                    _ => Ok("Available S3 commands: ls, cp, presign, sync".to_string()),
                 }
             }
 
@@ -278,7 +528,223 @@ impl Plugin for AWSPlugin {
                 }
             }
 
-            _ => Ok("Available commands: configure, ec2, s3, iam".to_string()),
+            Some("lambda") => {
+                match command.args.get(1).map(|s| s.as_str()) {
+                    Some("list") => self.list_functions().await,
+                    Some("invoke") => {
+                        let name = match command.args.get(2) {
+                            Some(name) => name,
+                            None => return Ok("Usage: aws lambda invoke <name> [--payload <json>]".to_string()),
+                        };
+                        self.invoke_function(name, lambda_payload(command)).await
+                    }
+                    _ => Ok("Available Lambda commands: list, invoke".to_string()),
+                }
+            }
+
+            _ => Ok("Available commands: configure, ec2, s3, iam, lambda".to_string()),
         }
     }
 }
+
+fn parse_s3_url(url: &str) -> Result<(&str, &str)> {
+    url.strip_prefix("s3://")
+        .and_then(|rest| rest.split_once('/'))
+        .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Invalid S3 URL '{}', expected s3://bucket/key", url))
+}
+
+/// The source and destination of `s3 sync`.
+fn sync_operands(command: &Command) -> Option<(String, String)> {
+    match &command.args[..] {
+        [_, _, source, destination] => Some((source.clone(), destination.clone())),
+        _ => None,
+    }
+}
+
+/// The `--payload` JSON, which the parser keeps whole and unquoted (`--payload '{"a": 1}'`).
+fn lambda_payload(command: &Command) -> Option<&str> {
+    command.flags.get("payload").and_then(|payload| payload.as_deref())
+}
+
+/// Splits `s3://bucket[/prefix]`, normalizing a non-empty prefix to end with `/`.
+fn parse_s3_prefix(url: &str) -> Result<(&str, String)> {
+    let rest = url.strip_prefix("s3://")
+        .ok_or_else(|| anyhow::anyhow!("Invalid S3 URL '{}', expected s3://bucket[/prefix]", url))?;
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return Err(anyhow::anyhow!("Invalid S3 URL '{}', expected s3://bucket[/prefix]", url));
+    }
+    let prefix = prefix.trim_end_matches('/');
+    Ok((bucket, if prefix.is_empty() { String::new() } else { format!("{}/", prefix) }))
+}
+
+/// Parses `--expires` values: plain seconds or a number with an `s`, `m`, `h` or `d` suffix.
+fn parse_expiry(value: &str) -> Result<Duration> {
+    let (number, unit) = match value.char_indices().last() {
+        Some((index, unit)) if unit.is_ascii_alphabetic() => (&value[..index], unit),
+        _ => (value, 's'),
+    };
+    let number: u64 = number.parse()
+        .map_err(|_| anyhow::anyhow!("Invalid expiry '{}', expected e.g. 900, 15m, 12h or 7d", value))?;
+    let scale = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return Err(anyhow::anyhow!("Invalid expiry unit in '{}', expected s, m, h or d", value)),
+    };
+    let seconds = number.checked_mul(scale)
+        .ok_or_else(|| anyhow::anyhow!("Expiry '{}' is too large", value))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::Parser;
+
+    #[test]
+    fn test_sync_delete_is_a_switch() {
+        let parser = Parser::new();
+        for line in ["aws s3 sync --delete ./site s3://b/web", "aws s3 sync ./site s3://b/web --delete"] {
+            let command = parser.parse(line).unwrap();
+            assert_eq!(sync_operands(&command), Some(("./site".to_string(), "s3://b/web".to_string())));
+            assert!(command.flags.contains_key("delete"));
+        }
+        assert_eq!(sync_operands(&parser.parse("aws s3 sync ./site").unwrap()), None);
+    }
+
+    #[test]
+    fn test_lambda_payload_is_unquoted() {
+        let parser = Parser::new();
+        let command = parser.parse(r#"aws lambda invoke echo --payload '{"a": 1}'"#).unwrap();
+        assert_eq!(lambda_payload(&command), Some(r#"{"a": 1}"#));
+        assert_eq!(lambda_payload(&parser.parse("aws lambda invoke echo").unwrap()), None);
+    }
+
+    #[cfg(feature = "localstack")]
+    async fn localstack_plugin(page_size: Option<i32>) -> AWSPlugin {
+        let endpoint = std::env::var("LOCALSTACK_ENDPOINT")
+            .unwrap_or_else(|_| "http://localhost:4566".to_string());
+
+        AWSPlugin {
+            config: RwLock::new(AWSConfig {
+                region: "us-east-1".to_string(),
+                endpoint_url: Some(endpoint),
+                page_size,
+                ..Default::default()
+            }),
+            clients: Mutex::new(None),
+        }
+    }
+
+    // Expects a function named by LOCALSTACK_LAMBDA_FUNCTION (default `nexusshell-echo`)
+    // that echoes its payload to be deployed on the emulator.
+    #[cfg(feature = "localstack")]
+    #[tokio::test]
+    async fn test_lambda_invoke_localstack() {
+        let function = std::env::var("LOCALSTACK_LAMBDA_FUNCTION")
+            .unwrap_or_else(|_| "nexusshell-echo".to_string());
+        let plugin = localstack_plugin(None).await;
+
+        let listing = plugin.list_functions().await.unwrap();
+        assert!(listing.contains(&function));
+
+        let output = plugin.invoke_function(&function, Some(r#"{"ping":"pong"}"#)).await.unwrap();
+        assert!(output.starts_with("Status: 200"));
+        assert!(output.contains("pong"));
+    }
+
+    #[cfg(feature = "localstack")]
+    #[tokio::test]
+    async fn test_listings_follow_pagination_localstack() {
+        let plugin = localstack_plugin(Some(5)).await;
+        let clients = plugin.init_clients().await.unwrap();
+
+        let resp = clients.ec2
+            .run_instances()
+            .image_id("ami-df5de72bdb3b")
+            .min_count(12)
+            .max_count(12)
+            .send()
+            .await
+            .unwrap();
+        let instance_ids: Vec<String> = resp.instances().unwrap_or_default().iter()
+            .filter_map(|instance| instance.instance_id().map(String::from))
+            .collect();
+        let listing = plugin.list_instances().await.unwrap();
+        for id in &instance_ids {
+            assert!(listing.contains(id.as_str()));
+        }
+        clients.ec2.terminate_instances().set_instance_ids(Some(instance_ids)).send().await.unwrap();
+
+        // A fresh temp dir name keeps the users of each run apart, so reruns don't collide
+        let temp_dir = tempfile::tempdir().unwrap();
+        let run = temp_dir.path().file_name().unwrap().to_string_lossy().trim_start_matches('.').to_string();
+        let users: Vec<String> = (0..12).map(|i| format!("nexus-page-{}-{}", run, i)).collect();
+        for user in &users {
+            clients.iam.create_user().user_name(user).send().await.unwrap();
+        }
+        let listing = plugin.list_users().await.unwrap();
+        for user in &users {
+            clients.iam.delete_user().user_name(user).send().await.unwrap();
+        }
+        for user in &users {
+            assert!(listing.contains(&format!("{} ", user)));
+        }
+    }
+
+    #[cfg(feature = "localstack")]
+    #[tokio::test]
+    async fn test_presign_url_localstack() {
+        let plugin = localstack_plugin(None).await;
+
+        let url = plugin.presign("s3://nexus-bucket/reports/q3.csv", parse_expiry("1h").unwrap(), "get").await.unwrap();
+        assert!(url.contains("/reports/q3.csv?"));
+        for param in ["X-Amz-Algorithm=AWS4-HMAC-SHA256", "X-Amz-Credential=", "X-Amz-Date=", "X-Amz-Expires=3600", "X-Amz-Signature="] {
+            assert!(url.contains(param), "{} missing from {}", param, url);
+        }
+
+        let upload = plugin.presign("s3://nexus-bucket/upload.bin", Duration::from_secs(60), "put").await.unwrap();
+        assert!(upload.contains("X-Amz-Expires=60"));
+
+        assert!(plugin.presign("s3://nexus-bucket/k", parse_expiry("8d").unwrap(), "get").await.is_err());
+        assert!(plugin.presign("s3://nexus-bucket", Duration::from_secs(60), "get").await.is_err());
+        assert!(parse_expiry("10w").is_err());
+        assert!(parse_expiry(&format!("{}d", u64::MAX)).is_err());
+    }
+
+    #[cfg(feature = "localstack")]
+    #[tokio::test]
+    async fn test_sync_transfers_only_changes_localstack() {
+        let plugin = localstack_plugin(None).await;
+        let _ = plugin.init_clients().await.unwrap().s3.create_bucket().bucket("nexus-sync").send().await;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = temp_dir.path().join("site");
+        std::fs::create_dir_all(source.join("css")).unwrap();
+        std::fs::write(source.join("index.html"), "<h1>hello</h1>").unwrap();
+        std::fs::write(source.join("css/site.css"), "body {}").unwrap();
+        let source = source.to_string_lossy().to_string();
+        let remote = format!("s3://nexus-sync/{}", uuid::Uuid::new_v4());
+
+        let first = plugin.sync(&source, &remote, false).await.unwrap();
+        assert_eq!(first.transfer, vec!["css/site.css".to_string(), "index.html".to_string()]);
+
+        std::fs::write(temp_dir.path().join("site/index.html"), "<h1>changed</h1>").unwrap();
+        let second = plugin.sync(&source, &remote, false).await.unwrap();
+        assert_eq!(second.transfer, vec!["index.html".to_string()]);
+        assert_eq!(second.unchanged, 1);
+
+        // Pull it back down, then prune a file the bucket no longer has
+        let copy = temp_dir.path().join("copy");
+        plugin.sync(&remote, &copy.to_string_lossy(), false).await.unwrap();
+        assert_eq!(std::fs::read_to_string(copy.join("index.html")).unwrap(), "<h1>changed</h1>");
+
+        std::fs::remove_file(temp_dir.path().join("site/css/site.css")).unwrap();
+        let pruned = plugin.sync(&source, &remote, true).await.unwrap();
+        assert_eq!(pruned.delete, vec!["css/site.css".to_string()]);
+        assert!(parse_s3_prefix("s3://").is_err());
+    }
+}
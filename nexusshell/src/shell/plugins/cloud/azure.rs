@@ -1,24 +1,34 @@
 use async_trait::async_trait;
 use super::super::super::{Command, Environment, Plugin};
+use super::{with_spinner, CloudStorageProvider, OPERATION_TIMEOUT};
+use super::super::super::util::{http_retryable, retry, RetryPolicy};
 use azure_identity::DefaultAzureCredential;
 use azure_storage::StorageCredentials;
 use azure_storage_blobs::prelude::*;
-use azure_mgmt_compute::{ComputeClient, VirtualMachine};
-use azure_mgmt_storage::StorageAccountClient;
 use azure_core::auth::TokenCredential;
+use azure_core::lro::location::{self, FinalState};
 use anyhow::{Result, Context};
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 use serde::{Serialize, Deserialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use indicatif::{ProgressBar, ProgressStyle};
 use futures::StreamExt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
+const ARM_ENDPOINT: &str = "https://management.azure.com";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct AzureConfig {
     subscription_id: String,
     tenant_id: Option<String>,
     resource_group: String,
     location: String,
+    /// Resource Manager endpoint, for sovereign clouds or testing
+    #[serde(default)]
+    management_endpoint: Option<String>,
 }
 
 impl Default for AzureConfig {
@@ -28,25 +38,82 @@ impl Default for AzureConfig {
             tenant_id: None,
             resource_group: "default-rg".to_string(),
             location: "westus2".to_string(),
+            management_endpoint: None,
+        }
+    }
+}
+
+struct AzureClients {
+    credential: Arc<dyn TokenCredential>,
+    /// Resource Manager endpoint the clients talk to, without a trailing slash
+    endpoint: String,
+    compute: azure_mgmt_compute::Client,
+    storage: azure_mgmt_storage::Client,
+}
+
+impl AzureClients {
+    fn new(credential: Arc<dyn TokenCredential>, endpoint: &str) -> Self {
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        AzureClients {
+            compute: azure_mgmt_compute::ClientBuilder::new(credential.clone()).endpoint(endpoint.clone()).build(),
+            storage: azure_mgmt_storage::ClientBuilder::new(credential.clone()).endpoint(endpoint.clone()).build(),
+            credential,
+            endpoint,
+        }
+    }
+}
+
+/// Power state changes `azure vm` can make; each is a long-running Resource Manager action.
+/// Stopping keeps the VM allocated (and billed); deallocating releases its compute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VmOperation {
+    Start,
+    Stop,
+    Deallocate,
+}
+
+impl VmOperation {
+    fn parse(verb: &str) -> Option<Self> {
+        match verb {
+            "start" => Some(VmOperation::Start),
+            "stop" => Some(VmOperation::Stop),
+            "deallocate" => Some(VmOperation::Deallocate),
+            _ => None,
+        }
+    }
+
+    fn progress(self) -> &'static str {
+        match self {
+            VmOperation::Start => "Starting",
+            VmOperation::Stop => "Stopping",
+            VmOperation::Deallocate => "Deallocating",
+        }
+    }
+
+    fn done(self) -> &'static str {
+        match self {
+            VmOperation::Start => "started",
+            VmOperation::Stop => "stopped",
+            VmOperation::Deallocate => "deallocated",
         }
     }
 }
 
 pub struct AzurePlugin {
-    config: AzureConfig,
-    compute_client: Option<ComputeClient>,
-    storage_client: Option<StorageAccountClient>,
-    credential: Option<DefaultAzureCredential>,
+    config: RwLock<AzureConfig>,
+    clients: Mutex<Option<Arc<AzureClients>>>,
+    http: reqwest::Client,
+    poll_interval: Duration,
 }
 
 impl AzurePlugin {
     pub async fn new() -> Self {
-        let config = Self::load_config().unwrap_or_default();
+        let config = Self::load_config().await.unwrap_or_default();
         AzurePlugin {
-            config,
-            compute_client: None,
-            storage_client: None,
-            credential: None,
+            config: RwLock::new(config),
+            clients: Mutex::new(None),
+            http: reqwest::Client::new(),
+            poll_interval: POLL_INTERVAL,
         }
     }
 
@@ -55,136 +122,177 @@ impl AzurePlugin {
         config_path.push(".nexusshell");
         config_path.push("azure_config.json");
 
+        // Without a config file the defaults apply; nothing is written to the home directory
         if !config_path.exists() {
-            let config = AzureConfig::default();
-            fs::create_dir_all(config_path.parent().unwrap()).await?;
-            fs::write(&config_path, serde_json::to_string_pretty(&config)?).await?;
-            Ok(config)
-        } else {
-            let content = fs::read_to_string(&config_path).await?;
-            Ok(serde_json::from_str(&content)?)
+            return Ok(AzureConfig::default());
         }
+        let content = fs::read_to_string(&config_path).await?;
+        Ok(serde_json::from_str(&content)?)
     }
 
-    async fn init_clients(&mut self) -> Result<()> {
-        self.credential = Some(DefaultAzureCredential::default());
-        let cred = self.credential.as_ref().unwrap();
-
-        self.compute_client = Some(ComputeClient::new(
-            cred,
-            &self.config.subscription_id
-        ));
-
-        self.storage_client = Some(StorageAccountClient::new(
-            cred,
-            &self.config.subscription_id
-        ));
+    fn config(&self) -> AzureConfig {
+        self.config.read().unwrap().clone()
+    }
 
-        Ok(())
+    /// The clients, created on first use and dropped when the configuration changes.
+    fn init_clients(&self) -> Result<Arc<AzureClients>> {
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(clients) = clients.as_ref() {
+            return Ok(clients.clone());
+        }
+        let config = self.config();
+        if config.subscription_id.is_empty() {
+            return Err(anyhow::anyhow!("No subscription configured; run `azure configure subscription <id>`"));
+        }
+        let credential: Arc<dyn TokenCredential> = Arc::new(DefaultAzureCredential::default());
+        let created = Arc::new(AzureClients::new(credential, config.management_endpoint.as_deref().unwrap_or(ARM_ENDPOINT)));
+        *clients = Some(created.clone());
+        Ok(created)
     }
 
     async fn list_vms(&self) -> Result<String> {
-        let client = self.compute_client.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Compute client not initialized"))?;
-
-        let vms = client
-            .virtual_machines
-            .list(&self.config.resource_group)
-            .into_stream()
-            .collect::<Vec<_>>()
-            .await;
+        let clients = self.init_clients()?;
+        let config = self.config();
+        let mut pages = clients.compute
+            .virtual_machines_client()
+            .list(config.resource_group, config.subscription_id)
+            .into_stream();
 
         let mut output = String::from("Virtual Machines:\n");
-        for vm in vms {
-            let vm = vm?;
-            output.push_str(&format!("Name: {}\n", vm.name()));
-            output.push_str(&format!("  Size: {}\n", vm.hardware_profile.vm_size));
-            output.push_str(&format!("  State: {}\n", vm.provisioning_state.unwrap_or_default()));
-            
-            if let Some(os_profile) = vm.os_profile {
-                output.push_str(&format!("  OS: {}\n", os_profile.computer_name.unwrap_or_default()));
+        while let Some(page) = pages.next().await {
+            for vm in page?.value {
+                output.push_str(&format!("Name: {}\n", vm.resource.name.unwrap_or_default()));
+                let Some(properties) = vm.properties else { continue };
+                let size = properties.hardware_profile.and_then(|h| h.vm_size);
+                output.push_str(&format!("  Size: {}\n", size.as_ref().map(enum_str).unwrap_or_default()));
+                output.push_str(&format!("  State: {}\n", properties.provisioning_state.unwrap_or_default()));
+
+                if let Some(os_profile) = properties.os_profile {
+                    output.push_str(&format!("  OS: {}\n", os_profile.computer_name.unwrap_or_default()));
+                }
             }
         }
 
         Ok(output)
     }
 
-    async fn list_storage_accounts(&self) -> Result<String> {
-        let client = self.storage_client.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Storage client not initialized"))?;
+    /// Sends the VM action through the compute client, then polls the long-running operation
+    /// it returns until it finishes.
+    async fn vm_power(&self, operation: VmOperation, name: &str) -> Result<String> {
+        let clients = self.init_clients()?;
+        let config = self.config();
+        let (group, subscription) = (config.resource_group, config.subscription_id);
+        let vms = clients.compute.virtual_machines_client();
+        let response = match operation {
+            VmOperation::Start => vms.start(group, name, subscription).send().await.map(Into::into),
+            VmOperation::Stop => vms.power_off(group, name, subscription).send().await.map(Into::into),
+            VmOperation::Deallocate => vms.deallocate(group, name, subscription).send().await.map(Into::into),
+        };
+        let response: azure_core::Response = response
+            .map_err(|e| anyhow::anyhow!("{} VM {} failed: {}", operation.progress(), name, e))?;
+
+        // 202 Accepted points at the operation to poll; 200 means it already finished
+        let headers = response.headers();
+        let poll_url = location::get_location(headers, FinalState::AzureAsyncOperation)?
+            .or(location::get_location(headers, FinalState::Location)?);
+        if let Some(poll_url) = poll_url {
+            let token = clients.credential.get_token(&format!("{}/", clients.endpoint)).await
+                .context("Failed to get a Resource Manager token")?;
+            let poll = self.wait_for_operation(poll_url.as_str(), token.token.secret());
+            with_spinner(format!("{} VM {}", operation.progress(), name), OPERATION_TIMEOUT, poll)
+                .await
+                .with_context(|| format!("{} VM {} failed", operation.progress(), name))?;
+        }
 
-        let accounts = client
-            .list()
-            .into_stream()
-            .collect::<Vec<_>>()
-            .await;
+        Ok(format!("VM {} {}", name, operation.done()))
+    }
+
+    async fn wait_for_operation(&self, poll_url: &str, token: &str) -> Result<()> {
+        loop {
+            tokio::time::sleep(self.poll_interval).await;
+            let response = retry(&RetryPolicy::default(), http_retryable, || async {
+                self.http.get(poll_url).bearer_auth(token).send().await?.error_for_status()
+            }).await?;
+            if response.status() == reqwest::StatusCode::ACCEPTED {
+                continue;
+            }
+            // `Location` polling finishes with a plain 200; `Azure-AsyncOperation` reports a status
+            let body: serde_json::Value = response.json().await.unwrap_or_default();
+            match body.get("status").and_then(|s| s.as_str()) {
+                None | Some("Succeeded") => return Ok(()),
+                Some("Failed") | Some("Canceled") => {
+                    let message = body.pointer("/error/message").and_then(|m| m.as_str()).unwrap_or("no details");
+                    return Err(anyhow::anyhow!("{}", message));
+                }
+                Some(_) => continue,
+            }
+        }
+    }
+
+    async fn list_storage_accounts(&self) -> Result<String> {
+        let clients = self.init_clients()?;
+        let mut pages = clients.storage
+            .storage_accounts_client()
+            .list(self.config().subscription_id)
+            .into_stream();
 
         let mut output = String::from("Storage Accounts:\n");
-        for account in accounts {
-            let account = account?;
-            output.push_str(&format!("Name: {}\n", account.name));
-            output.push_str(&format!("  Location: {}\n", account.location));
-            output.push_str(&format!("  Kind: {}\n", account.kind));
-            
-            if let Some(sku) = account.sku {
-                output.push_str(&format!("  SKU: {}\n", sku.name));
+        while let Some(page) = pages.next().await {
+            for account in page?.value {
+                let resource = account.tracked_resource;
+                output.push_str(&format!("Name: {}\n", resource.resource.name.unwrap_or_default()));
+                output.push_str(&format!("  Location: {}\n", resource.location));
+                output.push_str(&format!("  Kind: {}\n", account.kind.as_ref().map(enum_str).unwrap_or_default()));
+
+                if let Some(sku) = account.sku {
+                    output.push_str(&format!("  SKU: {}\n", enum_str(&sku.name)));
+                }
             }
         }
 
         Ok(output)
     }
 
-    async fn upload_blob(&self, account: &str, container: &str, blob_name: &str, file_path: &PathBuf) -> Result<String> {
-        let credential = self.credential.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Azure credential not initialized"))?;
+    fn blob_client(&self, account: &str, container: &str, blob_name: &str) -> Result<BlobClient> {
+        let credentials = StorageCredentials::token_credential(self.init_clients()?.credential.clone());
+        Ok(BlobServiceClient::new(account, credentials)
+            .container_client(container)
+            .blob_client(blob_name))
+    }
 
-        let blob_client = BlobClient::new(
-            account,
-            container,
-            blob_name,
-            credential.clone()
-        );
+    async fn upload_blob(&self, account: &str, container: &str, blob_name: &str, file_path: &Path) -> Result<String> {
+        let blob_client = self.blob_client(account, container, blob_name)?;
 
-        let file_size = fs::metadata(file_path).await?.len();
-        let pb = ProgressBar::new(file_size);
+        let data = fs::read(file_path).await
+            .with_context(|| format!("Failed to read {}", file_path.display()))?;
+        let size = data.len() as u64;
+        let pb = ProgressBar::new(size);
         pb.set_style(ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
             .progress_chars("#>-"));
 
-        let mut file = fs::File::open(file_path).await?;
-        blob_client
-            .put_block_blob(&mut file)
-            .content_length(file_size)
-            .send()
-            .await?;
+        blob_client.put_block_blob(data).await?;
+        pb.set_position(size);
 
         pb.finish_with_message("Upload complete");
         Ok(format!("Successfully uploaded {} to blob storage", file_path.display()))
     }
 
-    async fn download_blob(&self, account: &str, container: &str, blob_name: &str, file_path: &PathBuf) -> Result<String> {
-        let credential = self.credential.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Azure credential not initialized"))?;
-
-        let blob_client = BlobClient::new(
-            account,
-            container,
-            blob_name,
-            credential.clone()
-        );
+    async fn download_blob(&self, account: &str, container: &str, blob_name: &str, file_path: &Path) -> Result<String> {
+        let blob_client = self.blob_client(account, container, blob_name)?;
 
         let properties = blob_client.get_properties().await?;
-        let size = properties.content_length();
+        let size = properties.blob.properties.content_length;
         let pb = ProgressBar::new(size);
         pb.set_style(ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
             .progress_chars("#>-"));
 
         let mut file = fs::File::create(file_path).await?;
-        let mut stream = blob_client.get().await?.into_stream();
+        let mut stream = blob_client.get().into_stream();
 
         while let Some(chunk) = stream.next().await {
-            let data = chunk?;
+            let data = chunk?.data.collect().await?;
             file.write_all(&data).await?;
             pb.inc(data.len() as u64);
         }
@@ -194,6 +302,39 @@ impl AzurePlugin {
     }
 }
 
+/// The wire name of a generated Resource Manager enum, e.g. `Standard_LRS`.
+fn enum_str<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+/// Splits `az://account/container/blob`.
+fn parse_azure_url(url: &str) -> Result<(&str, &str, &str)> {
+    let invalid = || anyhow::anyhow!("Invalid Azure URL '{}', expected az://account/container/blob", url);
+    let rest = url.strip_prefix("az://").ok_or_else(invalid)?;
+    let (account, rest) = rest.split_once('/').ok_or_else(invalid)?;
+    let (container, blob) = rest.split_once('/').ok_or_else(invalid)?;
+    if account.is_empty() || container.is_empty() || blob.is_empty() {
+        return Err(invalid());
+    }
+    Ok((account, container, blob))
+}
+
+#[async_trait]
+impl CloudStorageProvider for AzurePlugin {
+    async fn upload_file(&self, source: &Path, destination: &str) -> Result<String> {
+        let (account, container, blob) = parse_azure_url(destination)?;
+        self.upload_blob(account, container, blob, source).await
+    }
+
+    async fn download_file(&self, source: &str, destination: &Path) -> Result<String> {
+        let (account, container, blob) = parse_azure_url(source)?;
+        self.download_blob(account, container, blob, destination).await
+    }
+}
+
 #[async_trait]
 impl Plugin for AzurePlugin {
     fn name(&self) -> &str {
@@ -212,28 +353,38 @@ impl Plugin for AzurePlugin {
                 }
                 let setting = &command.args[1];
                 let value = &command.args[2];
-                
-                match *setting {
+
+                let mut config = self.config.write().unwrap();
+                let message = match setting.as_str() {
                     "subscription" => {
-                        self.config.subscription_id = value.to_string();
+                        config.subscription_id = value.to_string();
                         Ok("Subscription ID updated successfully".to_string())
                     }
                     "resource-group" => {
-                        self.config.resource_group = value.to_string();
+                        config.resource_group = value.to_string();
                         Ok("Resource group updated successfully".to_string())
                     }
                     "location" => {
-                        self.config.location = value.to_string();
+                        config.location = value.to_string();
                         Ok("Location updated successfully".to_string())
                     }
                     _ => Err(anyhow::anyhow!("Invalid configuration setting"))
-                }
+                };
+                *self.clients.lock().unwrap() = None;
+                message
             }
 
             Some("vm") => {
                 match command.args.get(1).map(|s| s.as_str()) {
                     Some("list") => self.list_vms().await,
-                    _ => Ok("Available VM commands: list".to_string()),
+                    Some(verb) if VmOperation::parse(verb).is_some() => {
+                        let name = match command.args.get(2) {
+                            Some(name) => name,
+                            None => return Ok(format!("Usage: azure vm {} <name>", verb)),
+                        };
+                        self.vm_power(VmOperation::parse(verb).unwrap(), name).await
+                    }
+                    _ => Ok("Available VM commands: list, start, stop, deallocate".to_string()),
                 }
             }
 
@@ -248,7 +399,7 @@ impl Plugin for AzurePlugin {
                         let container = &command.args[3];
                         let blob_name = &command.args[4];
                         let file_path = PathBuf::from(&command.args[5]);
-                        
+
                         self.upload_blob(account, container, blob_name, &file_path).await
                     }
                     Some("download") => {
@@ -259,7 +410,7 @@ impl Plugin for AzurePlugin {
                         let container = &command.args[3];
                         let blob_name = &command.args[4];
                         let file_path = PathBuf::from(&command.args[5]);
-                        
+
                         self.download_blob(account, container, blob_name, &file_path).await
                     }
                     _ => Ok("Available storage commands: list, upload, download".to_string()),
@@ -270,3 +421,73 @@ impl Plugin for AzurePlugin {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::mock_http::{self, Reply};
+    use azure_core::auth::{AccessToken, TokenResponse};
+    use std::sync::OnceLock;
+
+    struct FakeCredential;
+
+    #[async_trait]
+    impl TokenCredential for FakeCredential {
+        async fn get_token(&self, _resource: &str) -> azure_core::Result<TokenResponse> {
+            let expires_on = azure_core::date::parse_rfc3339("2999-01-01T00:00:00Z")?;
+            Ok(TokenResponse::new(AccessToken::new("token"), expires_on))
+        }
+    }
+
+    /// Accepts VM actions with a 202 pointing at an operation that is in progress on the
+    /// first poll and succeeded on the next. Records each request line.
+    async fn spawn_arm_mock() -> (String, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let operation_url = Arc::new(OnceLock::<String>::new());
+        let poll_url = operation_url.clone();
+        let mut polls = 0;
+        let base = mock_http::serve(move |line| {
+            recorded.lock().unwrap().push(line.to_string());
+            if line.starts_with("POST") {
+                return Reply::new("202 Accepted", "").header("Azure-AsyncOperation", poll_url.get().unwrap().clone());
+            }
+            polls += 1;
+            let status = if polls % 2 == 1 { "InProgress" } else { "Succeeded" };
+            Reply::new("200 OK", format!("{{\"status\":\"{}\"}}", status)).header("Content-Type", "application/json")
+        }).await;
+        operation_url.set(format!("{}/operations/1", base)).unwrap();
+
+        (base, requests)
+    }
+
+    #[tokio::test]
+    async fn test_vm_verbs_issue_matching_operation() {
+        let (endpoint, requests) = spawn_arm_mock().await;
+        let plugin = AzurePlugin {
+            config: RwLock::new(AzureConfig {
+                subscription_id: "sub-1".to_string(),
+                resource_group: "rg-1".to_string(),
+                ..Default::default()
+            }),
+            clients: Mutex::new(Some(Arc::new(AzureClients::new(Arc::new(FakeCredential), &endpoint)))),
+            http: reqwest::Client::new(),
+            poll_interval: Duration::ZERO,
+        };
+
+        for (verb, action) in [("start", "start"), ("stop", "powerOff"), ("deallocate", "deallocate")] {
+            let operation = VmOperation::parse(verb).unwrap();
+            let output = plugin.vm_power(operation, "web-1").await.unwrap();
+            assert_eq!(output, format!("VM web-1 {}", operation.done()));
+
+            let requests = std::mem::take(&mut *requests.lock().unwrap());
+            assert_eq!(requests.len(), 3);
+            assert!(requests[0].starts_with(&format!(
+                "POST /subscriptions/sub-1/resourceGroups/rg-1/providers/Microsoft.Compute/virtualMachines/web-1/{}?api-version=",
+                action
+            )));
+            assert!(requests[1..].iter().all(|line| line.starts_with("GET /operations/1")));
+        }
+        assert!(VmOperation::parse("restart").is_none());
+    }
+}
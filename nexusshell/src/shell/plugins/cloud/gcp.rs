@@ -1,23 +1,34 @@
 use async_trait::async_trait;
 use super::super::super::{Command, Environment, Plugin};
+use super::{with_spinner, CloudStorageProvider, OPERATION_TIMEOUT};
+use super::super::super::util::{http_retryable, retry, RetryPolicy};
 use google_cloud_storage::client::{Client as StorageClient, ClientConfig};
-use google_cloud_compute::client::{Client as ComputeClient};
-use google_cloud_googleapis::cloud::compute::v1::{Instance, ListInstancesRequest};
-use google_cloud_googleapis::cloud::storage::v1::{Bucket, Object};
+use google_cloud_storage::http::buckets::list::ListBucketsRequest;
+use google_cloud_storage::http::objects::download::Range;
+use google_cloud_storage::http::objects::get::GetObjectRequest;
+use google_cloud_storage::http::objects::upload::{Media, UploadObjectRequest, UploadType};
 use google_cloud_auth::credentials::CredentialsFile;
 use anyhow::{Result, Context};
 use tokio::fs;
 use serde::{Serialize, Deserialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use indicatif::{ProgressBar, ProgressStyle};
-use futures::StreamExt;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
+const COMPUTE_ENDPOINT: &str = "https://compute.googleapis.com/compute/v1";
+const COMPUTE_SCOPES: [&str; 1] = ["https://www.googleapis.com/auth/compute"];
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct GCPConfig {
     project_id: String,
     credentials_file: PathBuf,
     region: String,
     zone: String,
+    /// Compute Engine API base URL, for testing against a fake server
+    #[serde(default)]
+    compute_endpoint: Option<String>,
 }
 
 impl Default for GCPConfig {
@@ -27,23 +38,62 @@ impl Default for GCPConfig {
             credentials_file: PathBuf::new(),
             region: "us-west1".to_string(),
             zone: "us-west1-a".to_string(),
+            compute_endpoint: None,
         }
     }
 }
 
 pub struct GCPPlugin {
-    config: GCPConfig,
-    storage_client: Option<StorageClient>,
-    compute_client: Option<ComputeClient>,
+    config: RwLock<GCPConfig>,
+    /// Created on first use, and again after `configure` changes the project or credentials
+    storage_client: Mutex<Option<StorageClient>>,
+    http: reqwest::Client,
+    poll_interval: Duration,
+}
+
+/// Instance lifecycle actions `gcp compute` can issue; each returns a zonal operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InstanceAction {
+    Start,
+    Stop,
+    Reset,
+}
+
+impl InstanceAction {
+    fn parse(verb: &str) -> Option<Self> {
+        match verb {
+            "start" => Some(InstanceAction::Start),
+            "stop" => Some(InstanceAction::Stop),
+            "reset" => Some(InstanceAction::Reset),
+            _ => None,
+        }
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            InstanceAction::Start => "start",
+            InstanceAction::Stop => "stop",
+            InstanceAction::Reset => "reset",
+        }
+    }
+
+    fn done(self) -> &'static str {
+        match self {
+            InstanceAction::Start => "started",
+            InstanceAction::Stop => "stopped",
+            InstanceAction::Reset => "reset",
+        }
+    }
 }
 
 impl GCPPlugin {
     pub async fn new() -> Self {
-        let config = Self::load_config().unwrap_or_default();
+        let config = Self::load_config().await.unwrap_or_default();
         GCPPlugin {
-            config,
-            storage_client: None,
-            compute_client: None,
+            config: RwLock::new(config),
+            storage_client: Mutex::new(None),
+            http: reqwest::Client::new(),
+            poll_interval: POLL_INTERVAL,
         }
     }
 
@@ -63,47 +113,67 @@ impl GCPPlugin {
         }
     }
 
-    async fn init_clients(&mut self) -> Result<()> {
-        let creds = CredentialsFile::new_from_file(&self.config.credentials_file).await?;
-
-        // Initialize Storage Client
-        let storage_config = ClientConfig::default()
-            .with_credentials(creds.clone())
-            .with_project_id(&self.config.project_id);
-        self.storage_client = Some(StorageClient::new(storage_config).await?);
-
-        // Initialize Compute Client
-        let compute_config = google_cloud_compute::client::ClientConfig::default()
-            .with_credentials(creds)
-            .with_project_id(&self.config.project_id);
-        self.compute_client = Some(ComputeClient::new(compute_config).await?);
+    fn config(&self) -> GCPConfig {
+        self.config.read().unwrap().clone()
+    }
 
-        Ok(())
+    async fn credentials(&self) -> Result<CredentialsFile> {
+        let path = self.config.read().unwrap().credentials_file.clone();
+        CredentialsFile::new_from_file(path.to_string_lossy().to_string()).await
+            .context("Failed to read GCP credentials file")
     }
 
-    async fn list_instances(&self) -> Result<String> {
-        let client = self.compute_client.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Compute client not initialized"))?;
+    /// The Cloud Storage client, created on first use.
+    async fn storage_client(&self) -> Result<StorageClient> {
+        if let Some(client) = self.storage_client.lock().unwrap().clone() {
+            return Ok(client);
+        }
+        let config = ClientConfig::default().with_credentials(self.credentials().await?).await?;
+        let client = StorageClient::new(config);
+        *self.storage_client.lock().unwrap() = Some(client.clone());
+        Ok(client)
+    }
 
-        let request = ListInstancesRequest {
-            project: self.config.project_id.clone(),
-            zone: self.config.zone.clone(),
+    /// An OAuth access token for the Compute Engine API.
+    async fn compute_token(&self) -> Result<String> {
+        let creds = self.credentials().await?;
+        let config = google_cloud_auth::project::Config {
+            scopes: Some(&COMPUTE_SCOPES),
             ..Default::default()
         };
+        let token_source = google_cloud_auth::project::create_token_source_from_credentials(&creds, &config).await?;
+        Ok(token_source.token().await?.access_token)
+    }
+
+    /// The Compute Engine URL for the configured project and zone.
+    fn zone_url(&self) -> String {
+        let config = self.config.read().unwrap();
+        let endpoint = config.compute_endpoint.as_deref().unwrap_or(COMPUTE_ENDPOINT).trim_end_matches('/');
+        format!("{}/projects/{}/zones/{}", endpoint, config.project_id, config.zone)
+    }
+
+    /// GETs a Compute API resource, retrying transient failures.
+    async fn get_json(&self, url: &str, token: &str) -> Result<serde_json::Value> {
+        let response = retry(&RetryPolicy::default(), http_retryable, || async {
+            self.http.get(url).bearer_auth(token).send().await?.error_for_status()
+        }).await?;
+        Ok(response.json().await?)
+    }
 
-        let instances = client.list_instances(request).await?;
-        
+    async fn list_instances(&self) -> Result<String> {
+        let token = self.compute_token().await?;
+        let listing = self.get_json(&format!("{}/instances", self.zone_url()), &token).await?;
+
+        let field = |value: &serde_json::Value, name: &str| value.get(name).and_then(|v| v.as_str()).unwrap_or_default().to_string();
         let mut output = String::from("Compute Engine Instances:\n");
-        for instance in instances.items.unwrap_or_default() {
-            output.push_str(&format!("Name: {}\n", instance.name.unwrap_or_default()));
-            output.push_str(&format!("  Machine Type: {}\n", instance.machine_type.unwrap_or_default()));
-            output.push_str(&format!("  Status: {}\n", instance.status.unwrap_or_default()));
-            
-            if let Some(network_interfaces) = instance.network_interfaces {
-                for interface in network_interfaces {
-                    if let Some(ip) = interface.network_ip {
-                        output.push_str(&format!("  Network IP: {}\n", ip));
-                    }
+        for instance in listing.get("items").and_then(|items| items.as_array()).into_iter().flatten() {
+            output.push_str(&format!("Name: {}\n", field(instance, "name")));
+            output.push_str(&format!("  Machine Type: {}\n", field(instance, "machineType")));
+            output.push_str(&format!("  Status: {}\n", field(instance, "status")));
+
+            for interface in instance.get("networkInterfaces").and_then(|n| n.as_array()).into_iter().flatten() {
+                if let Some(ip) = interface.get("networkIP").and_then(|ip| ip.as_str()) {
+                    output.push_str(&format!("  Network IP: {}\n", ip));
                 }
             }
         }
@@ -111,19 +181,59 @@ impl GCPPlugin {
         Ok(output)
     }
 
+    async fn instance_action(&self, action: InstanceAction, name: &str) -> Result<String> {
+        let token = self.compute_token().await?;
+        self.run_instance_action(action, name, &token).await
+    }
+
+    /// Issues the action for `name` in the configured zone, then polls the zonal operation
+    /// it returns until it is DONE.
+    async fn run_instance_action(&self, action: InstanceAction, name: &str, token: &str) -> Result<String> {
+        let zone_url = self.zone_url();
+
+        let response = self.http.post(format!("{}/instances/{}/{}", zone_url, name, action.verb()))
+            .bearer_auth(token)
+            .header(reqwest::header::CONTENT_LENGTH, 0)
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow::anyhow!("Instance '{}' not found in zone {}", name, self.config().zone));
+        }
+        let mut operation: serde_json::Value = response.error_for_status()?.json().await?;
+
+        let message = format!("Waiting for {} of instance {}", action.verb(), name);
+        let operation = with_spinner(message, OPERATION_TIMEOUT, async move {
+            while operation.get("status").and_then(|s| s.as_str()) != Some("DONE") {
+                let id = operation.get("name").and_then(|n| n.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Compute API returned an operation without a name"))?;
+                tokio::time::sleep(self.poll_interval).await;
+                operation = self.get_json(&format!("{}/operations/{}", zone_url, id), token).await?;
+            }
+            Ok(operation)
+        }).await?;
+
+        if let Some(errors) = operation.pointer("/error/errors").and_then(|e| e.as_array()) {
+            let messages: Vec<&str> = errors.iter().filter_map(|e| e.get("message").and_then(|m| m.as_str())).collect();
+            return Err(anyhow::anyhow!("Failed to {} instance {}: {}", action.verb(), name, messages.join("; ")));
+        }
+        Ok(format!("Instance {} {}", name, action.done()))
+    }
+
     async fn list_buckets(&self) -> Result<String> {
-        let client = self.storage_client.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Storage client not initialized"))?;
+        let client = self.storage_client().await?;
+        let request = ListBucketsRequest {
+            project: self.config().project_id,
+            ..Default::default()
+        };
+        let buckets = client.list_buckets(&request).await?;
 
-        let buckets = client.list_buckets().await?;
-        
         let mut output = String::from("Storage Buckets:\n");
-        for bucket in buckets {
-            output.push_str(&format!("Name: {}\n", bucket.name()));
-            output.push_str(&format!("  Location: {}\n", bucket.location().unwrap_or("Unknown")));
-            output.push_str(&format!("  Storage Class: {}\n", bucket.storage_class().unwrap_or("Unknown")));
-            
-            if let Some(created) = bucket.time_created() {
+        for bucket in buckets.items {
+            output.push_str(&format!("Name: {}\n", bucket.name));
+            output.push_str(&format!("  Location: {}\n", bucket.location));
+            output.push_str(&format!("  Storage Class: {}\n", bucket.storage_class));
+
+            if let Some(created) = bucket.time_created {
                 output.push_str(&format!("  Created: {}\n", created));
             }
         }
@@ -131,48 +241,72 @@ impl GCPPlugin {
         Ok(output)
     }
 
-    async fn upload_object(&self, bucket_name: &str, object_name: &str, file_path: &PathBuf) -> Result<String> {
-        let client = self.storage_client.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Storage client not initialized"))?;
+    async fn upload_object(&self, bucket_name: &str, object_name: &str, file_path: &Path) -> Result<String> {
+        let client = self.storage_client().await?;
 
         let file_size = fs::metadata(file_path).await?.len();
         let pb = ProgressBar::new(file_size);
         pb.set_style(ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
             .progress_chars("#>-"));
 
-        let mut file = fs::File::open(file_path).await?;
-        client.upload_object(
-            &bucket_name,
-            &object_name,
-            "application/octet-stream",
-            &mut file,
-        ).await?;
+        let data = fs::read(file_path).await?;
+        let request = UploadObjectRequest {
+            bucket: bucket_name.to_string(),
+            ..Default::default()
+        };
+        let mut media = Media::new(object_name.to_string());
+        media.content_length = Some(file_size);
+        client.upload_object(&request, data, &UploadType::Simple(media)).await?;
 
         pb.finish_with_message("Upload complete");
         Ok(format!("Successfully uploaded {} to gs://{}/{}", file_path.display(), bucket_name, object_name))
     }
 
-    async fn download_object(&self, bucket_name: &str, object_name: &str, file_path: &PathBuf) -> Result<String> {
-        let client = self.storage_client.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Storage client not initialized"))?;
+    async fn download_object(&self, bucket_name: &str, object_name: &str, file_path: &Path) -> Result<String> {
+        let client = self.storage_client().await?;
+
+        let request = GetObjectRequest {
+            bucket: bucket_name.to_string(),
+            object: object_name.to_string(),
+            ..Default::default()
+        };
+        let object = client.get_object(&request).await?;
 
-        let object = client.get_object(bucket_name, object_name).await?;
-        let size = object.size.unwrap_or(0) as u64;
-        
-        let pb = ProgressBar::new(size);
+        let pb = ProgressBar::new(object.size.max(0) as u64);
         pb.set_style(ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
             .progress_chars("#>-"));
 
-        let mut file = fs::File::create(file_path).await?;
-        client.download_object(bucket_name, object_name, &mut file).await?;
+        let data = client.download_object(&request, &Range::default()).await?;
+        fs::write(file_path, data).await?;
 
         pb.finish_with_message("Download complete");
         Ok(format!("Successfully downloaded gs://{}/{} to {}", bucket_name, object_name, file_path.display()))
     }
 }
 
+/// Splits `gs://bucket/object`.
+fn parse_gcs_url(url: &str) -> Result<(&str, &str)> {
+    url.strip_prefix("gs://")
+        .and_then(|rest| rest.split_once('/'))
+        .filter(|(bucket, object)| !bucket.is_empty() && !object.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Invalid GCS URL '{}', expected gs://bucket/object", url))
+}
+
+#[async_trait]
+impl CloudStorageProvider for GCPPlugin {
+    async fn upload_file(&self, source: &Path, destination: &str) -> Result<String> {
+        let (bucket, object) = parse_gcs_url(destination)?;
+        self.upload_object(bucket, object, source).await
+    }
+
+    async fn download_file(&self, source: &str, destination: &Path) -> Result<String> {
+        let (bucket, object) = parse_gcs_url(source)?;
+        self.download_object(bucket, object, destination).await
+    }
+}
+
 #[async_trait]
 impl Plugin for GCPPlugin {
     fn name(&self) -> &str {
@@ -192,21 +326,23 @@ impl Plugin for GCPPlugin {
                 let setting = &command.args[1];
                 let value = &command.args[2];
                 
-                match *setting {
+                match setting.as_str() {
                     "project" => {
-                        self.config.project_id = value.to_string();
+                        self.config.write().unwrap().project_id = value.to_string();
+                        *self.storage_client.lock().unwrap() = None;
                         Ok("Project ID updated successfully".to_string())
                     }
                     "credentials" => {
-                        self.config.credentials_file = PathBuf::from(value);
+                        self.config.write().unwrap().credentials_file = PathBuf::from(value);
+                        *self.storage_client.lock().unwrap() = None;
                         Ok("Credentials file updated successfully".to_string())
                     }
                     "region" => {
-                        self.config.region = value.to_string();
+                        self.config.write().unwrap().region = value.to_string();
                         Ok("Region updated successfully".to_string())
                     }
                     "zone" => {
-                        self.config.zone = value.to_string();
+                        self.config.write().unwrap().zone = value.to_string();
                         Ok("Zone updated successfully".to_string())
                     }
                     _ => Err(anyhow::anyhow!("Invalid configuration setting"))
@@ -216,7 +352,14 @@ impl Plugin for GCPPlugin {
             Some("compute") => {
                 match command.args.get(1).map(|s| s.as_str()) {
                     Some("list") => self.list_instances().await,
-                    _ => Ok("Available compute commands: list".to_string()),
+                    Some(verb) if InstanceAction::parse(verb).is_some() => {
+                        let name = match command.args.get(2) {
+                            Some(name) => name,
+                            None => return Ok(format!("Usage: gcp compute {} <instance>", verb)),
+                        };
+                        self.instance_action(InstanceAction::parse(verb).unwrap(), name).await
+                    }
+                    _ => Ok("Available compute commands: list, start, stop, reset".to_string()),
                 }
             }
 
@@ -230,9 +373,9 @@ impl Plugin for GCPPlugin {
                         let source = &command.args[2];
                         let dest = &command.args[3];
 
-                        if source.starts_with("gs://") {
+                        if let Some(url) = source.strip_prefix("gs://") {
                             // Download from GCS
-                            let parts: Vec<&str> = source[5..].splitn(2, '/').collect();
+                            let parts: Vec<&str> = url.splitn(2, '/').collect();
                             if parts.len() != 2 {
                                 return Err(anyhow::anyhow!("Invalid GCS URL"));
                             }
@@ -254,3 +397,62 @@ impl Plugin for GCPPlugin {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::mock_http::{self, Reply};
+    use std::sync::{Arc, Mutex};
+
+    /// A fake Compute Engine API that knows one instance, `vm-1`. Actions return a RUNNING
+    /// operation that is DONE on the first poll. Records each request line.
+    async fn spawn_compute_fake() -> (String, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let base = mock_http::serve(move |line| {
+            recorded.lock().unwrap().push(line.to_string());
+            let path = line.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = if path.contains("/operations/") {
+                ("200 OK", r#"{"name":"op-1","status":"DONE"}"#)
+            } else if path.contains("/instances/vm-1/") {
+                ("200 OK", r#"{"name":"op-1","status":"RUNNING"}"#)
+            } else {
+                ("404 Not Found", r#"{"error":{"code":404,"message":"not found"}}"#)
+            };
+            Reply::new(status, body).header("Content-Type", "application/json")
+        }).await;
+
+        (base, requests)
+    }
+
+    #[tokio::test]
+    async fn test_instance_actions_target_instance_and_zone() {
+        let (endpoint, requests) = spawn_compute_fake().await;
+        let plugin = GCPPlugin {
+            config: RwLock::new(GCPConfig {
+                project_id: "proj".to_string(),
+                zone: "europe-west1-b".to_string(),
+                compute_endpoint: Some(endpoint),
+                ..Default::default()
+            }),
+            storage_client: Mutex::new(None),
+            http: reqwest::Client::new(),
+            poll_interval: Duration::ZERO,
+        };
+
+        for verb in ["start", "stop", "reset"] {
+            let action = InstanceAction::parse(verb).unwrap();
+            let output = plugin.run_instance_action(action, "vm-1", "token").await.unwrap();
+            assert_eq!(output, format!("Instance vm-1 {}", action.done()));
+
+            let requests = std::mem::take(&mut *requests.lock().unwrap());
+            assert_eq!(requests, vec![
+                format!("POST /projects/proj/zones/europe-west1-b/instances/vm-1/{} HTTP/1.1", verb),
+                "GET /projects/proj/zones/europe-west1-b/operations/op-1 HTTP/1.1".to_string(),
+            ]);
+        }
+
+        let err = plugin.run_instance_action(InstanceAction::Stop, "missing", "token").await.unwrap_err();
+        assert_eq!(err.to_string(), "Instance 'missing' not found in zone europe-west1-b");
+    }
+}
@@ -1,28 +1,87 @@
 mod aws;
 mod azure;
 mod gcp;
+mod sync;
 
 pub use aws::AWSPlugin;
 pub use azure::AzurePlugin;
 pub use gcp::GCPPlugin;
 
 use async_trait::async_trait;
-use super::super::{Command, Environment};
+use super::super::{Command, Environment, Plugin};
 use anyhow::Result;
-use std::path::PathBuf;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long `azure vm` and `gcp compute` wait for an operation before giving up.
+const OPERATION_TIMEOUT: Duration = Duration::from_secs(600);
 
 #[async_trait]
 pub trait CloudStorageProvider: Send + Sync {
-    async fn upload_file(&self, source: &PathBuf, destination: &str) -> Result<String>;
-    async fn download_file(&self, source: &str, destination: &PathBuf) -> Result<String>;
-    async fn list_storage(&self) -> Result<String>;
+    async fn upload_file(&self, source: &Path, destination: &str) -> Result<String>;
+    async fn download_file(&self, source: &str, destination: &Path) -> Result<String>;
 }
 
-#[async_trait]
-pub trait CloudComputeProvider: Send + Sync {
-    async fn list_instances(&self) -> Result<String>;
-    async fn start_instance(&self, instance_id: &str) -> Result<String>;
-    async fn stop_instance(&self, instance_id: &str) -> Result<String>;
+/// Shows a spinner with `message` until `operation` finishes or `timeout` passes. The
+/// spinner is cleared either way, including when the operation bails out with an error.
+async fn with_spinner<T>(message: String, timeout: Duration, operation: impl Future<Output = Result<T>>) -> Result<T> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner()
+        .template("{spinner:.green} [{elapsed_precise}] {msg}")?
+        .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈"));
+    pb.set_message(message.clone());
+    pb.enable_steady_tick(Duration::from_millis(100));
+
+    let result = tokio::time::timeout(timeout, operation).await;
+    pb.finish_and_clear();
+    result.map_err(|_| anyhow::anyhow!("{}: timed out after {}s", message, timeout.as_secs()))?
+}
+
+/// Where one side of a `cloud cp` lives, decided by its URL scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageLocation {
+    Local,
+    S3,
+    Gcs,
+    Azure,
+}
+
+impl StorageLocation {
+    pub fn of(path: &str) -> Self {
+        if path.starts_with("s3://") {
+            StorageLocation::S3
+        } else if path.starts_with("gs://") {
+            StorageLocation::Gcs
+        } else if path.starts_with("az://") {
+            StorageLocation::Azure
+        } else {
+            StorageLocation::Local
+        }
+    }
+}
+
+/// How `cloud cp` moves an object between two locations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyRoute {
+    Upload(StorageLocation),
+    Download(StorageLocation),
+    /// Cloud to cloud, staged through a local temp file
+    Transfer(StorageLocation, StorageLocation),
+}
+
+impl CopyRoute {
+    pub fn between(source: &str, destination: &str) -> Result<Self> {
+        match (StorageLocation::of(source), StorageLocation::of(destination)) {
+            (StorageLocation::Local, StorageLocation::Local) => {
+                Err(anyhow::anyhow!("Neither path is a cloud URL (s3://, gs://, az://); use cp for local copies"))
+            }
+            (StorageLocation::Local, to) => Ok(CopyRoute::Upload(to)),
+            (from, StorageLocation::Local) => Ok(CopyRoute::Download(from)),
+            (from, to) => Ok(CopyRoute::Transfer(from, to)),
+        }
+    }
 }
 
 pub struct CloudManager {
@@ -40,24 +99,106 @@ impl CloudManager {
         }
     }
 
-    pub fn get_aws(&mut self) -> &mut AWSPlugin {
-        &mut self.aws
+    fn storage(&self, location: StorageLocation) -> &dyn CloudStorageProvider {
+        match location {
+            StorageLocation::S3 => &self.aws,
+            StorageLocation::Gcs => &self.gcp,
+            StorageLocation::Azure => &self.azure,
+            StorageLocation::Local => unreachable!("local paths have no storage provider"),
+        }
     }
 
-    pub fn get_azure(&mut self) -> &mut AzurePlugin {
-        &mut self.azure
+    /// `cloud cp <src> <dst>`: copies between local paths and s3://, gs:// or az:// URLs.
+    pub async fn copy(&self, source: &str, destination: &str, env: &Environment) -> Result<String> {
+        match CopyRoute::between(source, destination)? {
+            CopyRoute::Upload(to) => self.storage(to).upload_file(&env.expand_path(source), destination).await,
+            CopyRoute::Download(from) => {
+                let mut target = env.expand_path(destination);
+                if target.is_dir() {
+                    let name = source.rsplit('/').next().filter(|name| !name.is_empty())
+                        .ok_or_else(|| anyhow::anyhow!("Cannot name a local file after {}", source))?;
+                    target.push(name);
+                }
+                self.storage(from).download_file(source, &target).await
+            }
+            CopyRoute::Transfer(from, to) => {
+                let staging = std::env::temp_dir().join(format!("nexusshell-cloud-cp-{}", uuid::Uuid::new_v4()));
+                let result = async {
+                    self.storage(from).download_file(source, &staging).await?;
+                    self.storage(to).upload_file(&staging, destination).await
+                }.await;
+                let _ = tokio::fs::remove_file(&staging).await;
+                result.map(|_| format!("Copied {} to {}", source, destination))
+            }
+        }
+    }
+}
+
+/// Owns the three providers so `aws configure` and friends also apply to `cloud cp`.
+#[async_trait]
+impl Plugin for CloudManager {
+    fn name(&self) -> &str {
+        "cloud"
     }
 
-    pub fn get_gcp(&mut self) -> &mut GCPPlugin {
-        &mut self.gcp
+    fn description(&self) -> &str {
+        "Cloud storage copies and AWS, Azure and GCP management"
     }
 
-    pub async fn execute(&self, provider: &str, command: &Command, env: &Environment) -> Result<String> {
-        match provider {
+    fn commands(&self) -> Vec<&str> {
+        vec!["cloud", "aws", "azure", "gcp"]
+    }
+
+    async fn execute(&self, command: &Command, env: &Environment) -> Result<String> {
+        match command.name.as_str() {
             "aws" => self.aws.execute(command, env).await,
             "azure" => self.azure.execute(command, env).await,
             "gcp" => self.gcp.execute(command, env).await,
-            _ => Err(anyhow::anyhow!("Unknown cloud provider")),
+            _ => match command.args.as_slice() {
+                [cp, source, destination] if cp == "cp" => self.copy(source, destination, env).await,
+                _ => Ok("Usage: cloud cp <source> <destination> (s3://, gs://, az:// or a local path)".to_string()),
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_routes() {
+        use StorageLocation::*;
+        let route = |source: &str, destination: &str| CopyRoute::between(source, destination).unwrap();
+
+        assert_eq!(route("./report.csv", "s3://b/k"), CopyRoute::Upload(S3));
+        assert_eq!(route("/tmp/report.csv", "gs://b/o"), CopyRoute::Upload(Gcs));
+        assert_eq!(route("report.csv", "az://acct/c/blob"), CopyRoute::Upload(Azure));
+        assert_eq!(route("s3://b/k", "."), CopyRoute::Download(S3));
+        assert_eq!(route("gs://b/o", "./copy.csv"), CopyRoute::Download(Gcs));
+        assert_eq!(route("az://acct/c/blob", "/tmp/blob"), CopyRoute::Download(Azure));
+        assert_eq!(route("s3://b/k", "gs://b/o"), CopyRoute::Transfer(S3, Gcs));
+        assert_eq!(route("gs://b/o", "az://acct/c/blob"), CopyRoute::Transfer(Gcs, Azure));
+        assert_eq!(route("az://acct/c/blob", "s3://b/k"), CopyRoute::Transfer(Azure, S3));
+        assert_eq!(route("s3://a/k", "s3://b/k"), CopyRoute::Transfer(S3, S3));
+
+        assert!(CopyRoute::between("./a", "./b").is_err());
+        // Only the scheme prefix counts
+        assert!(CopyRoute::between("/tmp/s3://not-a-url", "./b").is_err());
+        assert_eq!(route("S3://b/k", "gs://b/o"), CopyRoute::Upload(Gcs));
+    }
+
+    #[tokio::test]
+    async fn test_with_spinner_gives_up_at_the_deadline() {
+        let done = with_spinner("done".to_string(), Duration::from_secs(5), async { Ok(7) }).await;
+        assert_eq!(done.unwrap(), 7);
+
+        let failed = with_spinner("failing".to_string(), Duration::from_secs(5), async {
+            Err::<(), _>(anyhow::anyhow!("quota exceeded"))
+        }).await;
+        assert_eq!(failed.unwrap_err().to_string(), "quota exceeded");
+
+        let stuck = with_spinner("stuck".to_string(), Duration::from_millis(20), std::future::pending::<Result<()>>()).await;
+        assert_eq!(stuck.unwrap_err().to_string(), "stuck: timed out after 0s");
+    }
+}
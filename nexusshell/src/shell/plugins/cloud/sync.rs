@@ -0,0 +1,137 @@
+use anyhow::Result;
+use md5::{Digest, Md5};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+/// What `sync` compares: size always, content hash when both sides have one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileState {
+    pub size: u64,
+    /// Hex MD5 of the content. S3 multipart ETags (`<hash>-<parts>`) are not content hashes
+    /// and are left out.
+    pub etag: Option<String>,
+}
+
+impl FileState {
+    /// The state of an S3 object from its listing entry.
+    pub fn remote(size: u64, etag: Option<&str>) -> Self {
+        let etag = etag
+            .map(|etag| etag.trim_matches('"').to_lowercase())
+            .filter(|etag| !etag.contains('-'));
+        FileState { size, etag }
+    }
+
+    fn differs_from(&self, other: &FileState) -> bool {
+        if self.size != other.size {
+            return true;
+        }
+        match (&self.etag, &other.etag) {
+            (Some(a), Some(b)) => a != b,
+            _ => false,
+        }
+    }
+}
+
+/// The work one `sync` run has to do, as paths relative to the synced roots.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SyncPlan {
+    pub transfer: Vec<String>,
+    pub delete: Vec<String>,
+    pub unchanged: usize,
+}
+
+/// Transfers whatever is missing or different at the destination. With `delete`, destination
+/// entries missing from the source are removed too.
+pub fn plan(source: &BTreeMap<String, FileState>, destination: &BTreeMap<String, FileState>, delete: bool) -> SyncPlan {
+    let mut plan = SyncPlan::default();
+    for (path, state) in source {
+        match destination.get(path) {
+            Some(existing) if !state.differs_from(existing) => plan.unchanged += 1,
+            _ => plan.transfer.push(path.clone()),
+        }
+    }
+    if delete {
+        plan.delete = destination.keys()
+            .filter(|path| !source.contains_key(*path))
+            .cloned()
+            .collect();
+    }
+    plan
+}
+
+/// Every file under `root`, keyed by its `/`-separated relative path.
+pub async fn local_files(root: &Path) -> Result<BTreeMap<String, FileState>> {
+    let mut files = BTreeMap::new();
+    if !root.exists() {
+        return Ok(files);
+    }
+
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() {
+                let relative = path.strip_prefix(root)?
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let state = FileState {
+                    size: entry.metadata().await?.len(),
+                    etag: Some(md5_hex(&path).await?),
+                };
+                files.insert(relative, state);
+            }
+        }
+    }
+    Ok(files)
+}
+
+async fn md5_hex(path: &Path) -> Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_plan_skips_unchanged_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(temp_dir.path().join("docs")).unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "hello").unwrap();
+        std::fs::write(temp_dir.path().join("docs/b.txt"), "world").unwrap();
+        let local = local_files(temp_dir.path()).await.unwrap();
+        assert_eq!(local.keys().collect::<Vec<_>>(), vec!["a.txt", "docs/b.txt"]);
+        assert_eq!(local["a.txt"].etag.as_deref(), Some("5d41402abc4b2a76b9719d911017c592"));
+
+        let remote = BTreeMap::from([
+            ("a.txt".to_string(), FileState::remote(5, Some("\"5d41402abc4b2a76b9719d911017c592\""))),
+            ("docs/b.txt".to_string(), FileState::remote(5, Some("\"00000000000000000000000000000000\""))),
+            ("stale.txt".to_string(), FileState::remote(1, None)),
+        ]);
+
+        let sync = plan(&local, &remote, false);
+        assert_eq!(sync, SyncPlan { transfer: vec!["docs/b.txt".to_string()], delete: Vec::new(), unchanged: 1 });
+        assert_eq!(plan(&local, &remote, true).delete, vec!["stale.txt".to_string()]);
+
+        // A multipart ETag can't be compared, so an equal size counts as unchanged
+        let multipart = BTreeMap::from([("a.txt".to_string(), FileState::remote(5, Some("\"abc-2\"")))]);
+        assert_eq!(plan(&local, &multipart, false).transfer, vec!["docs/b.txt".to_string()]);
+    }
+}
@@ -0,0 +1,236 @@
+use super::super::super::split_words;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Label identifying which compose project a container belongs to; `down` filters on it.
+pub const PROJECT_LABEL: &str = "nexusshell.compose.project";
+pub const SERVICE_LABEL: &str = "nexusshell.compose.service";
+
+pub const DEFAULT_COMPOSE_FILE: &str = "docker-compose.yml";
+
+#[derive(Debug, Deserialize)]
+pub struct ComposeFile {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub services: BTreeMap<String, ComposeService>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ComposeService {
+    pub image: String,
+    #[serde(default)]
+    pub container_name: Option<String>,
+    #[serde(default)]
+    pub command: Option<StringOrList>,
+    #[serde(default)]
+    pub ports: Vec<String>,
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    #[serde(default)]
+    pub environment: Option<ListOrMap>,
+    #[serde(default)]
+    pub depends_on: Option<ListOrMap>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum StringOrList {
+    String(String),
+    List(Vec<String>),
+}
+
+/// Compose accepts both `- KEY=value` lists and `KEY: value` maps for `environment`,
+/// and both plain lists and condition maps for `depends_on`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ListOrMap {
+    List(Vec<String>),
+    Map(BTreeMap<String, Option<serde_yaml::Value>>),
+}
+
+/// Everything needed to create one service's container.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerPlan {
+    pub service: String,
+    pub name: String,
+    pub image: String,
+    pub command: Option<Vec<String>>,
+    pub ports: Vec<String>,
+    pub volumes: Vec<String>,
+    pub env: Vec<String>,
+    pub labels: HashMap<String, String>,
+}
+
+impl ComposeFile {
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path).await
+            .with_context(|| format!("Failed to read compose file {}", path.display()))?;
+        Self::parse(&content)
+            .with_context(|| format!("Invalid compose file {}", path.display()))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(content)?)
+    }
+
+    /// The `name:` field if present, otherwise the compose file's directory name.
+    pub fn project_name(&self, path: &Path) -> String {
+        let raw = self.name.clone().unwrap_or_else(|| {
+            path.canonicalize().ok()
+                .and_then(|p| p.parent().and_then(|d| d.file_name()).map(|n| n.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| "default".to_string())
+        });
+        raw.to_lowercase()
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect()
+    }
+
+    /// Services ordered so that each comes after everything it `depends_on`.
+    pub fn startup_order(&self) -> Result<Vec<&str>> {
+        fn visit<'a>(
+            file: &'a ComposeFile,
+            name: &'a str,
+            visiting: &mut Vec<&'a str>,
+            order: &mut Vec<&'a str>,
+        ) -> Result<()> {
+            if order.contains(&name) {
+                return Ok(());
+            }
+            if visiting.contains(&name) {
+                return Err(anyhow::anyhow!("Dependency cycle: {} -> {}", visiting.join(" -> "), name));
+            }
+            let service = &file.services[name];
+            visiting.push(name);
+            for dependency in service.dependencies() {
+                let (dependency, _) = file.services.get_key_value(dependency)
+                    .ok_or_else(|| anyhow::anyhow!("Service '{}' depends on undefined service '{}'", name, dependency))?;
+                visit(file, dependency, visiting, order)?;
+            }
+            visiting.pop();
+            order.push(name);
+            Ok(())
+        }
+
+        let mut order = Vec::new();
+        for name in self.services.keys() {
+            visit(self, name, &mut Vec::new(), &mut order)?;
+        }
+        Ok(order)
+    }
+
+    /// One container per service, in startup order.
+    pub fn plan(&self, project: &str) -> Result<Vec<ContainerPlan>> {
+        Ok(self.startup_order()?
+            .into_iter()
+            .map(|name| {
+                let service = &self.services[name];
+                let labels = HashMap::from([
+                    (PROJECT_LABEL.to_string(), project.to_string()),
+                    (SERVICE_LABEL.to_string(), name.to_string()),
+                ]);
+                ContainerPlan {
+                    service: name.to_string(),
+                    name: service.container_name.clone()
+                        .unwrap_or_else(|| format!("{}-{}-1", project, name)),
+                    image: service.image.clone(),
+                    command: service.command.as_ref().map(|command| match command {
+                        StringOrList::String(s) => split_words(s),
+                        StringOrList::List(list) => list.clone(),
+                    }),
+                    ports: service.ports.clone(),
+                    volumes: service.volumes.clone(),
+                    env: service.env(),
+                    labels,
+                }
+            })
+            .collect())
+    }
+}
+
+impl ComposeService {
+    fn dependencies(&self) -> Vec<&str> {
+        match &self.depends_on {
+            Some(ListOrMap::List(list)) => list.iter().map(String::as_str).collect(),
+            Some(ListOrMap::Map(map)) => map.keys().map(String::as_str).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn env(&self) -> Vec<String> {
+        match &self.environment {
+            Some(ListOrMap::List(list)) => list.clone(),
+            Some(ListOrMap::Map(map)) => map.iter()
+                .map(|(key, value)| match value {
+                    Some(serde_yaml::Value::String(s)) => format!("{}={}", key, s),
+                    Some(serde_yaml::Value::Null) | None => format!("{}=", key),
+                    Some(other) => format!("{}={}", key, serde_yaml::to_string(other).unwrap_or_default().trim()),
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_SERVICES: &str = r#"
+services:
+  web:
+    image: nginx:1.25
+    command: nginx -g "daemon off;"
+    ports:
+      - "8080:80"
+    volumes:
+      - ./site:/usr/share/nginx/html
+    environment:
+      MODE: production
+      WORKERS: 4
+    depends_on:
+      - db
+  db:
+    image: postgres:16
+    container_name: app-db
+    environment:
+      - POSTGRES_PASSWORD=secret
+"#;
+
+    #[test]
+    fn test_plan_two_services() {
+        let file = ComposeFile::parse(TWO_SERVICES).unwrap();
+        let plan = file.plan("app").unwrap();
+
+        assert_eq!(plan.iter().map(|p| p.service.as_str()).collect::<Vec<_>>(), vec!["db", "web"]);
+
+        let db = &plan[0];
+        assert_eq!(db.name, "app-db");
+        assert_eq!(db.image, "postgres:16");
+        assert_eq!(db.command, None);
+        assert_eq!(db.env, vec!["POSTGRES_PASSWORD=secret"]);
+
+        let web = &plan[1];
+        assert_eq!(web.name, "app-web-1");
+        assert_eq!(web.ports, vec!["8080:80"]);
+        assert_eq!(web.volumes, vec!["./site:/usr/share/nginx/html"]);
+        assert_eq!(web.env, vec!["MODE=production", "WORKERS=4"]);
+        assert_eq!(web.command, Some(vec!["nginx".to_string(), "-g".to_string(), "daemon off;".to_string()]));
+        assert_eq!(web.labels[PROJECT_LABEL], "app");
+        assert_eq!(web.labels[SERVICE_LABEL], "web");
+    }
+
+    #[test]
+    fn test_plan_rejects_bad_dependencies() {
+        let missing = ComposeFile::parse("services:\n  web:\n    image: nginx\n    depends_on: [cache]\n").unwrap();
+        assert!(missing.plan("app").is_err());
+
+        let cycle = ComposeFile::parse(
+            "services:\n  a:\n    image: x\n    depends_on: [b]\n  b:\n    image: x\n    depends_on:\n      a:\n        condition: service_started\n",
+        ).unwrap();
+        assert!(cycle.plan("app").unwrap_err().to_string().contains("cycle"));
+    }
+}
@@ -1,39 +1,96 @@
 use async_trait::async_trait;
-use super::super::super::{Command, Environment, Plugin};
+use super::super::super::{split_words, Command, Environment, Plugin, ShellError, Table};
+use super::super::super::tty::{poll_terminal, RawMode, TerminalInput};
+use super::super::super::render::{OutputFormat, Record};
+use super::super::super::util::{docker_retryable, retry, RetryPolicy};
+use super::compose::{ComposeFile, ContainerPlan, DEFAULT_COMPOSE_FILE, PROJECT_LABEL, SERVICE_LABEL};
 use bollard::Docker;
 use bollard::container::{CreateContainerOptions, Config, ListContainersOptions, StartContainerOptions, StopContainerOptions, RemoveContainerOptions};
 use bollard::image::{CreateImageOptions, ListImagesOptions, RemoveImageOptions};
-use bollard::service::{ContainerSummary, ImageSummary, ContainerInspectResponse};
-use bollard::exec::{CreateExecOptions, StartExecOptions};
-use bollard::network::ListNetworksOptions;
-use bollard::volume::ListVolumesOptions;
+use bollard::service::{ContainerSummary, ContainerInspectResponse};
+use bollard::exec::{CreateExecOptions, ResizeExecOptions, StartExecOptions, StartExecResults};
 use futures::StreamExt;
 use std::collections::HashMap;
 use anyhow::{Result, Context};
 use tokio::fs;
 use serde::{Serialize, Deserialize};
 use indicatif::{ProgressBar, ProgressStyle};
-use chrono::{DateTime, Utc};
 use std::time::Duration;
 use colored::*;
+use crossterm::terminal;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::AsyncWriteExt;
+
+/// One row of `docker ps`.
+#[derive(Debug, Serialize)]
+struct ContainerInfo {
+    id: String,
+    status: String,
+    ports: Vec<String>,
+    names: Vec<String>,
+    image: String,
+}
+
+impl From<ContainerSummary> for ContainerInfo {
+    fn from(container: ContainerSummary) -> Self {
+        ContainerInfo {
+            id: container.id.unwrap_or_default(),
+            status: container.status.unwrap_or_default(),
+            ports: container.ports.unwrap_or_default()
+                .iter()
+                .map(|p| format!("{}:{}", p.private_port, p.public_port.unwrap_or(0)))
+                .collect(),
+            names: container.names.unwrap_or_default(),
+            image: container.image.unwrap_or_default(),
+        }
+    }
+}
+
+impl Record for ContainerInfo {
+    fn headers() -> &'static [&'static str] {
+        &["CONTAINER ID", "STATUS", "PORTS", "NAME", "IMAGE"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.chars().take(12).collect(),
+            self.status.clone(),
+            self.ports.join(", "),
+            self.names.join(", "),
+            self.image.clone(),
+        ]
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 struct DockerConfig {
-    default_registry: String,
+    /// Seconds an image pull may take
     pull_timeout: u64,
-    push_timeout: u64,
 }
 
 impl Default for DockerConfig {
     fn default() -> Self {
         DockerConfig {
-            default_registry: "docker.io".to_string(),
             pull_timeout: 300,
-            push_timeout: 300,
         }
     }
 }
 
+/// What `create_container` sets beyond the name and image; empty fields are left unset.
+#[derive(Debug, Default)]
+struct ContainerSpec {
+    command: Option<Vec<String>>,
+    /// `host:container` port pairs
+    ports: Vec<String>,
+    /// `host:container` bind mounts
+    volumes: Vec<String>,
+    /// `KEY=VALUE` pairs
+    env: Vec<String>,
+    labels: HashMap<String, String>,
+}
+
 pub struct DockerPlugin {
     config: DockerConfig,
     client: Docker,
@@ -66,34 +123,17 @@ impl DockerPlugin {
         }
     }
 
-    async fn list_containers(&self, all: bool) -> Result<String> {
-        let options = ListContainersOptions {
+    async fn list_containers(&self, all: bool, format: OutputFormat) -> Result<String> {
+        let options = ListContainersOptions::<String> {
             all,
             ..Default::default()
         };
 
-        let containers = self.client.list_containers(Some(options)).await?;
-        let mut output = String::new();
-        output.push_str(&format!("{}\n", "CONTAINERS".bright_green()));
-        output.push_str(&format!("{:<20} {:<15} {:<20} {:<15} {:<30}\n",
-            "CONTAINER ID", "STATUS", "PORTS", "NAME", "IMAGE"));
-
-        for container in containers {
-            let id = container.id.unwrap_or_default();
-            let status = container.status.unwrap_or_default();
-            let name = container.names.unwrap_or_default().join(", ");
-            let image = container.image.unwrap_or_default();
-            let ports = container.ports.unwrap_or_default()
-                .iter()
-                .map(|p| format!("{}:{}", p.private_port.unwrap_or(0), p.public_port.unwrap_or(0)))
-                .collect::<Vec<_>>()
-                .join(", ");
-
-            output.push_str(&format!("{:<20} {:<15} {:<20} {:<15} {:<30}\n",
-                &id[..12], status, ports, name, image));
-        }
-
-        Ok(output)
+        let containers = retry(&RetryPolicy::default(), docker_retryable, || {
+            self.client.list_containers(Some(options.clone()))
+        }).await?;
+        let containers: Vec<ContainerInfo> = containers.into_iter().map(ContainerInfo::from).collect();
+        format.render(&containers)
     }
 
     async fn pull_image(&self, image: &str) -> Result<String> {
@@ -104,63 +144,69 @@ impl DockerPlugin {
 
         let pb = ProgressBar::new_spinner();
         pb.set_style(ProgressStyle::default_spinner()
-            .template("{spinner:.green} [{elapsed_precise}] {msg}")
+            .template("{spinner:.green} [{elapsed_precise}] {msg}")?
             .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈"));
         pb.set_message(format!("Pulling image {}", image));
 
         let mut stream = self.client.create_image(Some(options), None, None);
-        while let Some(result) = stream.next().await {
-            match result {
-                Ok(info) => {
-                    if let Some(status) = info.status {
-                        pb.set_message(status);
-                    }
-                }
-                Err(e) => {
-                    pb.finish_with_message("Pull failed");
-                    return Err(anyhow::anyhow!("Failed to pull image: {}", e));
+        let pull = async {
+            while let Some(result) = stream.next().await {
+                if let Some(status) = result?.status {
+                    pb.set_message(status);
                 }
             }
+            Ok::<_, bollard::errors::Error>(())
+        };
+        let outcome = tokio::time::timeout(Duration::from_secs(self.config.pull_timeout), pull).await;
+        match outcome {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                pb.finish_with_message("Pull failed");
+                return Err(anyhow::anyhow!("Failed to pull image: {}", e));
+            }
+            Err(_) => {
+                pb.finish_with_message("Pull timed out");
+                return Err(anyhow::anyhow!("Pulling {} took longer than {}s", image, self.config.pull_timeout));
+            }
         }
 
         pb.finish_with_message(format!("Successfully pulled {}", image));
         Ok(format!("Successfully pulled image {}", image))
     }
 
-    async fn create_container(&self, name: &str, image: &str, command: Option<Vec<String>>, ports: Option<Vec<String>>, volumes: Option<Vec<String>>) -> Result<String> {
+    async fn create_container(&self, name: &str, image: &str, spec: ContainerSpec) -> Result<String> {
         let mut port_bindings = HashMap::new();
-        if let Some(port_mappings) = ports {
-            for port in port_mappings {
-                let parts: Vec<&str> = port.split(':').collect();
-                if parts.len() == 2 {
-                    port_bindings.insert(
-                        format!("{}/tcp", parts[1]),
-                        Some(vec![bollard::models::PortBinding {
-                            host_ip: Some("0.0.0.0".to_string()),
-                            host_port: Some(parts[0].to_string()),
-                        }]),
-                    );
-                }
+        for port in &spec.ports {
+            let parts: Vec<&str> = port.split(':').collect();
+            if parts.len() == 2 {
+                port_bindings.insert(
+                    format!("{}/tcp", parts[1]),
+                    Some(vec![bollard::models::PortBinding {
+                        host_ip: Some("0.0.0.0".to_string()),
+                        host_port: Some(parts[0].to_string()),
+                    }]),
+                );
             }
         }
 
         let mut volume_bindings = Vec::new();
-        if let Some(volume_mappings) = volumes {
-            for volume in volume_mappings {
-                let parts: Vec<&str> = volume.split(':').collect();
-                if parts.len() == 2 {
-                    volume_bindings.push(format!("{}:{}", parts[0], parts[1]));
-                }
+        for volume in &spec.volumes {
+            let parts: Vec<&str> = volume.split(':').collect();
+            if parts.len() == 2 {
+                volume_bindings.push(format!("{}:{}", parts[0], parts[1]));
             }
         }
 
         let options = CreateContainerOptions {
             name,
+            platform: None,
         };
 
         let config = Config {
             image: Some(image.to_string()),
-            cmd: command,
+            cmd: spec.command,
+            env: (!spec.env.is_empty()).then_some(spec.env),
+            labels: (!spec.labels.is_empty()).then_some(spec.labels),
             exposed_ports: Some(port_bindings.keys().map(|k| (k.clone(), HashMap::new())).collect()),
             host_config: Some(bollard::models::HostConfig {
                 port_bindings: Some(port_bindings),
@@ -174,6 +220,55 @@ impl DockerPlugin {
         Ok(format!("Created container {} with ID {}", name, container.id))
     }
 
+    async fn compose_up(&self, plan: Vec<ContainerPlan>) -> Result<String> {
+        let mut output = String::new();
+        for container in plan {
+            let spec = ContainerSpec {
+                command: container.command,
+                ports: container.ports,
+                volumes: container.volumes,
+                env: container.env,
+                labels: container.labels,
+            };
+            output.push_str(&self.create_container(&container.name, &container.image, spec).await.with_context(|| format!("Failed to create service {}", container.service))?);
+            output.push('\n');
+            output.push_str(&self.start_container(&container.name).await?);
+            output.push('\n');
+        }
+        Ok(output)
+    }
+
+    /// Stops and removes every container labelled with `project`, dependents first.
+    async fn compose_down(&self, project: &str, order: &[&str]) -> Result<String> {
+        let options = ListContainersOptions {
+            all: true,
+            filters: HashMap::from([("label".to_string(), vec![format!("{}={}", PROJECT_LABEL, project)])]),
+            ..Default::default()
+        };
+        let mut containers = self.client.list_containers(Some(options)).await?;
+        // Reverse startup order; services no longer in the compose file go down first.
+        containers.sort_by_key(|c| {
+            let service = c.labels.as_ref().and_then(|l| l.get(SERVICE_LABEL)).map(String::as_str);
+            service.and_then(|s| order.iter().position(|o| *o == s)).map_or(0, |i| order.len() - i)
+        });
+
+        if containers.is_empty() {
+            return Ok(format!("No containers found for project {}", project));
+        }
+
+        let mut output = String::new();
+        for container in containers {
+            let id = container.id.unwrap_or_default();
+            if container.state.as_deref() == Some("running") {
+                output.push_str(&self.stop_container(&id).await?);
+                output.push('\n');
+            }
+            output.push_str(&self.remove_container(&id, false).await?);
+            output.push('\n');
+        }
+        Ok(output)
+    }
+
     async fn start_container(&self, container_id: &str) -> Result<String> {
         self.client.start_container(container_id, None::<StartContainerOptions<String>>).await?;
         Ok(format!("Started container {}", container_id))
@@ -198,7 +293,7 @@ impl DockerPlugin {
         let options = bollard::container::LogsOptions::<String> {
             stdout: true,
             stderr: true,
-            tail: Some("100"),
+            tail: "100".to_string(),
             ..Default::default()
         };
 
@@ -207,7 +302,7 @@ impl DockerPlugin {
         while let Some(result) = stream.next().await {
             match result {
                 Ok(log) => {
-                    logs.push_str(&format!("{}\n", log.to_string()));
+                    logs.push_str(&format!("{}\n", log));
                 }
                 Err(e) => return Err(anyhow::anyhow!("Failed to get logs: {}", e)),
             }
@@ -222,7 +317,7 @@ impl DockerPlugin {
         
         output.push_str(&format!("Container Stats for {}\n", container_id));
         if let Some(state) = stats.state {
-            output.push_str(&format!("Status: {}\n", state.status.unwrap_or_default()));
+            output.push_str(&format!("Status: {}\n", state.status.map(|status| status.to_string()).unwrap_or_default()));
             output.push_str(&format!("Running: {}\n", state.running.unwrap_or_default()));
             output.push_str(&format!("Pid: {}\n", state.pid.unwrap_or_default()));
             if let Some(started) = state.started_at {
@@ -240,6 +335,18 @@ impl DockerPlugin {
         Ok(output)
     }
 
+    /// The full inspect document as pretty JSON, or `template` filled in from it.
+    async fn inspect_container(&self, container_id: &str, template: Option<&str>) -> Result<String> {
+        let inspect: ContainerInspectResponse = retry(&RetryPolicy::default(), docker_retryable, || {
+            self.client.inspect_container(container_id, None)
+        }).await?;
+        let document = serde_json::to_value(&inspect)?;
+        match template {
+            Some(template) => render_template(template, &document),
+            None => Ok(serde_json::to_string_pretty(&document)?),
+        }
+    }
+
     async fn list_images(&self) -> Result<String> {
         let options = ListImagesOptions::<String> {
             all: true,
@@ -247,30 +354,18 @@ impl DockerPlugin {
         };
 
         let images = self.client.list_images(Some(options)).await?;
-        let mut output = String::new();
-        output.push_str(&format!("{}\n", "IMAGES".bright_green()));
-        output.push_str(&format!("{:<20} {:<20} {:<20} {:<20}\n",
-            "IMAGE ID", "REPOSITORY", "TAG", "SIZE"));
-
+        let mut table = Table::new(["IMAGE ID", "REPOSITORY", "TAG", "SIZE"]);
         for image in images {
-            let id = image.id.unwrap_or_default();
-            let repo_tags = image.repo_tags.unwrap_or_default();
-            let size = image.size.unwrap_or(0) / 1024 / 1024; // Convert to MB
-
-            for tag in repo_tags {
-                let parts: Vec<&str> = tag.split(':').collect();
-                let (repo, tag) = if parts.len() == 2 {
-                    (parts[0], parts[1])
-                } else {
-                    (&tag[..], "latest")
-                };
+            let id = image.id.trim_start_matches("sha256:");
+            let size = format!("{}MB", image.size / 1024 / 1024);
 
-                output.push_str(&format!("{:<20} {:<20} {:<20} {:<20}MB\n",
-                    &id[7..19], repo, tag, size));
+            for tag in &image.repo_tags {
+                let (repo, tag) = tag.rsplit_once(':').unwrap_or((tag, "latest"));
+                table = table.row([&id[..id.len().min(12)], repo, tag, &size]);
             }
         }
 
-        Ok(output)
+        Ok(format!("{}\n{}", "IMAGES".bright_green(), table.render()))
     }
 
     async fn remove_image(&self, image: &str, force: bool) -> Result<String> {
@@ -292,7 +387,7 @@ impl DockerPlugin {
         }).await?;
 
         let mut output = String::new();
-        if let bollard::exec::StartExecResults::Attached { mut output: stream, .. } = 
+        if let bollard::exec::StartExecResults::Attached { output: mut stream, .. } = 
             self.client.start_exec(&exec.id, None::<StartExecOptions>).await? {
             while let Some(Ok(msg)) = stream.next().await {
                 output.push_str(&msg.to_string());
@@ -301,6 +396,91 @@ impl DockerPlugin {
 
         Ok(output)
     }
+
+    /// Runs `command` attached to the local terminal (`-i` forwards keystrokes, `-t` gives it
+    /// a TTY) until it exits. Returns its exit code.
+    async fn exec_interactive(&self, container_id: &str, command: Vec<String>, interactive: bool, tty: bool) -> Result<i64> {
+        let exec = self.client.create_exec(container_id, CreateExecOptions {
+            attach_stdin: Some(interactive),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            tty: Some(tty),
+            cmd: Some(command),
+            ..Default::default()
+        }).await?;
+
+        if !interactive {
+            return self.bridge_exec(&exec.id, tty, None, &mut std::io::stdout()).await;
+        }
+
+        let _raw = if tty { Some(RawMode::enable()?) } else { None };
+        let (sender, receiver) = tokio::sync::mpsc::channel(64);
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let stop = stop.clone();
+            tokio::task::spawn_blocking(move || -> Result<()> {
+                while !stop.load(Ordering::Relaxed) {
+                    if let Some(input) = poll_terminal()? {
+                        if sender.blocking_send(input).is_err() {
+                            break;
+                        }
+                    }
+                }
+                Ok(())
+            })
+        };
+
+        let status = self.bridge_exec(&exec.id, tty, Some(receiver), &mut std::io::stdout()).await;
+        stop.store(true, Ordering::Relaxed);
+        reader.await??;
+        status
+    }
+
+    /// Copies the exec's output to `output` and whatever arrives on `input` to its stdin or
+    /// TTY size, until the exec's output ends.
+    async fn bridge_exec<W: Write>(
+        &self,
+        exec_id: &str,
+        tty: bool,
+        mut input: Option<tokio::sync::mpsc::Receiver<TerminalInput>>,
+        output: &mut W,
+    ) -> Result<i64> {
+        let started = self.client.start_exec(exec_id, None::<StartExecOptions>).await?;
+        let (mut stream, mut stdin) = match started {
+            StartExecResults::Attached { output, input } => (output, input),
+            StartExecResults::Detached => anyhow::bail!("exec {} started detached", exec_id),
+        };
+        if tty {
+            let (width, height) = terminal::size().unwrap_or((80, 24));
+            // The exec may already have finished, in which case there's nothing to resize
+            let _ = self.client.resize_exec(exec_id, ResizeExecOptions { width, height }).await;
+        }
+
+        loop {
+            tokio::select! {
+                chunk = stream.next() => match chunk {
+                    Some(chunk) => {
+                        output.write_all(&chunk?.into_bytes())?;
+                        output.flush()?;
+                    }
+                    None => break,
+                },
+                event = async { input.as_mut().unwrap().recv().await }, if input.is_some() => match event {
+                    Some(TerminalInput::Bytes(bytes)) => {
+                        stdin.write_all(&bytes).await?;
+                        stdin.flush().await?;
+                    }
+                    Some(TerminalInput::Resize(width, height)) if tty => {
+                        self.client.resize_exec(exec_id, ResizeExecOptions { width, height }).await?;
+                    }
+                    Some(TerminalInput::Resize(..)) => {}
+                    None => input = None,
+                },
+            }
+        }
+
+        Ok(self.client.inspect_exec(exec_id).await?.exit_code.unwrap_or(0))
+    }
 }
 
 #[async_trait]
@@ -313,11 +493,11 @@ impl Plugin for DockerPlugin {
         "Docker container management and operations"
     }
 
-    async fn execute(&self, command: &Command, _env: &Environment) -> Result<String> {
+    async fn execute(&self, command: &Command, env: &Environment) -> Result<String> {
         match command.args.first().map(|s| s.as_str()) {
             Some("ps") => {
-                let all = command.args.get(1).map(|s| s == "-a").unwrap_or(false);
-                self.list_containers(all).await
+                let all = command.flags.contains_key("a") || command.flags.contains_key("all");
+                self.list_containers(all, OutputFormat::from_command(command)?).await
             }
 
             Some("pull") => {
@@ -360,25 +540,13 @@ impl Plugin for DockerPlugin {
                     }
                 }
 
-                let command_opt = if command_vec.is_empty() {
-                    None
-                } else {
-                    Some(command_vec)
+                let spec = ContainerSpec {
+                    command: (!command_vec.is_empty()).then_some(command_vec),
+                    ports,
+                    volumes,
+                    ..Default::default()
                 };
-
-                let ports_opt = if ports.is_empty() {
-                    None
-                } else {
-                    Some(ports)
-                };
-
-                let volumes_opt = if volumes.is_empty() {
-                    None
-                } else {
-                    Some(volumes)
-                };
-
-                self.create_container(name, image, command_opt, ports_opt, volumes_opt).await
+                self.create_container(name, image, spec).await
             }
 
             Some("start") => {
@@ -422,6 +590,15 @@ impl Plugin for DockerPlugin {
                 self.container_stats(&command.args[1]).await
             }
 
+            Some("inspect") => {
+                if command.args.len() < 2 {
+                    return Ok("Usage: docker inspect <container_id> [--format '{{.State.Status}}']".to_string());
+                }
+                let template = ["format", "f"].iter()
+                    .find_map(|flag| command.flags.get(*flag).and_then(|value| value.as_deref()));
+                self.inspect_container(&command.args[1], template).await
+            }
+
             Some("images") => {
                 self.list_images().await
             }
@@ -440,15 +617,205 @@ impl Plugin for DockerPlugin {
             }
 
             Some("exec") => {
-                if command.args.len() < 3 {
-                    return Ok("Usage: docker exec <container_id> <command>".to_string());
+                let request = match ExecRequest::parse(&command.raw_input) {
+                    Some(request) => request,
+                    None => return Ok("Usage: docker exec [-i] [-t] <container_id> <command>".to_string()),
+                };
+                if !request.interactive && !request.tty {
+                    return self.exec_in_container(&request.container, request.command).await;
+                }
+                let status = self.exec_interactive(&request.container, request.command, request.interactive, request.tty).await?;
+                if status != 0 {
+                    return Err(ShellError::External(format!("exec exited with status {}", status)).into());
+                }
+                Ok(String::new())
+            }
+
+            Some("compose") => {
+                let file = command.flags.get("f").and_then(|f| f.as_deref()).unwrap_or(DEFAULT_COMPOSE_FILE);
+                let path = env.expand_path(file);
+                let compose = ComposeFile::load(&path).await?;
+                let project = match command.flags.get("p").and_then(|p| p.as_deref()) {
+                    Some(project) => project.to_string(),
+                    None => compose.project_name(&path),
+                };
+
+                match command.args.get(1).map(|s| s.as_str()) {
+                    Some("up") => self.compose_up(compose.plan(&project)?).await,
+                    Some("down") => self.compose_down(&project, &compose.startup_order()?).await,
+                    _ => Ok("Usage: docker compose [-f <file>] [-p <project>] up|down".to_string()),
                 }
-                let container_id = &command.args[1];
-                let command = command.args[2..].to_vec();
-                self.exec_in_container(container_id, command).await
             }
 
-            _ => Ok("Available commands: ps, pull, run, start, stop, rm, logs, stats, images, rmi, exec".to_string()),
+            _ => Ok("Available commands: ps, pull, run, start, stop, rm, logs, stats, inspect, images, rmi, exec, compose".to_string()),
+        }
+    }
+}
+
+/// Fills in the `{{.Field.Path}}` actions of a Go-style `--format` template from an inspect
+/// document. Only field selectors are supported, plus `{{json .Path}}` to print a value as
+/// JSON. Strings print bare, other values as compact JSON, and missing fields as `<no value>`.
+fn render_template(template: &str, document: &serde_json::Value) -> Result<String> {
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let end = rest[start..].find("}}")
+            .ok_or_else(|| anyhow::anyhow!("Unterminated action in template: {}", template))?;
+        let action = rest[start + 2..start + end].trim();
+        let (as_json, selector) = match action.strip_prefix("json ") {
+            Some(selector) => (true, selector.trim()),
+            None => (false, action),
+        };
+        let path = selector.strip_prefix('.')
+            .ok_or_else(|| anyhow::anyhow!("Unsupported template action '{{{{{}}}}}' (expected a field like .State.Status)", action))?;
+
+        let value = path.split('.')
+            .filter(|field| !field.is_empty())
+            .try_fold(document, |value, field| value.get(field));
+        match value {
+            Some(value) if as_json => output.push_str(&serde_json::to_string(value)?),
+            Some(serde_json::Value::String(text)) => output.push_str(text),
+            Some(serde_json::Value::Null) | None => output.push_str("<no value>"),
+            Some(value) => output.push_str(&value.to_string()),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// `docker exec [-i] [-t] <container> <command...>`. Read from the raw line rather than the
+/// parsed flags, so options and quoted words meant for the command reach it intact.
+#[derive(Debug, PartialEq)]
+struct ExecRequest {
+    interactive: bool,
+    tty: bool,
+    container: String,
+    command: Vec<String>,
+}
+
+impl ExecRequest {
+    fn parse(raw_input: &str) -> Option<Self> {
+        let mut words = split_words(raw_input).into_iter().skip_while(|word| word != "exec").skip(1).peekable();
+        let (mut interactive, mut tty) = (false, false);
+        while let Some(option) = words.next_if(|word| word.starts_with('-')) {
+            match option.as_str() {
+                "--interactive" => interactive = true,
+                "--tty" => tty = true,
+                short if !short.starts_with("--") && short.len() > 1 => {
+                    for c in short[1..].chars() {
+                        match c {
+                            'i' => interactive = true,
+                            't' => tty = true,
+                            _ => return None,
+                        }
+                    }
+                }
+                _ => return None,
+            }
+        }
+        let container = words.next()?;
+        let command: Vec<String> = words.collect();
+        if command.is_empty() {
+            return None;
         }
+        Some(ExecRequest { interactive, tty, container, command })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let document = serde_json::json!({
+            "Name": "/web",
+            "State": { "Status": "running", "Pid": 42, "Health": null },
+            "Config": { "Env": ["A=1"] }
+        });
+        assert_eq!(render_template("{{.State.Status}}", &document).unwrap(), "running");
+        assert_eq!(render_template("{{ .Name }} pid={{.State.Pid}}", &document).unwrap(), "/web pid=42");
+        assert_eq!(render_template("{{json .Config.Env}}", &document).unwrap(), "[\"A=1\"]");
+        assert_eq!(render_template("{{.State.Health}} {{.Missing.Field}}", &document).unwrap(), "<no value> <no value>");
+        assert!(render_template("{{.State.Status", &document).is_err());
+        assert!(render_template("{{index .Config 0}}", &document).is_err());
+    }
+
+    #[test]
+    fn test_parse_exec_request() {
+        let request = ExecRequest::parse("docker exec -it web sh -c 'echo hi'").unwrap();
+        assert_eq!(request, ExecRequest {
+            interactive: true,
+            tty: true,
+            container: "web".to_string(),
+            command: vec!["sh".to_string(), "-c".to_string(), "echo hi".to_string()],
+        });
+
+        let request = ExecRequest::parse("docker exec web ls -la \"/my dir\"").unwrap();
+        assert!(!request.interactive && !request.tty);
+        assert_eq!(request.command, vec!["ls", "-la", "/my dir"]);
+        assert!(ExecRequest::parse("docker exec -t --interactive web sh").unwrap().interactive);
+
+        assert_eq!(ExecRequest::parse("docker exec -it web"), None);
+        assert_eq!(ExecRequest::parse("docker exec -x web sh"), None);
+    }
+
+    // Needs a reachable Docker daemon; pulls busybox.
+    #[cfg(feature = "docker")]
+    #[tokio::test]
+    async fn test_exec_with_tty() {
+        let plugin = DockerPlugin::new().await.unwrap();
+        plugin.pull_image("busybox:latest").await.unwrap();
+
+        let name = "nexusshell-exec-test";
+        let _ = plugin.remove_container(name, true).await;
+        plugin.create_container(name, "busybox:latest", ContainerSpec {
+            command: Some(vec!["sleep".to_string(), "30".to_string()]),
+            ..Default::default()
+        }).await.unwrap();
+        plugin.start_container(name).await.unwrap();
+
+        let request = ExecRequest::parse("docker exec -it nexusshell-exec-test sh -c 'echo hi'").unwrap();
+        let exec = plugin.client.create_exec(&request.container, CreateExecOptions {
+            attach_stdin: Some(true),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            tty: Some(true),
+            cmd: Some(request.command),
+            ..Default::default()
+        }).await.unwrap();
+        let (_sender, receiver) = tokio::sync::mpsc::channel(1);
+        let mut output = Vec::new();
+        let status = plugin.bridge_exec(&exec.id, true, Some(receiver), &mut output).await;
+        plugin.remove_container(name, true).await.unwrap();
+
+        assert_eq!(status.unwrap(), 0);
+        // A TTY turns the newline into CRLF
+        assert_eq!(String::from_utf8_lossy(&output), "hi\r\n");
+    }
+
+    // Needs a reachable Docker daemon; pulls busybox.
+    #[cfg(feature = "docker")]
+    #[tokio::test]
+    async fn test_inspect_container_status() {
+        let plugin = DockerPlugin::new().await.unwrap();
+        plugin.pull_image("busybox:latest").await.unwrap();
+
+        let name = "nexusshell-inspect-test";
+        let _ = plugin.remove_container(name, true).await;
+        plugin.create_container(name, "busybox:latest", ContainerSpec {
+            command: Some(vec!["true".to_string()]),
+            ..Default::default()
+        }).await.unwrap();
+
+        let status = plugin.inspect_container(name, Some("{{.State.Status}}")).await;
+        let full = plugin.inspect_container(name, None).await;
+        plugin.remove_container(name, true).await.unwrap();
+
+        assert_eq!(status.unwrap(), "created");
+        let full: serde_json::Value = serde_json::from_str(&full.unwrap()).unwrap();
+        assert_eq!(full["Name"], format!("/{}", name));
     }
 }
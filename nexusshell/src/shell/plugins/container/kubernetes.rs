@@ -1,57 +1,120 @@
 use async_trait::async_trait;
 use super::super::super::{Command, Environment, Plugin};
+use super::super::super::util::{kube_retryable, retry, RetryPolicy};
 use kube::{
-    api::{Api, DeleteParams, ListParams, PostParams},
+    api::{Api, DeleteParams, ListParams, LogParams, Patch, PatchParams, PostParams},
     Client,
     config::{KubeConfigOptions, Kubeconfig},
-    core::ObjectMeta,
+    runtime::watcher,
+    ResourceExt,
 };
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
 use k8s_openapi::api::{
-    core::v1::{Pod, Service, ConfigMap, Secret},
-    apps::v1::{Deployment, StatefulSet},
+    core::v1::{Pod, Service},
+    apps::v1::Deployment,
 };
-use futures::StreamExt;
+use futures::{AsyncBufReadExt, StreamExt, TryStreamExt};
+use futures::stream::BoxStream;
+use std::collections::HashSet;
+use std::sync::Mutex;
 use anyhow::{Result, Context};
 use tokio::fs;
 use serde::{Serialize, Deserialize};
+use std::ffi::OsStr;
 use std::path::PathBuf;
 use colored::*;
-use indicatif::{ProgressBar, ProgressStyle};
-use std::collections::BTreeMap;
-use chrono::{DateTime, Utc};
+use chrono::Utc;
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Pod template annotation `kubectl rollout restart` bumps to roll a deployment.
+const RESTARTED_AT_ANNOTATION: &str = "kubectl.kubernetes.io/restartedAt";
+
+/// Saved kubectl settings. Anything left unset comes from the kubeconfig, as kubectl does.
+#[derive(Debug, Default, Serialize, Deserialize)]
 struct KubernetesConfig {
+    /// Context to use; the kubeconfig's `current-context` when unset
+    #[serde(default)]
+    context: Option<String>,
+    /// Namespace to work in; the context's own namespace, or `default`, when unset
+    #[serde(default)]
+    namespace: Option<String>,
+    /// Kubeconfig to read; the `KUBECONFIG` path list (merged) or `~/.kube/config` when unset
+    #[serde(default)]
+    kubeconfig_path: Option<PathBuf>,
+}
+
+/// A kubeconfig together with the context and namespace picked from it.
+struct Target {
+    kubeconfig: Kubeconfig,
     context: String,
     namespace: String,
-    kubeconfig_path: PathBuf,
 }
 
-impl Default for KubernetesConfig {
-    fn default() -> Self {
-        let mut kubeconfig_path = dirs::home_dir().unwrap_or_default();
-        kubeconfig_path.push(".kube");
-        kubeconfig_path.push("config");
+impl KubernetesConfig {
+    /// The kubeconfig files to read, given the value of `KUBECONFIG`.
+    fn kubeconfig_paths(&self, kubeconfig_env: Option<&OsStr>) -> Vec<PathBuf> {
+        if let Some(path) = &self.kubeconfig_path {
+            return vec![path.clone()];
+        }
+        let from_env: Vec<PathBuf> = kubeconfig_env
+            .map(|value| std::env::split_paths(value).filter(|path| !path.as_os_str().is_empty()).collect())
+            .unwrap_or_default();
+        if !from_env.is_empty() {
+            return from_env;
+        }
+        vec![dirs::home_dir().unwrap_or_default().join(".kube").join("config")]
+    }
 
-        KubernetesConfig {
-            context: "default".to_string(),
-            namespace: "default".to_string(),
-            kubeconfig_path,
+    /// Reads and merges the kubeconfig files, skipping missing ones like kubectl does. The
+    /// first file to set `current-context` wins, as does the first definition of each context.
+    fn resolve(&self, kubeconfig_env: Option<&OsStr>) -> Result<Target> {
+        let paths = self.kubeconfig_paths(kubeconfig_env);
+        let mut merged: Option<Kubeconfig> = None;
+        for path in paths.iter().filter(|path| path.exists()) {
+            let kubeconfig = Kubeconfig::read_from(path)
+                .with_context(|| format!("Failed to read kubeconfig {}", path.display()))?;
+            merged = Some(match merged {
+                Some(merged) => merged.merge(kubeconfig)?,
+                None => kubeconfig,
+            });
         }
+        let kubeconfig = merged.ok_or_else(|| anyhow::anyhow!(
+            "No kubeconfig found at {}",
+            paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ")
+        ))?;
+
+        let context = self.context.clone()
+            .or_else(|| kubeconfig.current_context.clone())
+            .ok_or_else(|| anyhow::anyhow!("No context selected; run `kubectl config use-context <name>`"))?;
+        let namespace = self.namespace.clone()
+            .or_else(|| kubeconfig.contexts.iter()
+                .find(|named| named.name == context)
+                .and_then(|named| named.context.as_ref())
+                .and_then(|context| context.namespace.clone()))
+            .unwrap_or_else(|| "default".to_string());
+
+        Ok(Target { kubeconfig, context, namespace })
     }
 }
 
 pub struct KubernetesPlugin {
-    config: KubernetesConfig,
-    client: Option<Client>,
+    config: Mutex<KubernetesConfig>,
+    /// Namespace commands run in, resolved from the config and kubeconfig
+    namespace: Mutex<String>,
+    client: Mutex<Option<Client>>,
 }
 
 impl KubernetesPlugin {
     pub async fn new() -> Result<Self> {
         let config = Self::load_config().await.unwrap_or_default();
+        let namespace = config.resolve(std::env::var_os("KUBECONFIG").as_deref())
+            .map(|target| target.namespace)
+            .unwrap_or_else(|_| config.namespace.clone().unwrap_or_else(|| "default".to_string()));
         Ok(KubernetesPlugin {
-            config,
-            client: None,
+            config: Mutex::new(config),
+            namespace: Mutex::new(namespace),
+            client: Mutex::new(None),
         })
     }
 
@@ -71,151 +134,134 @@ impl KubernetesPlugin {
         }
     }
 
-    async fn init_client(&mut self) -> Result<()> {
-        if self.client.is_none() {
-            let kubeconfig = Kubeconfig::read_from(&self.config.kubeconfig_path)?;
-            let options = KubeConfigOptions {
-                context: Some(self.config.context.clone()),
-                ..Default::default()
-            };
-            let config = kube::Config::from_custom_kubeconfig(kubeconfig, &options).await?;
-            self.client = Some(Client::try_from(config)?);
+    /// The client for the selected context, connecting on first use, and the namespace to
+    /// work in.
+    async fn init_client(&self) -> Result<(Client, String)> {
+        let existing = self.client.lock().unwrap().clone();
+        if let Some(client) = existing {
+            return Ok((client, self.namespace.lock().unwrap().clone()));
         }
-        Ok(())
+
+        let target = self.config.lock().unwrap().resolve(std::env::var_os("KUBECONFIG").as_deref())?;
+        let options = KubeConfigOptions {
+            context: Some(target.context),
+            ..Default::default()
+        };
+        let config = kube::Config::from_custom_kubeconfig(target.kubeconfig, &options).await?;
+        let client = Client::try_from(config)?;
+        *self.namespace.lock().unwrap() = target.namespace.clone();
+        *self.client.lock().unwrap() = Some(client.clone());
+        Ok((client, target.namespace))
     }
 
     async fn list_pods(&self) -> Result<String> {
-        self.init_client().await?;
-        let client = self.client.as_ref().unwrap();
-        let pods: Api<Pod> = Api::namespaced(client.clone(), &self.config.namespace);
-        let pod_list = pods.list(&ListParams::default()).await?;
+        let (client, namespace) = self.init_client().await?;
+        let pods: Api<Pod> = Api::namespaced(client.clone(), &namespace);
+        let params = ListParams::default();
+        let pod_list = retry(&RetryPolicy::default(), kube_retryable, || pods.list(&params)).await?;
 
         let mut output = String::new();
         output.push_str(&format!("{}\n", "PODS".bright_green()));
-        output.push_str(&format!("{:<30} {:<15} {:<10} {:<15} {:<20}\n",
-            "NAME", "STATUS", "RESTARTS", "AGE", "IP"));
-
-        for pod in pod_list.items {
-            let name = pod.metadata.name.unwrap_or_default();
-            let status = pod.status.as_ref().and_then(|s| s.phase.clone()).unwrap_or_default();
-            let restarts = pod.status.as_ref()
-                .and_then(|s| s.container_statuses.as_ref())
-                .and_then(|cs| cs.first())
-                .map(|c| c.restart_count)
-                .unwrap_or(0);
-            let age = pod.metadata.creation_timestamp
-                .map(|t| humantime::format_duration(Utc::now().signed_duration_since(t).to_std().unwrap_or_default()).to_string())
-                .unwrap_or_default();
-            let ip = pod.status.as_ref()
-                .and_then(|s| s.pod_ip.clone())
-                .unwrap_or_default();
-
-            output.push_str(&format!("{:<30} {:<15} {:<10} {:<15} {:<20}\n",
-                name, status, restarts, age, ip));
+        output.push_str(&format!("{}\n", POD_HEADER));
+        for pod in &pod_list.items {
+            output.push_str(&format!("{}\n", pod_row(pod)));
         }
 
         Ok(output)
     }
 
     async fn list_deployments(&self) -> Result<String> {
-        self.init_client().await?;
-        let client = self.client.as_ref().unwrap();
-        let deployments: Api<Deployment> = Api::namespaced(client.clone(), &self.config.namespace);
+        let (client, namespace) = self.init_client().await?;
+        let deployments: Api<Deployment> = Api::namespaced(client.clone(), &namespace);
         let deployment_list = deployments.list(&ListParams::default()).await?;
 
         let mut output = String::new();
         output.push_str(&format!("{}\n", "DEPLOYMENTS".bright_green()));
-        output.push_str(&format!("{:<30} {:<10} {:<10} {:<10} {:<15}\n",
-            "NAME", "READY", "UP-TO-DATE", "AVAILABLE", "AGE"));
-
-        for deployment in deployment_list.items {
-            let name = deployment.metadata.name.unwrap_or_default();
-            let status = deployment.status.as_ref().unwrap();
-            let ready = format!("{}/{}", 
-                status.ready_replicas.unwrap_or(0),
-                status.replicas.unwrap_or(0));
-            let up_to_date = status.updated_replicas.unwrap_or(0);
-            let available = status.available_replicas.unwrap_or(0);
-            let age = deployment.metadata.creation_timestamp
-                .map(|t| humantime::format_duration(Utc::now().signed_duration_since(t).to_std().unwrap_or_default()).to_string())
-                .unwrap_or_default();
-
-            output.push_str(&format!("{:<30} {:<10} {:<10} {:<10} {:<15}\n",
-                name, ready, up_to_date, available, age));
+        output.push_str(&format!("{}\n", DEPLOYMENT_HEADER));
+        for deployment in &deployment_list.items {
+            output.push_str(&format!("{}\n", deployment_row(deployment)));
         }
 
         Ok(output)
     }
 
     async fn list_services(&self) -> Result<String> {
-        self.init_client().await?;
-        let client = self.client.as_ref().unwrap();
-        let services: Api<Service> = Api::namespaced(client.clone(), &self.config.namespace);
+        let (client, namespace) = self.init_client().await?;
+        let services: Api<Service> = Api::namespaced(client.clone(), &namespace);
         let service_list = services.list(&ListParams::default()).await?;
 
         let mut output = String::new();
         output.push_str(&format!("{}\n", "SERVICES".bright_green()));
-        output.push_str(&format!("{:<30} {:<15} {:<20} {:<15} {:<20}\n",
-            "NAME", "TYPE", "CLUSTER-IP", "EXTERNAL-IP", "PORTS"));
-
-        for service in service_list.items {
-            let name = service.metadata.name.unwrap_or_default();
-            let service_type = service.spec.as_ref()
-                .and_then(|s| s.type_.clone())
-                .unwrap_or_default();
-            let cluster_ip = service.spec.as_ref()
-                .and_then(|s| s.cluster_ip.clone())
-                .unwrap_or_default();
-            let external_ip = service.status.as_ref()
-                .and_then(|s| s.load_balancer.as_ref())
-                .and_then(|lb| lb.ingress.as_ref())
-                .and_then(|i| i.first())
-                .and_then(|i| i.ip.clone())
-                .unwrap_or_default();
-            let ports = service.spec.as_ref()
-                .map(|s| s.ports.as_ref())
-                .unwrap_or(None)
-                .map(|ports| ports.iter()
-                    .map(|p| format!("{}:{}", p.port, p.target_port.as_ref().map_or(0, |t| t.as_u16().unwrap_or(0))))
-                    .collect::<Vec<_>>()
-                    .join(", "))
-                .unwrap_or_default();
-
-            output.push_str(&format!("{:<30} {:<15} {:<20} {:<15} {:<20}\n",
-                name, service_type, cluster_ip, external_ip, ports));
+        output.push_str(&format!("{}\n", SERVICE_HEADER));
+        for service in &service_list.items {
+            output.push_str(&format!("{}\n", service_row(service)));
         }
 
         Ok(output)
     }
 
-    async fn get_pod_logs(&self, pod_name: &str) -> Result<String> {
-        self.init_client().await?;
-        let client = self.client.as_ref().unwrap();
-        let pods: Api<Pod> = Api::namespaced(client.clone(), &self.config.namespace);
-        
-        let mut params = BTreeMap::new();
-        params.insert("tailLines", "100");
-        params.insert("timestamps", "true");
+    /// Prints the usual `get` table, then one timestamped line per change until Ctrl+C.
+    async fn watch_resource(&self, resource: &str) -> Result<String> {
+        let (client, namespace) = self.init_client().await?;
+        let (snapshot, mut events) = match resource {
+            "pods" => (self.list_pods().await?, watch_events(Api::<Pod>::namespaced(client, &namespace), pod_row)),
+            "deployments" => (self.list_deployments().await?, watch_events(Api::<Deployment>::namespaced(client, &namespace), deployment_row)),
+            "services" => (self.list_services().await?, watch_events(Api::<Service>::namespaced(client, &namespace), service_row)),
+            _ => return Ok("Supported resources: pods, deployments, services".to_string()),
+        };
+
+        print!("{}", snapshot);
+        loop {
+            tokio::select! {
+                event = events.next() => match event {
+                    Some(line) => println!("{}", line?),
+                    None => break,
+                },
+                _ = tokio::signal::ctrl_c() => break,
+            }
+        }
+        Ok(String::new())
+    }
 
-        let logs = pods.logs(pod_name, &params).await?;
-        Ok(logs)
+    async fn get_pod_logs(&self, pod_name: &str, params: &LogParams) -> Result<String> {
+        let (client, namespace) = self.init_client().await?;
+        let pods: Api<Pod> = Api::namespaced(client.clone(), &namespace);
+
+        if !params.follow {
+            return pods.logs(pod_name, params).await
+                .with_context(|| format!("Failed to get logs for pod {}", pod_name));
+        }
+
+        // Lines are printed as they arrive; Ctrl+C stops following.
+        let stream = pods.log_stream(pod_name, params).await
+            .with_context(|| format!("Failed to stream logs for pod {}", pod_name))?;
+        let mut lines = stream.lines();
+        loop {
+            tokio::select! {
+                line = lines.try_next() => match line? {
+                    Some(line) => println!("{}", line),
+                    None => break,
+                },
+                _ = tokio::signal::ctrl_c() => break,
+            }
+        }
+        Ok(String::new())
     }
 
     async fn delete_resource(&self, resource_type: &str, name: &str) -> Result<String> {
-        self.init_client().await?;
-        let client = self.client.as_ref().unwrap();
+        let (client, namespace) = self.init_client().await?;
 
         match resource_type {
             "pod" => {
-                let pods: Api<Pod> = Api::namespaced(client.clone(), &self.config.namespace);
+                let pods: Api<Pod> = Api::namespaced(client.clone(), &namespace);
                 pods.delete(name, &DeleteParams::default()).await?;
             }
             "deployment" => {
-                let deployments: Api<Deployment> = Api::namespaced(client.clone(), &self.config.namespace);
+                let deployments: Api<Deployment> = Api::namespaced(client.clone(), &namespace);
                 deployments.delete(name, &DeleteParams::default()).await?;
             }
             "service" => {
-                let services: Api<Service> = Api::namespaced(client.clone(), &self.config.namespace);
+                let services: Api<Service> = Api::namespaced(client.clone(), &namespace);
                 services.delete(name, &DeleteParams::default()).await?;
             }
             _ => return Err(anyhow::anyhow!("Unsupported resource type")),
@@ -225,9 +271,8 @@ impl KubernetesPlugin {
     }
 
     async fn scale_deployment(&self, name: &str, replicas: i32) -> Result<String> {
-        self.init_client().await?;
-        let client = self.client.as_ref().unwrap();
-        let deployments: Api<Deployment> = Api::namespaced(client.clone(), &self.config.namespace);
+        let (client, namespace) = self.init_client().await?;
+        let deployments: Api<Deployment> = Api::namespaced(client.clone(), &namespace);
         
         let deployment = deployments.get(name).await?;
         let mut deployment_patch = deployment.clone();
@@ -237,10 +282,64 @@ impl KubernetesPlugin {
         Ok(format!("Scaled deployment {} to {} replicas", name, replicas))
     }
 
+    async fn rollout_restart(&self, name: &str) -> Result<String> {
+        let (client, namespace) = self.init_client().await?;
+        let deployments: Api<Deployment> = Api::namespaced(client.clone(), &namespace);
+
+        let patch = serde_json::json!({
+            "spec": {
+                "template": {
+                    "metadata": {
+                        "annotations": {
+                            RESTARTED_AT_ANNOTATION: Utc::now().to_rfc3339()
+                        }
+                    }
+                }
+            }
+        });
+        deployments.patch(name, &PatchParams::default(), &Patch::Merge(&patch)).await
+            .with_context(|| format!("Failed to restart deployment {}", name))?;
+
+        Ok(format!("deployment.apps/{} restarted", name))
+    }
+
+    async fn rollout_status(&self, name: &str, timeout: Duration) -> Result<String> {
+        let (client, namespace) = self.init_client().await?;
+        let deployments: Api<Deployment> = Api::namespaced(client.clone(), &namespace);
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let deployment = deployments.get(name).await?;
+            let desired = deployment.spec.as_ref().and_then(|s| s.replicas).unwrap_or(1);
+            let generation = deployment.metadata.generation.unwrap_or_default();
+            let status = deployment.status.unwrap_or_default();
+            let updated = status.updated_replicas.unwrap_or_default();
+            let available = status.available_replicas.unwrap_or_default();
+            let total = status.replicas.unwrap_or_default();
+
+            if status.observed_generation.unwrap_or_default() >= generation
+                && updated == desired
+                && available == desired
+                && total == desired
+            {
+                return Ok(format!("deployment \"{}\" successfully rolled out", name));
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(anyhow::anyhow!(
+                    "Timed out waiting for deployment {}: {} of {} updated replicas available",
+                    name, available, desired
+                ));
+            }
+            println!("Waiting for deployment \"{}\" rollout to finish: {} of {} updated replicas are available...",
+                name, available.min(updated), desired);
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+    }
+
     async fn describe_pod(&self, name: &str) -> Result<String> {
-        self.init_client().await?;
-        let client = self.client.as_ref().unwrap();
-        let pods: Api<Pod> = Api::namespaced(client.clone(), &self.config.namespace);
+        let (client, namespace) = self.init_client().await?;
+        let pods: Api<Pod> = Api::namespaced(client.clone(), &namespace);
         
         let pod = pods.get(name).await?;
         let mut output = String::new();
@@ -249,7 +348,7 @@ impl KubernetesPlugin {
         output.push_str("Metadata:\n");
         output.push_str(&format!("  Namespace: {}\n", pod.metadata.namespace.unwrap_or_default()));
         output.push_str(&format!("  Creation Time: {}\n", 
-            pod.metadata.creation_timestamp.map(|t| t.to_rfc3339()).unwrap_or_default()));
+            pod.metadata.creation_timestamp.map(|t| t.0.to_rfc3339()).unwrap_or_default()));
         
         if let Some(status) = pod.status {
             output.push_str("\nStatus:\n");
@@ -273,9 +372,9 @@ impl KubernetesPlugin {
             output.push_str("\nSpec:\n");
             output.push_str(&format!("  Node Name: {}\n", spec.node_name.unwrap_or_default()));
             
-            if let Some(containers) = spec.containers {
+            if !spec.containers.is_empty() {
                 output.push_str("\nContainers:\n");
-                for container in containers {
+                for container in spec.containers {
                     output.push_str(&format!("  - Name: {}\n", container.name));
                     output.push_str(&format!("    Image: {}\n", container.image.unwrap_or_default()));
                     if let Some(ports) = container.ports {
@@ -307,10 +406,14 @@ impl Plugin for KubernetesPlugin {
     async fn execute(&self, command: &Command, _env: &Environment) -> Result<String> {
         match command.args.first().map(|s| s.as_str()) {
             Some("get") => {
-                if command.args.len() < 2 {
-                    return Ok("Usage: kubectl get [pods|deployments|services]".to_string());
+                let resource = match command.args.get(1) {
+                    Some(resource) => resource,
+                    None => return Ok("Usage: kubectl get [pods|deployments|services] [-w|--watch]".to_string()),
+                };
+                if command.flags.contains_key("watch") || command.flags.contains_key("w") {
+                    return self.watch_resource(resource).await;
                 }
-                match command.args[1].as_str() {
+                match resource.as_str() {
                     "pods" => self.list_pods().await,
                     "deployments" => self.list_deployments().await,
                     "services" => self.list_services().await,
@@ -319,10 +422,10 @@ impl Plugin for KubernetesPlugin {
             }
 
             Some("logs") => {
-                if command.args.len() < 2 {
-                    return Ok("Usage: kubectl logs <pod_name>".to_string());
+                match log_request(command)? {
+                    Some((pod, params)) => self.get_pod_logs(&pod, &params).await,
+                    None => Ok("Usage: kubectl logs <pod_name> [-c <container>] [--tail <lines>|all] [--since <duration>] [--timestamps] [-f]".to_string()),
                 }
-                self.get_pod_logs(&command.args[1]).await
             }
 
             Some("delete") => {
@@ -333,16 +436,34 @@ impl Plugin for KubernetesPlugin {
             }
 
             Some("scale") => {
-                if command.args.len() < 4 {
+                let replicas = command.flags.get("replicas").and_then(|count| count.as_deref());
+                let (3, Some(replicas)) = (command.args.len(), replicas) else {
                     return Ok("Usage: kubectl scale deployment <name> --replicas=<count>".to_string());
-                }
-                let replicas = command.args[3]
-                    .strip_prefix("--replicas=")
-                    .and_then(|s| s.parse().ok())
-                    .ok_or_else(|| anyhow::anyhow!("Invalid replicas format"))?;
+                };
+                let replicas = replicas.parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid replicas format"))?;
                 self.scale_deployment(&command.args[2], replicas).await
             }
 
+            Some("rollout") => {
+                if command.args.len() < 4 || command.args[2] != "deployment" {
+                    return Ok("Usage: kubectl rollout [restart|status] deployment <name> [--timeout <secs>]".to_string());
+                }
+                match command.args[1].as_str() {
+                    "restart" => self.rollout_restart(&command.args[3]).await,
+                    "status" => {
+                        let timeout = command.flags.get("timeout")
+                            .and_then(|t| t.as_ref())
+                            .map(|t| t.parse::<u64>())
+                            .transpose()
+                            .context("Invalid --timeout value")?
+                            .unwrap_or(300);
+                        self.rollout_status(&command.args[3], Duration::from_secs(timeout)).await
+                    }
+                    _ => Ok("Supported rollout commands: restart, status".to_string()),
+                }
+            }
+
             Some("describe") => {
                 if command.args.len() < 3 {
                     return Ok("Usage: kubectl describe pod <name>".to_string());
@@ -359,19 +480,367 @@ impl Plugin for KubernetesPlugin {
                 }
                 match command.args[1].as_str() {
                     "use-context" => {
-                        self.config.context = command.args[2].clone();
-                        self.client = None; // Force client reinitialization
+                        self.config.lock().unwrap().context = Some(command.args[2].clone());
+                        *self.client.lock().unwrap() = None; // Force client reinitialization
                         Ok(format!("Switched to context {}", command.args[2]))
                     }
                     "set-namespace" => {
-                        self.config.namespace = command.args[2].clone();
+                        self.config.lock().unwrap().namespace = Some(command.args[2].clone());
+                        *self.namespace.lock().unwrap() = command.args[2].clone();
                         Ok(format!("Switched to namespace {}", command.args[2]))
                     }
                     _ => Ok("Supported config commands: use-context, set-namespace".to_string()),
                 }
             }
 
-            _ => Ok("Available commands: get, logs, delete, scale, describe, config".to_string()),
+            _ => Ok("Available commands: get, logs, delete, scale, rollout, describe, config".to_string()),
         }
     }
 }
+
+const POD_HEADER: &str = "NAME                           STATUS          RESTARTS   AGE             IP";
+const DEPLOYMENT_HEADER: &str = "NAME                           READY      UP-TO-DATE AVAILABLE  AGE";
+const SERVICE_HEADER: &str = "NAME                           TYPE            CLUSTER-IP           EXTERNAL-IP     PORTS";
+
+fn age(created: Option<&Time>) -> String {
+    created
+        .map(|t| humantime::format_duration(Utc::now().signed_duration_since(t.0).to_std().unwrap_or_default()).to_string())
+        .unwrap_or_default()
+}
+
+fn pod_row(pod: &Pod) -> String {
+    let name = pod.metadata.name.clone().unwrap_or_default();
+    let status = pod.status.as_ref().and_then(|s| s.phase.clone()).unwrap_or_default();
+    let restarts = pod.status.as_ref()
+        .and_then(|s| s.container_statuses.as_ref())
+        .and_then(|cs| cs.first())
+        .map(|c| c.restart_count)
+        .unwrap_or(0);
+    let ip = pod.status.as_ref()
+        .and_then(|s| s.pod_ip.clone())
+        .unwrap_or_default();
+
+    format!("{:<30} {:<15} {:<10} {:<15} {:<20}",
+        name, status, restarts, age(pod.metadata.creation_timestamp.as_ref()), ip)
+}
+
+fn deployment_row(deployment: &Deployment) -> String {
+    let name = deployment.metadata.name.clone().unwrap_or_default();
+    let status = deployment.status.clone().unwrap_or_default();
+    let ready = format!("{}/{}",
+        status.ready_replicas.unwrap_or(0),
+        status.replicas.unwrap_or(0));
+    let up_to_date = status.updated_replicas.unwrap_or(0);
+    let available = status.available_replicas.unwrap_or(0);
+
+    format!("{:<30} {:<10} {:<10} {:<10} {:<15}",
+        name, ready, up_to_date, available, age(deployment.metadata.creation_timestamp.as_ref()))
+}
+
+fn service_row(service: &Service) -> String {
+    let name = service.metadata.name.clone().unwrap_or_default();
+    let service_type = service.spec.as_ref()
+        .and_then(|s| s.type_.clone())
+        .unwrap_or_default();
+    let cluster_ip = service.spec.as_ref()
+        .and_then(|s| s.cluster_ip.clone())
+        .unwrap_or_default();
+    let external_ip = service.status.as_ref()
+        .and_then(|s| s.load_balancer.as_ref())
+        .and_then(|lb| lb.ingress.as_ref())
+        .and_then(|i| i.first())
+        .and_then(|i| i.ip.clone())
+        .unwrap_or_default();
+    let ports = service.spec.as_ref()
+        .and_then(|s| s.ports.as_ref())
+        .map(|ports| ports.iter()
+            .map(|p| format!("{}:{}", p.port, p.target_port.as_ref().map_or_else(|| p.port.to_string(), target_port)))
+            .collect::<Vec<_>>()
+            .join(", "))
+        .unwrap_or_default();
+
+    format!("{:<30} {:<15} {:<20} {:<15} {:<20}",
+        name, service_type, cluster_ip, external_ip, ports)
+}
+
+/// A service's target port, which may name a container port instead of numbering it.
+fn target_port(port: &IntOrString) -> String {
+    match port {
+        IntOrString::Int(number) => number.to_string(),
+        IntOrString::String(name) => name.clone(),
+    }
+}
+
+/// `<timestamp> ADDED|MODIFIED|DELETED <row>` for every change to the watched resources.
+/// The watcher's initial listing only seeds which names exist; a later relist (after the
+/// watch was interrupted) reports objects that appeared in the meantime.
+fn watch_events<K>(api: Api<K>, row: fn(&K) -> String) -> BoxStream<'static, Result<String>>
+where
+    K: kube::Resource + Clone + std::fmt::Debug + serde::de::DeserializeOwned + Send + 'static,
+{
+    let mut seen: Option<HashSet<String>> = None;
+    watcher(api, watcher::Config::default())
+        .flat_map(move |event| {
+            let lines: Vec<Result<String>> = match event {
+                Err(e) => vec![Err(e.into())],
+                Ok(watcher::Event::Restarted(objects)) => {
+                    let names: HashSet<String> = objects.iter().map(|object| object.name_any()).collect();
+                    let lines = match &seen {
+                        Some(previous) => objects.iter()
+                            .filter(|object| !previous.contains(&object.name_any()))
+                            .map(|object| Ok(event_line("ADDED", &row(object))))
+                            .collect(),
+                        None => Vec::new(),
+                    };
+                    seen = Some(names);
+                    lines
+                }
+                Ok(watcher::Event::Applied(object)) => {
+                    let name = object.name_any();
+                    let known = seen.get_or_insert_with(HashSet::new);
+                    let kind = if known.insert(name) { "ADDED" } else { "MODIFIED" };
+                    vec![Ok(event_line(kind, &row(&object)))]
+                }
+                Ok(watcher::Event::Deleted(object)) => {
+                    if let Some(known) = seen.as_mut() {
+                        known.remove(&object.name_any());
+                    }
+                    vec![Ok(event_line("DELETED", &row(&object)))]
+                }
+            };
+            futures::stream::iter(lines)
+        })
+        .boxed()
+}
+
+fn event_line(kind: &str, row: &str) -> String {
+    format!("{} {:<8} {}", Utc::now().format("%Y-%m-%dT%H:%M:%SZ"), kind, row)
+}
+
+/// Reads `kubectl logs <pod> [options]`. Without `--tail` the last 100 lines are shown.
+/// `None` when no pod is named.
+fn log_request(command: &Command) -> Result<Option<(String, LogParams)>> {
+    const OPTIONS: &[&str] = &["f", "follow", "timestamps", "c", "container", "tail", "since"];
+    if let Some(option) = command.flags.keys().find(|option| !OPTIONS.contains(&option.as_str())) {
+        anyhow::bail!("Unknown kubectl logs option '{}{}'", if option.len() == 1 { "-" } else { "--" }, option);
+    }
+    let value = |names: &[&str]| -> Result<Option<&str>> {
+        match names.iter().find_map(|name| command.flags.get(*name)) {
+            Some(Some(value)) => Ok(Some(value.as_str())),
+            Some(None) => anyhow::bail!("--{} requires a value", names[names.len() - 1]),
+            None => Ok(None),
+        }
+    };
+
+    let mut params = LogParams { tail_lines: Some(100), ..Default::default() };
+    params.follow = command.flags.contains_key("f") || command.flags.contains_key("follow");
+    params.timestamps = command.flags.contains_key("timestamps");
+    params.container = value(&["c", "container"])?.map(String::from);
+    if let Some(tail) = value(&["tail"])? {
+        params.tail_lines = match tail {
+            "all" => None,
+            lines => Some(lines.parse::<i64>().context("Invalid --tail value, expected a line count or 'all'")?),
+        };
+    }
+    if let Some(since) = value(&["since"])? {
+        let since = humantime::parse_duration(since)
+            .context("Invalid --since value, expected e.g. 30s, 5m or 1h")?;
+        params.since_seconds = Some(since.as_secs() as i64);
+    }
+
+    Ok(command.args.get(1).map(|pod| (pod.clone(), params)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = "apiVersion: v1
+kind: Config
+current-context: fixture
+clusters:
+- name: fixture
+  cluster:
+    server: https://127.0.0.1:6443
+contexts:
+- name: fixture
+  context:
+    cluster: fixture
+    user: fixture
+    namespace: team-a
+- name: other
+  context:
+    cluster: fixture
+    user: fixture
+users:
+- name: fixture
+  user:
+    token: fixture-token
+";
+
+    const SECOND: &str = "apiVersion: v1
+kind: Config
+current-context: second
+contexts:
+- name: second
+  context:
+    cluster: fixture
+    user: fixture
+    namespace: team-b
+";
+
+    #[test]
+    fn test_kubeconfig_env_selects_current_context() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fixture = temp_dir.path().join("fixture.yaml");
+        let second = temp_dir.path().join("second.yaml");
+        std::fs::write(&fixture, FIXTURE).unwrap();
+        std::fs::write(&second, SECOND).unwrap();
+        let missing = temp_dir.path().join("missing.yaml");
+        let kubeconfig_env = std::env::join_paths([&fixture, &missing, &second]).unwrap();
+
+        let target = KubernetesConfig::default().resolve(Some(&kubeconfig_env)).unwrap();
+        assert_eq!(target.context, "fixture");
+        assert_eq!(target.namespace, "team-a");
+        assert!(target.kubeconfig.contexts.iter().any(|named| named.name == "second"));
+
+        // Explicit settings win over the kubeconfig's
+        let config = KubernetesConfig { context: Some("other".to_string()), ..Default::default() };
+        assert_eq!(config.resolve(Some(&kubeconfig_env)).unwrap().namespace, "default");
+        let config = KubernetesConfig { context: Some("second".to_string()), ..Default::default() };
+        assert_eq!(config.resolve(Some(&kubeconfig_env)).unwrap().namespace, "team-b");
+        let config = KubernetesConfig { namespace: Some("mine".to_string()), kubeconfig_path: Some(second), ..Default::default() };
+        let target = config.resolve(Some(&kubeconfig_env)).unwrap();
+        assert_eq!((target.context.as_str(), target.namespace.as_str()), ("second", "mine"));
+
+        assert!(KubernetesConfig::default().resolve(Some(missing.as_os_str())).is_err());
+    }
+
+    /// Uses the current context of `KUBECONFIG` (or `~/.kube/config`) in namespace `default`.
+    #[cfg(feature = "kind")]
+    fn kind_plugin() -> KubernetesPlugin {
+        KubernetesPlugin {
+            config: Mutex::new(KubernetesConfig { namespace: Some("default".to_string()), ..Default::default() }),
+            namespace: Mutex::new("default".to_string()),
+            client: Mutex::new(None),
+        }
+    }
+
+    // Needs a deployment named by NEXUS_KIND_DEPLOYMENT (default `nexusshell-test`)
+    // in the default namespace of the current kubeconfig context.
+    #[cfg(feature = "kind")]
+    #[tokio::test]
+    async fn test_rollout_restart_sets_annotation() {
+        let name = std::env::var("NEXUS_KIND_DEPLOYMENT")
+            .unwrap_or_else(|_| "nexusshell-test".to_string());
+        let plugin = kind_plugin();
+
+        plugin.rollout_restart(&name).await.unwrap();
+
+        let (client, _) = plugin.init_client().await.unwrap();
+        let deployments: Api<Deployment> = Api::namespaced(client, "default");
+        let deployment = deployments.get(&name).await.unwrap();
+        let annotations = deployment.spec.unwrap().template.metadata.unwrap().annotations.unwrap();
+        assert!(annotations.contains_key(RESTARTED_AT_ANNOTATION));
+
+        let status = plugin.rollout_status(&name, Duration::from_secs(120)).await.unwrap();
+        assert!(status.contains("successfully rolled out"));
+    }
+
+    #[test]
+    fn test_log_options_keep_the_pod_name() {
+        let parse = |line: &str| {
+            let command = crate::shell::Parser::new().parse_pipeline(line).unwrap().remove(0);
+            log_request(&command).unwrap()
+        };
+
+        let (pod, params) = parse("kubectl logs -f web -c app --tail=5").unwrap();
+        assert_eq!(pod, "web");
+        assert!(params.follow);
+        assert_eq!(params.container.as_deref(), Some("app"));
+        assert_eq!(params.tail_lines, Some(5));
+
+        let (pod, params) = parse("kubectl logs --timestamps web --since 1m --tail all").unwrap();
+        assert_eq!(pod, "web");
+        assert!(params.timestamps && !params.follow);
+        assert_eq!(params.since_seconds, Some(60));
+        assert_eq!(params.tail_lines, None);
+
+        assert!(parse("kubectl logs -f").is_none());
+    }
+
+    #[cfg(feature = "kind")]
+    #[tokio::test]
+    async fn test_logs_honor_tail_limit() {
+        let plugin = kind_plugin();
+        let (client, _) = plugin.init_client().await.unwrap();
+        let pods: Api<Pod> = Api::namespaced(client, "default");
+
+        let name = "nexusshell-logs-test";
+        let pod: Pod = serde_json::from_value(serde_json::json!({
+            "metadata": { "name": name },
+            "spec": {
+                "restartPolicy": "Never",
+                "containers": [{ "name": "counter", "image": "busybox", "command": ["seq", "1", "20"] }]
+            }
+        })).unwrap();
+        let _ = pods.delete(name, &DeleteParams::default()).await;
+        pods.create(&PostParams::default(), &pod).await.unwrap();
+
+        for _ in 0..60 {
+            let phase = pods.get(name).await.unwrap().status.and_then(|s| s.phase);
+            if phase.as_deref() == Some("Succeeded") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+
+        let params = LogParams { tail_lines: Some(5), ..Default::default() };
+        let logs = plugin.get_pod_logs(name, &params).await.unwrap();
+        pods.delete(name, &DeleteParams::default()).await.unwrap();
+
+        assert_eq!(logs.lines().collect::<Vec<_>>(), vec!["16", "17", "18", "19", "20"]);
+    }
+
+    #[cfg(feature = "kind")]
+    #[tokio::test]
+    async fn test_watch_reports_added_pod() {
+        let plugin = kind_plugin();
+        let (client, _) = plugin.init_client().await.unwrap();
+        let pods: Api<Pod> = Api::namespaced(client, "default");
+
+        let name = "nexusshell-watch-test";
+        let _ = pods.delete(name, &DeleteParams::default()).await;
+        let mut events = watch_events(pods.clone(), pod_row);
+
+        // Create the pod once the watcher has had time to take its initial listing
+        let creator = {
+            let pods = pods.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                let pod: Pod = serde_json::from_value(serde_json::json!({
+                    "metadata": { "name": name },
+                    "spec": {
+                        "restartPolicy": "Never",
+                        "containers": [{ "name": "sleeper", "image": "busybox", "command": ["sleep", "30"] }]
+                    }
+                })).unwrap();
+                pods.create(&PostParams::default(), &pod).await.unwrap();
+            })
+        };
+
+        let added = tokio::time::timeout(Duration::from_secs(60), async {
+            while let Some(line) = events.next().await {
+                let line = line.unwrap();
+                if line.contains(name) {
+                    return line;
+                }
+            }
+            panic!("watch ended before the pod appeared");
+        }).await.unwrap();
+        creator.await.unwrap();
+        pods.delete(name, &DeleteParams::default()).await.unwrap();
+
+        assert!(added.split_whitespace().nth(1) == Some("ADDED"), "{}", added);
+    }
+}
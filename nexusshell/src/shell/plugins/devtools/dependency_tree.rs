@@ -0,0 +1,62 @@
+use std::collections::{BTreeMap, HashSet};
+
+/// Levels shown below the package when `--depth` isn't given.
+pub const DEFAULT_DEPTH: usize = 3;
+/// `--depth` is capped here; registries are queried once per package per level.
+pub const MAX_DEPTH: usize = 10;
+
+/// Each package (`name version`) with the packages it depends on. Packages that weren't
+/// resolved, such as those past the depth limit, have no entry.
+pub type DependencyGraph = BTreeMap<String, Vec<String>>;
+
+/// Renders `root` and its dependencies as a `├──`/`└──` tree, `max_depth` levels deep; deeper
+/// levels are cut to `…`. A package whose dependencies were already shown is marked `(*)`
+/// instead of repeating them, and one that depends back on a package above it `(cycle)`.
+pub fn render(root: &str, graph: &DependencyGraph, max_depth: usize) -> String {
+    let mut lines = vec![root.to_string()];
+    let mut expanded = HashSet::from([root]);
+    let mut path = vec![root];
+    render_children(root, graph, max_depth, "", &mut path, &mut expanded, &mut lines);
+    lines.join("\n")
+}
+
+fn render_children<'a>(
+    node: &'a str,
+    graph: &'a DependencyGraph,
+    depth_left: usize,
+    prefix: &str,
+    path: &mut Vec<&'a str>,
+    expanded: &mut HashSet<&'a str>,
+    lines: &mut Vec<String>,
+) {
+    let children = match graph.get(node) {
+        Some(children) if !children.is_empty() => children,
+        _ => return,
+    };
+    if depth_left == 0 {
+        lines.push(format!("{}└── …", prefix));
+        return;
+    }
+
+    for (i, child) in children.iter().enumerate() {
+        let (branch, indent) = if i + 1 == children.len() { ("└── ", "    ") } else { ("├── ", "│   ") };
+        let has_children = graph.get(child).is_some_and(|children| !children.is_empty());
+        if path.contains(&child.as_str()) {
+            lines.push(format!("{}{}{} (cycle)", prefix, branch, child));
+            continue;
+        }
+        if has_children && expanded.contains(child.as_str()) {
+            lines.push(format!("{}{}{} (*)", prefix, branch, child));
+            continue;
+        }
+
+        lines.push(format!("{}{}{}", prefix, branch, child));
+        // Only count it as shown if its own dependencies fit under the depth limit
+        if depth_left > 1 {
+            expanded.insert(child);
+        }
+        path.push(child);
+        render_children(child, graph, depth_left - 1, &format!("{}{}", prefix, indent), path, expanded, lines);
+        path.pop();
+    }
+}
@@ -3,11 +3,14 @@ use serde::{Serialize, Deserialize};
 use tokio::process::Command;
 use anyhow::Result;
 use std::path::{Path, PathBuf};
-use tokio::fs;
-use regex::Regex;
-use lazy_static::lazy_static;
 use std::collections::HashMap;
-use ignore::Walk;
+use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
+
+/// Per-directory file listing extra paths `format dir` should skip, in `.gitignore` syntax.
+pub const IGNORE_FILE: &str = ".nexusformatignore";
+/// Build output and vendored dependencies are never formatted.
+const DEFAULT_EXCLUDES: &[&str] = &["target/", "node_modules/", ".git/"];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FormatterConfig {
@@ -43,12 +46,44 @@ pub struct FormattingStats {
 #[async_trait]
 pub trait CodeFormatter: Send + Sync {
     async fn format_file(&self, path: &Path) -> Result<bool>;
-    async fn format_directory(&self, path: &Path, recursive: bool) -> Result<FormattingStats>;
+    async fn format_directory(&self, path: &Path, recursive: bool, exclude: &[String]) -> Result<FormattingStats>;
     fn supports_language(&self, language: &str) -> bool;
     fn get_config(&self) -> &FormatterConfig;
     fn set_config(&mut self, config: FormatterConfig);
 }
 
+/// Files under `path` with one of `extensions`. Paths matched by `.gitignore`, `IGNORE_FILE`,
+/// `DEFAULT_EXCLUDES` or an `exclude` glob are skipped; walk errors are returned alongside.
+pub fn source_files(path: &Path, recursive: bool, exclude: &[String], extensions: &[&str]) -> Result<(Vec<PathBuf>, Vec<String>)> {
+    let mut overrides = OverrideBuilder::new(path);
+    for pattern in DEFAULT_EXCLUDES.iter().copied().chain(exclude.iter().map(String::as_str)) {
+        overrides.add(&format!("!{}", pattern))?;
+    }
+
+    let mut builder = WalkBuilder::new(path);
+    builder
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .overrides(overrides.build()?)
+        .max_depth(if recursive { None } else { Some(1) });
+
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    for entry in builder.build() {
+        match entry {
+            Ok(entry) => {
+                let matches = entry.path().extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| extensions.contains(&ext));
+                if matches && entry.file_type().is_some_and(|t| t.is_file()) {
+                    files.push(entry.into_path());
+                }
+            }
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    Ok((files, errors))
+}
+
 pub struct RustFormatter {
     config: FormatterConfig,
 }
@@ -71,44 +106,31 @@ impl RustFormatter {
 #[async_trait]
 impl CodeFormatter for RustFormatter {
     async fn format_file(&self, path: &Path) -> Result<bool> {
-        if path.extension().map_or(false, |ext| ext == "rs") {
+        if path.extension().is_some_and(|ext| ext == "rs") {
             self.run_rustfmt(path).await
         } else {
             Ok(false)
         }
     }
 
-    async fn format_directory(&self, path: &Path, recursive: bool) -> Result<FormattingStats> {
+    async fn format_directory(&self, path: &Path, recursive: bool, exclude: &[String]) -> Result<FormattingStats> {
+        let (files, errors) = source_files(path, recursive, exclude, &["rs"])?;
         let mut stats = FormattingStats {
             files_processed: 0,
             files_changed: 0,
             total_changes: 0,
-            errors: Vec::new(),
+            errors,
         };
 
-        let walker = if recursive {
-            Walk::new(path)
-        } else {
-            Walk::new(path).max_depth(1)
-        };
-
-        for entry in walker {
-            match entry {
-                Ok(entry) => {
-                    let path = entry.path();
-                    if path.extension().map_or(false, |ext| ext == "rs") {
-                        stats.files_processed += 1;
-                        match self.format_file(path).await {
-                            Ok(true) => {
-                                stats.files_changed += 1;
-                                stats.total_changes += 1;
-                            }
-                            Err(e) => stats.errors.push(format!("{}: {}", path.display(), e)),
-                            _ => {}
-                        }
-                    }
+        for path in files {
+            stats.files_processed += 1;
+            match self.format_file(&path).await {
+                Ok(true) => {
+                    stats.files_changed += 1;
+                    stats.total_changes += 1;
                 }
-                Err(e) => stats.errors.push(e.to_string()),
+                Err(e) => stats.errors.push(format!("{}: {}", path.display(), e)),
+                _ => {}
             }
         }
 
@@ -152,44 +174,31 @@ impl PythonFormatter {
 #[async_trait]
 impl CodeFormatter for PythonFormatter {
     async fn format_file(&self, path: &Path) -> Result<bool> {
-        if path.extension().map_or(false, |ext| ext == "py") {
+        if path.extension().is_some_and(|ext| ext == "py") {
             self.run_black(path).await
         } else {
             Ok(false)
         }
     }
 
-    async fn format_directory(&self, path: &Path, recursive: bool) -> Result<FormattingStats> {
+    async fn format_directory(&self, path: &Path, recursive: bool, exclude: &[String]) -> Result<FormattingStats> {
+        let (files, errors) = source_files(path, recursive, exclude, &["py"])?;
         let mut stats = FormattingStats {
             files_processed: 0,
             files_changed: 0,
             total_changes: 0,
-            errors: Vec::new(),
-        };
-
-        let walker = if recursive {
-            Walk::new(path)
-        } else {
-            Walk::new(path).max_depth(1)
+            errors,
         };
 
-        for entry in walker {
-            match entry {
-                Ok(entry) => {
-                    let path = entry.path();
-                    if path.extension().map_or(false, |ext| ext == "py") {
-                        stats.files_processed += 1;
-                        match self.format_file(path).await {
-                            Ok(true) => {
-                                stats.files_changed += 1;
-                                stats.total_changes += 1;
-                            }
-                            Err(e) => stats.errors.push(format!("{}: {}", path.display(), e)),
-                            _ => {}
-                        }
-                    }
+        for path in files {
+            stats.files_processed += 1;
+            match self.format_file(&path).await {
+                Ok(true) => {
+                    stats.files_changed += 1;
+                    stats.total_changes += 1;
                 }
-                Err(e) => stats.errors.push(e.to_string()),
+                Err(e) => stats.errors.push(format!("{}: {}", path.display(), e)),
+                _ => {}
             }
         }
 
@@ -238,45 +247,31 @@ impl JavaScriptFormatter {
 #[async_trait]
 impl CodeFormatter for JavaScriptFormatter {
     async fn format_file(&self, path: &Path) -> Result<bool> {
-        if path.extension().map_or(false, |ext| ext == "js" || ext == "jsx" || ext == "ts" || ext == "tsx") {
+        if path.extension().is_some_and(|ext| ext == "js" || ext == "jsx" || ext == "ts" || ext == "tsx") {
             self.run_prettier(path).await
         } else {
             Ok(false)
         }
     }
 
-    async fn format_directory(&self, path: &Path, recursive: bool) -> Result<FormattingStats> {
+    async fn format_directory(&self, path: &Path, recursive: bool, exclude: &[String]) -> Result<FormattingStats> {
+        let (files, errors) = source_files(path, recursive, exclude, &["js", "jsx", "ts", "tsx"])?;
         let mut stats = FormattingStats {
             files_processed: 0,
             files_changed: 0,
             total_changes: 0,
-            errors: Vec::new(),
+            errors,
         };
 
-        let walker = if recursive {
-            Walk::new(path)
-        } else {
-            Walk::new(path).max_depth(1)
-        };
-
-        for entry in walker {
-            match entry {
-                Ok(entry) => {
-                    let path = entry.path();
-                    if path.extension().map_or(false, |ext| 
-                        ext == "js" || ext == "jsx" || ext == "ts" || ext == "tsx") {
-                        stats.files_processed += 1;
-                        match self.format_file(path).await {
-                            Ok(true) => {
-                                stats.files_changed += 1;
-                                stats.total_changes += 1;
-                            }
-                            Err(e) => stats.errors.push(format!("{}: {}", path.display(), e)),
-                            _ => {}
-                        }
-                    }
+        for path in files {
+            stats.files_processed += 1;
+            match self.format_file(&path).await {
+                Ok(true) => {
+                    stats.files_changed += 1;
+                    stats.total_changes += 1;
                 }
-                Err(e) => stats.errors.push(e.to_string()),
+                Err(e) => stats.errors.push(format!("{}: {}", path.display(), e)),
+                _ => {}
             }
         }
 
@@ -318,12 +313,8 @@ impl FormatterManager {
         }
     }
 
-    pub fn get_formatter(&self, language: &str) -> Option<&Box<dyn CodeFormatter>> {
-        self.formatters.values().find(|f| f.supports_language(language))
-    }
-
-    pub fn get_formatter_mut(&mut self, language: &str) -> Option<&mut Box<dyn CodeFormatter>> {
-        self.formatters.values_mut().find(|f| f.supports_language(language))
+    pub fn get_formatter(&self, language: &str) -> Option<&dyn CodeFormatter> {
+        self.formatters.values().find(|f| f.supports_language(language)).map(|f| f.as_ref())
     }
 
     pub async fn format_file(&self, path: &Path) -> Result<bool> {
@@ -346,7 +337,7 @@ impl FormatterManager {
         }
     }
 
-    pub async fn format_directory(&self, path: &Path, recursive: bool) -> Result<FormattingStats> {
+    pub async fn format_directory(&self, path: &Path, recursive: bool, exclude: &[String]) -> Result<FormattingStats> {
         let mut total_stats = FormattingStats {
             files_processed: 0,
             files_changed: 0,
@@ -355,7 +346,7 @@ impl FormatterManager {
         };
 
         for formatter in self.formatters.values() {
-            let stats = formatter.format_directory(path, recursive).await?;
+            let stats = formatter.format_directory(path, recursive, exclude).await?;
             total_stats.files_processed += stats.files_processed;
             total_stats.files_changed += stats.files_changed;
             total_stats.total_changes += stats.total_changes;
@@ -1,19 +1,94 @@
 mod package_manager;
 mod formatter;
+mod dependency_tree;
+#[cfg(test)]
+mod tests;
 
 use async_trait::async_trait;
 use super::super::{Command, Environment, Plugin};
+use super::super::render::{OutputFormat, Record};
 use anyhow::Result;
-use package_manager::{PackageManager, NodePackageManager, CargoPackageManager, PackageManagerConfig};
+use package_manager::{find_outdated, Package, PackageManager, NodePackageManager, CargoPackageManager, PackageManagerConfig};
 use formatter::{FormatterManager, FormatterConfig};
 use std::path::PathBuf;
 use colored::*;
-use std::collections::HashMap;
+use semver::Version;
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+/// One row of `dev package list`.
+#[derive(Debug, Serialize)]
+struct PackageRow {
+    name: String,
+    version: String,
+    description: Option<String>,
+}
+
+impl From<Package> for PackageRow {
+    fn from(package: Package) -> Self {
+        PackageRow {
+            name: package.name,
+            version: package.version.to_string(),
+            description: package.description,
+        }
+    }
+}
+
+impl Record for PackageRow {
+    fn headers() -> &'static [&'static str] {
+        &["PACKAGE", "VERSION", "DESCRIPTION"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![self.name.clone(), self.version.clone(), self.description.clone().unwrap_or_default()]
+    }
+}
+
+/// One row of `dev package list --outdated`. `latest` is missing when the registry lookup
+/// failed, and then the package isn't counted as outdated.
+#[derive(Debug, Serialize)]
+struct OutdatedRow {
+    name: String,
+    version: String,
+    latest: Option<String>,
+    outdated: bool,
+}
+
+impl From<(Package, Option<Version>)> for OutdatedRow {
+    fn from((package, latest): (Package, Option<Version>)) -> Self {
+        OutdatedRow {
+            outdated: latest.as_ref().is_some_and(|latest| *latest > package.version),
+            latest: latest.map(|latest| latest.to_string()),
+            version: package.version.to_string(),
+            name: package.name,
+        }
+    }
+}
+
+impl Record for OutdatedRow {
+    fn headers() -> &'static [&'static str] {
+        &["PACKAGE", "VERSION", "LATEST", "STATUS"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        let status = match (&self.latest, self.outdated) {
+            (None, _) => "unknown".dimmed().to_string(),
+            (Some(_), true) => "outdated".yellow().to_string(),
+            (Some(_), false) => "up to date".green().to_string(),
+        };
+        vec![
+            self.name.clone(),
+            self.version.clone(),
+            self.latest.clone().unwrap_or_else(|| "?".to_string()),
+            status,
+        ]
+    }
+}
 
 pub struct DevToolsPlugin {
-    npm: NodePackageManager,
-    cargo: CargoPackageManager,
-    formatter: FormatterManager,
+    npm: RwLock<NodePackageManager>,
+    cargo: RwLock<CargoPackageManager>,
+    formatter: RwLock<FormatterManager>,
 }
 
 impl DevToolsPlugin {
@@ -22,20 +97,23 @@ impl DevToolsPlugin {
         let formatter_config = FormatterConfig::default();
 
         Ok(DevToolsPlugin {
-            npm: NodePackageManager::new(package_config.clone()).await?,
-            cargo: CargoPackageManager::new(package_config).await?,
-            formatter: FormatterManager::new(formatter_config),
+            npm: RwLock::new(NodePackageManager::new(package_config.clone()).await?),
+            cargo: RwLock::new(CargoPackageManager::new(package_config).await?),
+            formatter: RwLock::new(FormatterManager::new(formatter_config)),
         })
     }
 
-    async fn handle_package(&self, args: &[String]) -> Result<String> {
+    async fn handle_package(&self, command: &Command) -> Result<String> {
+        let args = &command.args;
         if args.len() < 3 {
-            return Ok("Usage: dev package [npm|cargo] [install|uninstall|update|list|search|info] [args...]".to_string());
+            return Ok("Usage: dev package [npm|cargo] [install|uninstall|update|list [--outdated]|search|info] [args...]".to_string());
         }
 
+        let npm = self.npm.read().await;
+        let cargo = self.cargo.read().await;
         let manager = match args[1].as_str() {
-            "npm" => &self.npm as &dyn PackageManager,
-            "cargo" => &self.cargo as &dyn PackageManager,
+            "npm" => &*npm as &dyn PackageManager,
+            "cargo" => &*cargo as &dyn PackageManager,
             _ => return Ok("Supported package managers: npm, cargo".to_string()),
         };
 
@@ -66,23 +144,21 @@ impl DevToolsPlugin {
             }
 
             "list" => {
+                let format = OutputFormat::from_command(command)?;
                 let packages = manager.list_installed().await?;
-                if packages.is_empty() {
+                if packages.is_empty() && format == OutputFormat::Table {
                     return Ok("No packages installed".to_string());
                 }
 
-                let mut output = String::new();
-                output.push_str(&format!("{:<30} {:<15} {:<40}\n",
-                    "PACKAGE", "VERSION", "DESCRIPTION"));
-
-                for package in packages {
-                    output.push_str(&format!("{:<30} {:<15} {:<40}\n",
-                        package.name,
-                        package.version,
-                        package.description.unwrap_or_default()));
+                if command.flags.contains_key("outdated") {
+                    let rows: Vec<OutdatedRow> = find_outdated(manager, packages).await
+                        .into_iter()
+                        .map(OutdatedRow::from)
+                        .collect();
+                    return format.render(&rows);
                 }
-
-                Ok(output)
+                let rows: Vec<PackageRow> = packages.into_iter().map(PackageRow::from).collect();
+                format.render(&rows)
             }
 
             "search" => {
@@ -110,7 +186,17 @@ impl DevToolsPlugin {
 
             "info" => {
                 if args.len() < 4 {
-                    return Ok("Usage: dev package [npm|cargo] info <package>".to_string());
+                    return Ok("Usage: dev package [npm|cargo] info <package> [--deps-tree [--depth <n>]]".to_string());
+                }
+                if command.flags.contains_key("deps-tree") {
+                    let depth = match command.flags.get("depth") {
+                        Some(Some(depth)) => depth.parse::<usize>()
+                            .map_err(|_| anyhow::anyhow!("--depth must be a number, got '{}'", depth))?
+                            .min(dependency_tree::MAX_DEPTH),
+                        _ => dependency_tree::DEFAULT_DEPTH,
+                    };
+                    let (root, graph) = manager.dependency_tree(&args[3], depth).await?;
+                    return Ok(dependency_tree::render(&root, &graph, depth));
                 }
                 let package = manager.get_info(&args[3]).await?;
                 
@@ -142,9 +228,10 @@ impl DevToolsPlugin {
         }
     }
 
-    async fn handle_format(&self, args: &[String]) -> Result<String> {
+    async fn handle_format(&self, command: &Command) -> Result<String> {
+        let args = &command.args;
         if args.len() < 2 {
-            return Ok("Usage: dev format [file|dir] <path> [--recursive]".to_string());
+            return Ok("Usage: dev format [file|dir] <path> [--recursive] [--exclude <glob>[,<glob>...]]".to_string());
         }
 
         match args[1].as_str() {
@@ -153,7 +240,7 @@ impl DevToolsPlugin {
                     return Ok("Usage: dev format file <path>".to_string());
                 }
                 let path = PathBuf::from(&args[2]);
-                match self.formatter.format_file(&path).await {
+                match self.formatter.read().await.format_file(&path).await {
                     Ok(true) => Ok(format!("Formatted {}", path.display())),
                     Ok(false) => Ok(format!("No changes needed for {}", path.display())),
                     Err(e) => Ok(format!("Error formatting {}: {}", path.display(), e)),
@@ -162,15 +249,19 @@ impl DevToolsPlugin {
 
             "dir" => {
                 if args.len() < 3 {
-                    return Ok("Usage: dev format dir <path> [--recursive]".to_string());
+                    return Ok("Usage: dev format dir <path> [--recursive] [--exclude <glob>[,<glob>...]]".to_string());
                 }
                 let path = PathBuf::from(&args[2]);
-                let recursive = args.get(3).map_or(false, |arg| arg == "--recursive");
+                let recursive = command.flags.contains_key("recursive");
+                let exclude: Vec<String> = command.flags.get("exclude")
+                    .and_then(|globs| globs.as_deref())
+                    .map(|globs| globs.split(',').filter(|g| !g.is_empty()).map(String::from).collect())
+                    .unwrap_or_default();
 
-                let stats = self.formatter.format_directory(&path, recursive).await?;
+                let stats = self.formatter.read().await.format_directory(&path, recursive, &exclude).await?;
                 
                 let mut output = String::new();
-                output.push_str(&format!("Formatting Results:\n"));
+                output.push_str("Formatting Results:\n");
                 output.push_str(&format!("Files processed: {}\n", stats.files_processed));
                 output.push_str(&format!("Files changed: {}\n", stats.files_changed));
                 output.push_str(&format!("Total changes: {}\n", stats.total_changes));
@@ -197,7 +288,8 @@ impl DevToolsPlugin {
         match args[1].as_str() {
             "formatter" => {
                 if args.len() < 3 {
-                    let config = self.formatter.get_formatter("rust")
+                    let formatter = self.formatter.read().await;
+                    let config = formatter.get_formatter("rust")
                         .map(|f| f.get_config())
                         .ok_or_else(|| anyhow::anyhow!("No formatter found"))?;
 
@@ -256,7 +348,7 @@ impl DevToolsPlugin {
                         }
                     }
 
-                    self.formatter.update_config(config);
+                    self.formatter.write().await.update_config(config);
                     Ok("Updated formatter configuration".to_string())
                 }
             }
@@ -276,6 +368,7 @@ impl DevToolsPlugin {
                             output.push_str(&format!("Cache Directory: {}\n", config.cache_dir.display()));
                             output.push_str(&format!("Max Concurrent Downloads: {}\n", config.max_concurrent_downloads));
                             output.push_str(&format!("Timeout: {:?}\n", config.timeout));
+                            output.push_str(&format!("Request Interval: {:?}\n", config.request_interval));
 
                             Ok(output)
                         } else {
@@ -307,16 +400,22 @@ impl DevToolsPlugin {
                                             i += 2;
                                         }
                                     }
+                                    "--request-interval" => {
+                                        if i + 1 < args.len() {
+                                            config.request_interval = std::time::Duration::from_millis(args[i + 1].parse()?);
+                                            i += 2;
+                                        }
+                                    }
                                     _ => i += 1,
                                 }
                             }
 
                             match args[2].as_str() {
                                 "npm" => {
-                                    self.npm = NodePackageManager::new(config).await?;
+                                    *self.npm.write().await = NodePackageManager::new(config).await?;
                                 }
                                 "cargo" => {
-                                    self.cargo = CargoPackageManager::new(config).await?;
+                                    *self.cargo.write().await = CargoPackageManager::new(config).await?;
                                 }
                                 _ => unreachable!(),
                             }
@@ -345,8 +444,8 @@ impl Plugin for DevToolsPlugin {
 
     async fn execute(&self, command: &Command, _env: &Environment) -> Result<String> {
         match command.args.first().map(|s| s.as_str()) {
-            Some("package") => self.handle_package(&command.args).await,
-            Some("format") => self.handle_format(&command.args).await,
+            Some("package") => self.handle_package(command).await,
+            Some("format") => self.handle_format(command).await,
             Some("config") => self.handle_config(&command.args).await,
             _ => Ok("Available commands: package, format, config".to_string()),
         }
@@ -4,12 +4,18 @@ use tokio::process::Command;
 use anyhow::Result;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Mutex;
 use tokio::fs;
-use semver::Version;
+use semver::{Version, VersionReq};
+use super::dependency_tree::DependencyGraph;
+use super::super::super::util::{http_retryable, retry, RetryPolicy};
 use regex::Regex;
 use lazy_static::lazy_static;
 use chrono::{DateTime, Utc};
 use indicatif::{ProgressBar, ProgressStyle};
+use futures::stream::{self, StreamExt};
+
+const CRATES_IO_API: &str = "https://crates.io/api/v1";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Package {
@@ -25,11 +31,14 @@ pub struct Package {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PackageManagerConfig {
     pub default_registry: String,
     pub cache_dir: PathBuf,
     pub max_concurrent_downloads: usize,
     pub timeout: std::time::Duration,
+    /// Least time between two crates.io requests; its crawler policy asks for one per second
+    pub request_interval: std::time::Duration,
 }
 
 impl Default for PackageManagerConfig {
@@ -39,6 +48,7 @@ impl Default for PackageManagerConfig {
             cache_dir: dirs::cache_dir().unwrap_or_default().join("nexusshell/packages"),
             max_concurrent_downloads: 5,
             timeout: std::time::Duration::from_secs(300),
+            request_interval: std::time::Duration::from_secs(1),
         }
     }
 }
@@ -51,22 +61,87 @@ pub trait PackageManager: Send + Sync {
     async fn list_installed(&self) -> Result<Vec<Package>>;
     async fn search(&self, query: &str) -> Result<Vec<Package>>;
     async fn get_info(&self, package: &str) -> Result<Package>;
+    /// The newest release in the registry, cached for the life of the manager.
+    async fn latest_version(&self, package: &str) -> Result<Version>;
+    /// The root node of `package` and its dependencies, resolved `max_depth` levels deep.
+    async fn dependency_tree(&self, package: &str, max_depth: usize) -> Result<(String, DependencyGraph)>;
+    fn config(&self) -> &PackageManagerConfig;
+}
+
+/// Each installed package with the registry's latest version, or `None` where the lookup
+/// failed. At most `max_concurrent_downloads` lookups run at once; the order is kept.
+pub async fn find_outdated(manager: &dyn PackageManager, packages: Vec<Package>) -> Vec<(Package, Option<Version>)> {
+    let limit = manager.config().max_concurrent_downloads.max(1);
+    stream::iter(packages)
+        .map(|package| async move {
+            let latest = manager.latest_version(&package.name).await.ok();
+            (package, latest)
+        })
+        .buffered(limit)
+        .collect()
+        .await
+}
+
+fn node_label(name: &str, version: &str) -> String {
+    format!("{} v{}", name, version)
+}
+
+/// Adds the package `name` at `node` of `npm ls --json` output, and everything under it, to
+/// `graph`. Returns its label. npm prints a package's dependencies once and marks its other
+/// appearances `deduped`, so an empty entry never replaces a full one.
+fn add_npm_node(name: &str, node: &serde_json::Value, graph: &mut DependencyGraph) -> String {
+    let label = node_label(name, node["version"].as_str().unwrap_or("?"));
+    let children: Vec<String> = node["dependencies"]
+        .as_object()
+        .map(|deps| deps.iter().map(|(name, dep)| add_npm_node(name, dep, graph)).collect())
+        .unwrap_or_default();
+    if !children.is_empty() || !graph.contains_key(&label) {
+        graph.insert(label.clone(), children);
+    }
+    label
+}
+
+/// The first `package` node in `npm ls --json` output, at any depth.
+fn find_npm_node<'a>(node: &'a serde_json::Value, package: &str) -> Option<&'a serde_json::Value> {
+    let deps = node["dependencies"].as_object()?;
+    deps.get(package).or_else(|| deps.values().find_map(|dep| find_npm_node(dep, package)))
+}
+
+/// GETs `url` as JSON, retrying server errors and timeouts with exponential backoff.
+async fn get_json(client: &reqwest::Client, url: &str) -> Result<serde_json::Value> {
+    let response = retry(&RetryPolicy::default(), http_retryable, || async {
+        client.get(url).send().await?.error_for_status()
+    }).await?;
+    Ok(response.json().await?)
 }
 
 pub struct NodePackageManager {
     config: PackageManagerConfig,
-    installed_packages: HashMap<String, Package>,
+    registry_url: String,
+    client: reqwest::Client,
+    latest_versions: Mutex<HashMap<String, Version>>,
 }
 
 impl NodePackageManager {
     pub async fn new(config: PackageManagerConfig) -> Result<Self> {
         fs::create_dir_all(&config.cache_dir).await?;
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()?;
         Ok(NodePackageManager {
+            registry_url: config.default_registry.trim_end_matches('/').to_string(),
             config,
-            installed_packages: HashMap::new(),
+            client,
+            latest_versions: Mutex::new(HashMap::new()),
         })
     }
 
+    #[cfg(test)]
+    pub fn with_registry_url(mut self, registry_url: &str) -> Self {
+        self.registry_url = registry_url.trim_end_matches('/').to_string();
+        self
+    }
+
     async fn run_npm_command(&self, args: &[&str]) -> Result<String> {
         let output = Command::new("npm")
             .args(args)
@@ -116,11 +191,11 @@ impl PackageManager for NodePackageManager {
 
         let pb = ProgressBar::new_spinner();
         pb.set_style(ProgressStyle::default_spinner()
-            .template("{spinner:.green} [{elapsed_precise}] {msg}")
+            .template("{spinner:.green} [{elapsed_precise}] {msg}")?
             .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈"));
         pb.set_message(format!("Installing {}", package_spec));
 
-        let output = self.run_npm_command(&["install", &package_spec]).await?;
+        self.run_npm_command(&["install", &package_spec]).await?;
         pb.finish_with_message(format!("Installed {}", package_spec));
 
         // Parse installed package info
@@ -131,7 +206,7 @@ impl PackageManager for NodePackageManager {
     async fn uninstall(&self, package: &str) -> Result<()> {
         let pb = ProgressBar::new_spinner();
         pb.set_style(ProgressStyle::default_spinner()
-            .template("{spinner:.red} [{elapsed_precise}] {msg}")
+            .template("{spinner:.red} [{elapsed_precise}] {msg}")?
             .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈"));
         pb.set_message(format!("Uninstalling {}", package));
 
@@ -143,7 +218,7 @@ impl PackageManager for NodePackageManager {
     async fn update(&self, package: &str) -> Result<Package> {
         let pb = ProgressBar::new_spinner();
         pb.set_style(ProgressStyle::default_spinner()
-            .template("{spinner:.blue} [{elapsed_precise}] {msg}")
+            .template("{spinner:.blue} [{elapsed_precise}] {msg}")?
             .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈"));
         pb.set_message(format!("Updating {}", package));
 
@@ -160,7 +235,7 @@ impl PackageManager for NodePackageManager {
         
         let mut packages = Vec::new();
         if let Some(deps) = json["dependencies"].as_object() {
-            for (name, info) in deps {
+            for info in deps.values() {
                 if let Ok(package) = self.parse_package_json(&serde_json::to_string(info)?).await {
                     packages.push(package);
                 }
@@ -171,12 +246,12 @@ impl PackageManager for NodePackageManager {
     }
 
     async fn search(&self, query: &str) -> Result<Vec<Package>> {
-        let output = self.run_npm_command(&["search", query, "--json"]).await?;
-        let results: Vec<serde_json::Value> = serde_json::from_str(&output)?;
-        
+        let url = format!("{}/-/v1/search?text={}&size=20", self.registry_url, urlencoding::encode(query));
+        let results = get_json(&self.client, &url).await?;
+
         let mut packages = Vec::new();
-        for result in results {
-            if let Ok(package) = self.parse_package_json(&serde_json::to_string(&result)?).await {
+        for result in results["objects"].as_array().into_iter().flatten() {
+            if let Ok(package) = self.parse_package_json(&serde_json::to_string(&result["package"])?).await {
                 packages.push(package);
             }
         }
@@ -188,22 +263,155 @@ impl PackageManager for NodePackageManager {
         let output = self.run_npm_command(&["view", package, "--json"]).await?;
         self.parse_package_json(&output).await
     }
+
+    async fn latest_version(&self, package: &str) -> Result<Version> {
+        if let Some(version) = self.latest_versions.lock().unwrap().get(package) {
+            return Ok(version.clone());
+        }
+        // Scoped names keep their `@` but escape the `/`
+        let url = format!("{}/{}/latest", self.registry_url, package.replace('/', "%2F"));
+        let info = get_json(&self.client, &url).await?;
+        let version = Version::parse(info["version"].as_str()
+            .ok_or_else(|| anyhow::anyhow!("The npm registry has no version of {}", package))?)?;
+        self.latest_versions.lock().unwrap().insert(package.to_string(), version.clone());
+        Ok(version)
+    }
+
+    async fn dependency_tree(&self, package: &str, max_depth: usize) -> Result<(String, DependencyGraph)> {
+        let depth = format!("--depth={}", max_depth);
+        let output = self.run_npm_command(&["ls", package, "--all", &depth, "--json"]).await?;
+        let json: serde_json::Value = serde_json::from_str(&output)?;
+        let node = find_npm_node(&json, package)
+            .ok_or_else(|| anyhow::anyhow!("{} is not installed", package))?;
+
+        let mut graph = DependencyGraph::new();
+        let root = add_npm_node(package, node, &mut graph);
+        Ok((root, graph))
+    }
+
+    fn config(&self) -> &PackageManagerConfig {
+        &self.config
+    }
 }
 
 pub struct CargoPackageManager {
     config: PackageManagerConfig,
-    installed_packages: HashMap<String, Package>,
+    api_url: String,
+    client: reqwest::Client,
+    latest_versions: Mutex<HashMap<String, Version>>,
+    /// Published, unyanked versions of each crate looked up for a dependency tree
+    published_versions: Mutex<HashMap<String, Vec<Version>>>,
+    /// When the next crates.io request may start
+    next_request: tokio::sync::Mutex<tokio::time::Instant>,
 }
 
 impl CargoPackageManager {
     pub async fn new(config: PackageManagerConfig) -> Result<Self> {
         fs::create_dir_all(&config.cache_dir).await?;
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()?;
         Ok(CargoPackageManager {
             config,
-            installed_packages: HashMap::new(),
+            api_url: CRATES_IO_API.to_string(),
+            client,
+            latest_versions: Mutex::new(HashMap::new()),
+            published_versions: Mutex::new(HashMap::new()),
+            next_request: tokio::sync::Mutex::new(tokio::time::Instant::now()),
         })
     }
 
+    /// `get_json` against crates.io, spacing requests at least `request_interval` apart
+    /// however many lookups run concurrently.
+    async fn get_json(&self, url: &str) -> Result<serde_json::Value> {
+        {
+            let mut next_request = self.next_request.lock().await;
+            tokio::time::sleep_until(*next_request).await;
+            *next_request = tokio::time::Instant::now() + self.config.request_interval;
+        }
+        get_json(&self.client, url).await
+    }
+
+    #[cfg(test)]
+    pub fn with_api_url(mut self, api_url: &str) -> Self {
+        self.api_url = api_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    async fn crate_metadata(&self, name: &str, version: &str) -> Result<serde_json::Value> {
+        let cache_path = self.config.cache_dir
+            .join("crates")
+            .join(format!("{}-{}.json", name, version));
+
+        if let Ok(content) = fs::read_to_string(&cache_path).await {
+            if let Ok(info) = serde_json::from_str(&content) {
+                return Ok(info);
+            }
+        }
+
+        let url = format!("{}/crates/{}", self.api_url, name);
+        let info = self.get_json(&url).await?;
+
+        fs::create_dir_all(cache_path.parent().unwrap()).await?;
+        fs::write(&cache_path, serde_json::to_string(&info)?).await?;
+
+        Ok(info)
+    }
+
+    pub async fn lookup_installed(&self, installed: Vec<(String, String)>) -> Vec<Package> {
+        stream::iter(installed)
+            .map(|(name, version)| async move {
+                let info = self.crate_metadata(&name, &version).await.ok()?;
+                Self::parse_crate(&info["crate"], Some(&version)).ok()
+            })
+            .buffer_unordered(self.config.max_concurrent_downloads.max(1))
+            .filter_map(|package| async move { package })
+            .collect()
+            .await
+    }
+
+    /// The newest unyanked version of `name` that satisfies `req`.
+    async fn matching_version(&self, name: &str, req: &VersionReq) -> Result<Option<Version>> {
+        let cached = self.published_versions.lock().unwrap().get(name).cloned();
+        let versions = match cached {
+            Some(versions) => versions,
+            None => {
+                let info = self.get_json(&format!("{}/crates/{}", self.api_url, name)).await?;
+                let versions: Vec<Version> = info["versions"].as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter(|version| !version["yanked"].as_bool().unwrap_or(false))
+                    .filter_map(|version| version["num"].as_str().and_then(|num| Version::parse(num).ok()))
+                    .collect();
+                self.published_versions.lock().unwrap().insert(name.to_string(), versions.clone());
+                versions
+            }
+        };
+        Ok(versions.into_iter().filter(|version| req.matches(version)).max())
+    }
+
+    /// The normal, non-optional dependencies of one published version, each resolved to the
+    /// newest version its requirement allows.
+    async fn crate_dependencies(&self, name: &str, version: &Version) -> Result<Vec<(String, Version)>> {
+        let url = format!("{}/crates/{}/{}/dependencies", self.api_url, name, version);
+        let info = self.get_json(&url).await?;
+
+        let mut dependencies = Vec::new();
+        for dependency in info["dependencies"].as_array().into_iter().flatten() {
+            if dependency["kind"].as_str() != Some("normal") || dependency["optional"].as_bool().unwrap_or(false) {
+                continue;
+            }
+            let (Some(dependency_name), Some(req)) = (dependency["crate_id"].as_str(), dependency["req"].as_str()) else {
+                continue;
+            };
+            let req = VersionReq::parse(req)?;
+            if let Some(resolved) = self.matching_version(dependency_name, &req).await? {
+                dependencies.push((dependency_name.to_string(), resolved));
+            }
+        }
+        Ok(dependencies)
+    }
+
     async fn run_cargo_command(&self, args: &[&str]) -> Result<String> {
         let output = Command::new("cargo")
             .args(args)
@@ -218,31 +426,24 @@ impl CargoPackageManager {
         }
     }
 
-    async fn parse_cargo_toml(&self, content: &str) -> Result<Package> {
-        let toml: toml::Value = toml::from_str(content)?;
-        
+    /// A package from a crates.io `crate` object, at `version` if given or else its newest
+    /// stable release.
+    fn parse_crate(info: &serde_json::Value, version: Option<&str>) -> Result<Package> {
+        let version = version
+            .or_else(|| info["max_stable_version"].as_str())
+            .or_else(|| info["max_version"].as_str())
+            .unwrap_or("0.0.0");
+
         Ok(Package {
-            name: toml["package"]["name"].as_str().unwrap_or_default().to_string(),
-            version: Version::parse(toml["package"]["version"].as_str().unwrap_or("0.0.0"))?,
-            description: toml["package"]["description"].as_str().map(String::from),
-            dependencies: toml["dependencies"]
-                .as_table()
-                .map(|deps| deps.iter()
-                    .map(|(k, v)| match v {
-                        toml::Value::String(s) => (k.clone(), s.clone()),
-                        toml::Value::Table(t) => (k.clone(), t.get("version")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or_default()
-                            .to_string()),
-                        _ => (k.clone(), String::new()),
-                    })
-                    .collect())
-                .unwrap_or_default(),
+            name: info["name"].as_str().unwrap_or_default().to_string(),
+            version: Version::parse(version)?,
+            description: info["description"].as_str().map(String::from),
+            dependencies: HashMap::new(),
             installed_at: Utc::now(),
             size: 0,
-            license: toml["package"]["license"].as_str().map(String::from),
-            homepage: toml["package"]["homepage"].as_str().map(String::from),
-            repository: toml["package"]["repository"].as_str().map(String::from),
+            license: None,
+            homepage: info["homepage"].as_str().map(String::from),
+            repository: info["repository"].as_str().map(String::from),
         })
     }
 }
@@ -257,7 +458,7 @@ impl PackageManager for CargoPackageManager {
 
         let pb = ProgressBar::new_spinner();
         pb.set_style(ProgressStyle::default_spinner()
-            .template("{spinner:.green} [{elapsed_precise}] {msg}")
+            .template("{spinner:.green} [{elapsed_precise}] {msg}")?
             .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈"));
         pb.set_message(format!("Installing {}", package_spec));
 
@@ -265,17 +466,16 @@ impl PackageManager for CargoPackageManager {
         pb.finish_with_message(format!("Installed {}", package_spec));
 
         // Get package info from crates.io
-        let url = format!("https://crates.io/api/v1/crates/{}", package);
-        let response = reqwest::get(&url).await?;
-        let info: serde_json::Value = response.json().await?;
+        let url = format!("{}/crates/{}", self.api_url, package);
+        let info = self.get_json(&url).await?;
         
-        self.parse_cargo_toml(&serde_json::to_string(&info["crate"])?).await
+        Self::parse_crate(&info["crate"], None)
     }
 
     async fn uninstall(&self, package: &str) -> Result<()> {
         let pb = ProgressBar::new_spinner();
         pb.set_style(ProgressStyle::default_spinner()
-            .template("{spinner:.red} [{elapsed_precise}] {msg}")
+            .template("{spinner:.red} [{elapsed_precise}] {msg}")?
             .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈"));
         pb.set_message(format!("Uninstalling {}", package));
 
@@ -287,23 +487,21 @@ impl PackageManager for CargoPackageManager {
     async fn update(&self, package: &str) -> Result<Package> {
         let pb = ProgressBar::new_spinner();
         pb.set_style(ProgressStyle::default_spinner()
-            .template("{spinner:.blue} [{elapsed_precise}] {msg}")
+            .template("{spinner:.blue} [{elapsed_precise}] {msg}")?
             .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈"));
         pb.set_message(format!("Updating {}", package));
 
         self.run_cargo_command(&["install", package, "--force"]).await?;
         pb.finish_with_message(format!("Updated {}", package));
 
-        let url = format!("https://crates.io/api/v1/crates/{}", package);
-        let response = reqwest::get(&url).await?;
-        let info: serde_json::Value = response.json().await?;
+        let url = format!("{}/crates/{}", self.api_url, package);
+        let info = self.get_json(&url).await?;
         
-        self.parse_cargo_toml(&serde_json::to_string(&info["crate"])?).await
+        Self::parse_crate(&info["crate"], None)
     }
 
     async fn list_installed(&self) -> Result<Vec<Package>> {
         let output = self.run_cargo_command(&["install", "--list"]).await?;
-        let mut packages = Vec::new();
 
         lazy_static! {
             static ref PKG_RE: Regex = Regex::new(
@@ -311,41 +509,29 @@ impl PackageManager for CargoPackageManager {
             ).unwrap();
         }
 
-        for line in output.lines() {
-            if let Some(caps) = PKG_RE.captures(line) {
-                let name = caps.name("name").unwrap().as_str();
-                let version = caps.name("version").unwrap().as_str();
-
-                let url = format!("https://crates.io/api/v1/crates/{}", name);
-                if let Ok(response) = reqwest::get(&url).await {
-                    if let Ok(info) = response.json::<serde_json::Value>().await {
-                        if let Ok(package) = self.parse_cargo_toml(
-                            &serde_json::to_string(&info["crate"])?
-                        ).await {
-                            packages.push(package);
-                        }
-                    }
-                }
-            }
-        }
+        let installed = output.lines()
+            .filter_map(|line| PKG_RE.captures(line))
+            .map(|caps| (
+                caps.name("name").unwrap().as_str().to_string(),
+                caps.name("version").unwrap().as_str().to_string(),
+            ))
+            .collect();
 
-        Ok(packages)
+        Ok(self.lookup_installed(installed).await)
     }
 
     async fn search(&self, query: &str) -> Result<Vec<Package>> {
         let url = format!(
-            "https://crates.io/api/v1/crates?q={}&per_page=10",
+            "{}/crates?q={}&per_page=10",
+            self.api_url,
             urlencoding::encode(query)
         );
-        let response = reqwest::get(&url).await?;
-        let results: serde_json::Value = response.json().await?;
+        let results = self.get_json(&url).await?;
         
         let mut packages = Vec::new();
         if let Some(crates) = results["crates"].as_array() {
             for crate_info in crates {
-                if let Ok(package) = self.parse_cargo_toml(
-                    &serde_json::to_string(crate_info)?
-                ).await {
+                if let Ok(package) = Self::parse_crate(crate_info, None) {
                     packages.push(package);
                 }
             }
@@ -355,10 +541,66 @@ impl PackageManager for CargoPackageManager {
     }
 
     async fn get_info(&self, package: &str) -> Result<Package> {
-        let url = format!("https://crates.io/api/v1/crates/{}", package);
-        let response = reqwest::get(&url).await?;
-        let info: serde_json::Value = response.json().await?;
+        let url = format!("{}/crates/{}", self.api_url, package);
+        let info = self.get_json(&url).await?;
         
-        self.parse_cargo_toml(&serde_json::to_string(&info["crate"])?).await
+        Self::parse_crate(&info["crate"], None)
+    }
+
+    async fn latest_version(&self, package: &str) -> Result<Version> {
+        if let Some(version) = self.latest_versions.lock().unwrap().get(package) {
+            return Ok(version.clone());
+        }
+        let info = self.get_json(&format!("{}/crates/{}", self.api_url, package)).await?;
+        // Prefer the newest stable release over a newer pre-release
+        let latest = info["crate"]["max_stable_version"].as_str()
+            .or_else(|| info["crate"]["max_version"].as_str())
+            .ok_or_else(|| anyhow::anyhow!("crates.io has no version of {}", package))?;
+        let version = Version::parse(latest)?;
+        self.latest_versions.lock().unwrap().insert(package.to_string(), version.clone());
+        Ok(version)
+    }
+
+    /// Resolved through the crates.io dependency API from the newest stable release, one
+    /// level at a time. Each crate version is looked up once however often it appears.
+    async fn dependency_tree(&self, package: &str, max_depth: usize) -> Result<(String, DependencyGraph)> {
+        let version = self.latest_version(package).await?;
+        let root = node_label(package, &version.to_string());
+        let mut graph = DependencyGraph::new();
+        let mut level = vec![(package.to_string(), version)];
+
+        for _ in 0..max_depth {
+            level.retain(|(name, version)| !graph.contains_key(&node_label(name, &version.to_string())));
+            level.sort();
+            level.dedup();
+            if level.is_empty() {
+                break;
+            }
+
+            let resolved: Vec<_> = stream::iter(level)
+                .map(|(name, version)| async move {
+                    let dependencies = self.crate_dependencies(&name, &version).await;
+                    (name, version, dependencies)
+                })
+                .buffer_unordered(self.config.max_concurrent_downloads.max(1))
+                .collect()
+                .await;
+
+            let mut next = Vec::new();
+            for (name, version, dependencies) in resolved {
+                let dependencies = dependencies?;
+                let children = dependencies.iter()
+                    .map(|(name, version)| node_label(name, &version.to_string()))
+                    .collect();
+                graph.insert(node_label(&name, &version.to_string()), children);
+                next.extend(dependencies);
+            }
+            level = next;
+        }
+        Ok((root, graph))
+    }
+
+    fn config(&self) -> &PackageManagerConfig {
+        &self.config
     }
 }
@@ -1,68 +1,265 @@
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::tempdir;
-    use std::fs;
-    use std::path::Path;
-
-    #[tokio::test]
-    async fn test_package_manager() {
-        let config = PackageManagerConfig::default();
-        let npm = NodePackageManager::new(config.clone()).await.unwrap();
-        let cargo = CargoPackageManager::new(config).await.unwrap();
-
-        // Test npm package search
-        let results = npm.search("express").await.unwrap();
-        assert!(!results.is_empty());
-        assert!(results.iter().any(|p| p.name == "express"));
-
-        // Test cargo package search
-        let results = cargo.search("tokio").await.unwrap();
-        assert!(!results.is_empty());
-        assert!(results.iter().any(|p| p.name == "tokio"));
-    }
+use super::*;
+use crate::shell::mock_http::{self, Reply};
+use formatter::{CodeFormatter, RustFormatter};
+use std::collections::HashMap;
+use tempfile::tempdir;
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
-    #[tokio::test]
-    async fn test_formatter() {
-        let temp_dir = tempdir().unwrap();
-        let test_file = temp_dir.path().join("test.rs");
-        fs::write(&test_file, "fn main(){println!(\"Hello\");}\n").unwrap();
+/// Serves `body` as JSON for every request and counts how many arrive.
+async fn spawn_registry_mock(body: &'static str) -> (String, Arc<AtomicUsize>) {
+    spawn_flaky_registry_mock(body, 0).await
+}
 
-        let config = FormatterConfig::default();
-        let formatter = RustFormatter::new(config);
+/// Like `spawn_registry_mock`, but answers the first `failures` requests with a 503.
+async fn spawn_flaky_registry_mock(body: &'static str, failures: usize) -> (String, Arc<AtomicUsize>) {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    let base = mock_http::serve(move |_| {
+        if counter.fetch_add(1, Ordering::SeqCst) < failures {
+            Reply::new("503 Service Unavailable", "")
+        } else {
+            Reply::new("200 OK", body).header("Content-Type", "application/json")
+        }
+    }).await;
 
-        // Test formatting
-        let formatted = formatter.format_file(&test_file).await.unwrap();
-        assert!(formatted);
+    (base, hits)
+}
 
-        // Verify formatting
-        let content = fs::read_to_string(&test_file).unwrap();
-        assert!(content.contains("fn main() {\n    println!(\"Hello\");\n}\n"));
-    }
+#[tokio::test]
+async fn test_package_manager() {
+    let temp_dir = tempdir().unwrap();
+    let (registry_url, _) = spawn_registry_mock(
+        r#"{"objects":[{"package":{"name":"express","version":"4.18.2","description":"Fast web framework"}}]}"#,
+    ).await;
+    let (api_url, _) = spawn_registry_mock(
+        r#"{"crates":[{"name":"tokio","max_version":"1.33.0-rc.1","max_stable_version":"1.32.0"}]}"#,
+    ).await;
+
+    let config = PackageManagerConfig {
+        cache_dir: temp_dir.path().to_path_buf(),
+        request_interval: Duration::ZERO,
+        ..Default::default()
+    };
+    let npm = NodePackageManager::new(config.clone()).await.unwrap().with_registry_url(&registry_url);
+    let cargo = CargoPackageManager::new(config).await.unwrap().with_api_url(&api_url);
+
+    // Test npm package search
+    let results = npm.search("express").await.unwrap();
+    assert!(!results.is_empty());
+    assert!(results.iter().any(|p| p.name == "express"));
+
+    // Test cargo package search
+    let results = cargo.search("tokio").await.unwrap();
+    assert!(!results.is_empty());
+    assert!(results.iter().any(|p| p.name == "tokio" && p.version == Version::new(1, 32, 0)));
+}
+
+#[tokio::test]
+async fn test_formatter() {
+    let temp_dir = tempdir().unwrap();
+    let test_file = temp_dir.path().join("test.rs");
+    fs::write(&test_file, "fn main(){println!(\"Hello\");}\n").unwrap();
+
+    let config = FormatterConfig::default();
+    let formatter = RustFormatter::new(config);
 
-    #[tokio::test]
-    async fn test_formatter_manager() {
-        let config = FormatterConfig::default();
-        let manager = FormatterManager::new(config);
+    // Test formatting
+    let formatted = formatter.format_file(&test_file).await.unwrap();
+    assert!(formatted);
 
-        assert!(manager.get_formatter("rust").is_some());
-        assert!(manager.get_formatter("python").is_some());
-        assert!(manager.get_formatter("javascript").is_some());
-        assert!(manager.get_formatter("invalid").is_none());
+    // Verify formatting
+    let content = fs::read_to_string(&test_file).unwrap();
+    assert!(content.contains("fn main() {\n    println!(\"Hello\");\n}\n"));
+}
+
+#[test]
+fn test_format_dir_skips_excluded_paths() {
+    let temp_dir = tempdir().unwrap();
+    let root = temp_dir.path();
+    for file in ["src/main.rs", "target/debug/build.rs", "vendor/lib.rs", "generated/schema.rs", "node_modules/x/index.js"] {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "fn main(){}\n").unwrap();
     }
+    fs::write(root.join(formatter::IGNORE_FILE), "generated/\n").unwrap();
+
+    let exclude = vec!["vendor/".to_string()];
+    let (files, errors) = formatter::source_files(root, true, &exclude, &["rs", "js"]).unwrap();
+    assert!(errors.is_empty());
+    assert_eq!(files, vec![root.join("src/main.rs")]);
+
+    // Non-recursive only looks at the top level
+    let (files, _) = formatter::source_files(&root.join("src"), false, &[], &["rs"]).unwrap();
+    assert_eq!(files.len(), 1);
+}
 
-    #[tokio::test]
-    async fn test_config() {
-        let mut config = FormatterConfig::default();
-        config.indent_style = "tab".to_string();
-        config.indent_size = 2;
-        config.line_width = 80;
+#[tokio::test]
+async fn test_formatter_manager() {
+    let config = FormatterConfig::default();
+    let manager = FormatterManager::new(config);
 
-        let mut formatter = RustFormatter::new(FormatterConfig::default());
-        formatter.set_config(config.clone());
+    assert!(manager.get_formatter("rust").is_some());
+    assert!(manager.get_formatter("python").is_some());
+    assert!(manager.get_formatter("javascript").is_some());
+    assert!(manager.get_formatter("invalid").is_none());
+}
+
+#[tokio::test]
+async fn test_config() {
+    let config = FormatterConfig {
+        indent_style: "tab".to_string(),
+        indent_size: 2,
+        line_width: 80,
+        ..Default::default()
+    };
+
+    let mut formatter = RustFormatter::new(FormatterConfig::default());
+    formatter.set_config(config.clone());
+
+    assert_eq!(formatter.get_config().indent_style, "tab");
+    assert_eq!(formatter.get_config().indent_size, 2);
+    assert_eq!(formatter.get_config().line_width, 80);
+}
 
-        assert_eq!(formatter.get_config().indent_style, "tab");
-        assert_eq!(formatter.get_config().indent_size, 2);
-        assert_eq!(formatter.get_config().line_width, 80);
+#[tokio::test]
+async fn test_cargo_list_installed_uses_metadata_cache() {
+    let temp_dir = tempdir().unwrap();
+    let (api_url, hits) = spawn_registry_mock(
+        r#"{"crate":{"name":"ripgrep","max_version":"13.0.0"}}"#
+    ).await;
+
+    let config = PackageManagerConfig {
+        cache_dir: temp_dir.path().to_path_buf(),
+        max_concurrent_downloads: 2,
+        request_interval: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let cargo = CargoPackageManager::new(config).await.unwrap().with_api_url(&api_url);
+
+    let installed = vec![
+        ("ripgrep".to_string(), "13.0.0".to_string()),
+        ("fd-find".to_string(), "8.7.0".to_string()),
+        ("bat".to_string(), "0.23.0".to_string()),
+    ];
+
+    // Three requests, however concurrent, are spaced at least two intervals apart
+    let started = std::time::Instant::now();
+    cargo.lookup_installed(installed.clone()).await;
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+    assert!(started.elapsed() >= Duration::from_millis(400));
+
+    // The second lookup is served from the cache: no requests, and the cache isn't rewritten
+    let cached = temp_dir.path().join("crates/ripgrep-13.0.0.json");
+    let written = std::fs::metadata(&cached).unwrap().modified().unwrap();
+    cargo.lookup_installed(installed).await;
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+    assert_eq!(std::fs::metadata(&cached).unwrap().modified().unwrap(), written);
+}
+
+#[tokio::test]
+async fn test_cargo_retries_transient_failures() {
+    let temp_dir = tempdir().unwrap();
+    let (api_url, hits) = spawn_flaky_registry_mock(
+        r#"{"crate":{"name":"ripgrep","max_version":"13.0.0"}}"#,
+        2,
+    ).await;
+
+    let config = PackageManagerConfig {
+        cache_dir: temp_dir.path().to_path_buf(),
+        request_interval: Duration::ZERO,
+        ..Default::default()
+    };
+    let cargo = CargoPackageManager::new(config).await.unwrap().with_api_url(&api_url);
+
+    let package = cargo.get_info("ripgrep").await.unwrap();
+    assert_eq!(package.name, "ripgrep");
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn test_dependency_tree_rendering() {
+    let graph: dependency_tree::DependencyGraph = [
+        ("app v1.0.0", vec!["web v2.0.0", "log v1.0.0"]),
+        ("web v2.0.0", vec!["log v1.0.0", "util v1.0.0"]),
+        ("log v1.0.0", vec!["util v1.0.0"]),
+        ("util v1.0.0", vec!["app v1.0.0"]),
+    ]
+    .into_iter()
+    .map(|(node, children)| (node.to_string(), children.into_iter().map(String::from).collect()))
+    .collect();
+
+    assert_eq!(dependency_tree::render("app v1.0.0", &graph, 10), [
+        "app v1.0.0",
+        "├── web v2.0.0",
+        "│   ├── log v1.0.0",
+        "│   │   └── util v1.0.0",
+        "│   │       └── app v1.0.0 (cycle)",
+        "│   └── util v1.0.0 (*)",
+        "└── log v1.0.0 (*)",
+    ].join("\n"));
+
+    assert_eq!(dependency_tree::render("app v1.0.0", &graph, 1), [
+        "app v1.0.0",
+        "├── web v2.0.0",
+        "│   └── …",
+        "└── log v1.0.0",
+        "    └── …",
+    ].join("\n"));
+}
+
+fn installed(name: &str, version: &str) -> Package {
+    Package {
+        name: name.to_string(),
+        version: Version::parse(version).unwrap(),
+        description: None,
+        dependencies: HashMap::new(),
+        installed_at: chrono::Utc::now(),
+        size: 0,
+        license: None,
+        homepage: None,
+        repository: None,
     }
 }
+
+#[tokio::test]
+async fn test_outdated_packages_are_flagged() {
+    let temp_dir = tempdir().unwrap();
+    let (api_url, hits) = spawn_registry_mock(
+        r#"{"crate":{"name":"ripgrep","max_version":"15.0.0-beta.1","max_stable_version":"14.1.0"}}"#
+    ).await;
+    let config = PackageManagerConfig {
+        cache_dir: temp_dir.path().to_path_buf(),
+        max_concurrent_downloads: 2,
+        request_interval: Duration::ZERO,
+        ..Default::default()
+    };
+    let cargo = CargoPackageManager::new(config).await.unwrap().with_api_url(&api_url);
+    let packages = vec![installed("ripgrep", "13.0.0"), installed("fd-find", "14.1.0"), installed("bat", "15.0.0")];
+
+    let rows: Vec<OutdatedRow> = find_outdated(&cargo, packages.clone()).await
+        .into_iter()
+        .map(OutdatedRow::from)
+        .collect();
+    let flagged: Vec<(&str, bool)> = rows.iter().map(|row| (row.name.as_str(), row.outdated)).collect();
+    assert_eq!(flagged, vec![("ripgrep", true), ("fd-find", false), ("bat", false)]);
+    assert_eq!(rows[0].latest.as_deref(), Some("14.1.0"));
+    assert!(OutputFormat::Json.render(&rows).unwrap().contains(r#""outdated": true"#));
+
+    // Latest versions are cached, so a second pass doesn't query the registry
+    find_outdated(&cargo, packages).await;
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+    let (registry_url, _) = spawn_registry_mock(r#"{"name":"express","version":"4.19.2"}"#).await;
+    let npm = NodePackageManager::new(PackageManagerConfig {
+        cache_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    }).await.unwrap().with_registry_url(&registry_url);
+    let rows: Vec<OutdatedRow> = find_outdated(&npm, vec![installed("express", "4.18.0")]).await
+        .into_iter()
+        .map(OutdatedRow::from)
+        .collect();
+    assert!(rows[0].outdated);
+}
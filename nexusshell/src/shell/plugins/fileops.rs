@@ -1,17 +1,33 @@
 use async_trait::async_trait;
 use super::super::{Command, Environment, Plugin};
+use super::super::{color, ShellError};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use filetime::FileTime;
+use ring::digest;
+use similar::{ChangeTag, TextDiff};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tokio::fs as async_fs;
 
 pub struct FileOperationsPlugin;
 
+impl FileOperationsPlugin {
+    pub fn new() -> Self {
+        FileOperationsPlugin
+    }
+}
+
 #[async_trait]
 impl Plugin for FileOperationsPlugin {
     fn name(&self) -> &str {
         "fileops"
     }
 
+    fn commands(&self) -> Vec<&str> {
+        vec!["ls", "cp", "mv", "rm", "mkdir", "rmdir", "stat", "touch", "cat", "diff", "dedup", "find"]
+    }
+
     fn description(&self) -> &str {
         "Advanced file operations plugin"
     }
@@ -23,32 +39,38 @@ impl Plugin for FileOperationsPlugin {
             "mv" => self.move_file(command, env).await,
             "rm" => self.remove(command, env).await,
             "mkdir" => self.make_directory(command, env).await,
+            "rmdir" => self.remove_directory(command, env).await,
+            "stat" => self.stat(command, env).await,
             "touch" => self.touch(command, env).await,
             "cat" => self.cat(command, env).await,
-            _ => Err(anyhow::anyhow!("Unknown file operation command")),
+            "diff" => self.diff(command, env).await,
+            "dedup" => self.dedup(command, env).await,
+            "find" => self.find(command, env).await,
+            _ => Err(ShellError::NotFound(format!("File operation '{}'", command.name)).into()),
         }
     }
 }
 
 impl FileOperationsPlugin {
     async fn list_directory(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
-        let path = if command.args.is_empty() {
-            env.get_current_dir()
-        } else {
-            env.expand_path(&command.args[0])
+        let colored = ls_color_choice(command)?.should_color();
+        let path = match command.args.first() {
+            Some(dir) => env.expand_path(dir),
+            None => env.get_current_dir(),
         };
 
         let mut entries = Vec::new();
         let mut dirs = Vec::new();
         let mut files = Vec::new();
 
-        let mut read_dir = async_fs::read_dir(&path).await?;
+        let mut read_dir = async_fs::read_dir(&path).await
+            .map_err(|e| ShellError::from_io(&path, e))?;
         while let Some(entry) = read_dir.next_entry().await? {
             let metadata = entry.metadata().await?;
             let name = entry.file_name().to_string_lossy().to_string();
             
             if metadata.is_dir() {
-                dirs.push(format!("\x1b[1;34m{}/\x1b[0m", name));
+                dirs.push(color::paint_if(colored, "1;34", &format!("{}/", name)));
             } else {
                 let size = metadata.len();
                 let size_str = if size < 1024 {
@@ -73,17 +95,26 @@ impl FileOperationsPlugin {
     }
 
     async fn copy(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
-        if command.args.len() != 2 {
-            return Err(anyhow::anyhow!("Usage: cp <source> <destination>"));
+        let paths = &command.args;
+        if paths.len() != 2 {
+            return Err(ShellError::InvalidUsage("cp [-n|-i] <source> <destination>".to_string()).into());
         }
 
-        let source = env.expand_path(&command.args[0]);
-        let destination = env.expand_path(&command.args[1]);
+        let source = env.expand_path(&paths[0]);
+        let destination = env.expand_path(&paths[1]);
+
+        if !source.exists() {
+            return Err(ShellError::NotFound(source.display().to_string()).into());
+        }
+        if let Some(skipped) = check_clobber(command, &destination)? {
+            return Ok(skipped);
+        }
 
         if source.is_dir() {
             copy_dir_all(&source, &destination)?;
         } else {
-            async_fs::copy(&source, &destination).await?;
+            async_fs::copy(&source, &destination).await
+                .map_err(|e| ShellError::from_io(&destination, e))?;
         }
 
         Ok(format!("Copied {} to {}", 
@@ -92,14 +123,26 @@ impl FileOperationsPlugin {
     }
 
     async fn move_file(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
-        if command.args.len() != 2 {
-            return Err(anyhow::anyhow!("Usage: mv <source> <destination>"));
+        let paths = &command.args;
+        if paths.len() != 2 {
+            return Err(ShellError::InvalidUsage("mv [-n|-i] <source> <destination>".to_string()).into());
         }
 
-        let source = env.expand_path(&command.args[0]);
-        let destination = env.expand_path(&command.args[1]);
+        let source = env.expand_path(&paths[0]);
+        let mut destination = env.expand_path(&paths[1]);
+
+        // Moving onto an existing directory puts the source inside it
+        if destination.is_dir() {
+            if let Some(file_name) = source.file_name() {
+                destination = destination.join(file_name);
+            }
+        }
+        if let Some(skipped) = check_clobber(command, &destination)? {
+            return Ok(skipped);
+        }
 
-        async_fs::rename(&source, &destination).await?;
+        async_fs::rename(&source, &destination).await
+            .map_err(|e| ShellError::from_io(&source, e))?;
 
         Ok(format!("Moved {} to {}", 
             source.to_string_lossy(),
@@ -107,58 +150,674 @@ impl FileOperationsPlugin {
     }
 
     async fn remove(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
-        if command.args.is_empty() {
-            return Err(anyhow::anyhow!("Usage: rm <path> [-r]"));
+        let targets = &command.args;
+
+        if targets.is_empty() {
+            return Err(ShellError::InvalidUsage("rm [-r] [-f] [--force] <path>...".to_string()).into());
         }
 
-        let path = env.expand_path(&command.args[0]);
         let recursive = command.flags.contains_key("r") || command.flags.contains_key("recursive");
+        let force = command.flags.contains_key("force");
+        let ignore_missing = command.flags.contains_key("f") || force;
 
-        if path.is_dir() {
-            if recursive {
-                async_fs::remove_dir_all(&path).await?;
-            } else {
-                async_fs::remove_dir(&path).await?;
+        let mut removed = Vec::new();
+        for target in targets {
+            let path = env.expand_path(target);
+            // Not following symlinks, so a link (even a dangling one) is removed itself
+            let metadata = match async_fs::symlink_metadata(&path).await {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    if ignore_missing {
+                        continue;
+                    }
+                    return Err(ShellError::NotFound(path.display().to_string()).into());
+                }
+                Err(e) => return Err(ShellError::from_io(&path, e).into()),
+            };
+
+            if !force && !metadata.file_type().is_symlink() && is_protected_path(&path, env) {
+                return Err(ShellError::PermissionDenied(format!(
+                    "refusing to remove {} (use --force to override)",
+                    path.display()
+                )).into());
             }
-        } else {
-            async_fs::remove_file(&path).await?;
+
+            let result = if metadata.is_dir() {
+                if recursive {
+                    async_fs::remove_dir_all(&path).await
+                } else {
+                    async_fs::remove_dir(&path).await
+                }
+            } else {
+                async_fs::remove_file(&path).await
+            };
+            result.map_err(|e| ShellError::from_io(&path, e))?;
+            removed.push(format!("Removed {}", path.to_string_lossy()));
         }
 
-        Ok(format!("Removed {}", path.to_string_lossy()))
+        Ok(removed.join("\n"))
     }
 
     async fn make_directory(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
-        if command.args.is_empty() {
-            return Err(anyhow::anyhow!("Usage: mkdir <directory>"));
+        let parents = command.flags.contains_key("p") || command.flags.contains_key("parents");
+        let dirs = &command.args;
+
+        if dirs.is_empty() {
+            return Err(ShellError::InvalidUsage("mkdir [-p] [-m <mode>] <directory>...".to_string()).into());
         }
+        let mode = match command.flags.get("m").or_else(|| command.flags.get("mode")) {
+            Some(Some(mode)) => Some(parse_mode(mode)?),
+            Some(None) => return Err(ShellError::InvalidUsage("mkdir -m <mode> <directory>...".to_string()).into()),
+            None => None,
+        };
 
-        let path = env.expand_path(&command.args[0]);
-        async_fs::create_dir_all(&path).await?;
+        let mut created = Vec::new();
+        for dir in dirs {
+            let path = env.expand_path(dir);
+            // With -p, only the missing part of the path is new
+            let new_dirs: Vec<PathBuf> = if parents {
+                let mut missing: Vec<PathBuf> = path.ancestors()
+                    .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+                    .map(Path::to_path_buf)
+                    .collect();
+                missing.reverse();
+                missing
+            } else {
+                vec![path.clone()]
+            };
+
+            let result = if parents {
+                async_fs::create_dir_all(&path).await
+            } else {
+                async_fs::create_dir(&path).await
+            };
+            result.map_err(|e| ShellError::from_io(&path, e))?;
+            // Like mkdir(1), the mode applies to the named directory, not to intermediates
+            if let (Some(mode), Some(_)) = (mode, new_dirs.last()) {
+                set_mode(&path, mode).await?;
+            }
+            created.extend(new_dirs.iter().map(|dir| format!("Created directory {}", dir.to_string_lossy())));
+        }
 
-        Ok(format!("Created directory {}", path.to_string_lossy()))
+        Ok(created.join("\n"))
+    }
+
+    async fn remove_directory(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        let parents = command.flags.contains_key("p") || command.flags.contains_key("parents");
+        let dirs = &command.args;
+        if dirs.is_empty() {
+            return Err(ShellError::InvalidUsage("rmdir [-p] <directory>...".to_string()).into());
+        }
+
+        let mut removed = Vec::new();
+        for dir in dirs {
+            let path = env.expand_path(dir);
+            remove_empty_dir(&path).await?;
+            removed.push(format!("Removed directory {}", path.to_string_lossy()));
+
+            // `-p` walks up the operand as written: `rmdir -p a/b/c` also removes a/b and a
+            if parents {
+                let mut operand = Path::new(dir.as_str());
+                while let Some(parent) = operand.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                    let path = env.expand_path(&parent.to_string_lossy());
+                    remove_empty_dir(&path).await?;
+                    removed.push(format!("Removed directory {}", path.to_string_lossy()));
+                    operand = parent;
+                }
+            }
+        }
+
+        Ok(removed.join("\n"))
+    }
+
+    async fn stat(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        let format = match command.flags.get("format") {
+            Some(Some(format)) => Some(format.as_str()),
+            Some(None) => return Err(ShellError::InvalidUsage("stat --format <format> <path>...".to_string()).into()),
+            None => None,
+        };
+        if command.args.is_empty() {
+            return Err(ShellError::InvalidUsage("stat [--format <format>] <path>...".to_string()).into());
+        }
+
+        let mut reports = Vec::new();
+        for arg in &command.args {
+            let path = env.expand_path(arg);
+            let metadata = async_fs::symlink_metadata(&path).await
+                .map_err(|e| ShellError::from_io(&path, e))?;
+            let stat = FileStat::new(arg, &metadata);
+            reports.push(match format {
+                Some(format) => stat.format(format),
+                None => stat.render(),
+            });
+        }
+        Ok(reports.join("\n"))
     }
 
     async fn touch(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
         if command.args.is_empty() {
-            return Err(anyhow::anyhow!("Usage: touch <file>"));
+            return Err(ShellError::InvalidUsage("touch [-t <time>|--date <time>] <file>...".to_string()).into());
         }
 
-        let path = env.expand_path(&command.args[0]);
-        async_fs::File::create(&path).await?;
+        let mtime = match command.flags.get("t").or_else(|| command.flags.get("date")) {
+            Some(Some(value)) => parse_touch_time(value)
+                .ok_or_else(|| ShellError::InvalidUsage(format!("touch: invalid time '{}'", value)))?,
+            Some(None) => return Err(ShellError::InvalidUsage("touch -t <time> <file>...".to_string()).into()),
+            None => FileTime::now(),
+        };
 
-        Ok(format!("Created file {}", path.to_string_lossy()))
+        let mut touched = Vec::new();
+        for file in &command.args {
+            let path = env.expand_path(file);
+            let existed = path.exists();
+            // Never truncate: an existing file only gets its timestamps bumped
+            async_fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await
+                .map_err(|e| ShellError::from_io(&path, e))?;
+            filetime::set_file_times(&path, mtime, mtime)
+                .map_err(|e| ShellError::from_io(&path, e))?;
+
+            let action = if existed { "Touched" } else { "Created file" };
+            touched.push(format!("{} {}", action, path.to_string_lossy()));
+        }
+
+        Ok(touched.join("\n"))
     }
 
     async fn cat(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
         if command.args.is_empty() {
-            return Err(anyhow::anyhow!("Usage: cat <file>"));
+            return Err(ShellError::InvalidUsage("cat <file>".to_string()).into());
         }
 
         let path = env.expand_path(&command.args[0]);
-        let content = async_fs::read_to_string(&path).await?;
+        let content = async_fs::read_to_string(&path).await
+            .map_err(|e| ShellError::from_io(&path, e))?;
 
         Ok(content)
     }
+
+    async fn diff(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        let files = &command.args;
+        if files.len() != 2 {
+            return Err(ShellError::InvalidUsage("diff [--stat] [--context <n>] <a> <b>".to_string()).into());
+        }
+
+        let context = match command.flags.get("context").and_then(|value| value.as_ref()) {
+            Some(value) => value.parse::<usize>()
+                .map_err(|_| ShellError::InvalidUsage("diff --context <n>".to_string()))?,
+            None => 3,
+        };
+
+        let (old_path, new_path) = (env.expand_path(&files[0]), env.expand_path(&files[1]));
+        let old = async_fs::read(&old_path).await.map_err(|e| ShellError::from_io(&old_path, e))?;
+        let new = async_fs::read(&new_path).await.map_err(|e| ShellError::from_io(&new_path, e))?;
+
+        let (old_text, new_text) = match (text_content(&old), text_content(&new)) {
+            (Some(old_text), Some(new_text)) => (old_text, new_text),
+            _ if old == new => return Ok(String::new()),
+            _ => return Ok(format!("Binary files {} and {} differ", files[0], files[1])),
+        };
+
+        let diff = TextDiff::from_lines(old_text, new_text);
+        if command.flags.contains_key("stat") {
+            let (mut insertions, mut deletions) = (0, 0);
+            for change in diff.iter_all_changes() {
+                match change.tag() {
+                    ChangeTag::Insert => insertions += 1,
+                    ChangeTag::Delete => deletions += 1,
+                    ChangeTag::Equal => {}
+                }
+            }
+            if insertions == 0 && deletions == 0 {
+                return Ok(String::new());
+            }
+            return Ok(format!(
+                "{} => {} | {} insertions(+), {} deletions(-)",
+                files[0], files[1], insertions, deletions
+            ));
+        }
+
+        let mut lines = Vec::new();
+        let mut unified = diff.unified_diff();
+        unified.context_radius(context);
+        for hunk in unified.iter_hunks() {
+            if lines.is_empty() {
+                lines.push(color::paint("1", &format!("--- {}", files[0])));
+                lines.push(color::paint("1", &format!("+++ {}", files[1])));
+            }
+            lines.push(color::paint("36", &hunk.header().to_string()));
+            for change in hunk.iter_changes() {
+                let value = change.value().trim_end_matches('\n');
+                lines.push(match change.tag() {
+                    ChangeTag::Insert => color::paint("32", &format!("+{}", value)),
+                    ChangeTag::Delete => color::paint("31", &format!("-{}", value)),
+                    ChangeTag::Equal => format!(" {}", value),
+                });
+            }
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    async fn dedup(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        let delete = command.flags.contains_key("delete");
+        let dir = match command.args.as_slice() {
+            [dir] => env.expand_path(dir),
+            _ => return Err(ShellError::InvalidUsage("dedup [--delete] <dir>".to_string()).into()),
+        };
+        if !async_fs::metadata(&dir).await.map_err(|e| ShellError::from_io(&dir, e))?.is_dir() {
+            return Err(ShellError::External(format!("dedup: {}: Not a directory", dir.display())).into());
+        }
+
+        let groups = find_duplicates(&dir).await?;
+        if groups.is_empty() {
+            return Ok("No duplicate files found".to_string());
+        }
+
+        // The first path of each (sorted) group is the copy that is kept
+        let mut report = Vec::new();
+        for (size, paths) in &groups {
+            report.push(format!("{} bytes, {} copies:", size, paths.len()));
+            report.push(format!("  {}", paths[0].display()));
+            for path in &paths[1..] {
+                if delete {
+                    async_fs::remove_file(path).await.map_err(|e| ShellError::from_io(path, e))?;
+                    report.push(format!("  {} (removed)", path.display()));
+                } else {
+                    report.push(format!("  {}", path.display()));
+                }
+            }
+        }
+        Ok(report.join("\n"))
+    }
+
+    /// `find [-L] [path...] [--name <glob>] [--type f|d|l]`. Symlinks are listed but not
+    /// descended into unless `-L`/`--follow-symlinks` is given.
+    async fn find(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        let usage = || ShellError::InvalidUsage("find [-L] [path...] [--name <glob>] [--type f|d|l]".to_string());
+        let follow = command.flags.contains_key("L") || command.flags.contains_key("follow-symlinks");
+        let name = match command.flags.get("name") {
+            Some(Some(name)) => Some(glob::Pattern::new(name).map_err(|e| ShellError::InvalidUsage(format!("find --name: {}", e)))?),
+            Some(None) => return Err(usage().into()),
+            None => None,
+        };
+        let kind = match command.flags.get("type").map(|kind| kind.as_deref()) {
+            Some(Some(kind @ ("f" | "d" | "l"))) => Some(kind),
+            Some(_) => return Err(usage().into()),
+            None => None,
+        };
+
+        let mut roots = command.args.clone();
+        if roots.is_empty() {
+            roots.push(".".to_string());
+        }
+
+        let mut lines = Vec::new();
+        let mut warnings = Vec::new();
+        for root in roots {
+            let path = env.expand_path(&root);
+            let (walk_root, shown) = (path.clone(), PathBuf::from(&root));
+            let (found, root_warnings) = tokio::task::spawn_blocking(move || {
+                let mut warnings = Vec::new();
+                walk_find(&walk_root, &shown, follow, &mut warnings).map(|found| (found, warnings))
+            })
+            .await?
+            .map_err(|e| ShellError::from_io(&path, e))?;
+            warnings.extend(root_warnings);
+            lines.extend(found.into_iter()
+                .filter(|(shown, file_type)| {
+                    let type_matches = match kind {
+                        Some("f") => file_type.is_file(),
+                        Some("d") => file_type.is_dir(),
+                        Some(_) => file_type.is_symlink(),
+                        None => true,
+                    };
+                    let name_matches = name.as_ref().is_none_or(|pattern| {
+                        shown.file_name().is_some_and(|file_name| pattern.matches(&file_name.to_string_lossy()))
+                    });
+                    type_matches && name_matches
+                })
+                .map(|(shown, _)| shown.to_string_lossy().to_string()));
+        }
+
+        // Reported after the results, like checksum mismatches
+        lines.extend(warnings.into_iter().map(|warning| format!("find: {}", warning)));
+        Ok(lines.join("\n"))
+    }
+}
+
+/// The `ls` color choice from `--color=WHEN`, defaulting to auto. A bare `--color` means
+/// always.
+fn ls_color_choice(command: &Command) -> Result<color::ColorChoice, ShellError> {
+    match command.flags.get("color") {
+        None => Ok(color::ColorChoice::Auto),
+        Some(None) => Ok(color::ColorChoice::Always),
+        Some(Some(value)) => color::ColorChoice::parse(value)
+            .ok_or_else(|| ShellError::InvalidUsage(format!("ls --color=auto|always|never (got '{}')", value))),
+    }
+}
+
+/// Text content of a file, or `None` if it looks binary.
+fn text_content(bytes: &[u8]) -> Option<&str> {
+    if bytes.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(bytes).ok()
+}
+
+/// An octal permission mode for `mkdir -m`, e.g. `755` or `0700`.
+fn parse_mode(mode: &str) -> Result<u32, ShellError> {
+    if cfg!(not(unix)) {
+        return Err(ShellError::InvalidUsage("mkdir -m is only supported on Unix".to_string()));
+    }
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| ShellError::InvalidUsage(format!("mkdir: invalid mode '{}', expected octal such as 755", mode)))
+}
+
+#[cfg(unix)]
+async fn set_mode(path: &Path, mode: u32) -> Result<(), ShellError> {
+    use std::os::unix::fs::PermissionsExt;
+    async_fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .await
+        .map_err(|e| ShellError::from_io(path, e))
+}
+
+#[cfg(not(unix))]
+async fn set_mode(_path: &Path, _mode: u32) -> Result<(), ShellError> {
+    Ok(())
+}
+
+/// Applies `-n`/`--no-clobber` and `-i`/`--interactive` to an existing destination. Returns a
+/// skip message for no-clobber, or asks the shell to confirm the overwrite for interactive.
+fn check_clobber(command: &Command, destination: &Path) -> Result<Option<String>, ShellError> {
+    if !destination.exists() {
+        return Ok(None);
+    }
+    if command.flags.contains_key("n") || command.flags.contains_key("no-clobber") {
+        return Ok(Some(format!("Skipped {} (destination exists)", destination.display())));
+    }
+    if command.flags.contains_key("i") || command.flags.contains_key("interactive") {
+        return Err(ShellError::ConfirmationRequired(format!("Overwrite {}?", destination.display())));
+    }
+    Ok(None)
+}
+
+/// Parses `touch` times: RFC 3339, `YYYY-MM-DD[THH:MM[:SS]]` or POSIX `[CC]YYMMDDhhmm[.ss]`, in local time.
+fn parse_touch_time(value: &str) -> Option<FileTime> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(FileTime::from_unix_time(time.timestamp(), 0));
+    }
+
+    let naive = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y%m%d%H%M.%S", "%Y%m%d%H%M", "%y%m%d%H%M.%S", "%y%m%d%H%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))?;
+    let local = Local.from_local_datetime(&naive).earliest()?;
+    Some(FileTime::from_unix_time(local.timestamp(), 0))
+}
+
+/// Whether `path` is `/`, the user's home or the current directory, which `rm` won't touch without `--force`.
+fn is_protected_path(path: &Path, env: &Environment) -> bool {
+    let resolved = match path.canonicalize() {
+        Ok(resolved) => resolved,
+        Err(_) => return false,
+    };
+
+    let mut protected = vec![PathBuf::from("/"), env.get_current_dir()];
+    if let Some(home) = env.get_var("HOME") {
+        protected.push(PathBuf::from(home));
+    }
+
+    protected
+        .iter()
+        .filter_map(|candidate| candidate.canonicalize().ok())
+        .any(|candidate| candidate == resolved)
+}
+
+/// What `stat` reports about one path. Fields the platform doesn't provide are `None`.
+struct FileStat {
+    name: String,
+    file_type: &'static str,
+    size: u64,
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    inode: Option<u64>,
+    accessed: Option<DateTime<Local>>,
+    modified: Option<DateTime<Local>>,
+    created: Option<DateTime<Local>>,
+}
+
+impl FileStat {
+    fn new(name: &str, metadata: &fs::Metadata) -> Self {
+        let file_type = metadata.file_type();
+        #[cfg(unix)]
+        let file_type_name = {
+            use std::os::unix::fs::FileTypeExt;
+            if file_type.is_block_device() {
+                "block special file"
+            } else if file_type.is_char_device() {
+                "character special file"
+            } else if file_type.is_fifo() {
+                "fifo"
+            } else if file_type.is_socket() {
+                "socket"
+            } else {
+                basic_file_type(&file_type)
+            }
+        };
+        #[cfg(not(unix))]
+        let file_type_name = basic_file_type(&file_type);
+
+        #[cfg(unix)]
+        let (mode, uid, gid, inode) = {
+            use std::os::unix::fs::MetadataExt;
+            (Some(metadata.mode()), Some(metadata.uid()), Some(metadata.gid()), Some(metadata.ino()))
+        };
+        #[cfg(not(unix))]
+        let (mode, uid, gid, inode) = (None, None, None, None);
+
+        FileStat {
+            name: name.to_string(),
+            file_type: file_type_name,
+            size: metadata.len(),
+            mode,
+            uid,
+            gid,
+            inode,
+            accessed: metadata.accessed().ok().map(DateTime::from),
+            modified: metadata.modified().ok().map(DateTime::from),
+            created: metadata.created().ok().map(DateTime::from),
+        }
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "  File: {}\n  Size: {:<12} Type: {}\n  Mode: {} ({})  Uid: {}  Gid: {}\n Inode: {}\nAccess: {}\nModify: {}\n Birth: {}",
+            self.name,
+            self.size,
+            self.file_type,
+            self.token('a'),
+            self.token('A'),
+            self.token('u'),
+            self.token('g'),
+            self.token('i'),
+            self.token('x'),
+            self.token('y'),
+            self.token('w'),
+        )
+    }
+
+    /// Expands `%` tokens: `%n` name, `%s` size, `%F` type, `%a`/`%A` octal/symbolic mode,
+    /// `%u`/`%g` owner ids, `%i` inode, `%x`/`%y`/`%w` access/modify/birth time, `%%`.
+    fn format(&self, format: &str) -> String {
+        let mut output = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                output.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('%') => output.push('%'),
+                Some(token) => output.push_str(&self.token(token)),
+                None => output.push('%'),
+            }
+        }
+        output
+    }
+
+    fn token(&self, token: char) -> String {
+        let number = |value: Option<u64>| value.map_or("-".to_string(), |value| value.to_string());
+        let time = |value: Option<DateTime<Local>>| {
+            value.map_or("-".to_string(), |value| value.format("%Y-%m-%d %H:%M:%S %z").to_string())
+        };
+        match token {
+            'n' => self.name.clone(),
+            's' => self.size.to_string(),
+            'F' => self.file_type.to_string(),
+            'a' => self.mode.map_or("-".to_string(), |mode| format!("{:04o}", mode & 0o7777)),
+            'A' => self.mode.map_or("-".to_string(), |mode| symbolic_mode(self.file_type, mode)),
+            'u' => number(self.uid.map(u64::from)),
+            'g' => number(self.gid.map(u64::from)),
+            'i' => number(self.inode),
+            'x' => time(self.accessed),
+            'y' => time(self.modified),
+            'w' => time(self.created),
+            other => format!("%{}", other),
+        }
+    }
+}
+
+fn basic_file_type(file_type: &fs::FileType) -> &'static str {
+    if file_type.is_symlink() {
+        "symbolic link"
+    } else if file_type.is_dir() {
+        "directory"
+    } else {
+        "regular file"
+    }
+}
+
+/// `ls -l` style permissions, e.g. `drwxr-xr-x`.
+fn symbolic_mode(file_type: &str, mode: u32) -> String {
+    let kind = match file_type {
+        "directory" => 'd',
+        "symbolic link" => 'l',
+        "block special file" => 'b',
+        "character special file" => 'c',
+        "fifo" => 'p',
+        "socket" => 's',
+        _ => '-',
+    };
+    let mut symbolic = String::from(kind);
+    for (shift, special, set_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = (mode >> shift) & 0o7;
+        symbolic.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        symbolic.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        symbolic.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => set_char,
+            (false, true) => set_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    symbolic
+}
+
+/// Every path under `root` (itself included) with its type, shown relative to `shown` and
+/// sorted within each directory. With `follow`, symlinks resolve to their targets and a
+/// directory that is its own ancestor (by canonical path) is reported in `warnings` instead
+/// of walked; one reached through several links is walked each time, as find(1) does.
+fn walk_find(root: &Path, shown: &Path, follow: bool, warnings: &mut Vec<String>) -> std::io::Result<Vec<(PathBuf, fs::FileType)>> {
+    let metadata = |path: &Path| if follow { fs::metadata(path) } else { fs::symlink_metadata(path) };
+
+    let mut found = Vec::new();
+    // Each entry carries the directories above it as (canonical, shown) pairs
+    let mut pending = vec![(root.to_path_buf(), shown.to_path_buf(), Vec::<(PathBuf, PathBuf)>::new())];
+    while let Some((path, shown, mut ancestors)) = pending.pop() {
+        let file_type = match metadata(&path) {
+            Ok(metadata) => metadata.file_type(),
+            // A dangling link under -L is still listed, as the link itself
+            Err(_) if follow && path != root => fs::symlink_metadata(&path)?.file_type(),
+            Err(e) => return Err(e),
+        };
+        found.push((shown.clone(), file_type));
+        if !file_type.is_dir() {
+            continue;
+        }
+        if follow {
+            let canonical = fs::canonicalize(&path)?;
+            if let Some((_, ancestor)) = ancestors.iter().find(|(dir, _)| *dir == canonical) {
+                warnings.push(format!(
+                    "File system loop detected; '{}' is part of the same file system loop as '{}'",
+                    shown.display(),
+                    ancestor.display()
+                ));
+                continue;
+            }
+            ancestors.push((canonical, shown.clone()));
+        }
+
+        let mut children: Vec<_> = fs::read_dir(&path)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .collect();
+        // Reversed so the stack pops them in order
+        children.sort_by(|a, b| b.cmp(a));
+        pending.extend(children.into_iter().map(|name| (path.join(&name), shown.join(&name), ancestors.clone())));
+    }
+    Ok(found)
+}
+
+/// Groups of identical non-empty files under `dir` as `(size, paths)`, each group sorted by
+/// path. Files are only hashed when another file has the same size.
+async fn find_duplicates(dir: &Path) -> anyhow::Result<Vec<(u64, Vec<PathBuf>)>> {
+    let mut by_size: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+    for entry in ignore::Walk::new(dir) {
+        let entry = entry?;
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let size = entry.metadata()?.len();
+        if size > 0 {
+            by_size.entry(size).or_default().push(entry.into_path());
+        }
+    }
+
+    let mut groups = Vec::new();
+    for (size, paths) in by_size.into_iter().filter(|(_, paths)| paths.len() > 1) {
+        let mut by_digest: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        for path in paths {
+            let digest = super::checksum::hash_file(&digest::SHA256, &path).await?;
+            by_digest.entry(digest).or_default().push(path);
+        }
+        for mut paths in by_digest.into_values().filter(|paths| paths.len() > 1) {
+            paths.sort();
+            groups.push((size, paths));
+        }
+    }
+    groups.sort_by(|a, b| a.1[0].cmp(&b.1[0]));
+    Ok(groups)
+}
+
+/// Removes `path` only if it is an empty directory.
+async fn remove_empty_dir(path: &Path) -> anyhow::Result<()> {
+    let metadata = async_fs::metadata(path).await.map_err(|e| ShellError::from_io(path, e))?;
+    if !metadata.is_dir() {
+        return Err(ShellError::External(format!("rmdir: {}: Not a directory", path.display())).into());
+    }
+    let mut entries = async_fs::read_dir(path).await.map_err(|e| ShellError::from_io(path, e))?;
+    if entries.next_entry().await?.is_some() {
+        return Err(ShellError::External(format!("rmdir: {}: Directory not empty", path.display())).into());
+    }
+    async_fs::remove_dir(path).await.map_err(|e| ShellError::from_io(path, e))?;
+    Ok(())
 }
 
 fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> std::io::Result<()> {
@@ -174,3 +833,426 @@ fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> std::io::Result
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::Parser;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    fn command(name: &str, args: &[&str]) -> Command {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        Command::new(name.to_string(), args.clone(), HashMap::new(), format!("{} {}", name, args.join(" ")))
+    }
+
+    fn parse(line: &str) -> Command {
+        Parser::new().parse(line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ls_without_color() {
+        let temp_dir = tempdir().unwrap();
+        fs::create_dir(temp_dir.path().join("subdir")).unwrap();
+        fs::write(temp_dir.path().join("file.txt"), "content").unwrap();
+
+        let path = temp_dir.path().to_string_lossy().to_string();
+        let output = {
+            let _color = color::scoped(false);
+            FileOperationsPlugin
+                .list_directory(&command("ls", &[&path]), &Environment::new())
+                .await
+                .unwrap()
+        };
+
+        assert!(output.contains("subdir/"));
+        assert!(output.contains("file.txt"));
+        assert!(!output.contains('\x1b'));
+    }
+
+    #[tokio::test]
+    async fn test_error_variants() {
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        let missing = temp_dir.path().join("missing.txt").to_string_lossy().to_string();
+
+        let err = FileOperationsPlugin.cat(&command("cat", &[&missing]), &env).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::NotFound(_))));
+
+        let err = FileOperationsPlugin.cat(&command("cat", &[]), &env).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::InvalidUsage(_))));
+        assert_eq!(err.downcast_ref::<ShellError>().unwrap().exit_code(), 2);
+
+        let err = FileOperationsPlugin.copy(&command("cp", &[&missing, "copy.txt"]), &env).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_mkdir_parents() {
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        let nested = temp_dir.path().join("a/b/c").to_string_lossy().to_string();
+        let first = temp_dir.path().join("one").to_string_lossy().to_string();
+        let second = temp_dir.path().join("two").to_string_lossy().to_string();
+
+        let err = FileOperationsPlugin.make_directory(&command("mkdir", &[&nested]), &env).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::NotFound(_))));
+
+        let with_parents = parse(&format!("mkdir -p {}", nested));
+        FileOperationsPlugin.make_directory(&with_parents, &env).await.unwrap();
+        assert!(temp_dir.path().join("a/b/c").is_dir());
+
+        // -p is idempotent, plain mkdir is not
+        FileOperationsPlugin.make_directory(&with_parents, &env).await.unwrap();
+        let err = FileOperationsPlugin.make_directory(&command("mkdir", &[&nested]), &env).await.unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+
+        FileOperationsPlugin.make_directory(&command("mkdir", &[&first, &second]), &env).await.unwrap();
+        assert!(temp_dir.path().join("one").is_dir());
+        assert!(temp_dir.path().join("two").is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_mkdir_parents_reports_intermediates() {
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        fs::create_dir(temp_dir.path().join("x")).unwrap();
+        let nested = temp_dir.path().join("x/y/z").to_string_lossy().to_string();
+
+        let with_parents = parse(&format!("mkdir -p {}", nested));
+        let output = FileOperationsPlugin.make_directory(&with_parents, &env).await.unwrap();
+        let expected: Vec<String> = ["x/y", "x/y/z"].iter()
+            .map(|dir| format!("Created directory {}", temp_dir.path().join(dir).to_string_lossy()))
+            .collect();
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+
+        // Nothing new the second time round
+        assert_eq!(FileOperationsPlugin.make_directory(&with_parents, &env).await.unwrap(), "");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_mkdir_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        let private = temp_dir.path().join("private").to_string_lossy().to_string();
+        let nested = temp_dir.path().join("a/b").to_string_lossy().to_string();
+        let mode = |dir: &str| fs::metadata(temp_dir.path().join(dir)).unwrap().permissions().mode() & 0o7777;
+
+        FileOperationsPlugin.make_directory(&parse(&format!("mkdir -m 751 {}", private)), &env).await.unwrap();
+        assert_eq!(mode("private"), 0o751);
+
+        FileOperationsPlugin.make_directory(&parse(&format!("mkdir -p -m 0700 {}", nested)), &env).await.unwrap();
+        assert_eq!(mode("a/b"), 0o700);
+        assert_ne!(mode("a"), 0o700);
+
+        for bad in ["rwx", "999", "17777"] {
+            let mkdir = parse(&format!("mkdir -m {} {}", bad, private));
+            let err = FileOperationsPlugin.make_directory(&mkdir, &env).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::InvalidUsage(_))), "{}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rmdir() {
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        let empty = temp_dir.path().join("empty");
+        let full = temp_dir.path().join("full");
+        fs::create_dir(&empty).unwrap();
+        fs::create_dir(&full).unwrap();
+        fs::write(full.join("keep.txt"), "data").unwrap();
+
+        let empty_arg = empty.to_string_lossy().to_string();
+        let full_arg = full.to_string_lossy().to_string();
+        FileOperationsPlugin.remove_directory(&command("rmdir", &[&empty_arg]), &env).await.unwrap();
+        assert!(!empty.exists());
+
+        let err = FileOperationsPlugin.remove_directory(&command("rmdir", &[&full_arg]), &env).await.unwrap_err();
+        assert!(err.to_string().contains("Directory not empty"));
+        assert!(full.join("keep.txt").exists());
+
+        // -p removes each component of the operand as written, relative to the current directory
+        let base = tempfile::tempdir_in(env.get_current_dir()).unwrap();
+        fs::create_dir_all(base.path().join("a/b/c")).unwrap();
+        let operand = format!("{}/a/b/c", base.path().file_name().unwrap().to_string_lossy());
+        let with_parents = parse(&format!("rmdir -p {}", operand));
+        let output = FileOperationsPlugin.remove_directory(&with_parents, &env).await.unwrap();
+        assert_eq!(output.lines().count(), 4);
+        assert!(!base.path().exists());
+    }
+
+    #[tokio::test]
+    async fn test_stat_reports_size_and_type() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("hello.txt");
+        fs::write(&file, "hello").unwrap();
+        let file = file.to_string_lossy().to_string();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        let env = Environment::new();
+
+        let output = FileOperationsPlugin.stat(&command("stat", &[&file]), &env).await.unwrap();
+        assert!(output.contains("Size: 5"));
+        assert!(output.contains("Type: regular file"));
+
+        let mut formatted = command("stat", &[&file, &dir]);
+        formatted.flags.insert("format".to_string(), Some("%s|%F|%%".to_string()));
+        let output = FileOperationsPlugin.stat(&formatted, &env).await.unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "5|regular file|%");
+        assert!(lines[1].ends_with("|directory|%"));
+
+        assert_eq!(symbolic_mode("directory", 0o40755), "drwxr-xr-x");
+        assert_eq!(symbolic_mode("regular file", 0o4754), "-rwsr-xr--");
+    }
+
+    #[tokio::test]
+    async fn test_rm_guards() {
+        let env = Environment::new();
+
+        let err = FileOperationsPlugin.remove(&command("rm", &["."]), &env).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::PermissionDenied(_))));
+        assert!(env.get_current_dir().exists());
+
+        let temp_dir = tempdir().unwrap();
+        let missing = temp_dir.path().join("missing.txt").to_string_lossy().to_string();
+
+        let err = FileOperationsPlugin.remove(&command("rm", &[&missing]), &env).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::NotFound(_))));
+
+        FileOperationsPlugin.remove(&parse(&format!("rm -f {}", missing)), &env).await.unwrap();
+
+        // Combined short switches
+        let tree = temp_dir.path().join("tree");
+        fs::create_dir_all(tree.join("sub")).unwrap();
+        FileOperationsPlugin.remove(&parse(&format!("rm -rf {} {}", tree.display(), missing)), &env).await.unwrap();
+        assert!(!tree.exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_rm_removes_symlinks_not_their_targets() {
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        let dir = temp_dir.path().join("dir");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("kept.txt"), "kept").unwrap();
+
+        let dangling = temp_dir.path().join("dangling");
+        std::os::unix::fs::symlink(temp_dir.path().join("missing"), &dangling).unwrap();
+        let dir_link = temp_dir.path().join("dlink");
+        std::os::unix::fs::symlink(&dir, &dir_link).unwrap();
+
+        for link in [&dangling, &dir_link] {
+            FileOperationsPlugin.remove(&command("rm", &[&link.to_string_lossy()]), &env).await.unwrap();
+            assert!(fs::symlink_metadata(link).is_err(), "{}", link.display());
+        }
+        assert!(dir.join("kept.txt").exists());
+
+        // -r on a link to a directory still removes just the link
+        std::os::unix::fs::symlink(&dir, &dir_link).unwrap();
+        FileOperationsPlugin.remove(&parse(&format!("rm -r {}", dir_link.display())), &env).await.unwrap();
+        assert!(fs::symlink_metadata(&dir_link).is_err());
+        assert!(dir.join("kept.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_diff() {
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        let a = temp_dir.path().join("a.txt");
+        let b = temp_dir.path().join("b.txt");
+        fs::write(&a, "one\ntwo\nthree\n").unwrap();
+        fs::write(&b, "one\n2\nthree\nfour\n").unwrap();
+        let (a, b) = (a.to_string_lossy().to_string(), b.to_string_lossy().to_string());
+
+        let output = FileOperationsPlugin.diff(&command("diff", &[&a, &b]), &env).await.unwrap();
+        assert!(output.contains("-two"));
+        assert!(output.contains("+2"));
+        assert!(output.contains("+four"));
+        assert!(!output.contains("-one"));
+
+        let stat = parse(&format!("diff --stat {} {}", a, b));
+        let output = FileOperationsPlugin.diff(&stat, &env).await.unwrap();
+        assert!(output.contains("2 insertions(+), 1 deletions(-)"));
+
+        let output = FileOperationsPlugin.diff(&command("diff", &[&a, &a]), &env).await.unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_diff_binary() {
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        let a = temp_dir.path().join("a.bin");
+        let b = temp_dir.path().join("b.bin");
+        fs::write(&a, [0u8, 1, 2]).unwrap();
+        fs::write(&b, [0u8, 1, 3]).unwrap();
+        let (a, b) = (a.to_string_lossy().to_string(), b.to_string_lossy().to_string());
+
+        let output = FileOperationsPlugin.diff(&command("diff", &[&a, &b]), &env).await.unwrap();
+        assert!(output.starts_with("Binary files"));
+    }
+
+    #[tokio::test]
+    async fn test_touch_preserves_content() {
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        let existing = temp_dir.path().join("existing.txt");
+        fs::write(&existing, "keep me").unwrap();
+        filetime::set_file_mtime(&existing, FileTime::from_unix_time(1_000_000_000, 0)).unwrap();
+        let new_file = temp_dir.path().join("new.txt");
+
+        let args = [existing.to_string_lossy().to_string(), new_file.to_string_lossy().to_string()];
+        FileOperationsPlugin.touch(&command("touch", &[&args[0], &args[1]]), &env).await.unwrap();
+
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "keep me");
+        let mtime = FileTime::from_last_modification_time(&fs::metadata(&existing).unwrap());
+        assert!(mtime.unix_seconds() > 1_000_000_000);
+        assert!(new_file.exists());
+
+        let mut dated = command("touch", &[&args[0]]);
+        dated.flags.insert("date".to_string(), Some("2001-09-09T01:46:40Z".to_string()));
+        FileOperationsPlugin.touch(&dated, &env).await.unwrap();
+        let mtime = FileTime::from_last_modification_time(&fs::metadata(&existing).unwrap());
+        assert_eq!(mtime.unix_seconds(), 1_000_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_cp_no_clobber_and_interactive() {
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        let source = temp_dir.path().join("source.txt");
+        let destination = temp_dir.path().join("destination.txt");
+        fs::write(&source, "new").unwrap();
+        fs::write(&destination, "old").unwrap();
+        let (source, destination) = (source.to_string_lossy().to_string(), destination.to_string_lossy().to_string());
+
+        // Wherever the switch goes, the operands keep their order
+        for line in [format!("cp -n {} {}", source, destination), format!("cp {} -n {}", source, destination)] {
+            let output = FileOperationsPlugin.copy(&parse(&line), &env).await.unwrap();
+            assert!(output.starts_with("Skipped"), "{}", line);
+            assert_eq!(fs::read_to_string(&destination).unwrap(), "old");
+        }
+
+        for line in [format!("cp --interactive {} {}", source, destination), format!("mv {} -i {}", source, destination)] {
+            let err = FileOperationsPlugin.execute(&parse(&line), &env).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::ConfirmationRequired(_))), "{}", line);
+            assert_eq!(fs::read_to_string(&destination).unwrap(), "old");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mv_into_directory() {
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        let source = temp_dir.path().join("report.txt");
+        let target_dir = temp_dir.path().join("archive");
+        fs::write(&source, "data").unwrap();
+        fs::create_dir(&target_dir).unwrap();
+
+        let args = [source.to_string_lossy().to_string(), target_dir.to_string_lossy().to_string()];
+        FileOperationsPlugin.move_file(&command("mv", &[&args[0], &args[1]]), &env).await.unwrap();
+
+        assert!(!source.exists());
+        assert_eq!(fs::read_to_string(target_dir.join("report.txt")).unwrap(), "data");
+    }
+
+    #[tokio::test]
+    async fn test_dedup() {
+        let temp_dir = tempdir().unwrap();
+        let env = Environment::new();
+        fs::create_dir(temp_dir.path().join("nested")).unwrap();
+        fs::write(temp_dir.path().join("a.txt"), "same content").unwrap();
+        fs::write(temp_dir.path().join("nested/b.txt"), "same content").unwrap();
+        fs::write(temp_dir.path().join("c.txt"), "same length!").unwrap();
+        fs::write(temp_dir.path().join("unique.txt"), "different").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+
+        let groups = find_duplicates(temp_dir.path()).await.unwrap();
+        assert_eq!(groups, vec![(12, vec![temp_dir.path().join("a.txt"), temp_dir.path().join("nested/b.txt")])]);
+
+        let delete = parse(&format!("dedup --delete {}", dir));
+        let output = FileOperationsPlugin.dedup(&delete, &env).await.unwrap();
+        assert!(output.contains("(removed)"));
+        assert!(temp_dir.path().join("a.txt").exists());
+        assert!(!temp_dir.path().join("nested/b.txt").exists());
+        assert!(temp_dir.path().join("c.txt").exists());
+
+        let output = FileOperationsPlugin.dedup(&delete, &env).await.unwrap();
+        assert_eq!(output, "No duplicate files found");
+    }
+
+    #[tokio::test]
+    async fn test_ls_color_choice() {
+        let temp_dir = tempdir().unwrap();
+        fs::create_dir(temp_dir.path().join("subdir")).unwrap();
+        let path = temp_dir.path().to_string_lossy().to_string();
+        let env = Environment::new();
+
+        let never = parse(&format!("ls --color=never {}", path));
+        let output = FileOperationsPlugin.list_directory(&never, &env).await.unwrap();
+        assert_eq!(output, "subdir/");
+
+        // A bare `--color` doesn't take the directory as its value
+        let always = parse(&format!("ls --color {}", path));
+        let output = FileOperationsPlugin.list_directory(&always, &env).await.unwrap();
+        assert_eq!(output, "\x1b[1;34msubdir/\x1b[0m");
+
+        let invalid = parse(&format!("ls --color=sometimes {}", path));
+        let err = FileOperationsPlugin.list_directory(&invalid, &env).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::InvalidUsage(_))));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_find_symlink_loop() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub/file.txt"), "data").unwrap();
+        std::os::unix::fs::symlink(root, root.join("sub/loop")).unwrap();
+
+        let mut warnings = Vec::new();
+        let plain: Vec<PathBuf> = walk_find(root, Path::new("t"), false, &mut warnings).unwrap()
+            .into_iter().map(|(path, _)| path).collect();
+        assert_eq!(plain, ["t", "t/sub", "t/sub/file.txt", "t/sub/loop"].map(PathBuf::from));
+        assert!(warnings.is_empty());
+
+        // Following the link reaches the root again, which is reported rather than walked
+        let followed = walk_find(root, Path::new("t"), true, &mut warnings).unwrap();
+        assert_eq!(followed.len(), 4);
+        assert!(followed[3].1.is_dir());
+        assert_eq!(warnings, ["File system loop detected; 't/sub/loop' is part of the same file system loop as 't'"]);
+
+        // The warning follows the results in the command's output
+        let find = parse(&format!("find -L {} --name *.txt", root.display()));
+        let output = FileOperationsPlugin.find(&find, &Environment::new()).await.unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], root.join("sub/file.txt").to_string_lossy());
+        assert!(lines[1].starts_with("find: File system loop detected"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_find_follows_links_to_the_same_directory() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("shared")).unwrap();
+        fs::write(root.join("shared/file.txt"), "data").unwrap();
+        std::os::unix::fs::symlink(root.join("shared"), root.join("a")).unwrap();
+        std::os::unix::fs::symlink(root.join("shared"), root.join("b")).unwrap();
+
+        // Not a loop: neither link leads back to a directory above it
+        let mut warnings = Vec::new();
+        let files: Vec<PathBuf> = walk_find(root, Path::new("t"), true, &mut warnings).unwrap()
+            .into_iter()
+            .filter(|(_, file_type)| file_type.is_file())
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(files, ["t/a/file.txt", "t/b/file.txt", "t/shared/file.txt"].map(PathBuf::from));
+        assert!(warnings.is_empty());
+    }
+}
@@ -1,7 +1,13 @@
 use async_trait::async_trait;
-use super::super::{Command, Environment, Plugin};
+use super::super::{color, Command, Environment, Plugin};
+use std::path::{Path, PathBuf};
 use tokio::process::Command as TokioCommand;
 
+/// Subcommands that are valid outside of a repository.
+const REPO_FREE_COMMANDS: &[&str] = &["init", "clone", "help", "version", "config", "--version", "--help"];
+
+const DEFAULT_LOG_FORMAT: &str = "--pretty=format:%C(yellow)%h%Creset %C(cyan)%ad%Creset %s %C(green)<%an>%Creset";
+
 pub struct GitPlugin;
 
 impl GitPlugin {
@@ -9,9 +15,10 @@ impl GitPlugin {
         GitPlugin
     }
 
-    async fn execute_git_command(&self, args: &[String]) -> anyhow::Result<String> {
+    async fn execute_git_command(&self, args: &[String], dir: &Path) -> anyhow::Result<String> {
         let output = TokioCommand::new("git")
             .args(args)
+            .current_dir(dir)
             .output()
             .await?;
 
@@ -25,6 +32,154 @@ impl GitPlugin {
 
         Ok(result)
     }
+
+    fn find_repo_root(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .find(|ancestor| ancestor.join(".git").exists())
+            .map(Path::to_path_buf)
+    }
+
+    async fn log(&self, args: &[String], dir: &Path) -> anyhow::Result<String> {
+        let mut preset = "default";
+        let mut raw = false;
+        let mut rest = Vec::new();
+
+        let mut i = 1;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--preset" if i + 1 < args.len() => {
+                    preset = args[i + 1].as_str();
+                    i += 2;
+                    continue;
+                }
+                "--raw" => raw = true,
+                other => rest.push(other.to_string()),
+            }
+            i += 1;
+        }
+
+        let mut log_args = vec!["log".to_string()];
+        if !raw {
+            match preset {
+                "default" => {
+                    log_args.push(DEFAULT_LOG_FORMAT.to_string());
+                    log_args.push("--date=short".to_string());
+                }
+                "oneline" => log_args.push("--oneline".to_string()),
+                "full" => log_args.push("--pretty=full".to_string()),
+                "graph" => {
+                    log_args.push(DEFAULT_LOG_FORMAT.to_string());
+                    log_args.push("--date=short".to_string());
+                    log_args.push("--graph".to_string());
+                    log_args.push("--all".to_string());
+                }
+                _ => return Err(anyhow::anyhow!("Unknown log preset '{}'. Available presets: oneline, full, graph", preset)),
+            }
+        }
+        log_args.extend(rest);
+
+        self.execute_git_command(&log_args, dir).await
+    }
+
+    /// Blame as aligned `hash author date line` columns. Extra arguments such as
+    /// `-L <start>,<end>` are passed to git.
+    async fn blame(&self, args: &[String], dir: &Path) -> anyhow::Result<String> {
+        let mut git_args = vec!["blame".to_string(), "--line-porcelain".to_string()];
+        git_args.extend(args.iter().skip(1).cloned());
+        let output = TokioCommand::new("git")
+            .args(&git_args)
+            .current_dir(dir)
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("{}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+
+        let porcelain = String::from_utf8_lossy(&output.stdout);
+        match format_blame(&porcelain) {
+            Some(formatted) => Ok(formatted),
+            None => self.execute_git_command(args, dir).await,
+        }
+    }
+
+    /// Colored, grouped status; `--porcelain` gives git's stable `XY path` lines for scripts.
+    async fn status(&self, args: &[String], dir: &Path) -> anyhow::Result<String> {
+        let porcelain = args.iter().skip(1).any(|arg| arg == "--porcelain" || arg.starts_with("--porcelain="));
+        let mut git_args = vec!["-c", "status.renames=true", "status", "--porcelain=v1"];
+        if !porcelain {
+            git_args.push("-b");
+        }
+        let output = TokioCommand::new("git").args(&git_args).current_dir(dir).output().await?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("git status failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if porcelain {
+            return Ok(stdout.trim_end().to_string());
+        }
+        Ok(format_status(&stdout))
+    }
+
+    /// `stash list` is numbered and colored, and `show`/`apply`/`pop` accept a bare index
+    /// (`git stash pop 2`). Other stash subcommands go to git untouched.
+    async fn stash(&self, args: &[String], dir: &Path) -> anyhow::Result<String> {
+        let stash_ref = |arg: &String| match arg.parse::<usize>() {
+            Ok(index) => format!("stash@{{{}}}", index),
+            Err(_) => arg.clone(),
+        };
+
+        match args.get(1).map(|s| s.as_str()) {
+            Some("list") => {
+                let entries = self.stash_entries(dir).await?;
+                if entries.is_empty() {
+                    return Ok("No stash entries".to_string());
+                }
+                Ok(entries.iter()
+                    .enumerate()
+                    .map(|(index, (name, subject))| {
+                        format!("{:>3}  {}  {}", index, color::paint("33", name), subject)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            Some("show") => {
+                let mut git_args = vec!["stash".to_string(), "show".to_string()];
+                if color::enabled() {
+                    git_args.push("--color".to_string());
+                }
+                git_args.extend(args.iter().skip(2).map(stash_ref));
+                self.execute_git_command(&git_args, dir).await
+            }
+            Some(action @ ("pop" | "apply")) => {
+                if self.stash_entries(dir).await?.is_empty() {
+                    return Ok(format!("Nothing to {}: the stash is empty", action));
+                }
+                let mut git_args = vec!["stash".to_string(), action.to_string()];
+                git_args.extend(args.iter().skip(2).map(stash_ref));
+                self.execute_git_command(&git_args, dir).await
+            }
+            _ => self.execute_git_command(args, dir).await,
+        }
+    }
+
+    /// `(stash@{n}, subject)` pairs, newest first.
+    async fn stash_entries(&self, dir: &Path) -> anyhow::Result<Vec<(String, String)>> {
+        let output = TokioCommand::new("git")
+            .args(["stash", "list", "--format=%gd%x09%gs"])
+            .current_dir(dir)
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("{}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(name, subject)| (name.to_string(), subject.to_string()))
+            .collect())
+    }
 }
 
 #[async_trait]
@@ -37,36 +192,305 @@ impl Plugin for GitPlugin {
         "Git version control system integration"
     }
 
-    async fn execute(&self, command: &Command, _env: &Environment) -> anyhow::Result<String> {
+    async fn execute(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        self.run(&command.raw_args(), &env.get_current_dir()).await
+    }
+}
+
+impl GitPlugin {
+    async fn run(&self, args: &[String], dir: &Path) -> anyhow::Result<String> {
+        let subcommand = args.first().map(|s| s.as_str());
+        let needs_repo = subcommand.is_some_and(|cmd| !REPO_FREE_COMMANDS.contains(&cmd));
+        if needs_repo && Self::find_repo_root(dir).is_none() {
+            return Err(anyhow::anyhow!(
+                "Not a git repository: {} (or any parent directory). Run 'git init' to create one.",
+                dir.display()
+            ));
+        }
+
         // Special handling for common git commands
-        match command.args.first().map(|s| s.as_str()) {
-            Some("status") => {
-                let output = self.execute_git_command(&["status", "--porcelain", "-b"]).await?;
-                if output.is_empty() {
-                    Ok("No changes (working directory clean)".to_string())
-                } else {
-                    Ok(output)
-                }
-            }
-            Some("log") => {
-                let mut args = vec!["log", "--pretty=format:%C(yellow)%h%Creset %C(cyan)%ad%Creset %s %C(green)<%an>%Creset", "--date=short"];
-                args.extend(command.args.iter().skip(1).map(|s| s.as_str()));
-                self.execute_git_command(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>()).await
-            }
+        match subcommand {
+            Some("status") => self.status(args, dir).await,
+            Some("log") => self.log(args, dir).await,
+            Some("stash") => self.stash(args, dir).await,
+            Some("blame") => self.blame(args, dir).await,
             Some("diff") => {
-                let mut args = vec!["diff", "--color"];
-                args.extend(command.args.iter().skip(1).map(|s| s.as_str()));
-                self.execute_git_command(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>()).await
+                let mut git_args = vec!["diff", "--color"];
+                git_args.extend(args.iter().skip(1).map(|s| s.as_str()));
+                self.execute_git_command(&git_args.iter().map(|s| s.to_string()).collect::<Vec<_>>(), dir).await
             }
             Some("branch") => {
-                let mut args = vec!["branch", "--color"];
-                args.extend(command.args.iter().skip(1).map(|s| s.as_str()));
-                self.execute_git_command(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>()).await
+                let mut git_args = vec!["branch", "--color"];
+                git_args.extend(args.iter().skip(1).map(|s| s.as_str()));
+                self.execute_git_command(&git_args.iter().map(|s| s.to_string()).collect::<Vec<_>>(), dir).await
             }
             _ => {
                 // For all other git commands, pass through as-is
-                self.execute_git_command(&command.args).await
+                self.execute_git_command(args, dir).await
+            }
+        }
+    }
+}
+
+/// Groups `git status --porcelain=v1 -b` lines under headings, like `git status` does.
+fn format_status(porcelain: &str) -> String {
+    let mut branch = None;
+    let (mut staged, mut unstaged, mut unmerged, mut untracked) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for line in porcelain.lines() {
+        if let Some(header) = line.strip_prefix("## ") {
+            branch = Some(header.to_string());
+            continue;
+        }
+        let mut codes = line.chars();
+        let (x, y) = match (codes.next(), codes.next()) {
+            (Some(x), Some(y)) if line.len() > 3 => (x, y),
+            _ => continue,
+        };
+        let path = &line[3..];
+        match (x, y) {
+            ('?', '?') => untracked.push(color::paint("31", path)),
+            ('!', '!') => {}
+            ('U', _) | (_, 'U') | ('A', 'A') | ('D', 'D') => unmerged.push(color::paint("31", path)),
+            _ => {
+                if x != ' ' {
+                    staged.push(color::paint("32", &format!("{:<9} {}", change_label(x), path)));
+                }
+                if y != ' ' {
+                    unstaged.push(color::paint("31", &format!("{:<9} {}", change_label(y), path)));
+                }
             }
         }
     }
+
+    let mut output = Vec::new();
+    if let Some(branch) = branch {
+        output.push(format!("On branch {}", color::paint("36", &branch)));
+    }
+    for (heading, entries) in [
+        ("Changes to be committed:", staged),
+        ("Unmerged paths:", unmerged),
+        ("Changes not staged for commit:", unstaged),
+        ("Untracked files:", untracked),
+    ] {
+        if !entries.is_empty() {
+            output.push(heading.to_string());
+            output.extend(entries.iter().map(|entry| format!("  {}", entry)));
+        }
+    }
+    if output.len() <= 1 {
+        output.push("No changes (working directory clean)".to_string());
+    }
+    output.join("\n")
+}
+
+fn change_label(code: char) -> &'static str {
+    match code {
+        'M' => "modified:",
+        'A' => "new file:",
+        'D' => "deleted:",
+        'R' => "renamed:",
+        'C' => "copied:",
+        'T' => "typechange:",
+        _ => "changed:",
+    }
+}
+
+struct BlameLine {
+    hash: String,
+    author: String,
+    time: i64,
+    line: usize,
+    content: String,
+}
+
+/// Reformats `git blame --line-porcelain` output; `None` if it doesn't look like porcelain.
+fn format_blame(porcelain: &str) -> Option<String> {
+    let mut lines = Vec::new();
+    let mut current: Option<BlameLine> = None;
+    for raw in porcelain.lines() {
+        if let Some(content) = raw.strip_prefix('\t') {
+            let mut line = current.take()?;
+            line.content = content.to_string();
+            lines.push(line);
+        } else if let Some(entry) = current.as_mut() {
+            if let Some(author) = raw.strip_prefix("author ") {
+                entry.author = author.to_string();
+            } else if let Some(time) = raw.strip_prefix("author-time ") {
+                entry.time = time.parse().ok()?;
+            }
+        } else {
+            let mut header = raw.split(' ');
+            let hash = header.next().filter(|hash| hash.len() >= 8 && hash.chars().all(|c| c.is_ascii_hexdigit()))?;
+            let line = header.nth(1)?.parse().ok()?;
+            current = Some(BlameLine {
+                hash: hash[..8].to_string(),
+                author: String::new(),
+                time: 0,
+                line,
+                content: String::new(),
+            });
+        }
+    }
+    if current.is_some() {
+        return None;
+    }
+
+    let author_width = lines.iter().map(|line| line.author.chars().count()).max().unwrap_or(0);
+    let line_width = lines.iter().map(|line| line.line.to_string().len()).max().unwrap_or(0);
+    Some(lines.iter()
+        .map(|line| {
+            let date = chrono::DateTime::from_timestamp(line.time, 0)
+                .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d").to_string())
+                .unwrap_or_default();
+            format!("{} {} {} {} {}",
+                color::paint("33", &line.hash),
+                color::paint("32", &format!("{:<width$}", line.author, width = author_width)),
+                color::paint("36", &date),
+                color::paint("2", &format!("{:>width$}", line.line, width = line_width)),
+                line.content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::Parser;
+    use std::process::Command as StdCommand;
+    use tempfile::tempdir;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = StdCommand::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    fn init_repo(dir: &Path, commits: usize) {
+        git(dir, &["init", "-q"]);
+        git(dir, &["config", "user.name", "Test User"]);
+        git(dir, &["config", "user.email", "test@example.com"]);
+        for i in 0..commits {
+            std::fs::write(dir.join("file.txt"), format!("revision {}", i)).unwrap();
+            git(dir, &["add", "file.txt"]);
+            git(dir, &["commit", "-q", "-m", &format!("commit {}", i)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_oneline_preset() {
+        let temp_dir = tempdir().unwrap();
+        init_repo(temp_dir.path(), 2);
+
+        // As typed, so the parser doesn't take `oneline` as the value of a flag
+        let plugin = GitPlugin::new();
+        let args = Parser::new().parse("git log --preset oneline").unwrap().raw_args();
+        let output = plugin.run(&args, temp_dir.path()).await.unwrap();
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("commit 1"));
+        assert!(lines[1].ends_with("commit 0"));
+
+        let args = Parser::new().parse("git log --raw --format=%s -1").unwrap().raw_args();
+        assert_eq!(plugin.run(&args, temp_dir.path()).await.unwrap().trim_end(), "commit 1");
+    }
+
+    #[tokio::test]
+    async fn test_status_porcelain_codes() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path();
+        init_repo(dir, 1);
+        std::fs::write(dir.join("rename-me.txt"), "content long enough to match as a rename\n").unwrap();
+        git(dir, &["add", "rename-me.txt"]);
+        git(dir, &["commit", "-q", "-m", "add rename-me"]);
+
+        git(dir, &["mv", "rename-me.txt", "renamed.txt"]);
+        std::fs::write(dir.join("staged.txt"), "staged").unwrap();
+        git(dir, &["add", "staged.txt"]);
+        std::fs::write(dir.join("file.txt"), "modified").unwrap();
+        std::fs::write(dir.join("untracked.txt"), "untracked").unwrap();
+
+        let args = vec!["status".to_string(), "--porcelain".to_string()];
+        let output = GitPlugin::new().run(&args, dir).await.unwrap();
+        let mut lines: Vec<&str> = output.lines().collect();
+        lines.sort();
+        assert_eq!(lines, vec![" M file.txt", "?? untracked.txt", "A  staged.txt", "R  rename-me.txt -> renamed.txt"]);
+
+        let _color = color::scoped(false);
+        let human = GitPlugin::new().run(&["status".to_string()], dir).await.unwrap();
+        assert!(human.starts_with("On branch "));
+        assert!(human.contains("Changes to be committed:\n  renamed:  rename-me.txt -> renamed.txt\n  new file: staged.txt"));
+        assert!(human.contains("Changes not staged for commit:\n  modified: file.txt"));
+        assert!(human.contains("Untracked files:\n  untracked.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_status_outside_repo() {
+        let temp_dir = tempdir().unwrap();
+
+        let args = vec!["status".to_string()];
+        let err = GitPlugin::new().run(&args, temp_dir.path()).await.unwrap_err();
+        assert!(err.to_string().contains("Not a git repository"));
+
+        let args = vec!["init".to_string(), "-q".to_string()];
+        assert!(GitPlugin::new().run(&args, temp_dir.path()).await.is_ok());
+        assert!(GitPlugin::find_repo_root(&temp_dir.path().join("nested")).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_stash_list_and_pop() {
+        let temp_dir = tempdir().unwrap();
+        init_repo(temp_dir.path(), 1);
+        let plugin = GitPlugin::new();
+        let args = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(plugin.run(&args(&["stash", "list"]), temp_dir.path()).await.unwrap(), "No stash entries");
+        let output = plugin.run(&args(&["stash", "pop"]), temp_dir.path()).await.unwrap();
+        assert_eq!(output, "Nothing to pop: the stash is empty");
+
+        std::fs::write(temp_dir.path().join("file.txt"), "work in progress").unwrap();
+        git(temp_dir.path(), &["stash", "push", "-q", "-m", "wip"]);
+
+        let _color = color::scoped(false);
+        let output = plugin.run(&args(&["stash", "list"]), temp_dir.path()).await.unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].trim_start().starts_with('0'));
+        assert!(lines[0].contains("stash@{0}"));
+        assert!(lines[0].contains("wip"));
+
+        // `-p` is passed through and the index after it still becomes a stash ref
+        let show = Parser::new().parse("git stash show -p 0").unwrap().raw_args();
+        let output = plugin.run(&show, temp_dir.path()).await.unwrap();
+        assert!(output.contains("+work in progress"));
+
+        plugin.run(&args(&["stash", "pop", "0"]), temp_dir.path()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join("file.txt")).unwrap(), "work in progress");
+    }
+
+    #[tokio::test]
+    async fn test_blame_columns() {
+        let temp_dir = tempdir().unwrap();
+        init_repo(temp_dir.path(), 1);
+        std::fs::write(temp_dir.path().join("file.txt"), "first\nsecond\nthird\n").unwrap();
+        git(temp_dir.path(), &["commit", "-q", "-am", "three lines"]);
+        let _color = color::scoped(false);
+
+        let args = |line: &str| Parser::new().parse(line).unwrap().raw_args();
+        let output = GitPlugin::new().run(&args("git blame file.txt"), temp_dir.path()).await.unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| line.contains("Test User")));
+        assert!(lines[1].ends_with("2 second"));
+
+        // `-L` keeps its range and the file stays an operand
+        let output = GitPlugin::new().run(&args("git blame -L 2,3 file.txt"), temp_dir.path()).await.unwrap();
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.starts_with(&lines[1][..8]));
+    }
 }
@@ -0,0 +1,311 @@
+use async_trait::async_trait;
+use super::super::{Command, Environment, Plugin, ShellError};
+use ring::{digest, hmac};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Trailing line of a signed manifest, followed by the hex HMAC-SHA256 of every line above it.
+const SIGNATURE_PREFIX: &str = "# hmac-sha256 ";
+
+pub struct ManifestPlugin;
+
+#[async_trait]
+impl Plugin for ManifestPlugin {
+    fn name(&self) -> &str {
+        "manifest"
+    }
+
+    fn description(&self) -> &str {
+        "SHA-256 manifests of directory trees (create, verify)"
+    }
+
+    async fn execute(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        let key = match command.flags.get("key") {
+            Some(Some(path)) => {
+                let path = env.expand_path(path);
+                Some(tokio::fs::read(&path).await.map_err(|e| ShellError::from_io(&path, e))?)
+            }
+            Some(None) => return Err(ShellError::InvalidUsage("manifest <create|verify> ... --key <keyfile>".to_string()).into()),
+            None => None,
+        };
+
+        match command.args.first().map(String::as_str) {
+            Some("create") if command.args.len() >= 2 => {
+                let dir = env.expand_path(&command.args[1]);
+                let output = command.args.get(2).map(|path| env.expand_path(path));
+                let entries = scan(&dir, output.as_deref()).await?;
+                let manifest = render(&entries, key.as_deref());
+                match output {
+                    Some(path) => {
+                        tokio::fs::write(&path, &manifest).await.map_err(|e| ShellError::from_io(&path, e))?;
+                        Ok(format!("Wrote {} entries to {}", entries.len(), path.display()))
+                    }
+                    None => Ok(manifest.trim_end().to_string()),
+                }
+            }
+            Some("verify") if command.args.len() >= 3 => {
+                let dir = env.expand_path(&command.args[1]);
+                let path = env.expand_path(&command.args[2]);
+                let content = tokio::fs::read_to_string(&path).await.map_err(|e| ShellError::from_io(&path, e))?;
+                let expected = parse(&content, key.as_deref())?;
+                let actual = scan(&dir, Some(&path)).await?;
+                let changes = compare(&expected, &actual);
+                if changes.is_empty() {
+                    return Ok(format!("{}: OK ({} files)", dir.display(), actual.len()));
+                }
+                Err(ShellError::External(format!(
+                    "{}\n{} file(s) changed since the manifest was taken",
+                    changes.iter().map(Change::to_string).collect::<Vec<_>>().join("\n"),
+                    changes.len()
+                )).into())
+            }
+            _ => Err(ShellError::InvalidUsage(
+                "manifest create <dir> [manifest] [--key <keyfile>] | manifest verify <dir> <manifest> [--key <keyfile>]".to_string(),
+            ).into()),
+        }
+    }
+
+    fn complete(&self, args: &[String], partial: &str) -> Vec<String> {
+        if !args.is_empty() {
+            return Vec::new();
+        }
+        ["create", "verify"]
+            .iter()
+            .filter(|name| name.starts_with(partial))
+            .map(|name| name.to_string())
+            .collect()
+    }
+}
+
+impl ManifestPlugin {
+    pub fn new() -> Self {
+        ManifestPlugin
+    }
+}
+
+/// A difference between a manifest and the directory it describes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    Modified(String),
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added(path) => write!(f, "added: {}", path),
+            Change::Removed(path) => write!(f, "removed: {}", path),
+            Change::Modified(path) => write!(f, "modified: {}", path),
+        }
+    }
+}
+
+/// SHA-256 of every file under `dir`, keyed by its `/`-separated relative path. Dotfiles and
+/// ignored files count too: a manifest covers the whole tree. `skip` (the manifest itself) is
+/// left out.
+async fn scan(dir: &Path, skip: Option<&Path>) -> anyhow::Result<BTreeMap<String, String>> {
+    if !dir.is_dir() {
+        return Err(ShellError::NotFound(dir.display().to_string()).into());
+    }
+    let skip = skip.and_then(|path| path.canonicalize().ok());
+
+    let mut entries = BTreeMap::new();
+    let walk = ignore::WalkBuilder::new(dir)
+        .hidden(false)
+        .git_ignore(false)
+        .git_global(false)
+        .git_exclude(false)
+        .ignore(false)
+        .parents(false)
+        .build();
+    for entry in walk {
+        let entry = entry?;
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        if skip.is_some() && entry.path().canonicalize().ok() == skip {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(dir)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let digest = super::checksum::hash_file(&digest::SHA256, entry.path()).await?;
+        entries.insert(relative, digest);
+    }
+    Ok(entries)
+}
+
+/// `sha256sum`-style lines, signed with `key` when one is given.
+fn render(entries: &BTreeMap<String, String>, key: Option<&[u8]>) -> String {
+    let mut manifest: String = entries.iter()
+        .map(|(path, digest)| format!("{}  {}\n", digest, path))
+        .collect();
+    if let Some(key) = key {
+        let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), manifest.as_bytes());
+        manifest.push_str(&format!("{}{}\n", SIGNATURE_PREFIX, hex(tag.as_ref())));
+    }
+    manifest
+}
+
+/// Reads a manifest back, checking its signature against `key`. A signed manifest can't be
+/// verified without the key, and a key is only accepted for a signed manifest.
+fn parse(content: &str, key: Option<&[u8]>) -> anyhow::Result<BTreeMap<String, String>> {
+    let trimmed = content.trim_end();
+    let last_line = trimmed.rfind('\n').map_or(0, |newline| newline + 1);
+    let (body, signature) = match trimmed[last_line..].strip_prefix(SIGNATURE_PREFIX) {
+        Some(signature) => (&content[..last_line], Some(signature)),
+        None => (content, None),
+    };
+
+    match (signature, key) {
+        (Some(signature), Some(key)) => {
+            let tag = unhex(signature.trim())
+                .ok_or_else(|| ShellError::External("Malformed manifest signature".to_string()))?;
+            hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, key), body.as_bytes(), &tag)
+                .map_err(|_| ShellError::External("Manifest signature does not match; it was altered or signed with another key".to_string()))?;
+        }
+        (Some(_), None) => {
+            return Err(ShellError::InvalidUsage("The manifest is signed; pass --key <keyfile> to verify it".to_string()).into());
+        }
+        (None, Some(_)) => {
+            return Err(ShellError::External("The manifest is not signed".to_string()).into());
+        }
+        (None, None) => {}
+    }
+
+    let mut entries = BTreeMap::new();
+    for line in body.lines().filter(|line| !line.trim().is_empty()) {
+        let (digest, path) = line.split_once("  ")
+            .ok_or_else(|| ShellError::External(format!("Malformed manifest line: {}", line)))?;
+        entries.insert(path.to_string(), digest.to_lowercase());
+    }
+    Ok(entries)
+}
+
+fn compare(expected: &BTreeMap<String, String>, actual: &BTreeMap<String, String>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (path, digest) in expected {
+        match actual.get(path) {
+            None => changes.push(Change::Removed(path.clone())),
+            Some(current) if current != digest => changes.push(Change::Modified(path.clone())),
+            Some(_) => {}
+        }
+    }
+    changes.extend(actual.keys()
+        .filter(|path| !expected.contains_key(*path))
+        .map(|path| Change::Added(path.clone())));
+    changes
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn command(args: &[&str], flags: &[(&str, &str)]) -> Command {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let flags: HashMap<String, Option<String>> = flags
+            .iter()
+            .map(|(name, value)| (name.to_string(), Some(value.to_string())))
+            .collect();
+        Command::new("manifest".to_string(), args.clone(), flags, format!("manifest {}", args.join(" ")))
+    }
+
+    #[tokio::test]
+    async fn test_verify_reports_changes() {
+        let temp_dir = tempdir().unwrap();
+        let tree = temp_dir.path().join("tree");
+        fs::create_dir_all(tree.join("src")).unwrap();
+        fs::write(tree.join("README"), "hello\n").unwrap();
+        fs::write(tree.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(tree.join("src/old.rs"), "// gone soon\n").unwrap();
+        let tree = tree.to_string_lossy().to_string();
+        let manifest = temp_dir.path().join("tree.manifest").to_string_lossy().to_string();
+        let env = Environment::new();
+
+        ManifestPlugin.execute(&command(&["create", &tree, &manifest], &[]), &env).await.unwrap();
+        let content = fs::read_to_string(&manifest).unwrap();
+        assert!(content.starts_with("5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03  README\n"));
+        assert!(content.contains("  src/main.rs\n"));
+
+        let output = ManifestPlugin.execute(&command(&["verify", &tree, &manifest], &[]), &env).await.unwrap();
+        assert!(output.ends_with("OK (3 files)"));
+
+        fs::write(Path::new(&tree).join("src/main.rs"), "fn main() { println!(\"changed\"); }\n").unwrap();
+        fs::remove_file(Path::new(&tree).join("src/old.rs")).unwrap();
+        fs::write(Path::new(&tree).join("NEW"), "new\n").unwrap();
+        let err = ManifestPlugin.execute(&command(&["verify", &tree, &manifest], &[]), &env).await.unwrap_err();
+        let report = err.to_string();
+        assert!(report.contains("modified: src/main.rs"));
+        assert!(report.contains("removed: src/old.rs"));
+        assert!(report.contains("added: NEW"));
+        assert!(report.contains("3 file(s) changed"));
+    }
+
+    #[tokio::test]
+    async fn test_signed_manifest() {
+        let temp_dir = tempdir().unwrap();
+        let tree = temp_dir.path().join("tree");
+        fs::create_dir(&tree).unwrap();
+        fs::write(tree.join("a.txt"), "a\n").unwrap();
+        fs::write(temp_dir.path().join("key"), "secret").unwrap();
+        fs::write(temp_dir.path().join("other"), "not the secret").unwrap();
+        let tree = tree.to_string_lossy().to_string();
+        let key = temp_dir.path().join("key").to_string_lossy().to_string();
+        let other = temp_dir.path().join("other").to_string_lossy().to_string();
+        // Written inside the tree, so the manifest must skip itself
+        let manifest = Path::new(&tree).join("MANIFEST").to_string_lossy().to_string();
+        let env = Environment::new();
+
+        ManifestPlugin.execute(&command(&["create", &tree, &manifest], &[("key", &key)]), &env).await.unwrap();
+        assert!(fs::read_to_string(&manifest).unwrap().lines().last().unwrap().starts_with(SIGNATURE_PREFIX));
+        ManifestPlugin.execute(&command(&["verify", &tree, &manifest], &[("key", &key)]), &env).await.unwrap();
+
+        let err = ManifestPlugin.execute(&command(&["verify", &tree, &manifest], &[("key", &other)]), &env).await.unwrap_err();
+        assert!(err.to_string().contains("signature does not match"));
+        let err = ManifestPlugin.execute(&command(&["verify", &tree, &manifest], &[]), &env).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::InvalidUsage(_))));
+
+        // Editing an entry breaks the signature even when the tree matches the edit
+        let content = fs::read_to_string(&manifest).unwrap().replace("a.txt", "b.txt");
+        fs::write(&manifest, content).unwrap();
+        let err = ManifestPlugin.execute(&command(&["verify", &tree, &manifest], &[("key", &key)]), &env).await.unwrap_err();
+        assert!(err.to_string().contains("signature does not match"));
+    }
+
+    #[tokio::test]
+    async fn test_dotfiles_and_ignored_files_are_covered() {
+        let temp_dir = tempdir().unwrap();
+        let tree = temp_dir.path().join("tree");
+        fs::create_dir_all(tree.join(".config")).unwrap();
+        fs::write(tree.join(".env"), "SECRET=1\n").unwrap();
+        fs::write(tree.join(".config/settings"), "x\n").unwrap();
+        fs::write(tree.join(".gitignore"), "build.log\n").unwrap();
+        fs::write(tree.join(".ignore"), "notes.txt\n").unwrap();
+        fs::write(tree.join("build.log"), "log\n").unwrap();
+        fs::write(tree.join("notes.txt"), "notes\n").unwrap();
+
+        let entries = scan(&tree, None).await.unwrap();
+        let paths: Vec<&str> = entries.keys().map(String::as_str).collect();
+        assert_eq!(paths, [".config/settings", ".env", ".gitignore", ".ignore", "build.log", "notes.txt"]);
+    }
+}
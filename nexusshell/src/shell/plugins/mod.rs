@@ -2,67 +2,377 @@ mod fileops;
 mod process;
 mod git;
 mod network;
+mod checksum;
+mod manifest;
+mod scripting;
+mod scheduler;
+mod security;
+mod devtools;
+mod remote;
+mod cloud;
+mod container;
 
 pub use fileops::FileOperationsPlugin;
 pub use process::ProcessPlugin;
 pub use git::GitPlugin;
 pub use network::NetworkPlugin;
+pub use checksum::ChecksumPlugin;
+pub use manifest::ManifestPlugin;
 
 use async_trait::async_trait;
-use super::{Command, Environment};
-use std::collections::HashMap;
-use std::sync::RwLock;
+use super::{util, Command, Environment, JobTable};
+use cloud::CloudManager;
+use container::{DockerPlugin, KubernetesPlugin};
+use devtools::DevToolsPlugin;
+use futures::future::{BoxFuture, FutureExt};
+use remote::{SFTPPlugin, SSHPlugin};
+use scheduler::SchedulerPlugin;
+use scripting::{ScriptEngine, ScriptingPlugin};
+use security::SecurityPlugin;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, RwLock, Weak};
 
 #[async_trait]
 pub trait Plugin: Send + Sync {
     fn name(&self) -> &str;
     fn description(&self) -> &str;
     async fn execute(&self, command: &Command, env: &Environment) -> anyhow::Result<String>;
+
+    /// The top-level commands routed to this plugin; by default just its name.
+    fn commands(&self) -> Vec<&str> {
+        vec![self.name()]
+    }
+
+    fn complete(&self, _args: &[String], _partial: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Called once before the plugin handles its first command, e.g. to open clients.
+    async fn on_startup(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called once when the shell exits, e.g. to flush state to disk.
+    async fn on_shutdown(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 pub struct PluginManager {
-    plugins: RwLock<HashMap<String, Box<dyn Plugin + Send + Sync>>>,
+    plugins: RwLock<HashMap<String, Arc<dyn Plugin + Send + Sync>>>,
+    jobs: Arc<JobTable>,
+    started: Mutex<HashSet<String>>,
 }
 
 impl PluginManager {
-    pub fn new() -> Self {
-        let mut manager = PluginManager {
+    /// The manager with the built-in plugins registered. It's shared, so plugins that look up
+    /// other commands (such as `schedule create --verify`) can hold a reference back to it.
+    pub fn new() -> Arc<Self> {
+        Arc::new_cyclic(|this| PluginManager::with_plugins(this.clone()))
+    }
+
+    fn with_plugins(this: Weak<PluginManager>) -> Self {
+        let manager = PluginManager {
             plugins: RwLock::new(HashMap::new()),
+            jobs: Arc::new(JobTable::new()),
+            started: Mutex::new(HashSet::new()),
         };
 
         // Register built-in plugins
         let _ = manager.register_plugin(Box::new(FileOperationsPlugin::new()));
-        let _ = manager.register_plugin(Box::new(ProcessPlugin::new()));
+        let _ = manager.register_plugin(Box::new(ProcessPlugin::new(manager.jobs.clone())));
         let _ = manager.register_plugin(Box::new(GitPlugin::new()));
         let _ = manager.register_plugin(Box::new(NetworkPlugin::new()));
+        let _ = manager.register_plugin(Box::new(ChecksumPlugin::new()));
+        let _ = manager.register_plugin(Box::new(ManifestPlugin::new()));
+
+        // These read config files, key stores or cloud credentials, so they're built on first use
+        let scripts: Arc<OnceCell<ScriptEngine>> = Arc::default();
+        let engine = scripts.clone();
+        let _ = manager.register_plugin(Box::new(LazyPlugin::new("script", "Script management and execution", &["script"], move || {
+            let engine = engine.clone();
+            async move { Ok(ScriptingPlugin::new(shared_engine(&engine).await?)) }
+        })));
+        let _ = manager.register_plugin(Box::new(LazyPlugin::new("schedule", "Job scheduling and task management", &["schedule"], move || {
+            let (scripts, plugins) = (scripts.clone(), this.clone());
+            async move { SchedulerPlugin::new(shared_engine(&scripts).await?, plugins).await }
+        })));
+        let _ = manager.register_plugin(Box::new(LazyPlugin::new("security", "Security and credential management", &["security"], SecurityPlugin::new)));
+        let _ = manager.register_plugin(Box::new(LazyPlugin::new("dev", "Development tools and utilities", &["dev"], DevToolsPlugin::new)));
+        let _ = manager.register_plugin(Box::new(LazyPlugin::new("ssh", "SSH client integration for remote operations", &["ssh"], || async { Ok(SSHPlugin::new()) })));
+        let _ = manager.register_plugin(Box::new(LazyPlugin::new("sftp", "SFTP file transfer operations", &["sftp"], || async { Ok(SFTPPlugin::new()) })));
+        let _ = manager.register_plugin(Box::new(LazyPlugin::new("docker", "Docker container management and operations", &["docker"], DockerPlugin::new)));
+        let _ = manager.register_plugin(Box::new(LazyPlugin::new("kubectl", "Kubernetes cluster management and operations", &["kubectl"], KubernetesPlugin::new)));
+        let _ = manager.register_plugin(Box::new(LazyPlugin::new(
+            "cloud",
+            "Cloud storage copies and AWS, Azure and GCP management",
+            &["cloud", "aws", "azure", "gcp"],
+            || async { Ok(CloudManager::new().await) },
+        )));
 
         manager
     }
 
+    /// Registers a plugin as the owner of the top-level commands it lists. Fails if another
+    /// plugin already owns one of them or has the same name, or one is a builtin.
     pub fn register_plugin(&self, plugin: Box<dyn Plugin + Send + Sync>) -> anyhow::Result<()> {
         let name = plugin.name().to_string();
         let mut plugins = self.plugins.write().map_err(|_| anyhow::anyhow!("Failed to acquire write lock"))?;
-        plugins.insert(name, plugin);
+        check_available(&plugins, plugin.as_ref())?;
+        plugins.insert(name, plugin.into());
         Ok(())
     }
 
-    pub fn get_plugin(&self, name: &str) -> Option<Box<dyn Plugin + Send + Sync>> {
+    /// Runs the startup hook of every plugin that hasn't been started yet. A failing plugin
+    /// stays registered; its error is returned alongside its name.
+    pub async fn startup(&self) -> Vec<(String, anyhow::Error)> {
+        // Hooks are awaited outside the lock, so the plugins are taken out and put back
+        let plugins = self.take_plugins();
+        let mut errors = Vec::new();
+        for (name, plugin) in &plugins {
+            if self.started.lock().unwrap().contains(name) {
+                continue;
+            }
+            if let Err(e) = plugin.on_startup().await {
+                errors.push((name.clone(), e));
+            }
+            self.started.lock().unwrap().insert(name.clone());
+        }
+        if let Ok(mut registered) = self.plugins.write() {
+            for (name, plugin) in plugins {
+                registered.entry(name).or_insert(plugin);
+            }
+        }
+        errors
+    }
+
+    /// Runs the shutdown hook of every started plugin and unregisters all plugins.
+    pub async fn shutdown(&self) -> Vec<(String, anyhow::Error)> {
+        let plugins = self.take_plugins();
+        let mut errors = Vec::new();
+        for (name, plugin) in &plugins {
+            if !self.started.lock().unwrap().remove(name) {
+                continue;
+            }
+            if let Err(e) = plugin.on_shutdown().await {
+                errors.push((name.clone(), e));
+            }
+        }
+        errors
+    }
+
+    fn take_plugins(&self) -> HashMap<String, Arc<dyn Plugin + Send + Sync>> {
+        self.plugins
+            .write()
+            .map(|mut plugins| std::mem::take(&mut *plugins))
+            .unwrap_or_default()
+    }
+
+    /// The plugin that handles the top-level `command`.
+    pub fn get_plugin(&self, command: &str) -> Option<Arc<dyn Plugin + Send + Sync>> {
         self.plugins
             .read()
             .ok()?
-            .get(name)
-            .map(|p| Box::new(p.as_ref()) as Box<dyn Plugin + Send + Sync>)
+            .values()
+            .find(|plugin| plugin.commands().contains(&command))
+            .cloned()
     }
 
-    pub fn list_plugins(&self) -> Vec<(String, String)> {
-        self.plugins
+    /// Every command handled by a plugin, sorted.
+    pub fn commands(&self) -> Vec<String> {
+        let mut commands: Vec<String> = self.plugins
             .read()
             .map(|plugins| {
                 plugins
-                    .iter()
-                    .map(|(name, plugin)| (name.clone(), plugin.description().to_string()))
+                    .values()
+                    .flat_map(|plugin| plugin.commands().into_iter().map(String::from).collect::<Vec<_>>())
                     .collect()
             })
-            .unwrap_or_default()
+            .unwrap_or_default();
+        commands.sort();
+        commands
+    }
+
+    pub fn jobs(&self) -> &Arc<JobTable> {
+        &self.jobs
+    }
+}
+
+type PluginFactory = Box<dyn Fn() -> BoxFuture<'static, anyhow::Result<Arc<dyn Plugin + Send + Sync>>> + Send + Sync>;
+
+/// Stands in for a plugin whose constructor is async or touches the disk, building it (and
+/// running its startup hook) when the first command is routed to it or its arguments are
+/// first completed.
+struct LazyPlugin {
+    name: &'static str,
+    description: &'static str,
+    commands: &'static [&'static str],
+    factory: PluginFactory,
+    plugin: OnceCell<Arc<dyn Plugin + Send + Sync>>,
+}
+
+impl LazyPlugin {
+    fn new<F, Fut, P>(name: &'static str, description: &'static str, commands: &'static [&'static str], factory: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<P>> + Send + 'static,
+        P: Plugin + 'static,
+    {
+        LazyPlugin {
+            name,
+            description,
+            commands,
+            factory: Box::new(move || {
+                factory()
+                    .map(|plugin| plugin.map(|plugin| Arc::new(plugin) as Arc<dyn Plugin + Send + Sync>))
+                    .boxed()
+            }),
+            plugin: OnceCell::new(),
+        }
+    }
+
+    async fn plugin(&self) -> anyhow::Result<&Arc<dyn Plugin + Send + Sync>> {
+        self.plugin
+            .get_or_try_init(|| async {
+                let plugin = (self.factory)().await?;
+                plugin.on_startup().await?;
+                Ok(plugin)
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl Plugin for LazyPlugin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn commands(&self) -> Vec<&str> {
+        self.commands.to_vec()
+    }
+
+    async fn execute(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        self.plugin().await?.execute(command, env).await
+    }
+
+    fn complete(&self, args: &[String], partial: &str) -> Vec<String> {
+        if let Some(plugin) = self.plugin.get() {
+            return plugin.complete(args, partial);
+        }
+        match util::block_on(self.plugin()) {
+            Ok(Ok(plugin)) => plugin.complete(args, partial),
+            _ => Vec::new(),
+        }
+    }
+
+    async fn on_shutdown(&self) -> anyhow::Result<()> {
+        match self.plugin.get() {
+            Some(plugin) => plugin.on_shutdown().await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// The script engine `script` and `schedule` share, opened by whichever is used first.
+async fn shared_engine(scripts: &OnceCell<ScriptEngine>) -> anyhow::Result<ScriptEngine> {
+    scripts.get_or_try_init(ScriptingPlugin::open_engine).await.cloned()
+}
+
+fn is_builtin(name: &str) -> bool {
+    Command::new(name.to_string(), Vec::new(), HashMap::new(), name.to_string()).is_builtin()
+}
+
+/// Errors naming the current owner when the plugin's name or one of its commands is taken.
+fn check_available(plugins: &HashMap<String, Arc<dyn Plugin + Send + Sync>>, plugin: &dyn Plugin) -> anyhow::Result<()> {
+    if let Some(builtin) = plugin.commands().into_iter().find(|command| is_builtin(command)) {
+        return Err(anyhow::anyhow!("'{}' is a builtin command and can't be provided by a plugin", builtin));
+    }
+    for owner in plugins.values() {
+        if let Some(command) = overlapping_command(owner.as_ref(), plugin) {
+            return Err(anyhow::anyhow!(
+                "The '{}' command is already provided by a registered plugin ({})",
+                command,
+                owner.description()
+            ));
+        }
+    }
+    if let Some(owner) = plugins.get(plugin.name()) {
+        return Err(anyhow::anyhow!(
+            "A plugin named '{}' is already registered ({})",
+            plugin.name(),
+            owner.description()
+        ));
+    }
+    Ok(())
+}
+
+/// A command both plugins claim, if any.
+fn overlapping_command<'a>(owner: &dyn Plugin, plugin: &'a dyn Plugin) -> Option<&'a str> {
+    let owned = owner.commands();
+    plugin.commands().into_iter().find(|command| owned.contains(command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Named {
+        name: &'static str,
+        description: &'static str,
+    }
+
+    #[async_trait]
+    impl Plugin for Named {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn description(&self) -> &str {
+            self.description
+        }
+
+        async fn execute(&self, _command: &Command, _env: &Environment) -> anyhow::Result<String> {
+            Ok(self.description.to_string())
+        }
+    }
+
+    #[test]
+    fn test_duplicate_registration_is_rejected() {
+        let manager = PluginManager::new();
+        manager.register_plugin(Box::new(Named { name: "deploy", description: "original" })).unwrap();
+
+        let err = manager.register_plugin(Box::new(Named { name: "deploy", description: "impostor" })).unwrap_err();
+        assert!(err.to_string().contains("already provided"));
+        assert!(err.to_string().contains("original"));
+        assert_eq!(manager.get_plugin("deploy").unwrap().description(), "original");
+
+        // Built-in plugins and shell builtins are protected the same way
+        assert!(manager.register_plugin(Box::new(Named { name: "git", description: "other git" })).is_err());
+        assert!(manager.register_plugin(Box::new(Named { name: "cd", description: "cd" })).is_err());
+    }
+
+    #[test]
+    fn test_commands_route_to_their_plugin() {
+        let manager = PluginManager::new();
+        for command in ["rm", "ls", "cp", "mv", "cat", "find", "mkdir"] {
+            assert_eq!(manager.get_plugin(command).unwrap().name(), "fileops", "{}", command);
+        }
+        assert_eq!(manager.get_plugin("kill").unwrap().name(), "process");
+        assert_eq!(manager.get_plugin("ps").unwrap().name(), "process");
+        assert_eq!(manager.get_plugin("net").unwrap().name(), "network");
+        assert!(manager.get_plugin("fileops").is_none());
+        assert!(manager.commands().contains(&"rm".to_string()));
+
+        // A plugin can't claim another plugin's command under a new name
+        let err = manager.register_plugin(Box::new(Named { name: "rm", description: "other rm" })).unwrap_err();
+        assert!(err.to_string().contains("already provided"));
     }
 }
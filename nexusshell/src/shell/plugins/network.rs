@@ -1,8 +1,12 @@
 use async_trait::async_trait;
-use super::super::{Command, Environment, Plugin};
+use super::super::{Command, Environment, Plugin, ShellError};
+use super::super::render::{OutputFormat, Record};
+use network_interface::{Addr, NetworkInterface, NetworkInterfaceConfig};
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::process::Command as TokioCommand;
-use std::time::Duration;
-use tokio::time::sleep;
+use std::net::{IpAddr, Ipv4Addr};
 
 pub struct NetworkPlugin;
 
@@ -18,6 +22,10 @@ impl Plugin for NetworkPlugin {
         "network"
     }
 
+    fn commands(&self) -> Vec<&str> {
+        vec!["ping", "curl", "wget", "netstat", "net"]
+    }
+
     fn description(&self) -> &str {
         "Network operations and diagnostics"
     }
@@ -28,6 +36,11 @@ impl Plugin for NetworkPlugin {
             "curl" => self.curl(command).await,
             "wget" => self.wget(command).await,
             "netstat" => self.netstat(command).await,
+            "net" => match command.args.first().map(|s| s.as_str()) {
+                Some("listen") => self.listen(command).await,
+                Some("interfaces") => OutputFormat::from_command(command)?.render(&list_interfaces()?),
+                _ => Err(ShellError::InvalidUsage("net listen <port> [--once] [--bind <addr>] | net interfaces [--json]".to_string()).into()),
+            },
             _ => Err(anyhow::anyhow!("Unknown network command")),
         }
     }
@@ -93,16 +106,223 @@ impl NetworkPlugin {
     async fn netstat(&self, _command: &Command) -> anyhow::Result<String> {
         let output = if cfg!(windows) {
             TokioCommand::new("netstat")
-                .args(&["-ano"])
+                .args(["-ano"])
                 .output()
                 .await?
         } else {
             TokioCommand::new("netstat")
-                .args(&["-tulpn"])
+                .args(["-tulpn"])
                 .output()
                 .await?
         };
 
         Ok(String::from_utf8_lossy(&output.stdout).to_string())
     }
+
+    /// Echo server for connectivity checks: everything a client sends is written back.
+    /// Runs until Ctrl-C, or until the first client disconnects with `--once`. Only loopback
+    /// clients can connect unless `--bind` names another address.
+    async fn listen(&self, command: &Command) -> anyhow::Result<String> {
+        let options = ListenOptions::parse(command)?;
+
+        let listener = TcpListener::bind((options.bind, options.port)).await?;
+        println!("Listening on {}", listener.local_addr()?);
+
+        let connections = tokio::select! {
+            result = echo_server(listener, options.once) => result?,
+            _ = tokio::signal::ctrl_c() => return Ok("Stopped listening".to_string()),
+        };
+        Ok(format!("Served {} connection(s)", connections))
+    }
+}
+
+/// `net listen <port> [--once] [--bind <addr>]`.
+#[derive(Debug, PartialEq)]
+struct ListenOptions {
+    port: u16,
+    bind: IpAddr,
+    once: bool,
+}
+
+impl ListenOptions {
+    fn parse(command: &Command) -> anyhow::Result<Self> {
+        let usage = || ShellError::InvalidUsage("net listen <port> [--once] [--bind <addr>]".to_string());
+        if command.flags.keys().any(|flag| flag != "once" && flag != "bind") {
+            return Err(usage().into());
+        }
+        let port = match &command.args[1..] {
+            [port] => port.parse()
+                .map_err(|_| ShellError::InvalidUsage(format!("net listen: invalid port '{}'", port)))?,
+            _ => return Err(usage().into()),
+        };
+        let bind = match command.flags.get("bind") {
+            Some(Some(addr)) => addr.parse()
+                .map_err(|_| ShellError::InvalidUsage(format!("net listen: invalid address '{}'", addr)))?,
+            Some(None) => return Err(usage().into()),
+            None => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        };
+        Ok(ListenOptions { port, bind, once: command.flags.contains_key("once") })
+    }
+}
+
+/// A local network interface with all of its addresses.
+#[derive(Debug, Serialize)]
+struct InterfaceInfo {
+    name: String,
+    status: &'static str,
+    mac: Option<String>,
+    ipv4: Vec<String>,
+    ipv6: Vec<String>,
+}
+
+impl Record for InterfaceInfo {
+    fn headers() -> &'static [&'static str] {
+        &["NAME", "STATUS", "MAC", "IPV4", "IPV6"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.status.to_string(),
+            self.mac.clone().unwrap_or_default(),
+            self.ipv4.join(", "),
+            self.ipv6.join(", "),
+        ]
+    }
+}
+
+/// Local interfaces in the order the OS reports them. Some platforms list an interface once
+/// per address, so entries with the same name are merged.
+fn list_interfaces() -> anyhow::Result<Vec<InterfaceInfo>> {
+    let mut interfaces: Vec<InterfaceInfo> = Vec::new();
+    for interface in NetworkInterface::show()? {
+        let index = match interfaces.iter().position(|info| info.name == interface.name) {
+            Some(index) => index,
+            None => {
+                interfaces.push(InterfaceInfo {
+                    status: link_status(&interface.name),
+                    name: interface.name.clone(),
+                    mac: None,
+                    ipv4: Vec::new(),
+                    ipv6: Vec::new(),
+                });
+                interfaces.len() - 1
+            }
+        };
+        let info = &mut interfaces[index];
+        if info.mac.is_none() {
+            info.mac = interface.mac_addr.clone();
+        }
+        for addr in &interface.addr {
+            match addr {
+                Addr::V4(v4) if !info.ipv4.contains(&v4.ip.to_string()) => info.ipv4.push(v4.ip.to_string()),
+                Addr::V6(v6) if !info.ipv6.contains(&v6.ip.to_string()) => info.ipv6.push(v6.ip.to_string()),
+                _ => {}
+            }
+        }
+    }
+    Ok(interfaces)
+}
+
+/// `up` or `down` from the interface's IFF_UP flag, where the platform exposes it.
+#[cfg(target_os = "linux")]
+fn link_status(name: &str) -> &'static str {
+    const IFF_UP: u32 = 0x1;
+    let flags = std::fs::read_to_string(format!("/sys/class/net/{}/flags", name))
+        .ok()
+        .and_then(|flags| u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16).ok());
+    match flags {
+        Some(flags) if flags & IFF_UP != 0 => "up",
+        Some(_) => "down",
+        None => "unknown",
+    }
+}
+
+/// Link state isn't read on other platforms, so the status column always says `unknown`.
+#[cfg(not(target_os = "linux"))]
+fn link_status(_name: &str) -> &'static str {
+    "unknown"
+}
+
+/// Accepts clients on `listener` and echoes their data, returning how many were served.
+/// With `once` it handles a single client inline and returns when it disconnects.
+async fn echo_server(listener: TcpListener, once: bool) -> anyhow::Result<usize> {
+    let mut connections = 0;
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        connections += 1;
+        println!("Connection from {}", peer);
+
+        if once {
+            let bytes = echo(stream).await?;
+            println!("{} disconnected ({} bytes echoed)", peer, bytes);
+            return Ok(connections);
+        }
+        tokio::spawn(async move {
+            match echo(stream).await {
+                Ok(bytes) => println!("{} disconnected ({} bytes echoed)", peer, bytes),
+                Err(e) => println!("{} failed: {}", peer, e),
+            }
+        });
+    }
+}
+
+async fn echo(mut stream: TcpStream) -> std::io::Result<u64> {
+    let mut buffer = [0u8; 8192];
+    let mut total = 0;
+    loop {
+        let n = stream.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(total);
+        }
+        stream.write_all(&buffer[..n]).await?;
+        total += n as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_echo_server_once() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(echo_server(listener, true));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"ping\x00\xffpong").await.unwrap();
+        let mut echoed = [0u8; 10];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"ping\x00\xffpong");
+
+        drop(client);
+        assert_eq!(server.await.unwrap().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_listen_defaults_to_loopback() {
+        let parse = |line: &str| ListenOptions::parse(&crate::shell::Parser::new().parse(line).unwrap());
+
+        let options = parse("net listen --once 9000").unwrap();
+        assert_eq!(options, ListenOptions { port: 9000, bind: IpAddr::V4(Ipv4Addr::LOCALHOST), once: true });
+
+        let options = parse("net listen 9000 --bind 0.0.0.0").unwrap();
+        assert_eq!(options, ListenOptions { port: 9000, bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED), once: false });
+
+        assert!(parse("net listen --bind 9000").is_err());
+        assert!(parse("net listen 9000 --bind").is_err());
+    }
+
+    #[test]
+    fn test_interfaces_include_loopback() {
+        let interfaces = list_interfaces().unwrap();
+        let loopback = interfaces.iter()
+            .find(|info| info.ipv4.iter().any(|ip| ip == "127.0.0.1"))
+            .expect("no interface has 127.0.0.1");
+        assert_ne!(loopback.status, "down");
+
+        let json = OutputFormat::Json.render(&interfaces).unwrap();
+        assert!(json.contains("\"127.0.0.1\""));
+    }
 }
@@ -1,18 +1,23 @@
 use async_trait::async_trait;
-use super::super::{Command, Environment, Plugin};
-use std::collections::HashMap;
-use sysinfo::{System, SystemExt, ProcessExt};
+use super::super::{Command, Environment, JobTable, Plugin, ShellError};
+use super::super::render::{OutputFormat, Record};
+use serde::Serialize;
+use sysinfo::{DiskExt, Pid, PidExt, ProcessStatus, System, SystemExt, ProcessExt};
 use tokio::process::Command as TokioCommand;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::{Arc, Mutex};
 
 pub struct ProcessPlugin {
-    sys: System,
+    sys: Mutex<System>,
+    jobs: Arc<JobTable>,
 }
 
 impl ProcessPlugin {
-    pub fn new() -> Self {
+    pub fn new(jobs: Arc<JobTable>) -> Self {
         ProcessPlugin {
-            sys: System::new_all(),
+            sys: Mutex::new(System::new_all()),
+            jobs,
         }
     }
 
@@ -35,81 +40,94 @@ impl Plugin for ProcessPlugin {
         "process"
     }
 
+    fn commands(&self) -> Vec<&str> {
+        vec!["ps", "kill", "bg", "fg", "jobs", "top", "df", "free"]
+    }
+
     fn description(&self) -> &str {
         "Process management and monitoring"
     }
 
-    async fn execute(&self, command: &Command, _env: &Environment) -> anyhow::Result<String> {
+    async fn execute(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
         match command.name.as_str() {
             "ps" => self.list_processes(command).await,
             "kill" => self.kill_process(command).await,
-            "bg" => self.background_process(command).await,
+            "bg" => self.background_process(command, env).await,
+            "jobs" => Ok(self.list_jobs()),
             "fg" => self.foreground_process(command).await,
             "top" => self.show_top_processes(command).await,
+            "df" => self.disk_free(command, env).await,
+            "free" => self.memory_free(command).await,
             _ => Err(anyhow::anyhow!("Unknown process command")),
         }
     }
 }
 
 impl ProcessPlugin {
-    async fn list_processes(&self, _command: &Command) -> anyhow::Result<String> {
-        self.sys.refresh_all();
-        
-        let mut processes = Vec::new();
-        processes.push(format!("{:<8} {:<8} {:<8} {:<20}", "PID", "CPU%", "MEM", "NAME"));
-        processes.push("-".repeat(50));
-
-        for (pid, process) in self.sys.processes() {
-            processes.push(format!("{:<8} {:<8.1} {:<8} {:<20}",
-                pid,
-                process.cpu_usage(),
-                Self::format_size(process.memory()),
-                process.name()
-            ));
-        }
+    async fn list_processes(&self, command: &Command) -> anyhow::Result<String> {
+        let processes = {
+            let mut sys = self.sys.lock().unwrap();
+            sys.refresh_all();
+            sys.processes()
+                .iter()
+                .map(|(pid, process)| ProcessInfo::new(pid.to_string(), process))
+                .collect::<Vec<_>>()
+        };
 
-        Ok(processes.join("\n"))
+        render_processes(&processes, command)
     }
 
+    /// `kill [--force|-9] [--timeout <secs>] <pid>`: SIGTERM by default, SIGKILL with
+    /// `--force`. With `--timeout`, a process still running after TERM is killed.
     async fn kill_process(&self, command: &Command) -> anyhow::Result<String> {
-        if command.args.is_empty() {
-            return Err(anyhow::anyhow!("Usage: kill <pid>"));
-        }
+        let usage = || ShellError::InvalidUsage("kill [--force|-9] [--timeout <secs>] <pid>".to_string());
+        let force = command.flags.contains_key("force") || command.flags.contains_key("9");
+        let pid = command.args.first()
+            .ok_or_else(usage)?
+            .parse::<u32>()
+            .map_err(|_| usage())?;
+        let timeout = match command.flags.get("timeout") {
+            Some(Some(secs)) => Some(std::time::Duration::from_secs(secs.parse().map_err(|_| usage())?)),
+            Some(None) => return Err(usage().into()),
+            None => None,
+        };
 
-        let pid = command.args[0].parse::<i32>()?;
-        
-        if cfg!(windows) {
-            TokioCommand::new("taskkill")
-                .args(&["/PID", &pid.to_string(), "/F"])
-                .output()
-                .await?;
-        } else {
-            TokioCommand::new("kill")
-                .arg("-9")
-                .arg(pid.to_string())
-                .output()
-                .await?;
+        if force {
+            Signal::Kill.send(pid).await?;
+            return Ok(format!("Process {} killed", pid));
         }
-
-        Ok(format!("Process {} killed", pid))
+        terminate(pid, timeout).await
     }
 
-    async fn background_process(&self, command: &Command) -> anyhow::Result<String> {
-        if command.args.is_empty() {
-            return Err(anyhow::anyhow!("Usage: bg <command>"));
-        }
-
-        let mut cmd = TokioCommand::new(&command.args[0]);
-        if command.args.len() > 1 {
-            cmd.args(&command.args[1..]);
+    /// `bg <command>`: like ending the command with `&`, recorded in the job table so `jobs`
+    /// lists it and `exit` warns while it runs.
+    async fn background_process(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        let args = command.raw_args();
+        if args.is_empty() {
+            return Err(ShellError::InvalidUsage("bg <command>".to_string()).into());
         }
 
-        cmd.stdin(Stdio::null())
-           .stdout(Stdio::null())
-           .stderr(Stdio::null());
+        let child = TokioCommand::new(&args[0])
+            .args(&args[1..])
+            .envs(env.vars())
+            .current_dir(env.get_current_dir())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| ShellError::from_io(Path::new(&args[0]), e))?;
+        let line = args.join(" ");
+        let pid = self.jobs.add(line.clone(), child)
+            .ok_or_else(|| ShellError::External(format!("{}: exited before it could be tracked", args[0])))?;
+        Ok(format!("[{}] {}", pid, line))
+    }
 
-        let child = cmd.spawn()?;
-        Ok(format!("Process started in background with PID: {}", child.id().unwrap()))
+    fn list_jobs(&self) -> String {
+        self.jobs.running()
+            .iter()
+            .map(|(pid, command)| format!("[{}] Running  {}", pid, command))
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 
     async fn foreground_process(&self, command: &Command) -> anyhow::Result<String> {
@@ -126,28 +144,385 @@ impl ProcessPlugin {
         Ok(String::from_utf8_lossy(&output.stdout).to_string())
     }
 
-    async fn show_top_processes(&self, _command: &Command) -> anyhow::Result<String> {
-        self.sys.refresh_all();
-        
-        let mut processes: Vec<_> = self.sys.processes()
-            .values()
-            .collect();
-        
-        processes.sort_by(|a, b| b.cpu_usage().partial_cmp(&a.cpu_usage()).unwrap());
-        
+    async fn show_top_processes(&self, command: &Command) -> anyhow::Result<String> {
+        let mut processes = {
+            let mut sys = self.sys.lock().unwrap();
+            sys.refresh_all();
+            sys.processes()
+                .values()
+                .map(|process| ProcessInfo::new(process.pid().to_string(), process))
+                .collect::<Vec<_>>()
+        };
+
+        processes.sort_by(|a, b| b.cpu.partial_cmp(&a.cpu).unwrap_or(std::cmp::Ordering::Equal));
+        processes.truncate(10);
+
+        render_processes(&processes, command)
+    }
+}
+
+impl ProcessPlugin {
+    async fn disk_free(&self, command: &Command, env: &Environment) -> anyhow::Result<String> {
+        let path = command.args.first().map(|path| env.expand_path(path));
+        let human = command.flags.contains_key("h") || command.flags.contains_key("human-readable");
+
+        let disks = disk_usage(path.as_deref())?;
+        let size = |bytes: u64| if human { Self::format_size(bytes) } else { (bytes / 1024).to_string() };
+
         let mut output = Vec::new();
-        output.push(format!("{:<8} {:<8} {:<8} {:<20}", "PID", "CPU%", "MEM", "NAME"));
-        output.push("-".repeat(50));
-
-        for process in processes.iter().take(10) {
-            output.push(format!("{:<8} {:<8.1} {:<8} {:<20}",
-                process.pid(),
-                process.cpu_usage(),
-                Self::format_size(process.memory()),
-                process.name()
+        output.push(format!("{:<20} {:>10} {:>10} {:>10} {:>5} {}",
+            "Filesystem", if human { "Size" } else { "1K-blocks" }, "Used", "Avail", "Use%", "Mounted on"));
+        for disk in &disks {
+            output.push(format!("{:<20} {:>10} {:>10} {:>10} {:>4}% {}",
+                disk.name,
+                size(disk.total),
+                size(disk.used()),
+                size(disk.available),
+                disk.percent_used(),
+                disk.mount_point.display()
             ));
         }
 
         Ok(output.join("\n"))
     }
 }
+
+impl ProcessPlugin {
+    async fn memory_free(&self, command: &Command) -> anyhow::Result<String> {
+        let human = command.flags.contains_key("h") || command.flags.contains_key("human-readable");
+        let watch = match command.flags.get("watch") {
+            Some(Some(secs)) => Some(secs.parse::<u64>()
+                .map_err(|_| ShellError::InvalidUsage("free [-h] [--watch <secs>]".to_string()))?),
+            Some(None) => return Err(ShellError::InvalidUsage("free [-h] [--watch <secs>]".to_string()).into()),
+            None => None,
+        };
+
+        let interval = match watch {
+            Some(secs) => std::time::Duration::from_secs(secs.max(1)),
+            None => return Ok(memory_usage().render(human)),
+        };
+
+        // Redraw until interrupted
+        loop {
+            print!("\x1B[2J\x1B[1;1H");
+            println!("{}", memory_usage().render(human));
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => break,
+                _ = tokio::time::sleep(interval) => {}
+            }
+        }
+        Ok(String::new())
+    }
+}
+
+/// The signals `kill` sends. Windows has no SIGTERM: `Terminate` asks the process to close
+/// (`taskkill` without `/F`) and `Kill` ends it forcibly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Signal {
+    Terminate,
+    Kill,
+}
+
+impl Signal {
+    #[cfg(unix)]
+    async fn send(self, pid: u32) -> anyhow::Result<()> {
+        let name = match self {
+            Signal::Terminate => "TERM",
+            Signal::Kill => "KILL",
+        };
+        let output = TokioCommand::new("kill")
+            .args(["-s", name, &pid.to_string()])
+            .output()
+            .await?;
+        signal_result(pid, output)
+    }
+
+    #[cfg(windows)]
+    async fn send(self, pid: u32) -> anyhow::Result<()> {
+        let mut taskkill = TokioCommand::new("taskkill");
+        taskkill.args(["/PID", &pid.to_string()]);
+        if self == Signal::Kill {
+            taskkill.arg("/F");
+        }
+        signal_result(pid, taskkill.output().await?)
+    }
+}
+
+fn signal_result(pid: u32, output: std::process::Output) -> anyhow::Result<()> {
+    if output.status.success() {
+        Ok(())
+    } else if !is_running(pid) {
+        Err(ShellError::NotFound(format!("Process {}", pid)).into())
+    } else {
+        Err(anyhow::anyhow!("Failed to signal process {}: {}", pid, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+/// Whether `pid` is alive. Zombies count as exited; they only wait for their parent to reap them.
+fn is_running(pid: u32) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut sys = System::new();
+    sys.refresh_process(pid)
+        && sys.process(pid).is_some_and(|process| process.status() != ProcessStatus::Zombie)
+}
+
+/// Sends `Terminate`, then `Kill` if the process outlives `timeout`.
+async fn terminate(pid: u32, timeout: Option<std::time::Duration>) -> anyhow::Result<String> {
+    Signal::Terminate.send(pid).await?;
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Ok(format!("Sent SIGTERM to process {}", pid)),
+    };
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    while tokio::time::Instant::now() < deadline {
+        if !is_running(pid) {
+            return Ok(format!("Process {} terminated", pid));
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    if !is_running(pid) {
+        return Ok(format!("Process {} terminated", pid));
+    }
+
+    Signal::Kill.send(pid).await?;
+    Ok(format!("Process {} did not exit within {}s; killed", pid, timeout.as_secs()))
+}
+
+/// RAM and swap figures in bytes, as reported by `free`.
+#[derive(Debug, Clone)]
+struct MemoryUsage {
+    total: u64,
+    used: u64,
+    available: u64,
+    swap_total: u64,
+    swap_used: u64,
+}
+
+impl MemoryUsage {
+    fn render(&self, human: bool) -> String {
+        let size = |bytes: u64| if human { ProcessPlugin::format_size(bytes) } else { (bytes / 1024).to_string() };
+        let mut output = Vec::new();
+        output.push(format!("{:<6} {:>12} {:>12} {:>12}", "", "total", "used", "available"));
+        output.push(format!("{:<6} {:>12} {:>12} {:>12}", "Mem:", size(self.total), size(self.used), size(self.available)));
+        output.push(format!("{:<6} {:>12} {:>12} {:>12}", "Swap:",
+            size(self.swap_total),
+            size(self.swap_used),
+            size(self.swap_total.saturating_sub(self.swap_used))));
+        output.join("\n")
+    }
+}
+
+fn memory_usage() -> MemoryUsage {
+    let mut sys = System::new();
+    sys.refresh_memory();
+    MemoryUsage {
+        total: sys.total_memory(),
+        used: sys.used_memory(),
+        available: sys.available_memory(),
+        swap_total: sys.total_swap(),
+        swap_used: sys.used_swap(),
+    }
+}
+
+/// Space on one mounted filesystem, as reported by `df`.
+#[derive(Debug, Clone)]
+struct DiskUsage {
+    name: String,
+    mount_point: PathBuf,
+    total: u64,
+    available: u64,
+}
+
+impl DiskUsage {
+    fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+
+    fn percent_used(&self) -> u64 {
+        if self.total == 0 {
+            0
+        } else {
+            (self.used() as f64 / self.total as f64 * 100.0).ceil() as u64
+        }
+    }
+}
+
+/// Mounted filesystems, or only the one containing `path` (the longest matching mount point).
+fn disk_usage(path: Option<&Path>) -> anyhow::Result<Vec<DiskUsage>> {
+    let mut sys = System::new();
+    sys.refresh_disks_list();
+
+    let disks: Vec<DiskUsage> = sys.disks()
+        .iter()
+        .map(|disk| DiskUsage {
+            name: disk.name().to_string_lossy().to_string(),
+            mount_point: disk.mount_point().to_path_buf(),
+            total: disk.total_space(),
+            available: disk.available_space(),
+        })
+        .collect();
+
+    let path = match path {
+        Some(path) => path.canonicalize().map_err(|e| ShellError::from_io(path, e))?,
+        None => return Ok(disks),
+    };
+    let containing = disks.into_iter()
+        .filter(|disk| path.starts_with(&disk.mount_point))
+        .max_by_key(|disk| disk.mount_point.components().count())
+        .ok_or_else(|| ShellError::NotFound(format!("Filesystem for {}", path.display())))?;
+    Ok(vec![containing])
+}
+
+/// One row of `ps`/`top` output, shared by the `--output` renderers and CSV.
+#[derive(Debug, Clone, Serialize)]
+struct ProcessInfo {
+    pid: String,
+    cpu: f32,
+    mem: u64,
+    name: String,
+}
+
+impl ProcessInfo {
+    fn new(pid: String, process: &impl ProcessExt) -> Self {
+        ProcessInfo {
+            pid,
+            cpu: process.cpu_usage(),
+            mem: process.memory(),
+            name: process.name().to_string(),
+        }
+    }
+}
+
+impl Record for ProcessInfo {
+    fn headers() -> &'static [&'static str] {
+        &["PID", "CPU%", "MEM", "NAME"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.pid.clone(),
+            format!("{:.1}", self.cpu),
+            ProcessPlugin::format_size(self.mem),
+            self.name.clone(),
+        ]
+    }
+}
+
+fn render_processes(processes: &[ProcessInfo], command: &Command) -> anyhow::Result<String> {
+    if command.flags.contains_key("csv") {
+        return Ok(render_csv(processes));
+    }
+    OutputFormat::from_command(command)?.render(processes)
+}
+
+fn render_csv(processes: &[ProcessInfo]) -> String {
+    let mut output = vec!["pid,cpu,mem,name".to_string()];
+    for process in processes {
+        output.push(format!("{},{:.1},{},{}", process.pid, process.cpu, process.mem, csv_field(&process.name)));
+    }
+    output.join("\n")
+}
+
+/// Quotes a CSV field when it contains a separator, quote or newline.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> Vec<ProcessInfo> {
+        vec![
+            ProcessInfo { pid: "42".to_string(), cpu: 12.5, mem: 2048, name: "nexusshell".to_string() },
+            ProcessInfo { pid: "7".to_string(), cpu: 0.0, mem: 512, name: "odd,name".to_string() },
+        ]
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let csv = render_csv(&fixture());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "pid,cpu,mem,name");
+        assert_eq!(lines[1], "42,12.5,2048,nexusshell");
+        assert_eq!(lines[2], "7,0.0,512,\"odd,name\"");
+    }
+
+    #[test]
+    fn test_render_json_and_text_share_rows() {
+        let processes = fixture();
+        let json: serde_json::Value = serde_json::from_str(&OutputFormat::Json.render(&processes).unwrap()).unwrap();
+        assert_eq!(json[0]["name"], "nexusshell");
+        assert_eq!(json[0]["mem"], 2048);
+        let table = OutputFormat::Table.render(&processes).unwrap();
+        assert!(table.contains("nexusshell"));
+        assert!(table.contains("2.0K"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_disk_usage_includes_root() {
+        let disks = disk_usage(Some(Path::new("/"))).unwrap();
+        assert_eq!(disks.len(), 1);
+        assert_eq!(disks[0].mount_point, PathBuf::from("/"));
+        assert!(disks[0].total > 0);
+        assert!(disks[0].available <= disks[0].total);
+        assert!(disks[0].percent_used() <= 100);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_terminate_lets_process_exit_gracefully() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let marker = temp_dir.path().join("cleaned-up");
+        let script = format!(
+            "trap 'touch {}; exit 0' TERM; while true; do sleep 0.1; done",
+            marker.display()
+        );
+        let mut child = std::process::Command::new("sh").args(["-c", &script]).spawn().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let started = std::time::Instant::now();
+        let output = terminate(child.id(), Some(std::time::Duration::from_secs(5))).await.unwrap();
+        assert_eq!(output, format!("Process {} terminated", child.id()));
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert!(marker.exists());
+
+        let status = child.wait().unwrap();
+        assert_eq!(status.code(), Some(0));
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let memory = memory_usage();
+        assert!(memory.total > 0);
+        assert!(memory.used <= memory.total);
+        assert!(memory.swap_used <= memory.swap_total);
+        assert!(memory.render(true).contains("Mem:"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bg_is_listed_by_jobs() {
+        let plugin_manager = crate::shell::PluginManager::new();
+        let parser = crate::shell::Parser::new();
+        let env = Environment::new();
+        let run = |line: &str| {
+            let command = parser.parse_pipeline(line).unwrap().remove(0);
+            let plugin = plugin_manager.get_plugin(&command.name).unwrap();
+            let env = &env;
+            async move { plugin.execute(&command, env).await.unwrap() }
+        };
+
+        let started = run("bg sleep 1").await;
+        let running = plugin_manager.jobs().running();
+        assert_eq!(running.len(), 1);
+        assert_eq!(started, format!("[{}] sleep 1", running[0].0));
+        assert_eq!(run("jobs").await, format!("[{}] Running  sleep 1", running[0].0));
+    }
+}
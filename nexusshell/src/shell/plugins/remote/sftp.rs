@@ -1,26 +1,51 @@
 use async_trait::async_trait;
 use super::super::super::{Command, Environment, Plugin};
-use ssh2::{Session, Sftp};
+use ssh2::{OpenFlags, OpenType, Session, Sftp};
 use std::net::TcpStream;
 use std::path::{Path, PathBuf};
-use std::fs::{self, File};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use anyhow::{Result, Context};
-use futures::StreamExt;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use indicatif::{ProgressBar, ProgressStyle};
+use ring::digest;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// `--resume` continues into a shorter destination; `--verify` first compares the part
+/// already transferred and starts over if it differs.
+#[derive(Debug, Clone, Copy, Default)]
+struct TransferOptions {
+    resume: bool,
+    verify: bool,
+}
+
+impl TransferOptions {
+    fn from_command(command: &Command) -> Self {
+        TransferOptions {
+            resume: command.flags.contains_key("resume"),
+            verify: command.flags.contains_key("verify"),
+        }
+    }
+}
 
 pub struct SFTPPlugin {
-    sessions: std::collections::HashMap<String, (Session, Sftp)>,
+    sessions: Mutex<HashMap<String, (Session, Arc<Sftp>)>>,
 }
 
 impl SFTPPlugin {
     pub fn new() -> Self {
         SFTPPlugin {
-            sessions: std::collections::HashMap::new(),
+            sessions: Mutex::new(HashMap::new()),
         }
     }
 
-    async fn connect(&mut self, host: &str, username: &str, port: u16) -> Result<()> {
+    fn sftp(&self, host: &str) -> Result<Arc<Sftp>> {
+        self.sessions.lock().unwrap().get(host)
+            .map(|(_, sftp)| sftp.clone())
+            .ok_or_else(|| anyhow::anyhow!("Not connected to {}", host))
+    }
+
+    async fn connect(&self, host: &str, username: &str, port: u16) -> Result<()> {
         let tcp = TcpStream::connect(format!("{}:{}", host, port))
             .with_context(|| format!("Failed to connect to {}:{}", host, port))?;
 
@@ -40,113 +65,97 @@ impl SFTPPlugin {
         }
 
         let sftp = session.sftp()?;
-        self.sessions.insert(host.to_string(), (session, sftp));
+        self.sessions.lock().unwrap().insert(host.to_string(), (session, Arc::new(sftp)));
         Ok(())
     }
 
-    async fn upload_file(&self, host: &str, local_path: &Path, remote_path: &Path) -> Result<()> {
-        let (_, sftp) = self.sessions.get(host)
-            .ok_or_else(|| anyhow::anyhow!("Not connected to {}", host))?;
+    async fn upload_file(&self, host: &str, local_path: &Path, remote_path: &Path, options: TransferOptions) -> Result<u64> {
+        let sftp = self.sftp(host)?;
 
         let file_size = fs::metadata(local_path)?.len();
-        let pb = ProgressBar::new(file_size);
-        pb.set_style(ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .progress_chars("#>-"));
-
         let mut local_file = File::open(local_path)?;
-        let mut remote_file = sftp.create(remote_path)?;
-
-        let mut buffer = [0; 8192];
-        let mut uploaded = 0;
-        loop {
-            let n = local_file.read(&mut buffer)?;
-            if n == 0 {
-                break;
+
+        // A shorter remote file is taken to be an interrupted upload of this one
+        let partial = if options.resume {
+            sftp.stat(remote_path).ok().and_then(|stat| stat.size).filter(|size| *size <= file_size)
+        } else {
+            None
+        };
+        let (mut remote_file, offset) = match partial {
+            Some(size) if size > 0 => {
+                let mut remote_file = sftp.open_mode(remote_path, OpenFlags::READ | OpenFlags::WRITE, 0o644, OpenType::File)?;
+                if options.verify && !overlap_matches(&mut local_file, &mut remote_file, size)? {
+                    (sftp.create(remote_path)?, 0)
+                } else {
+                    (remote_file, size)
+                }
             }
-            remote_file.write_all(&buffer[..n])?;
-            uploaded += n;
-            pb.set_position(uploaded as u64);
-        }
+            _ => (sftp.create(remote_path)?, 0),
+        };
 
+        let pb = transfer_progress(file_size);
+        copy_from(&mut local_file, &mut remote_file, offset, &pb)?;
         pb.finish_with_message("Upload complete");
-        Ok(())
+        Ok(offset)
     }
 
-    async fn download_file(&self, host: &str, remote_path: &Path, local_path: &Path) -> Result<()> {
-        let (_, sftp) = self.sessions.get(host)
-            .ok_or_else(|| anyhow::anyhow!("Not connected to {}", host))?;
+    async fn download_file(&self, host: &str, remote_path: &Path, local_path: &Path, options: TransferOptions) -> Result<u64> {
+        let sftp = self.sftp(host)?;
 
         let file_size = sftp.stat(remote_path)?.size.unwrap_or(0);
-        let pb = ProgressBar::new(file_size);
-        pb.set_style(ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .progress_chars("#>-"));
-
         let mut remote_file = sftp.open(remote_path)?;
-        let mut local_file = File::create(local_path)?;
-
-        let mut buffer = [0; 8192];
-        let mut downloaded = 0;
-        loop {
-            let n = remote_file.read(&mut buffer)?;
-            if n == 0 {
-                break;
+
+        let partial = if options.resume {
+            fs::metadata(local_path).ok().map(|metadata| metadata.len()).filter(|size| *size <= file_size)
+        } else {
+            None
+        };
+        let (mut local_file, offset) = match partial {
+            Some(size) if size > 0 => {
+                let mut local_file = OpenOptions::new().read(true).write(true).open(local_path)?;
+                if options.verify && !overlap_matches(&mut remote_file, &mut local_file, size)? {
+                    (File::create(local_path)?, 0)
+                } else {
+                    (local_file, size)
+                }
             }
-            local_file.write_all(&buffer[..n])?;
-            downloaded += n;
-            pb.set_position(downloaded as u64);
-        }
+            _ => (File::create(local_path)?, 0),
+        };
 
+        let pb = transfer_progress(file_size);
+        copy_from(&mut remote_file, &mut local_file, offset, &pb)?;
         pb.finish_with_message("Download complete");
-        Ok(())
+        Ok(offset)
     }
 
-    async fn list_directory(&self, host: &str, remote_path: &Path) -> Result<String> {
-        let (_, sftp) = self.sessions.get(host)
-            .ok_or_else(|| anyhow::anyhow!("Not connected to {}", host))?;
+    /// Lists `remote_path`, naming entries relative to it. With `recursive`, each subdirectory's
+    /// contents follow it depth-first.
+    async fn list_directory(&self, host: &str, remote_path: &Path, recursive: bool) -> Result<String> {
+        let sftp = self.sftp(host)?;
 
         let mut output = String::new();
         output.push_str(&format!("Contents of {}:\n", remote_path.display()));
-
-        for entry in sftp.readdir(remote_path)? {
-            let filename = entry.0;
-            let attrs = entry.1;
-            
-            let file_type = if attrs.is_dir() { "DIR" } else { "FILE" };
-            let size = attrs.size.unwrap_or(0);
-            let permissions = attrs.permissions.unwrap_or(0);
-            
-            output.push_str(&format!("{:<4} {:>10} {:o} {}\n",
-                file_type,
-                size,
-                permissions,
-                filename.display()
-            ));
-        }
-
+        let mut visited = HashSet::new();
+        list_entries(&sftp, remote_path, remote_path, recursive, &mut visited, &mut output)?;
         Ok(output)
     }
 
     async fn create_directory(&self, host: &str, remote_path: &Path) -> Result<()> {
-        let (_, sftp) = self.sessions.get(host)
-            .ok_or_else(|| anyhow::anyhow!("Not connected to {}", host))?;
+        let sftp = self.sftp(host)?;
 
         sftp.mkdir(remote_path, 0o755)?;
         Ok(())
     }
 
     async fn remove_file(&self, host: &str, remote_path: &Path) -> Result<()> {
-        let (_, sftp) = self.sessions.get(host)
-            .ok_or_else(|| anyhow::anyhow!("Not connected to {}", host))?;
+        let sftp = self.sftp(host)?;
 
         sftp.unlink(remote_path)?;
         Ok(())
     }
 
     async fn remove_directory(&self, host: &str, remote_path: &Path) -> Result<()> {
-        let (_, sftp) = self.sessions.get(host)
-            .ok_or_else(|| anyhow::anyhow!("Not connected to {}", host))?;
+        let sftp = self.sftp(host)?;
 
         sftp.rmdir(remote_path)?;
         Ok(())
@@ -185,36 +194,35 @@ impl Plugin for SFTPPlugin {
 
             Some("upload") => {
                 if command.args.len() != 4 {
-                    return Err(anyhow::anyhow!("Usage: sftp upload hostname local_path remote_path"));
+                    return Err(anyhow::anyhow!("Usage: sftp upload hostname local_path remote_path [--resume] [--verify]"));
                 }
                 let host = &command.args[1];
                 let local_path = PathBuf::from(&command.args[2]);
                 let remote_path = PathBuf::from(&command.args[3]);
 
-                self.upload_file(host, &local_path, &remote_path).await?;
-                Ok("Upload completed successfully".to_string())
+                let resumed_at = self.upload_file(host, &local_path, &remote_path, TransferOptions::from_command(command)).await?;
+                Ok(transfer_summary("Upload", resumed_at))
             }
 
             Some("download") => {
                 if command.args.len() != 4 {
-                    return Err(anyhow::anyhow!("Usage: sftp download hostname remote_path local_path"));
+                    return Err(anyhow::anyhow!("Usage: sftp download hostname remote_path local_path [--resume] [--verify]"));
                 }
                 let host = &command.args[1];
                 let remote_path = PathBuf::from(&command.args[2]);
                 let local_path = PathBuf::from(&command.args[3]);
 
-                self.download_file(host, &remote_path, &local_path).await?;
-                Ok("Download completed successfully".to_string())
+                let resumed_at = self.download_file(host, &remote_path, &local_path, TransferOptions::from_command(command)).await?;
+                Ok(transfer_summary("Download", resumed_at))
             }
 
             Some("ls") => {
-                if command.args.len() != 3 {
-                    return Err(anyhow::anyhow!("Usage: sftp ls hostname remote_path"));
-                }
-                let host = &command.args[1];
-                let remote_path = PathBuf::from(&command.args[2]);
+                let recursive = command.flags.contains_key("R") || command.flags.contains_key("recursive");
+                let [_, host, remote_path] = &command.args[..] else {
+                    return Err(anyhow::anyhow!("Usage: sftp ls hostname remote_path [-R|--recursive]"));
+                };
 
-                self.list_directory(host, &remote_path).await
+                self.list_directory(host, Path::new(remote_path), recursive).await
             }
 
             Some("mkdir") => {
@@ -254,3 +262,164 @@ impl Plugin for SFTPPlugin {
         }
     }
 }
+
+/// Appends a line per entry of `dir`, recursing into subdirectories when `recursive`.
+/// Directories are keyed by their `realpath` in `visited`, so a symlinked loop is listed once.
+fn list_entries(sftp: &Sftp, root: &Path, dir: &Path, recursive: bool, visited: &mut HashSet<PathBuf>, output: &mut String) -> Result<()> {
+    visited.insert(sftp.realpath(dir).unwrap_or_else(|_| dir.to_path_buf()));
+
+    let mut entries = sftp.readdir(dir)
+        .with_context(|| format!("Cannot list {}", dir.display()))?;
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (path, attrs) in entries {
+        let file_type = if attrs.is_dir() { "DIR" } else { "FILE" };
+        let name = path.strip_prefix(root).unwrap_or(&path);
+        output.push_str(&format!("{:<4} {:>10} {:o} {}\n",
+            file_type,
+            attrs.size.unwrap_or(0),
+            attrs.perm.unwrap_or(0),
+            name.display()
+        ));
+
+        if recursive && attrs.is_dir() {
+            let real = sftp.realpath(&path).unwrap_or_else(|_| path.clone());
+            if !visited.contains(&real) {
+                list_entries(sftp, root, &path, recursive, visited, output)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn transfer_progress(total: u64) -> ProgressBar {
+    let pb = ProgressBar::new(total);
+    pb.set_style(ProgressStyle::default_bar()
+        .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+        .expect("valid progress template")
+        .progress_chars("#>-"));
+    pb
+}
+
+fn transfer_summary(direction: &str, resumed_at: u64) -> String {
+    if resumed_at > 0 {
+        format!("{} completed successfully (resumed at byte {})", direction, resumed_at)
+    } else {
+        format!("{} completed successfully", direction)
+    }
+}
+
+/// Copies `source` into `destination` from `offset` onwards in both, returning the number
+/// of bytes copied.
+fn copy_from<R: Read + Seek, W: Write + Seek>(
+    source: &mut R,
+    destination: &mut W,
+    offset: u64,
+    pb: &ProgressBar,
+) -> io::Result<u64> {
+    source.seek(SeekFrom::Start(offset))?;
+    destination.seek(SeekFrom::Start(offset))?;
+    pb.set_position(offset);
+
+    let mut buffer = [0; 8192];
+    let mut copied = 0;
+    loop {
+        let n = source.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        destination.write_all(&buffer[..n])?;
+        copied += n as u64;
+        pb.set_position(offset + copied);
+    }
+    destination.flush()?;
+    Ok(copied)
+}
+
+/// Whether the first `len` bytes of `a` and `b` have the same SHA-256.
+fn overlap_matches<A: Read + Seek, B: Read + Seek>(a: &mut A, b: &mut B, len: u64) -> io::Result<bool> {
+    Ok(prefix_digest(a, len)? == prefix_digest(b, len)?)
+}
+
+fn prefix_digest<R: Read + Seek>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut context = digest::Context::new(&digest::SHA256);
+    let mut limited = reader.take(len);
+    let mut buffer = [0; 8192];
+    loop {
+        let n = limited.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        context.update(&buffer[..n]);
+    }
+    Ok(context.finish().as_ref().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resume_completes_partial_file() {
+        let temp_dir = tempdir().unwrap();
+        let source_path = temp_dir.path().join("source.bin");
+        let partial_path = temp_dir.path().join("partial.bin");
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&source_path, &content).unwrap();
+        fs::write(&partial_path, &content[..30_000]).unwrap();
+
+        let mut source = File::open(&source_path).unwrap();
+        let mut partial = OpenOptions::new().read(true).write(true).open(&partial_path).unwrap();
+        assert!(overlap_matches(&mut source, &mut partial, 30_000).unwrap());
+
+        let copied = copy_from(&mut source, &mut partial, 30_000, &ProgressBar::hidden()).unwrap();
+        assert_eq!(copied, 70_000);
+        assert_eq!(fs::read(&partial_path).unwrap(), content);
+    }
+
+    #[test]
+    fn test_verify_detects_mismatched_overlap() {
+        let temp_dir = tempdir().unwrap();
+        let source_path = temp_dir.path().join("source.bin");
+        let partial_path = temp_dir.path().join("partial.bin");
+        fs::write(&source_path, b"hello world").unwrap();
+        fs::write(&partial_path, b"jello").unwrap();
+
+        let mut source = File::open(&source_path).unwrap();
+        let mut partial = File::open(&partial_path).unwrap();
+        assert!(!overlap_matches(&mut source, &mut partial, 5).unwrap());
+    }
+
+    #[cfg(feature = "sshd")]
+    #[tokio::test]
+    async fn test_recursive_listing_against_sshd() {
+        let target = std::env::var("NEXUSSHELL_TEST_SSH")
+            .unwrap_or_else(|_| format!("{}@127.0.0.1", std::env::var("USER").unwrap_or_default()));
+        let (username, address) = target.split_once('@').unwrap();
+        let (hostname, port) = address.split_once(':').map_or((address, 22), |(host, port)| (host, port.parse().unwrap()));
+        let plugin = SFTPPlugin::new();
+        plugin.connect(hostname, username, port).await.unwrap();
+
+        let temp_dir = tempdir().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src/deeper")).unwrap();
+        fs::write(temp_dir.path().join("top.txt"), "top").unwrap();
+        fs::write(temp_dir.path().join("src/nested.txt"), "nested").unwrap();
+        fs::write(temp_dir.path().join("src/deeper/leaf.txt"), "leaf").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(temp_dir.path(), temp_dir.path().join("src/deeper/loop")).unwrap();
+
+        let flat = plugin.list_directory(hostname, temp_dir.path(), false).await.unwrap();
+        assert!(flat.lines().any(|line| line.ends_with(" top.txt")));
+        assert!(!flat.contains("nested.txt"));
+
+        let output = plugin.list_directory(hostname, temp_dir.path(), true).await.unwrap();
+        let names: Vec<&str> = output.lines().skip(1).filter_map(|line| line.split_whitespace().last()).collect();
+        let position = |name: &str| names.iter().position(|n| *n == name).unwrap();
+        assert!(position("src") < position("src/deeper"));
+        assert!(position("src/deeper") < position("src/deeper/leaf.txt"));
+        assert!(position("src/deeper/leaf.txt") < position("src/nested.txt"));
+        assert!(!output.contains("loop/"));
+    }
+}
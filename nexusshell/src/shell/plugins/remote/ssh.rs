@@ -3,12 +3,18 @@ use super::super::super::{Command, Environment, Plugin};
 use ssh2::{Session, Channel};
 use std::io::prelude::*;
 use std::net::TcpStream;
-use std::path::{Path, PathBuf};
-use tokio::fs;
-use std::fs::File;
+use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::sync::{Mutex, RwLock};
 use anyhow::{Result, Context};
 use serde::{Serialize, Deserialize};
 use dirs::home_dir;
+use super::super::super::tty::{poll_terminal, RawMode, TerminalInput};
+use super::sftp::transfer_progress;
+use indicatif::ProgressBar;
+use ring::digest;
+use crossterm::terminal;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SSHConfig {
@@ -31,16 +37,16 @@ impl Default for SSHConfig {
 }
 
 pub struct SSHPlugin {
-    config: SSHConfig,
-    sessions: std::collections::HashMap<String, Session>,
+    config: RwLock<SSHConfig>,
+    sessions: Mutex<HashMap<String, Session>>,
 }
 
 impl SSHPlugin {
     pub fn new() -> Self {
         let config = Self::load_config().unwrap_or_default();
         SSHPlugin {
-            config,
-            sessions: std::collections::HashMap::new(),
+            config: RwLock::new(config),
+            sessions: Mutex::new(HashMap::new()),
         }
     }
 
@@ -61,7 +67,13 @@ impl SSHPlugin {
         }
     }
 
-    async fn connect(&mut self, host: &str, username: &str, port: u16) -> Result<()> {
+    fn session(&self, host: &str) -> Result<Session> {
+        self.sessions.lock().unwrap().get(host)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Not connected to {}", host))
+    }
+
+    async fn connect(&self, host: &str, username: &str, port: u16) -> Result<()> {
         let tcp = TcpStream::connect(format!("{}:{}", host, port))
             .with_context(|| format!("Failed to connect to {}:{}", host, port))?;
 
@@ -70,11 +82,12 @@ impl SSHPlugin {
         session.handshake()?;
 
         // Try private key authentication first
-        for key_path in &self.config.private_keys {
+        let private_keys = self.config.read().unwrap().private_keys.clone();
+        for key_path in &private_keys {
             if key_path.exists() {
                 match session.userauth_pubkey_file(username, None, key_path, None) {
                     Ok(_) => {
-                        self.sessions.insert(host.to_string(), session);
+                        self.sessions.lock().unwrap().insert(host.to_string(), session);
                         return Ok(());
                     }
                     Err(_) => continue,
@@ -87,8 +100,7 @@ impl SSHPlugin {
     }
 
     async fn execute_remote(&self, host: &str, command: &str) -> Result<String> {
-        let session = self.sessions.get(host)
-            .ok_or_else(|| anyhow::anyhow!("Not connected to {}", host))?;
+        let session = self.session(host)?;
 
         let mut channel = session.channel_session()?;
         channel.exec(command)?;
@@ -100,21 +112,65 @@ impl SSHPlugin {
         Ok(output)
     }
 
-    async fn copy_file(&self, host: &str, src: &Path, dest: &Path, to_remote: bool) -> Result<()> {
-        let session = self.sessions.get(host)
-            .ok_or_else(|| anyhow::anyhow!("Not connected to {}", host))?;
+    /// Opens a PTY shell on `host` and bridges the local terminal to it until the remote
+    /// side exits. Returns the remote exit status.
+    async fn interactive_shell(&self, host: &str) -> Result<i32> {
+        let session = self.session(host)?;
+
+        let size = terminal::size().unwrap_or((80, 24));
+        let _raw = RawMode::enable()?;
+        bridge_shell(&session, size, poll_terminal, &mut std::io::stdout())
+    }
+
+    /// Copies a file over SCP with a progress bar. With `verify`, the SHA-256 of the bytes
+    /// sent or received is checked against `sha256sum` of the remote file afterwards.
+    async fn copy_file(&self, request: &CopyRequest) -> Result<String> {
+        let session = self.session(&request.host)?;
+
+        let (bytes, sha256) = if request.to_remote {
+            let size = request.src.metadata()
+                .with_context(|| format!("Cannot read {}", request.src.display()))?
+                .len();
+            let mut remote_file = session.scp_send(&request.dest, 0o644, size, None)?;
+            let mut local_file = File::open(&request.src)?;
+            let pb = transfer_progress(size);
+            let copied = transfer(&mut local_file, &mut remote_file, &pb)?;
+            pb.finish_and_clear();
+            // The remote file is only complete once the channel has closed
+            remote_file.send_eof()?;
+            remote_file.wait_eof()?;
+            remote_file.close()?;
+            remote_file.wait_close()?;
+            copied
+        } else {
+            let (mut remote_file, stat) = session.scp_recv(&request.src)?;
+            let mut local_file = File::create(&request.dest)
+                .with_context(|| format!("Cannot create {}", request.dest.display()))?;
+            let pb = transfer_progress(stat.size());
+            let copied = transfer(&mut remote_file, &mut local_file, &pb)?;
+            pb.finish_and_clear();
+            copied
+        };
 
-        if to_remote {
-            let mut remote_file = session.scp_send(dest, 0o644, src.metadata()?.len(), None)?;
-            let mut local_file = File::open(src)?;
-            std::io::copy(&mut local_file, &mut remote_file)?;
+        let (target, remote_path) = if request.to_remote {
+            (format!("{}:{}", request.host, request.dest.display()), &request.dest)
         } else {
-            let (mut remote_file, _) = session.scp_recv(src)?;
-            let mut local_file = File::create(dest)?;
-            std::io::copy(&mut remote_file, &mut local_file)?;
+            (request.dest.display().to_string(), &request.src)
+        };
+        if !request.verify {
+            return Ok(format!("Copied {} bytes to {}", bytes, target));
         }
 
-        Ok(())
+        let output = self.execute_remote(&request.host, &format!("sha256sum {}", shell_quote(&remote_path.to_string_lossy()))).await?;
+        match output.split_whitespace().next() {
+            Some(remote) if remote.eq_ignore_ascii_case(&sha256) => {
+                Ok(format!("Copied {} bytes to {} (SHA-256 verified)", bytes, target))
+            }
+            Some(remote) if remote.len() == 64 => Err(anyhow::anyhow!(
+                "Checksum mismatch after copying to {}: sent {}, remote has {}", target, sha256, remote
+            )),
+            _ => Err(anyhow::anyhow!("Could not verify {}: sha256sum failed on {}: {}", target, request.host, output.trim())),
+        }
     }
 
     fn parse_host_string(host_str: &str) -> Result<(String, String, u16)> {
@@ -162,29 +218,26 @@ impl Plugin for SSHPlugin {
                 self.execute_remote(host, &remote_command).await
             }
 
-            Some("copy") => {
-                if command.args.len() != 4 {
-                    return Err(anyhow::anyhow!("Usage: ssh copy hostname src_path dest_path direction(to/from)"));
-                }
-                let host = &command.args[1];
-                let src = Path::new(&command.args[2]);
-                let dest = Path::new(&command.args[3]);
-                let direction = &command.args[4];
-                
-                match direction.as_str() {
-                    "to" => self.copy_file(host, src, dest, true).await?,
-                    "from" => self.copy_file(host, src, dest, false).await?,
-                    _ => return Err(anyhow::anyhow!("Direction must be 'to' or 'from'")),
+            Some("shell") => {
+                if command.args.len() != 2 {
+                    return Err(anyhow::anyhow!("Usage: ssh shell hostname"));
                 }
-                Ok("File transfer completed successfully".to_string())
+                let status = self.interactive_shell(&command.args[1]).await?;
+                Ok(format!("Connection to {} closed (exit status {})", command.args[1], status))
+            }
+
+            Some("copy") => {
+                let request = CopyRequest::parse(command)?;
+                self.copy_file(&request).await
             }
 
             Some("list-keys") => {
                 let mut output = String::from("Configured SSH keys:\n");
-                for key in &self.config.private_keys {
+                let config = self.config.read().unwrap();
+                for key in &config.private_keys {
                     output.push_str(&format!("- {}\n", key.display()));
                 }
-                if let Some(default) = &self.config.default_key {
+                if let Some(default) = &config.default_key {
                     output.push_str(&format!("\nDefault key: {}", default.display()));
                 }
                 Ok(output)
@@ -198,11 +251,200 @@ impl Plugin for SSHPlugin {
                 if !key_path.exists() {
                     return Err(anyhow::anyhow!("Key file does not exist"));
                 }
-                self.config.private_keys.push(key_path);
+                self.config.write().unwrap().private_keys.push(key_path);
                 Ok("SSH key added successfully".to_string())
             }
 
-            _ => Ok("Available commands: connect, exec, copy, list-keys, add-key".to_string()),
+            _ => Ok("Available commands: connect, exec, shell, copy, list-keys, add-key".to_string()),
+        }
+    }
+}
+
+/// `ssh copy hostname src_path dest_path to|from [--verify]`. `to` uploads the local
+/// `src_path`, `from` downloads the remote one.
+#[derive(Debug, PartialEq)]
+struct CopyRequest {
+    host: String,
+    src: PathBuf,
+    dest: PathBuf,
+    to_remote: bool,
+    verify: bool,
+}
+
+impl CopyRequest {
+    fn parse(command: &Command) -> Result<Self> {
+        let verify = command.flags.contains_key("verify");
+        let [_, host, src, dest, direction] = &command.args[..] else {
+            return Err(anyhow::anyhow!("Usage: ssh copy hostname src_path dest_path to|from [--verify]"));
+        };
+        let to_remote = match direction.as_str() {
+            "to" => true,
+            "from" => false,
+            _ => return Err(anyhow::anyhow!("Direction must be 'to' or 'from'")),
+        };
+        Ok(CopyRequest {
+            host: host.clone(),
+            src: PathBuf::from(src),
+            dest: PathBuf::from(dest),
+            to_remote,
+            verify,
+        })
+    }
+}
+
+/// Copies `reader` to `writer`, advancing `pb`. Returns the byte count and the hex SHA-256
+/// of what was copied.
+fn transfer<R: Read, W: Write>(reader: &mut R, writer: &mut W, pb: &ProgressBar) -> std::io::Result<(u64, String)> {
+    let mut context = digest::Context::new(&digest::SHA256);
+    let mut buffer = [0; 8192];
+    let mut copied = 0;
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
         }
+        writer.write_all(&buffer[..n])?;
+        context.update(&buffer[..n]);
+        copied += n as u64;
+        pb.set_position(copied);
+    }
+    writer.flush()?;
+    let sha256 = context.finish().as_ref().iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok((copied, sha256))
+}
+
+/// Single-quotes `text` for a POSIX shell.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Runs a PTY shell over `session`, writing its output to `output` and forwarding whatever
+/// `next_input` yields. The session is polled non-blocking so output keeps flowing while
+/// waiting for input.
+fn bridge_shell<W: Write>(
+    session: &Session,
+    (cols, rows): (u16, u16),
+    mut next_input: impl FnMut() -> Result<Option<TerminalInput>>,
+    output: &mut W,
+) -> Result<i32> {
+    let mut channel = session.channel_session()?;
+    channel.request_pty("xterm", None, Some((cols as u32, rows as u32, 0, 0)))?;
+    channel.shell()?;
+
+    session.set_blocking(false);
+    let bridged = forward(session, &mut channel, &mut next_input, output);
+    session.set_blocking(true);
+    bridged?;
+
+    channel.wait_close()?;
+    Ok(channel.exit_status()?)
+}
+
+fn forward<W: Write>(
+    session: &Session,
+    channel: &mut Channel,
+    next_input: &mut impl FnMut() -> Result<Option<TerminalInput>>,
+    output: &mut W,
+) -> Result<()> {
+    let mut buffer = [0u8; 8192];
+    loop {
+        match channel.read(&mut buffer) {
+            Ok(0) => {}
+            Ok(n) => {
+                output.write_all(&buffer[..n])?;
+                output.flush()?;
+                continue;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+        if channel.eof() {
+            return Ok(());
+        }
+
+        // Writes are rare and small, so make them blocking rather than retry partial ones
+        match next_input()? {
+            Some(TerminalInput::Bytes(bytes)) => {
+                session.set_blocking(true);
+                let written = channel.write_all(&bytes).and_then(|_| channel.flush());
+                session.set_blocking(false);
+                written?;
+            }
+            Some(TerminalInput::Resize(cols, rows)) => {
+                session.set_blocking(true);
+                let resized = channel.request_pty_size(cols as u32, rows as u32, None, None);
+                session.set_blocking(false);
+                resized?;
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::super::Parser;
+
+    #[test]
+    fn test_copy_arguments() {
+        let parse = |line: &str| CopyRequest::parse(&Parser::new().parse(line).unwrap());
+
+        let request = parse("ssh copy example.com ./report.pdf /tmp/report.pdf to").unwrap();
+        assert_eq!(request, CopyRequest {
+            host: "example.com".to_string(),
+            src: PathBuf::from("./report.pdf"),
+            dest: PathBuf::from("/tmp/report.pdf"),
+            to_remote: true,
+            verify: false,
+        });
+        let request = parse("ssh copy --verify example.com /var/log/app.log app.log from").unwrap();
+        assert!(!request.to_remote && request.verify);
+        assert_eq!(request.host, "example.com");
+
+        assert!(parse("ssh copy example.com a b").is_err());
+        assert!(parse("ssh copy example.com a b sideways").unwrap_err().to_string().contains("'to' or 'from'"));
+    }
+
+    #[test]
+    fn test_transfer_round_trip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(temp_dir.path().join("source.bin"), &content).unwrap();
+
+        let mut source = File::open(temp_dir.path().join("source.bin")).unwrap();
+        let mut destination = File::create(temp_dir.path().join("copy.bin")).unwrap();
+        let (bytes, sha256) = transfer(&mut source, &mut destination, &ProgressBar::hidden()).unwrap();
+
+        assert_eq!(bytes, 100_000);
+        assert_eq!(std::fs::read(temp_dir.path().join("copy.bin")).unwrap(), content);
+        let expected: String = digest::digest(&digest::SHA256, &content).as_ref().iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(sha256, expected);
+        assert_eq!(shell_quote("it's here"), "'it'\\''s here'");
+    }
+
+    #[cfg(feature = "sshd")]
+    #[tokio::test]
+    async fn test_shell_session_against_sshd() {
+        let target = std::env::var("NEXUSSHELL_TEST_SSH")
+            .unwrap_or_else(|_| format!("{}@127.0.0.1", std::env::var("USER").unwrap_or_default()));
+        let (username, hostname, port) = SSHPlugin::parse_host_string(&target).unwrap();
+        let plugin = SSHPlugin::new();
+        plugin.connect(&hostname, &username, port).await.unwrap();
+        let sessions = plugin.sessions.lock().unwrap();
+        let session = sessions.get(&hostname).unwrap();
+
+        let mut script = Some(b"echo hi; exit\n".to_vec());
+        let mut output = Vec::new();
+        let status = bridge_shell(session, (80, 24), || {
+            std::thread::sleep(super::super::super::super::tty::INPUT_POLL);
+            Ok(script.take().map(TerminalInput::Bytes))
+        }, &mut output).unwrap();
+
+        assert_eq!(status, 0);
+        let output = String::from_utf8_lossy(&output);
+        assert!(output.lines().any(|line| line.trim_end() == "hi"));
     }
 }
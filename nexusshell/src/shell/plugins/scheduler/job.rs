@@ -7,6 +7,11 @@ use anyhow::Result;
 use cron::Schedule;
 use std::str::FromStr;
 use tokio::time;
+use super::super::scripting::ScriptEngine;
+use super::super::super::Environment;
+
+/// Marks a job `command` as a stored script to run on the scripting engine, e.g. `script:<id>`.
+pub const SCRIPT_PREFIX: &str = "script:";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum JobSchedule {
@@ -50,26 +55,25 @@ pub struct Job {
     pub dependencies: Vec<String>,
 }
 
+/// Parses a cron expression. The usual five fields get a leading seconds field, which the
+/// `cron` crate requires.
+fn cron_schedule(expr: &str) -> Option<Schedule> {
+    if expr.split_whitespace().count() == 5 {
+        Schedule::from_str(&format!("0 {}", expr)).ok()
+    } else {
+        Schedule::from_str(expr).ok()
+    }
+}
+
 impl Job {
-    pub fn new(
-        name: String,
-        command: String,
-        args: Vec<String>,
-        schedule: JobSchedule,
-        env: HashMap<String, String>,
-        working_dir: Option<String>,
-        timeout: Option<Duration>,
-        retry_count: u32,
-        retry_delay: Duration,
-        dependencies: Vec<String>,
-    ) -> Self {
+    /// A pending job with no env, working directory, timeout, retries or dependencies; set
+    /// those fields afterwards as needed.
+    pub fn new(name: String, command: String, args: Vec<String>, schedule: JobSchedule) -> Self {
         let now = Utc::now();
         let next_run = match &schedule {
             JobSchedule::Once(time) => Some(*time),
             JobSchedule::Recurring(cron_expr) => {
-                Schedule::from_str(cron_expr)
-                    .ok()
-                    .and_then(|schedule| schedule.upcoming(Utc).next())
+                cron_schedule(cron_expr).and_then(|schedule| schedule.upcoming(Utc).next())
             }
             JobSchedule::Interval(_) => Some(now),
         };
@@ -88,12 +92,12 @@ impl Job {
                 next_run,
                 run_count: 0,
             },
-            env,
-            working_dir,
-            timeout,
-            retry_count,
-            retry_delay,
-            dependencies,
+            env: HashMap::new(),
+            working_dir: None,
+            timeout: None,
+            retry_count: 0,
+            retry_delay: Duration::seconds(30),
+            dependencies: Vec::new(),
         }
     }
 
@@ -107,9 +111,7 @@ impl Job {
         self.metadata.next_run = match &self.schedule {
             JobSchedule::Once(_) => None,
             JobSchedule::Recurring(cron_expr) => {
-                Schedule::from_str(cron_expr)
-                    .ok()
-                    .and_then(|schedule| schedule.upcoming(Utc).next())
+                cron_schedule(cron_expr).and_then(|schedule| schedule.upcoming(Utc).next())
             }
             JobSchedule::Interval(duration) => {
                 Some(now + *duration)
@@ -117,6 +119,49 @@ impl Job {
         };
     }
 
+    /// The id of the stored script a `script:<id>` job runs.
+    pub fn script_id(&self) -> Option<&str> {
+        self.command.strip_prefix(SCRIPT_PREFIX)
+    }
+
+    /// Runs a `script:` job on `scripts`, passing the job's args and env. What the script prints
+    /// and returns becomes the result's output. Scripts run to completion, so neither the
+    /// timeout nor retries apply.
+    pub async fn execute_script(&mut self, scripts: Option<&ScriptEngine>, tx: mpsc::Sender<JobResult>) -> Result<()> {
+        let now = Utc::now();
+        self.metadata.last_run = Some(now);
+        self.metadata.run_count += 1;
+        self.update_status(JobStatus::Running);
+
+        let script_id = self.script_id().unwrap_or_default().to_string();
+        let mut env = Environment::new();
+        for (key, value) in &self.env {
+            env.set_var(key.clone(), value.clone());
+        }
+        let (output, outcome) = match scripts {
+            Some(scripts) => scripts.execute_captured(&script_id, &self.args, &env).await,
+            None => (String::new(), Err(anyhow::anyhow!("No scripting engine to run script {}", script_id))),
+        };
+
+        let result = JobResult {
+            job_id: self.id.clone(),
+            success: outcome.is_ok(),
+            output,
+            error: outcome.err().map(|e| e.to_string()),
+            exit_code: None,
+            started_at: Some(now),
+            completed_at: Utc::now(),
+        };
+        self.update_status(match &result.error {
+            None => JobStatus::Completed,
+            Some(error) => JobStatus::Failed(error.clone()),
+        });
+
+        self.update_next_run();
+        tx.send(result).await?;
+        Ok(())
+    }
+
     pub async fn execute(&mut self, tx: mpsc::Sender<JobResult>) -> Result<()> {
         let now = Utc::now();
         self.metadata.last_run = Some(now);
@@ -131,9 +176,39 @@ impl Job {
             command.current_dir(dir);
         }
 
+        // Dropping a timed-out `output()` future drops the child, which kills it.
+        command.kill_on_drop(true);
+        let deadline = self.timeout
+            .and_then(|timeout| timeout.to_std().ok())
+            .map(|timeout| time::Instant::now() + timeout);
+        let retry_delay = self.retry_delay.to_std().unwrap_or_default();
+        // A retry is only attempted if its delay still leaves time before the deadline.
+        let can_retry = |retry_count: u32| {
+            retry_count < self.retry_count
+                && deadline.is_none_or(|deadline| time::Instant::now() + retry_delay < deadline)
+        };
+
         let mut retry_count = 0;
         let result = loop {
-            match command.output().await {
+            let output = match deadline {
+                Some(deadline) => match time::timeout_at(deadline, command.output()).await {
+                    Ok(output) => output,
+                    Err(_) => {
+                        break JobResult {
+                            job_id: self.id.clone(),
+                            success: false,
+                            output: String::new(),
+                            error: Some("timed out".to_string()),
+                            exit_code: None,
+                            started_at: Some(now),
+                            completed_at: Utc::now(),
+                        };
+                    }
+                },
+                None => command.output().await,
+            };
+
+            match output {
                 Ok(output) => {
                     if output.status.success() {
                         break JobResult {
@@ -142,13 +217,14 @@ impl Job {
                             output: String::from_utf8_lossy(&output.stdout).to_string(),
                             error: None,
                             exit_code: output.status.code(),
+                            started_at: Some(now),
                             completed_at: Utc::now(),
                         };
                     } else {
                         let error = String::from_utf8_lossy(&output.stderr).to_string();
-                        if retry_count < self.retry_count {
+                        if can_retry(retry_count) {
                             retry_count += 1;
-                            time::sleep(self.retry_delay).await;
+                            time::sleep(retry_delay).await;
                             continue;
                         }
                         break JobResult {
@@ -157,14 +233,15 @@ impl Job {
                             output: String::from_utf8_lossy(&output.stdout).to_string(),
                             error: Some(error),
                             exit_code: output.status.code(),
+                            started_at: Some(now),
                             completed_at: Utc::now(),
                         };
                     }
                 }
                 Err(e) => {
-                    if retry_count < self.retry_count {
+                    if can_retry(retry_count) {
                         retry_count += 1;
-                        time::sleep(self.retry_delay).await;
+                        time::sleep(retry_delay).await;
                         continue;
                     }
                     break JobResult {
@@ -173,6 +250,7 @@ impl Job {
                         output: String::new(),
                         error: Some(e.to_string()),
                         exit_code: None,
+                        started_at: Some(now),
                         completed_at: Utc::now(),
                     };
                 }
@@ -182,7 +260,7 @@ impl Job {
         self.update_status(if result.success {
             JobStatus::Completed
         } else {
-            JobStatus::Failed(result.error.unwrap_or_default())
+            JobStatus::Failed(result.error.clone().unwrap_or_default())
         });
 
         self.update_next_run();
@@ -191,6 +269,56 @@ impl Job {
     }
 }
 
+/// The user-authored part of a `Job`, as written by `schedule export`. Status and run
+/// metadata are left out and regenerated when the definition is imported.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobDefinition {
+    pub id: String,
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub schedule: JobSchedule,
+    pub env: HashMap<String, String>,
+    pub working_dir: Option<String>,
+    pub timeout: Option<Duration>,
+    pub retry_count: u32,
+    pub retry_delay: Duration,
+    pub dependencies: Vec<String>,
+}
+
+impl From<&Job> for JobDefinition {
+    fn from(job: &Job) -> Self {
+        JobDefinition {
+            id: job.id.clone(),
+            name: job.name.clone(),
+            command: job.command.clone(),
+            args: job.args.clone(),
+            schedule: job.schedule.clone(),
+            env: job.env.clone(),
+            working_dir: job.working_dir.clone(),
+            timeout: job.timeout,
+            retry_count: job.retry_count,
+            retry_delay: job.retry_delay,
+            dependencies: job.dependencies.clone(),
+        }
+    }
+}
+
+impl JobDefinition {
+    pub fn into_job(self) -> Job {
+        Job {
+            id: self.id,
+            env: self.env,
+            working_dir: self.working_dir,
+            timeout: self.timeout,
+            retry_count: self.retry_count,
+            retry_delay: self.retry_delay,
+            dependencies: self.dependencies,
+            ..Job::new(self.name, self.command, self.args, self.schedule)
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JobResult {
     pub job_id: String,
@@ -198,6 +326,9 @@ pub struct JobResult {
     pub output: String,
     pub error: Option<String>,
     pub exit_code: Option<i32>,
+    /// Missing from results recorded before start times were kept
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
     pub completed_at: DateTime<Utc>,
 }
 
@@ -1,24 +1,77 @@
 mod job;
 mod queue;
+#[cfg(test)]
+mod tests;
 
 use async_trait::async_trait;
-use super::super::{Command, Environment, Plugin};
+use super::super::{Command, Completer, Environment, Plugin, PluginManager};
+use super::super::render::{OutputFormat, Page, Record};
+use super::super::timespec::{parse_absolute, parse_time};
 use anyhow::Result;
 use chrono::{DateTime, Utc, Duration};
-use job::{Job, JobSchedule, JobStatus, JobFilter};
+use job::{Job, JobDefinition, JobSchedule, JobStatus, JobFilter, SCRIPT_PREFIX};
+use super::scripting::ScriptEngine;
 use queue::{JobQueue, QueueConfig};
 use std::collections::HashMap;
+use std::sync::Weak;
 use colored::*;
-use std::str::FromStr;
 use tokio::fs;
-use std::path::PathBuf;
+use serde::Serialize;
+
+/// One row of `schedule list`.
+#[derive(Debug, Serialize)]
+struct JobRow {
+    id: String,
+    name: String,
+    status: String,
+    next_run: Option<DateTime<Utc>>,
+    last_run: Option<DateTime<Utc>>,
+}
+
+impl From<Job> for JobRow {
+    fn from(job: Job) -> Self {
+        JobRow {
+            status: match &job.status {
+                JobStatus::Pending => "pending".to_string(),
+                JobStatus::Running => "running".to_string(),
+                JobStatus::Completed => "completed".to_string(),
+                JobStatus::Failed(err) => format!("failed: {}", err),
+                JobStatus::Cancelled => "cancelled".to_string(),
+            },
+            next_run: job.metadata.next_run,
+            last_run: job.metadata.last_run,
+            id: job.id,
+            name: job.name,
+        }
+    }
+}
+
+impl Record for JobRow {
+    fn headers() -> &'static [&'static str] {
+        &["ID", "NAME", "STATUS", "NEXT RUN", "LAST RUN"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.name.clone(),
+            self.status.to_uppercase(),
+            self.next_run.map(|t| t.to_rfc3339()).unwrap_or_else(|| "N/A".to_string()),
+            self.last_run.map(|t| t.to_rfc3339()).unwrap_or_else(|| "Never".to_string()),
+        ]
+    }
+}
 
 pub struct SchedulerPlugin {
     queue: JobQueue,
+    /// The manager this plugin is registered with, for `create --verify`.
+    plugins: Weak<PluginManager>,
 }
 
 impl SchedulerPlugin {
-    pub async fn new() -> Result<Self> {
+    /// `scripts` runs `script:<id>` jobs; pass the scripting plugin's engine so jobs see the
+    /// scripts it stores. `plugins` is the manager whose commands `create --verify` accepts.
+    pub async fn new(scripts: ScriptEngine, plugins: Weak<PluginManager>) -> Result<Self> {
         let mut config_path = dirs::home_dir().unwrap_or_default();
         config_path.push(".nexusshell");
         config_path.push("scheduler");
@@ -28,8 +81,8 @@ impl SchedulerPlugin {
             ..Default::default()
         };
 
-        let queue = JobQueue::new(config).await?;
-        let scheduler = SchedulerPlugin { queue };
+        let queue = JobQueue::new(config).await?.with_scripts(scripts);
+        let scheduler = SchedulerPlugin { queue, plugins };
 
         // Start the job processing loop
         let queue_clone = scheduler.queue.clone();
@@ -41,52 +94,102 @@ impl SchedulerPlugin {
     }
 
     async fn create_job(&self, args: &[String]) -> Result<String> {
-        if args.len() < 4 {
-            return Ok("Usage: schedule create <name> <command> <schedule> [args...]".to_string());
+        let mut working_dir = None;
+        let mut run_at = None;
+        let mut job_env = HashMap::new();
+        let mut dependencies = Vec::new();
+        let mut verify = false;
+        let mut positional = Vec::new();
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--cwd" => {
+                    let dir = args.get(i + 1)
+                        .ok_or_else(|| anyhow::anyhow!("--cwd requires a directory"))?;
+                    if !fs::metadata(dir).await.map(|m| m.is_dir()).unwrap_or(false) {
+                        return Err(anyhow::anyhow!("Working directory {} does not exist", dir));
+                    }
+                    working_dir = Some(dir.clone());
+                    i += 2;
+                }
+                "--at" => {
+                    let value = args.get(i + 1)
+                        .ok_or_else(|| anyhow::anyhow!("--at requires a date and time"))?;
+                    run_at = Some(parse_run_at(value)?);
+                    i += 2;
+                }
+                "--depends-on" => {
+                    let dependency = args.get(i + 1)
+                        .ok_or_else(|| anyhow::anyhow!("--depends-on requires a job id or name"))?;
+                    dependencies.push(self.resolve_job_id(dependency).await);
+                    i += 2;
+                }
+                "--env" => {
+                    let pair = args.get(i + 1)
+                        .ok_or_else(|| anyhow::anyhow!("--env requires KEY=VALUE"))?;
+                    let (key, value) = pair.split_once('=')
+                        .filter(|(key, _)| !key.is_empty())
+                        .ok_or_else(|| anyhow::anyhow!("Invalid --env value '{}', expected KEY=VALUE", pair))?;
+                    job_env.insert(key.to_string(), value.to_string());
+                    i += 2;
+                }
+                "--verify" => {
+                    verify = true;
+                    i += 1;
+                }
+                _ => {
+                    positional.push(args[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        let args = &positional;
+
+        // `--at` takes the place of the schedule argument
+        let required = if run_at.is_some() { 3 } else { 4 };
+        if args.len() < required {
+            return Ok("Usage: schedule create <name> <command>|script:<id> <schedule>|--at <time> [--cwd <dir>] [--env KEY=VALUE]... [--depends-on <job>]... [--verify] [args...]".to_string());
         }
 
         let name = args[1].clone();
         let command = args[2].clone();
-        let schedule_str = args[3].clone();
-        let job_args = args[4..].to_vec();
-
-        let schedule = if schedule_str.starts_with("@") {
-            match schedule_str.as_str() {
-                "@once" => JobSchedule::Once(Utc::now()),
-                "@hourly" => JobSchedule::Recurring("0 * * * *".to_string()),
-                "@daily" => JobSchedule::Recurring("0 0 * * *".to_string()),
-                "@weekly" => JobSchedule::Recurring("0 0 * * 0".to_string()),
-                "@monthly" => JobSchedule::Recurring("0 0 1 * *".to_string()),
-                "@yearly" => JobSchedule::Recurring("0 0 1 1 *".to_string()),
-                _ if schedule_str.starts_with("@every") => {
-                    let duration_str = schedule_str.trim_start_matches("@every").trim();
-                    let duration = parse_duration(duration_str)?;
-                    JobSchedule::Interval(duration)
-                }
-                _ => return Ok("Invalid schedule format".to_string()),
+        let mut warnings = String::new();
+
+        if let Some(script_id) = command.strip_prefix(SCRIPT_PREFIX) {
+            let exists = match self.queue.scripts() {
+                Some(scripts) => scripts.get_script(script_id).await.is_some(),
+                None => false,
+            };
+            if !exists {
+                return Err(anyhow::anyhow!("Script {} not found", script_id));
+            }
+        } else if verify && !self.completer().is_command(&command) {
+            // Jobs inherit the process environment, so check its PATH rather than the session's
+            warnings.push_str(&format!("Warning: '{}' is not a builtin, plugin or executable on PATH, so the job will fail when it runs\n", command));
+        }
+
+        let (schedule, job_args) = if let Some(time) = run_at {
+            if time <= Utc::now() {
+                warnings.push_str(&format!("Warning: {} is in the past, so the job will run right away\n", time.to_rfc3339()));
             }
+            (JobSchedule::Once(time), args[3..].to_vec())
         } else {
-            JobSchedule::Recurring(schedule_str)
+            (parse_schedule(&args[3])?, args[4..].to_vec())
         };
 
-        let job = Job::new(
-            name,
-            command,
-            job_args,
-            schedule,
-            HashMap::new(),
-            None,
-            None,
-            3,
-            std::time::Duration::from_secs(30),
-            Vec::new(),
-        );
+        let job = Job {
+            env: job_env,
+            working_dir,
+            retry_count: 3,
+            dependencies,
+            ..Job::new(name, command, job_args, schedule)
+        };
 
         let job_id = self.queue.submit_job(job).await?;
-        Ok(format!("Created job with ID: {}", job_id))
+        Ok(format!("{}Created job with ID: {}", warnings, job_id))
     }
 
-    async fn list_jobs(&self, args: &[String]) -> Result<String> {
+    async fn list_jobs(&self, args: &[String], format: OutputFormat, page: Page) -> Result<String> {
         let mut filter = JobFilter {
             status: None,
             name: None,
@@ -98,77 +201,53 @@ impl SchedulerPlugin {
         let mut i = 1;
         while i < args.len() {
             match args[i].as_str() {
-                "--status" => {
-                    if i + 1 < args.len() {
-                        filter.status = match args[i + 1].as_str() {
-                            "pending" => Some(JobStatus::Pending),
-                            "running" => Some(JobStatus::Running),
-                            "completed" => Some(JobStatus::Completed),
-                            "cancelled" => Some(JobStatus::Cancelled),
-                            "failed" => Some(JobStatus::Failed(String::new())),
-                            _ => return Ok("Invalid status filter".to_string()),
-                        };
-                        i += 2;
-                    }
+                "--status" if i + 1 < args.len() => {
+                    filter.status = match args[i + 1].as_str() {
+                        "pending" => Some(JobStatus::Pending),
+                        "running" => Some(JobStatus::Running),
+                        "completed" => Some(JobStatus::Completed),
+                        "cancelled" => Some(JobStatus::Cancelled),
+                        "failed" => Some(JobStatus::Failed(String::new())),
+                        _ => return Ok("Invalid status filter".to_string()),
+                    };
+                    i += 2;
                 }
-                "--name" => {
-                    if i + 1 < args.len() {
-                        filter.name = Some(args[i + 1].clone());
-                        i += 2;
-                    }
+                "--name" if i + 1 < args.len() => {
+                    filter.name = Some(args[i + 1].clone());
+                    i += 2;
                 }
-                "--after" => {
-                    if i + 1 < args.len() {
-                        filter.created_after = Some(DateTime::from_str(&args[i + 1])?);
-                        i += 2;
-                    }
+                "--after" if i + 1 < args.len() => {
+                    filter.created_after = Some(parse_time(&args[i + 1])?);
+                    i += 2;
                 }
-                "--before" => {
-                    if i + 1 < args.len() {
-                        filter.created_before = Some(DateTime::from_str(&args[i + 1])?);
-                        i += 2;
-                    }
+                "--before" if i + 1 < args.len() => {
+                    filter.created_before = Some(parse_time(&args[i + 1])?);
+                    i += 2;
                 }
-                "--command" => {
-                    if i + 1 < args.len() {
-                        filter.command = Some(args[i + 1].clone());
-                        i += 2;
-                    }
+                "--command" if i + 1 < args.len() => {
+                    filter.command = Some(args[i + 1].clone());
+                    i += 2;
                 }
                 _ => i += 1,
             }
         }
 
-        let jobs = self.queue.list_jobs(Some(filter)).await;
-        if jobs.is_empty() {
+        let mut jobs = self.queue.list_jobs(Some(filter)).await;
+        if jobs.is_empty() && format == OutputFormat::Table {
             return Ok("No jobs found".to_string());
         }
-
-        let mut output = String::new();
-        output.push_str(&format!("{:<36} {:<20} {:<15} {:<20} {:<20}\n",
-            "ID", "NAME", "STATUS", "NEXT RUN", "LAST RUN"));
-
-        for job in jobs {
-            let status = match &job.status {
-                JobStatus::Pending => "PENDING".yellow(),
-                JobStatus::Running => "RUNNING".blue(),
-                JobStatus::Completed => "COMPLETED".green(),
-                JobStatus::Failed(err) => format!("FAILED: {}", err).red(),
-                JobStatus::Cancelled => "CANCELLED".red(),
-            };
-
-            let next_run = job.metadata.next_run
-                .map(|t| t.to_rfc3339())
-                .unwrap_or_else(|| "N/A".to_string());
-
-            let last_run = job.metadata.last_run
-                .map(|t| t.to_rfc3339())
-                .unwrap_or_else(|| "Never".to_string());
-
-            output.push_str(&format!("{:<36} {:<20} {:<15} {:<20} {:<20}\n",
-                job.id, job.name, status, next_run, last_run));
+        // Oldest first, so pages stay put as jobs are added
+        jobs.sort_by(|a, b| a.metadata.created_at.cmp(&b.metadata.created_at).then_with(|| a.id.cmp(&b.id)));
+
+        let total = jobs.len();
+        let window = page.window(total);
+        let rows: Vec<JobRow> = page.apply(jobs).into_iter().map(JobRow::from).collect();
+        let mut output = format.render(&rows)?;
+        if format == OutputFormat::Table {
+            if let Some(footer) = page.footer(window.start + 1, window.end, total) {
+                output.push_str(&format!("\n{}", footer));
+            }
         }
-
         Ok(output)
     }
 
@@ -177,17 +256,51 @@ impl SchedulerPlugin {
             return Ok("Usage: schedule cancel <job_id>".to_string());
         }
 
-        let job_id = &args[1];
+        let job_id = &self.resolve_job_id(&args[1]).await;
         self.queue.cancel_job(job_id).await?;
         Ok(format!("Cancelled job {}", job_id))
     }
 
+    async fn run_job_now(&self, args: &[String]) -> Result<String> {
+        if args.len() < 2 {
+            return Ok("Usage: schedule run-now <job_id>".to_string());
+        }
+
+        let job_id = &self.resolve_job_id(&args[1]).await;
+        self.queue.run_now(job_id).await?;
+        Ok(format!("Queued job {} to run now", job_id))
+    }
+
+    async fn export_jobs(&self, args: &[String]) -> Result<String> {
+        if args.len() < 2 {
+            return Ok("Usage: schedule export <file>".to_string());
+        }
+
+        let definitions = self.queue.export_jobs().await;
+        fs::write(&args[1], serde_json::to_string_pretty(&definitions)?).await?;
+        Ok(format!("Exported {} jobs to {}", definitions.len(), args[1]))
+    }
+
+    async fn import_jobs(&self, args: &[String]) -> Result<String> {
+        let overwrite = args.iter().any(|arg| arg == "--overwrite");
+        let file = match args.iter().skip(1).find(|arg| !arg.starts_with("--")) {
+            Some(file) => file,
+            None => return Ok("Usage: schedule import <file> [--overwrite]".to_string()),
+        };
+
+        let content = fs::read_to_string(file).await?;
+        let definitions: Vec<JobDefinition> = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid job export {}: {}", file, e))?;
+        let count = self.queue.import_jobs(definitions, overwrite).await?;
+        Ok(format!("Imported {} jobs from {}", count, file))
+    }
+
     async fn show_job(&self, args: &[String]) -> Result<String> {
         if args.len() < 2 {
             return Ok("Usage: schedule show <job_id>".to_string());
         }
 
-        let job_id = &args[1];
+        let job_id = &self.resolve_job_id(&args[1]).await;
         if let Some(job) = self.queue.get_job(job_id).await {
             let mut output = String::new();
             output.push_str(&format!("Job Details for {}\n", job_id.bright_green()));
@@ -207,6 +320,19 @@ impl SchedulerPlugin {
                 JobSchedule::Interval(duration) => format!("Every {:?}", duration),
             }));
 
+            if !job.dependencies.is_empty() {
+                output.push_str("Depends On:\n");
+                for dependency in &job.dependencies {
+                    match self.queue.get_job(dependency).await {
+                        Some(dependency) => {
+                            let row = JobRow::from(dependency);
+                            output.push_str(&format!("  {} {} ({})\n", row.id, row.name, row.status));
+                        }
+                        None => output.push_str(&format!("  {} (removed)\n", dependency)),
+                    }
+                }
+            }
+
             output.push_str("\nMetadata:\n");
             output.push_str(&format!("  Created: {}\n", job.metadata.created_at));
             output.push_str(&format!("  Updated: {}\n", job.metadata.updated_at));
@@ -237,14 +363,86 @@ impl SchedulerPlugin {
         }
     }
 
-    async fn cleanup_jobs(&self, args: &[String]) -> Result<String> {
-        let days = if args.len() > 1 {
-            args[1].parse().unwrap_or(30)
-        } else {
-            30
-        };
+    async fn configure(&self, args: &[String]) -> Result<String> {
+        let usage = "Usage: schedule config show | schedule config --max-concurrent <n>";
+        match args.get(1).map(|s| s.as_str()) {
+            Some("show") => Ok(format!(
+                "{:<16} {}\n{:<16} {}\n",
+                "max-concurrent:", self.queue.max_concurrent_jobs(),
+                "storage:", self.queue.storage_path().display()
+            )),
+            Some("--max-concurrent") => {
+                let max = args.get(2)
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .ok_or_else(|| anyhow::anyhow!("--max-concurrent requires a positive number"))?;
+                self.queue.set_max_concurrent_jobs(max).await?;
+                Ok(format!("Scheduler will run at most {} jobs at once", max))
+            }
+            _ => Ok(usage.to_string()),
+        }
+    }
+
+    async fn show_stats(&self) -> Result<String> {
+        let stats = self.queue.stats().await;
+        let mut output = String::new();
+        output.push_str(&format!("{:<12} {}\n", "Pending:", stats.pending));
+        output.push_str(&format!("{:<12} {}\n", "Running:", stats.running));
+        output.push_str(&format!("{:<12} {}\n", "Completed:", stats.completed));
+        output.push_str(&format!("{:<12} {}\n", "Failed:", stats.failed));
+        output.push_str(&format!("{:<12} {}\n", "Cancelled:", stats.cancelled));
+
+        output.push_str(&format!("\nAverage duration: {}\n", stats.average_duration
+            .and_then(|duration| duration.to_std().ok())
+            .map(|duration| humantime::format_duration(std::time::Duration::from_millis(duration.as_millis() as u64)).to_string())
+            .unwrap_or_else(|| "N/A".to_string())));
+        output.push_str(&format!("Success rate: {}\n", stats.success_rate
+            .map(|rate| format!("{:.1}%", rate * 100.0))
+            .unwrap_or_else(|| "N/A".to_string())));
+        output.push_str(&format!("Busiest job: {}\n", stats.busiest
+            .map(|(name, runs)| format!("{} ({} runs)", name, runs))
+            .unwrap_or_else(|| "N/A".to_string())));
+
+        Ok(output)
+    }
+
+    fn completer(&self) -> Completer {
+        let completer = Completer::new(Environment::new());
+        match self.plugins.upgrade() {
+            Some(plugins) => completer.with_plugins(plugins),
+            None => completer,
+        }
+    }
+
+    async fn resolve_job_id(&self, id_or_name: &str) -> String {
+        if self.queue.get_job(id_or_name).await.is_some() {
+            return id_or_name.to_string();
+        }
+
+        self.queue.list_jobs(None).await
+            .into_iter()
+            .find(|job| job.name == id_or_name)
+            .map(|job| job.id)
+            .unwrap_or_else(|| id_or_name.to_string())
+    }
+
+    async fn cleanup_jobs(&self, args: &[String], dry_run: bool) -> Result<String> {
+        let days = args.iter()
+            .skip(1)
+            .find(|arg| !arg.starts_with("--"))
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or(30);
 
         let older_than = Utc::now() - Duration::days(days);
+
+        if dry_run {
+            let jobs = self.queue.find_old_jobs(older_than).await;
+            let mut output = format!("Would clean up {} old jobs\n", jobs.len());
+            for job in jobs {
+                output.push_str(&format!("  {} {}\n", job.id, job.name));
+            }
+            return Ok(output);
+        }
+
         let count = self.queue.cleanup_old_jobs(older_than).await?;
         Ok(format!("Cleaned up {} old jobs", count))
     }
@@ -262,22 +460,74 @@ impl Plugin for SchedulerPlugin {
 
     async fn execute(&self, command: &Command, _env: &Environment) -> Result<String> {
         match command.args.first().map(|s| s.as_str()) {
-            Some("create") => self.create_job(&command.args).await,
-            Some("list") => self.list_jobs(&command.args).await,
+            // `--cwd` and friends take values, so read them as typed rather than as parsed flags
+            Some("create") => self.create_job(&command.raw_args()).await,
+            Some("list") => self.list_jobs(&command.raw_args(), OutputFormat::from_command(command)?, Page::from_command(command)?).await,
             Some("cancel") => self.cancel_job(&command.args).await,
             Some("show") => self.show_job(&command.args).await,
-            Some("cleanup") => self.cleanup_jobs(&command.args).await,
-            _ => Ok("Available commands: create, list, cancel, show, cleanup".to_string()),
+            Some("cleanup") => self.cleanup_jobs(&command.args, command.flags.contains_key("dry-run")).await,
+            Some("run-now") => self.run_job_now(&command.args).await,
+            Some("export") => self.export_jobs(&command.args).await,
+            Some("import") => self.import_jobs(&command.raw_args()).await,
+            Some("stats") => self.show_stats().await,
+            Some("config") => self.configure(&command.raw_args()).await,
+            _ => Ok("Available commands: create, list, cancel, show, cleanup, run-now, export, import, stats, config".to_string()),
+        }
+    }
+
+    fn complete(&self, args: &[String], partial: &str) -> Vec<String> {
+        match args.first().map(|s| s.as_str()) {
+            Some("show") | Some("cancel") | Some("run-now") if args.len() == 1 => {
+                let mut completions: Vec<String> = self.queue.try_list_jobs()
+                    .into_iter()
+                    .flat_map(|job| vec![job.id, job.name])
+                    .filter(|candidate| candidate.starts_with(partial))
+                    .collect();
+                completions.sort();
+                completions.dedup();
+                completions
+            }
+            _ => Vec::new(),
         }
     }
 }
 
+/// Parses the `@once`/`@daily`/`@every 5m`-style shorthands or a cron expression.
+fn parse_schedule(schedule_str: &str) -> Result<JobSchedule> {
+    let schedule_str = schedule_str.to_string();
+    Ok(if schedule_str.starts_with("@") {
+        match schedule_str.as_str() {
+            "@once" => JobSchedule::Once(Utc::now()),
+            "@hourly" => JobSchedule::Recurring("0 * * * *".to_string()),
+            "@daily" => JobSchedule::Recurring("0 0 * * *".to_string()),
+            "@weekly" => JobSchedule::Recurring("0 0 * * Sun".to_string()),
+            "@monthly" => JobSchedule::Recurring("0 0 1 * *".to_string()),
+            "@yearly" => JobSchedule::Recurring("0 0 1 1 *".to_string()),
+            _ if schedule_str.starts_with("@every") => {
+                let duration_str = schedule_str.trim_start_matches("@every").trim();
+                let duration = parse_duration(duration_str)?;
+                JobSchedule::Interval(duration)
+            }
+            _ => return Err(anyhow::anyhow!("Invalid schedule format")),
+        }
+    } else {
+        JobSchedule::Recurring(schedule_str)
+    })
+}
+
+/// Parses `--at` times: RFC 3339 (`2025-01-01T09:00:00Z`), or `YYYY-MM-DD[ HH:MM[:SS]]`
+/// (also with a `T`) in local time.
+fn parse_run_at(value: &str) -> Result<DateTime<Utc>> {
+    parse_absolute(value)
+        .ok_or_else(|| anyhow::anyhow!("Invalid --at time '{}', expected e.g. 2025-01-01T09:00:00Z or 2025-01-01 09:00", value))
+}
+
 fn parse_duration(duration_str: &str) -> Result<Duration> {
     let mut total_seconds = 0i64;
     let mut current_number = String::new();
 
     for c in duration_str.chars() {
-        if c.is_digit(10) {
+        if c.is_ascii_digit() {
             current_number.push(c);
         } else {
             let number = current_number.parse::<i64>().unwrap_or(0);
@@ -1,16 +1,17 @@
-use super::job::{Job, JobResult, JobStatus, JobFilter};
+use super::job::{Job, JobDefinition, JobResult, JobStatus, JobFilter};
 use tokio::sync::{mpsc, RwLock};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use anyhow::Result;
 use tokio::time::{self, Duration};
 use chrono::{DateTime, Utc};
-use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
 use tokio::fs;
 use std::path::PathBuf;
+use super::super::scripting::ScriptEngine;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueueConfig {
     pub max_concurrent_jobs: usize,
     pub max_retries: u32,
@@ -29,15 +30,45 @@ impl Default for QueueConfig {
     }
 }
 
-#[derive(Debug)]
+/// Summary numbers reported by `schedule stats`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueueStats {
+    pub pending: usize,
+    pub running: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    /// Mean run time of results that recorded a start time
+    pub average_duration: Option<chrono::Duration>,
+    /// Fraction of recorded results that succeeded
+    pub success_rate: Option<f64>,
+    /// Name and run count of the job that has run most often
+    pub busiest: Option<(String, u64)>,
+}
+
+/// Settings changed at runtime with `schedule config`, kept in `settings.json`.
+#[derive(Debug, Serialize, Deserialize)]
+struct QueueSettings {
+    max_concurrent_jobs: usize,
+}
+
+#[derive(Debug, Clone)]
 pub struct JobQueue {
     jobs: Arc<RwLock<HashMap<String, Job>>>,
     pending: Arc<RwLock<VecDeque<String>>>,
     running: Arc<RwLock<HashSet<String>>>,
+    /// Jobs queued by `run_now`, started ahead of `pending` regardless of `next_run`.
+    immediate: Arc<RwLock<VecDeque<String>>>,
+    /// Running jobs that were started by `run_now` and must not be rescheduled on completion.
+    manual_runs: Arc<RwLock<HashSet<String>>>,
     completed: Arc<RwLock<Vec<JobResult>>>,
+    /// The live concurrency cap; starts as `config.max_concurrent_jobs`
+    max_concurrent_jobs: Arc<AtomicUsize>,
     config: QueueConfig,
     tx: mpsc::Sender<JobResult>,
     rx: Arc<RwLock<mpsc::Receiver<JobResult>>>,
+    /// Runs `script:` jobs; without it they fail
+    scripts: Option<ScriptEngine>,
 }
 
 impl JobQueue {
@@ -47,10 +78,14 @@ impl JobQueue {
             jobs: Arc::new(RwLock::new(HashMap::new())),
             pending: Arc::new(RwLock::new(VecDeque::new())),
             running: Arc::new(RwLock::new(HashSet::new())),
+            immediate: Arc::new(RwLock::new(VecDeque::new())),
+            manual_runs: Arc::new(RwLock::new(HashSet::new())),
             completed: Arc::new(RwLock::new(Vec::new())),
+            max_concurrent_jobs: Arc::new(AtomicUsize::new(config.max_concurrent_jobs)),
             config,
             tx,
             rx: Arc::new(RwLock::new(rx)),
+            scripts: None,
         };
 
         queue.load_state().await?;
@@ -88,9 +123,48 @@ impl JobQueue {
             *completed = stored_completed;
         }
 
+        let settings_path = self.config.storage_path.join("settings.json");
+        if settings_path.exists() {
+            let content = fs::read_to_string(&settings_path).await?;
+            let settings: QueueSettings = serde_json::from_str(&content)?;
+            self.max_concurrent_jobs.store(settings.max_concurrent_jobs, Ordering::SeqCst);
+        }
+
         Ok(())
     }
 
+    /// Runs `script:<id>` jobs on `scripts`.
+    pub fn with_scripts(mut self, scripts: ScriptEngine) -> Self {
+        self.scripts = Some(scripts);
+        self
+    }
+
+    pub fn scripts(&self) -> Option<&ScriptEngine> {
+        self.scripts.as_ref()
+    }
+
+    pub fn max_concurrent_jobs(&self) -> usize {
+        self.max_concurrent_jobs.load(Ordering::SeqCst)
+    }
+
+    /// Changes the concurrency cap from the next scheduling pass on. Jobs already running
+    /// are left to finish.
+    pub async fn set_max_concurrent_jobs(&self, max: usize) -> Result<()> {
+        if max == 0 {
+            return Err(anyhow::anyhow!("The concurrency cap must be at least 1"));
+        }
+        self.max_concurrent_jobs.store(max, Ordering::SeqCst);
+
+        fs::create_dir_all(&self.config.storage_path).await?;
+        let settings = QueueSettings { max_concurrent_jobs: max };
+        fs::write(self.config.storage_path.join("settings.json"), serde_json::to_string_pretty(&settings)?).await?;
+        Ok(())
+    }
+
+    pub fn storage_path(&self) -> &std::path::Path {
+        &self.config.storage_path
+    }
+
     async fn save_state(&self) -> Result<()> {
         let jobs = self.jobs.read().await;
         let pending = self.pending.read().await;
@@ -110,14 +184,19 @@ impl JobQueue {
         Ok(())
     }
 
+    /// Adds a job to the queue. Its dependencies must name existing jobs and must not lead
+    /// back to the job itself.
     pub async fn submit_job(&self, job: Job) -> Result<String> {
         let job_id = job.id.clone();
         let mut jobs = self.jobs.write().await;
+        check_dependencies(&jobs, &job)?;
         let mut pending = self.pending.write().await;
 
         jobs.insert(job_id.clone(), job);
         pending.push_back(job_id.clone());
-        
+        drop(pending);
+        drop(jobs);
+
         self.save_state().await?;
         Ok(job_id)
     }
@@ -132,6 +211,9 @@ impl JobQueue {
             pending.retain(|id| id != job_id);
             running.remove(job_id);
         }
+        drop(running);
+        drop(pending);
+        drop(jobs);
 
         self.save_state().await?;
         Ok(())
@@ -156,6 +238,13 @@ impl JobQueue {
             .collect()
     }
 
+    pub fn try_list_jobs(&self) -> Vec<Job> {
+        self.jobs
+            .try_read()
+            .map(|jobs| jobs.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
     pub async fn get_job_result(&self, job_id: &str) -> Option<JobResult> {
         let completed = self.completed.read().await;
         completed.iter()
@@ -163,17 +252,86 @@ impl JobQueue {
             .cloned()
     }
 
+    pub async fn stats(&self) -> QueueStats {
+        let jobs = self.jobs.read().await;
+        let running = self.running.read().await;
+        let completed = self.completed.read().await;
+
+        let mut stats = QueueStats::default();
+        for (id, job) in jobs.iter() {
+            // Runs happen on a clone, so the stored job only learns it ran once it finishes
+            if running.contains(id) {
+                stats.running += 1;
+                continue;
+            }
+            match job.status {
+                JobStatus::Pending => stats.pending += 1,
+                JobStatus::Running => stats.running += 1,
+                JobStatus::Completed => stats.completed += 1,
+                JobStatus::Failed(_) => stats.failed += 1,
+                JobStatus::Cancelled => stats.cancelled += 1,
+            }
+        }
+
+        let durations: Vec<chrono::Duration> = completed.iter()
+            .filter_map(|result| result.started_at.map(|started| result.completed_at - started))
+            .collect();
+        if !durations.is_empty() {
+            let total = durations.iter().fold(chrono::Duration::zero(), |total, d| total + *d);
+            stats.average_duration = Some(total / durations.len() as i32);
+        }
+        if !completed.is_empty() {
+            let succeeded = completed.iter().filter(|result| result.success).count();
+            stats.success_rate = Some(succeeded as f64 / completed.len() as f64);
+        }
+
+        stats.busiest = jobs.values()
+            .filter(|job| job.metadata.run_count > 0)
+            .max_by(|a, b| a.metadata.run_count.cmp(&b.metadata.run_count).then_with(|| b.name.cmp(&a.name)))
+            .map(|job| (job.name.clone(), job.metadata.run_count));
+
+        stats
+    }
+
+    /// Queues `job_id` to start on the next tick, ignoring its `next_run` but still
+    /// subject to the concurrency limit. Its regular schedule is left as is.
+    pub async fn run_now(&self, job_id: &str) -> Result<()> {
+        {
+            let jobs = self.jobs.read().await;
+            let job = jobs.get(job_id)
+                .ok_or_else(|| anyhow::anyhow!("Job {} not found", job_id))?;
+            if matches!(job.status, JobStatus::Cancelled) {
+                return Err(anyhow::anyhow!("Job {} is cancelled", job_id));
+            }
+        }
+        if self.running.read().await.contains(job_id) {
+            return Err(anyhow::anyhow!("Job {} is already running", job_id));
+        }
+
+        let mut immediate = self.immediate.write().await;
+        if !immediate.iter().any(|id| id == job_id) {
+            immediate.push_back(job_id.to_string());
+        }
+        Ok(())
+    }
+
     pub async fn process_jobs(&self) {
         loop {
-            self.check_and_start_jobs().await;
-            self.process_completed_jobs().await;
+            self.tick().await;
             time::sleep(Duration::from_secs(1)).await;
         }
     }
 
+    /// One scheduling pass: start whatever is due, then collect finished results.
+    pub async fn tick(&self) {
+        self.check_and_start_jobs().await;
+        self.process_completed_jobs().await;
+    }
+
     async fn check_and_start_jobs(&self) {
+        let max_concurrent_jobs = self.max_concurrent_jobs();
         let running_count = self.running.read().await.len();
-        if running_count >= self.config.max_concurrent_jobs {
+        if running_count >= max_concurrent_jobs {
             return;
         }
 
@@ -181,9 +339,30 @@ impl JobQueue {
         let mut running = self.running.write().await;
         let mut jobs = self.jobs.write().await;
 
-        while running.len() < self.config.max_concurrent_jobs {
+        let mut immediate = self.immediate.write().await;
+        while running.len() < max_concurrent_jobs {
+            let job_id = match immediate.pop_front() {
+                Some(job_id) => job_id,
+                None => break,
+            };
+            if running.contains(&job_id) {
+                continue;
+            }
+            if let Some(job) = jobs.get_mut(&job_id) {
+                job.update_status(JobStatus::Running);
+                self.start_job(job);
+                self.manual_runs.write().await.insert(job_id.clone());
+                running.insert(job_id);
+            }
+        }
+        drop(immediate);
+
+        // Each pending job is looked at once per pass; jobs that aren't due go to the back.
+        let mut remaining = pending.len();
+        while remaining > 0 && running.len() < max_concurrent_jobs {
+            remaining -= 1;
             if let Some(job_id) = pending.pop_front() {
-                if let Some(job) = jobs.get_mut(&job_id) {
+                if let Some(job) = jobs.get(&job_id) {
                     let now = Utc::now();
                     if let Some(next_run) = job.metadata.next_run {
                         if next_run > now {
@@ -205,26 +384,45 @@ impl JobQueue {
                         continue;
                     }
 
-                    let tx = self.tx.clone();
-                    let mut job_clone = job.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = job_clone.execute(tx).await {
-                            eprintln!("Job execution error: {}", e);
-                        }
-                    });
+                    if running.contains(&job_id) {
+                        pending.push_back(job_id);
+                        continue;
+                    }
 
+                    if let Some(job) = jobs.get_mut(&job_id) {
+                        job.update_status(JobStatus::Running);
+                        self.start_job(job);
+                    }
                     running.insert(job_id);
                 }
             } else {
                 break;
             }
         }
+        drop(jobs);
+        drop(running);
+        drop(pending);
 
         self.save_state().await.unwrap_or_else(|e| {
             eprintln!("Error saving queue state: {}", e);
         });
     }
 
+    fn start_job(&self, job: &Job) {
+        let tx = self.tx.clone();
+        let mut job_clone = job.clone();
+        let scripts = self.scripts.clone();
+        tokio::spawn(async move {
+            let outcome = match job_clone.script_id() {
+                Some(_) => job_clone.execute_script(scripts.as_ref(), tx).await,
+                None => job_clone.execute(tx).await,
+            };
+            if let Err(e) = outcome {
+                eprintln!("Job execution error: {}", e);
+            }
+        });
+    }
+
     async fn process_completed_jobs(&self) {
         let mut rx = self.rx.write().await;
         while let Ok(result) = rx.try_recv() {
@@ -234,25 +432,94 @@ impl JobQueue {
             let mut pending = self.pending.write().await;
 
             running.remove(&result.job_id);
+            let manual = self.manual_runs.write().await.remove(&result.job_id);
             if let Some(job) = jobs.get_mut(&result.job_id) {
+                // The run itself happened on a clone; record it on the stored job
+                job.metadata.last_run = Some(result.completed_at);
+                job.metadata.run_count += 1;
+                job.update_status(if result.success {
+                    JobStatus::Completed
+                } else {
+                    JobStatus::Failed(result.error.clone().unwrap_or_default())
+                });
+
                 match job.schedule {
+                    _ if manual => {
+                        // Triggered by run_now; the job keeps its place in the schedule
+                    }
                     super::job::JobSchedule::Once(_) => {
                         // Job is done, no need to reschedule
                     }
                     super::job::JobSchedule::Recurring(_) | super::job::JobSchedule::Interval(_) => {
                         job.update_next_run();
-                        pending.push_back(result.job_id.clone());
+                        if !pending.contains(&result.job_id) {
+                            pending.push_back(result.job_id.clone());
+                        }
                     }
                 }
             }
 
             completed.push(result);
+            drop(pending);
+            drop(completed);
+            drop(jobs);
+            drop(running);
             self.save_state().await.unwrap_or_else(|e| {
                 eprintln!("Error saving queue state: {}", e);
             });
         }
     }
 
+    /// Definitions of every job, ordered by name, without their runtime state.
+    pub async fn export_jobs(&self) -> Vec<JobDefinition> {
+        let jobs = self.jobs.read().await;
+        let mut definitions: Vec<JobDefinition> = jobs.values().map(JobDefinition::from).collect();
+        definitions.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        definitions
+    }
+
+    /// Adds exported jobs as fresh pending jobs. Ids that already exist are replaced when
+    /// `overwrite` is set; otherwise nothing is imported.
+    pub async fn import_jobs(&self, definitions: Vec<JobDefinition>, overwrite: bool) -> Result<usize> {
+        let mut jobs = self.jobs.write().await;
+        let mut pending = self.pending.write().await;
+
+        if !overwrite {
+            let collisions: Vec<&str> = definitions.iter()
+                .filter(|definition| jobs.contains_key(&definition.id))
+                .map(|definition| definition.id.as_str())
+                .collect();
+            if !collisions.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Jobs already exist: {} (use --overwrite to replace them)",
+                    collisions.join(", ")
+                ));
+            }
+        }
+
+        let count = definitions.len();
+        for definition in definitions {
+            let job = definition.into_job();
+            if !pending.contains(&job.id) {
+                pending.push_back(job.id.clone());
+            }
+            jobs.insert(job.id.clone(), job);
+        }
+        drop(pending);
+        drop(jobs);
+
+        self.save_state().await?;
+        Ok(count)
+    }
+
+    pub async fn find_old_jobs(&self, older_than: DateTime<Utc>) -> Vec<Job> {
+        let jobs = self.jobs.read().await;
+        jobs.values()
+            .filter(|job| is_expired(job, older_than))
+            .cloned()
+            .collect()
+    }
+
     pub async fn cleanup_old_jobs(&self, older_than: DateTime<Utc>) -> Result<usize> {
         let mut jobs = self.jobs.write().await;
         let mut completed = self.completed.write().await;
@@ -260,30 +527,48 @@ impl JobQueue {
 
         // Remove old completed jobs
         jobs.retain(|_, job| {
-            if matches!(job.status, JobStatus::Completed | JobStatus::Failed(_)) {
-                if let Some(last_run) = job.metadata.last_run {
-                    if last_run < older_than {
-                        count += 1;
-                        return false;
-                    }
-                }
+            if is_expired(job, older_than) {
+                count += 1;
+                return false;
             }
             true
         });
 
         // Remove old job results
         completed.retain(|result| result.completed_at >= older_than);
+        drop(completed);
+        drop(jobs);
 
         self.save_state().await?;
         Ok(count)
     }
 }
 
-#[async_trait]
-pub trait QueueManager: Send + Sync {
-    async fn submit_job(&self, job: Job) -> Result<String>;
-    async fn cancel_job(&self, job_id: &str) -> Result<()>;
-    async fn get_job(&self, job_id: &str) -> Option<Job>;
-    async fn list_jobs(&self, filter: Option<JobFilter>) -> Vec<Job>;
-    async fn get_job_result(&self, job_id: &str) -> Option<JobResult>;
+/// Fails if one of `job`'s dependencies doesn't exist or depends, directly or through other
+/// jobs, on `job` itself.
+fn check_dependencies(jobs: &HashMap<String, Job>, job: &Job) -> Result<()> {
+    for dependency in &job.dependencies {
+        if dependency != &job.id && !jobs.contains_key(dependency) {
+            return Err(anyhow::anyhow!("Job {} depends on unknown job {}", job.name, dependency));
+        }
+    }
+
+    let mut visited = HashSet::new();
+    let mut stack: Vec<&String> = job.dependencies.iter().collect();
+    while let Some(id) = stack.pop() {
+        if id == &job.id {
+            return Err(anyhow::anyhow!("Dependency cycle: job {} would end up depending on itself", job.name));
+        }
+        if visited.insert(id) {
+            if let Some(dependency) = jobs.get(id) {
+                stack.extend(&dependency.dependencies);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_expired(job: &Job, older_than: DateTime<Utc>) -> bool {
+    matches!(job.status, JobStatus::Completed | JobStatus::Failed(_))
+        && job.metadata.last_run.is_some_and(|last_run| last_run < older_than)
 }
@@ -0,0 +1,493 @@
+use super::*;
+use super::super::LazyPlugin;
+use crate::shell::{Completer, Parser, PluginManager};
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+use chrono::Timelike;
+use tempfile::tempdir;
+
+async fn test_scheduler(storage_path: PathBuf) -> SchedulerPlugin {
+    let config = QueueConfig {
+        storage_path,
+        ..Default::default()
+    };
+    SchedulerPlugin {
+        queue: JobQueue::new(config).await.unwrap(),
+        plugins: Weak::new(),
+    }
+}
+
+fn test_job(name: &str) -> Job {
+    Job::new(
+        name.to_string(),
+        "echo".to_string(),
+        vec!["hello".to_string()],
+        JobSchedule::Recurring("0 0 * * *".to_string()),
+    )
+}
+
+// Completion blocks on the runtime to build the scheduler, which needs worker threads
+#[tokio::test(flavor = "multi_thread")]
+async fn test_complete_cancel_lists_job_ids() {
+    let temp_dir = tempdir().unwrap();
+    let storage_path = temp_dir.path().to_path_buf();
+    let job_id = test_scheduler(storage_path.clone()).await
+        .queue.submit_job(test_job("nightly-backup")).await.unwrap();
+
+    // Registered unbuilt like the shell's own `schedule`, but on the test's job store
+    let plugin_manager = PluginManager::new();
+    plugin_manager.register_plugin(Box::new(LazyPlugin::new("jobs-test", "Test scheduler", &["jobs-test"], move || {
+        let storage_path = storage_path.clone();
+        async move { Ok(test_scheduler(storage_path).await) }
+    }))).unwrap();
+    let completer = Completer::new(Environment::new()).with_plugins(plugin_manager);
+
+    let completions = completer.complete("jobs-test cancel ");
+    assert!(completions.contains(&job_id));
+    assert!(completions.contains(&"nightly-backup".to_string()));
+
+    let completions = completer.complete("jobs-test cancel nightly");
+    assert_eq!(completions, vec!["nightly-backup".to_string()]);
+
+    assert!(completer.complete("jobs-test list ").is_empty());
+}
+
+#[tokio::test]
+async fn test_cleanup_dry_run_reports_without_removing() {
+    let temp_dir = tempdir().unwrap();
+    let scheduler = test_scheduler(temp_dir.path().to_path_buf()).await;
+
+    let mut old_job = test_job("old-report");
+    old_job.status = JobStatus::Completed;
+    old_job.metadata.last_run = Some(Utc::now() - Duration::days(60));
+    let old_id = scheduler.queue.submit_job(old_job).await.unwrap();
+    let fresh_id = scheduler.queue.submit_job(test_job("fresh-report")).await.unwrap();
+
+    // The day count after --dry-run is still the cutoff, not the flag's value
+    let command = Parser::new().parse("schedule cleanup --dry-run 30").unwrap();
+    let result = scheduler.execute(&command, &Environment::new()).await.unwrap();
+    assert!(result.contains("Would clean up 1 old jobs"));
+    assert!(result.contains(&old_id));
+    assert!(!result.contains(&fresh_id));
+
+    assert!(scheduler.queue.get_job(&old_id).await.is_some());
+    assert!(scheduler.queue.get_job(&fresh_id).await.is_some());
+}
+
+#[tokio::test]
+async fn test_run_now_ignores_future_next_run() {
+    let temp_dir = tempdir().unwrap();
+    let scheduler = test_scheduler(temp_dir.path().to_path_buf()).await;
+    let job_id = scheduler.queue.submit_job(test_job("nightly-report")).await.unwrap();
+    let next_run = scheduler.queue.get_job(&job_id).await.unwrap().metadata.next_run;
+    assert!(next_run.unwrap() > Utc::now());
+
+    // Not due, so a regular tick leaves it alone
+    scheduler.queue.tick().await;
+    assert_eq!(scheduler.queue.get_job(&job_id).await.unwrap().metadata.run_count, 0);
+
+    let result = scheduler.run_job_now(&["run-now".to_string(), "nightly-report".to_string()]).await.unwrap();
+    assert!(result.contains(&job_id));
+
+    for _ in 0..50 {
+        scheduler.queue.tick().await;
+        if scheduler.queue.get_job_result(&job_id).await.is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    let job = scheduler.queue.get_job(&job_id).await.unwrap();
+    assert_eq!(job.metadata.run_count, 1);
+    assert!(job.metadata.last_run.is_some());
+    assert_eq!(job.metadata.next_run, next_run);
+    assert!(scheduler.queue.get_job_result(&job_id).await.unwrap().success);
+}
+
+#[tokio::test]
+async fn test_create_with_cwd_and_env() {
+    let temp_dir = tempdir().unwrap();
+    let scheduler = test_scheduler(temp_dir.path().join("state")).await;
+    let cwd = temp_dir.path().to_string_lossy().to_string();
+
+    let env = Environment::new();
+
+    // Both `--env` pairs survive, though the parser keeps only one value per flag
+    let line = format!("schedule create report --cwd {} --env MODE=full make @daily --env TARGET=docs/out docs", cwd);
+    let output = scheduler.execute(&Parser::new().parse(&line).unwrap(), &env).await.unwrap();
+    let job_id = output.trim_start_matches("Created job with ID: ");
+
+    let job = scheduler.queue.get_job(job_id).await.unwrap();
+    assert_eq!(job.working_dir.as_deref(), Some(cwd.as_str()));
+    assert_eq!(job.env.get("MODE").map(|s| s.as_str()), Some("full"));
+    assert_eq!(job.env.get("TARGET").map(|s| s.as_str()), Some("docs/out"));
+    assert_eq!(job.command, "make");
+    assert_eq!(job.args, vec!["docs".to_string()]);
+
+    let missing = temp_dir.path().join("missing").to_string_lossy().to_string();
+    let line = format!("schedule create bad --cwd {} make @daily", missing);
+    assert!(scheduler.execute(&Parser::new().parse(&line).unwrap(), &env).await.is_err());
+}
+
+#[tokio::test]
+async fn test_timeout_kills_running_job() {
+    let mut job = Job {
+        timeout: Some(Duration::seconds(1)),
+        retry_count: 3,
+        retry_delay: Duration::seconds(1),
+        ..Job::new("slow".to_string(), "sleep".to_string(), vec!["10".to_string()], JobSchedule::Once(Utc::now()))
+    };
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+
+    let started = std::time::Instant::now();
+    job.execute(tx).await.unwrap();
+    assert!(started.elapsed() < std::time::Duration::from_secs(3));
+
+    let result = rx.recv().await.unwrap();
+    assert!(!result.success);
+    assert_eq!(result.error.as_deref(), Some("timed out"));
+    assert!(matches!(job.status, JobStatus::Failed(ref reason) if reason == "timed out"));
+}
+
+#[tokio::test]
+async fn test_export_import_round_trip() {
+    let temp_dir = tempdir().unwrap();
+    let source = test_scheduler(temp_dir.path().join("source")).await;
+    let mut job = test_job("backup");
+    job.env.insert("TARGET".to_string(), "/srv".to_string());
+    job.status = JobStatus::Completed;
+    job.metadata.run_count = 7;
+    let job_id = source.queue.submit_job(job).await.unwrap();
+    source.queue.submit_job(test_job("report")).await.unwrap();
+
+    let file = temp_dir.path().join("jobs.json").to_string_lossy().to_string();
+    let output = source.export_jobs(&["export".to_string(), file.clone()]).await.unwrap();
+    assert_eq!(output, format!("Exported 2 jobs to {}", file));
+    let exported = std::fs::read_to_string(&file).unwrap();
+    assert!(!exported.contains("run_count"));
+
+    let target = test_scheduler(temp_dir.path().join("target")).await;
+    let import = vec!["import".to_string(), file.clone()];
+    target.import_jobs(&import).await.unwrap();
+
+    let imported = target.queue.get_job(&job_id).await.unwrap();
+    assert_eq!(imported.name, "backup");
+    assert_eq!(imported.command, "echo");
+    assert_eq!(imported.args, vec!["hello".to_string()]);
+    assert_eq!(imported.env.get("TARGET").map(|s| s.as_str()), Some("/srv"));
+    assert!(matches!(imported.status, JobStatus::Pending));
+    assert_eq!(imported.metadata.run_count, 0);
+    assert_eq!(
+        serde_json::to_value(target.queue.export_jobs().await).unwrap(),
+        serde_json::to_value(source.queue.export_jobs().await).unwrap()
+    );
+
+    assert!(target.import_jobs(&import).await.is_err());
+    // `--overwrite` is a switch, not the flag value that takes the file
+    let overwrite = Parser::new().parse(&format!("schedule import --overwrite {}", file)).unwrap();
+    target.execute(&overwrite, &Environment::new()).await.unwrap();
+    assert_eq!(target.queue.list_jobs(None).await.len(), 2);
+}
+
+#[tokio::test]
+async fn test_stats_summarize_queue() {
+    let temp_dir = tempdir().unwrap();
+    let started = Utc::now() - Duration::hours(1);
+    let result = |job_id: &str, success: bool, seconds: i64| job::JobResult {
+        job_id: job_id.to_string(),
+        success,
+        output: String::new(),
+        error: None,
+        exit_code: Some(if success { 0 } else { 1 }),
+        started_at: Some(started),
+        completed_at: started + Duration::seconds(seconds),
+    };
+    let mut legacy = result("legacy", true, 0);
+    legacy.started_at = None;
+    let completed = vec![result("a", true, 2), result("a", true, 4), result("b", false, 6), legacy];
+    std::fs::write(temp_dir.path().join("completed.json"), serde_json::to_string(&completed).unwrap()).unwrap();
+
+    let scheduler = test_scheduler(temp_dir.path().to_path_buf()).await;
+    let seed = |name: &str, status: JobStatus, runs: u64| {
+        let mut job = test_job(name);
+        job.status = status;
+        job.metadata.run_count = runs;
+        job
+    };
+    for job in [
+        seed("pending", JobStatus::Pending, 0),
+        seed("nightly", JobStatus::Completed, 12),
+        seed("hourly", JobStatus::Completed, 5),
+        seed("broken", JobStatus::Failed("exit 1".to_string()), 3),
+        seed("stopped", JobStatus::Cancelled, 1),
+    ] {
+        scheduler.queue.submit_job(job).await.unwrap();
+    }
+
+    let stats = scheduler.queue.stats().await;
+    assert_eq!((stats.pending, stats.running, stats.completed, stats.failed, stats.cancelled), (1, 0, 2, 1, 1));
+    assert_eq!(stats.average_duration, Some(Duration::seconds(4)));
+    assert_eq!(stats.success_rate, Some(0.75));
+    assert_eq!(stats.busiest, Some(("nightly".to_string(), 12)));
+
+    let output = scheduler.show_stats().await.unwrap();
+    assert!(output.contains("Success rate: 75.0%"));
+    assert!(output.contains("Average duration: 4s"));
+    assert!(output.contains("Busiest job: nightly (12 runs)"));
+}
+
+#[tokio::test]
+async fn test_list_renders_output_formats() {
+    let temp_dir = tempdir().unwrap();
+    let scheduler = test_scheduler(temp_dir.path().to_path_buf()).await;
+    let job_id = scheduler.queue.submit_job(test_job("nightly-backup")).await.unwrap();
+    let args = vec!["list".to_string()];
+
+    let table = scheduler.list_jobs(&args, OutputFormat::Table, Page::default()).await.unwrap();
+    assert!(table.starts_with("ID"));
+    assert!(table.contains("PENDING"));
+
+    let json: serde_json::Value = serde_json::from_str(&scheduler.list_jobs(&args, OutputFormat::Json, Page::default()).await.unwrap()).unwrap();
+    assert_eq!(json[0]["id"], job_id.as_str());
+    assert_eq!(json[0]["status"], "pending");
+    assert!(json[0]["last_run"].is_null());
+
+    let yaml: serde_yaml::Value = serde_yaml::from_str(&scheduler.list_jobs(&args, OutputFormat::Yaml, Page::default()).await.unwrap()).unwrap();
+    assert_eq!(yaml[0]["name"].as_str(), Some("nightly-backup"));
+}
+
+#[tokio::test]
+async fn test_list_filters_by_creation_time() {
+    let temp_dir = tempdir().unwrap();
+    let scheduler = test_scheduler(temp_dir.path().to_path_buf()).await;
+    let mut old = test_job("old");
+    old.metadata.created_at = Utc::now() - Duration::days(3);
+    scheduler.queue.submit_job(old).await.unwrap();
+    scheduler.queue.submit_job(test_job("new")).await.unwrap();
+
+    // `-1d` would otherwise parse as a flag of its own
+    let names = |line: &str| {
+        let command = Parser::new().parse(line).unwrap();
+        let scheduler = &scheduler;
+        async move {
+            let output = scheduler.execute(&command, &Environment::new()).await.unwrap();
+            let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+            json.as_array().unwrap().iter().map(|job| job["name"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(names("schedule list --after -1d --json").await, vec!["new"]);
+    assert_eq!(names("schedule list --json --before 2d").await, vec!["old"]);
+    assert_eq!(names("schedule list --json --after").await.len(), 2);
+}
+
+#[tokio::test]
+async fn test_lowering_max_concurrent_limits_new_jobs() {
+    let temp_dir = tempdir().unwrap();
+    let scheduler = test_scheduler(temp_dir.path().to_path_buf()).await;
+    let env = Environment::new();
+    let config = |line: &str| Parser::new().parse(line).unwrap();
+
+    scheduler.execute(&config("schedule config --max-concurrent 1"), &env).await.unwrap();
+    assert!(scheduler.execute(&config("schedule config --max-concurrent 0"), &env).await.is_err());
+    assert!(scheduler.execute(&config("schedule config show"), &env).await.unwrap().contains("max-concurrent:  1"));
+
+    for name in ["first", "second", "third"] {
+        let mut job = test_job(name);
+        job.command = "sleep".to_string();
+        job.args = vec!["2".to_string()];
+        job.schedule = JobSchedule::Once(Utc::now());
+        job.metadata.next_run = Some(Utc::now());
+        scheduler.queue.submit_job(job).await.unwrap();
+    }
+
+    scheduler.queue.tick().await;
+    scheduler.queue.tick().await;
+    assert_eq!(scheduler.queue.stats().await.running, 1);
+
+    // The cap is persisted and picked up by a queue reloading the same storage
+    let reloaded = test_scheduler(temp_dir.path().to_path_buf()).await;
+    assert_eq!(reloaded.queue.max_concurrent_jobs(), 1);
+}
+
+#[tokio::test]
+async fn test_create_at_absolute_time() {
+    let temp_dir = tempdir().unwrap();
+    let scheduler = test_scheduler(temp_dir.path().to_path_buf()).await;
+    let at = (Utc::now() + Duration::days(30)).with_nanosecond(0).unwrap();
+
+    let env = Environment::new();
+    let create = |line: String| Parser::new().parse(&line).unwrap();
+
+    let output = scheduler.execute(&create(format!("schedule create launch echo --at {} go", at.to_rfc3339())), &env).await.unwrap();
+    let job = scheduler.queue.get_job(output.trim_start_matches("Created job with ID: ")).await.unwrap();
+    assert!(matches!(job.schedule, JobSchedule::Once(time) if time == at));
+    assert_eq!(job.metadata.next_run, Some(at));
+    assert_eq!(job.args, vec!["go".to_string()]);
+
+    let past = create("schedule create late echo --at '2020-01-01 00:00'".to_string());
+    assert!(scheduler.execute(&past, &env).await.unwrap().starts_with("Warning"));
+
+    assert!(parse_run_at("2030-06-01 09:30").is_ok());
+    assert!(parse_run_at("next tuesday").is_err());
+}
+
+#[tokio::test]
+async fn test_depends_on_waits_for_dependency() {
+    let temp_dir = tempdir().unwrap();
+    let scheduler = test_scheduler(temp_dir.path().to_path_buf()).await;
+    let env = Environment::new();
+    let create = |line: &str| Parser::new().parse(line).unwrap();
+
+    let output = scheduler.execute(&create("schedule create extract sleep --at 2020-01-01T00:00:00Z 1"), &env).await.unwrap();
+    let a = output.rsplit("ID: ").next().unwrap().to_string();
+    let load = create("schedule create load echo --at 2020-01-01T00:00:00Z --depends-on extract done");
+    let output = scheduler.execute(&load, &env).await.unwrap();
+    let b = output.rsplit("ID: ").next().unwrap().to_string();
+    assert_eq!(scheduler.queue.get_job(&b).await.unwrap().dependencies, vec![a.clone()]);
+    assert_eq!(scheduler.queue.get_job(&b).await.unwrap().args, vec!["done".to_string()]);
+    assert!(scheduler.execute(&create(&format!("schedule show {}", b)), &env).await.unwrap().contains(&format!("  {} extract", a)));
+
+    // Both are due, but only the dependency may start
+    scheduler.queue.tick().await;
+    assert!(matches!(scheduler.queue.get_job(&a).await.unwrap().status, JobStatus::Running));
+    assert!(matches!(scheduler.queue.get_job(&b).await.unwrap().status, JobStatus::Pending));
+
+    for _ in 0..50 {
+        scheduler.queue.tick().await;
+        if scheduler.queue.get_job_result(&b).await.is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    let first = scheduler.queue.get_job_result(&a).await.unwrap();
+    let second = scheduler.queue.get_job_result(&b).await.unwrap();
+    assert!(second.success);
+    assert!(second.started_at.unwrap() >= first.completed_at);
+
+    let unknown = create("schedule create orphan echo @daily --depends-on no-such-job");
+    assert!(scheduler.execute(&unknown, &env).await.unwrap_err().to_string().contains("unknown job"));
+}
+
+#[tokio::test]
+async fn test_dependency_cycles_are_rejected() {
+    let temp_dir = tempdir().unwrap();
+    let scheduler = test_scheduler(temp_dir.path().to_path_buf()).await;
+    let a = scheduler.queue.submit_job(test_job("a")).await.unwrap();
+    let mut b = test_job("b");
+    b.dependencies = vec![a.clone()];
+    let b = scheduler.queue.submit_job(b).await.unwrap();
+
+    // Replacing `a` with a version that depends on `b` would close the loop
+    let mut looped = test_job("a");
+    looped.id = a;
+    looped.dependencies = vec![b];
+    assert!(scheduler.queue.submit_job(looped).await.unwrap_err().to_string().contains("cycle"));
+
+    let mut own = test_job("self");
+    own.dependencies = vec![own.id.clone()];
+    assert!(scheduler.queue.submit_job(own).await.is_err());
+}
+
+#[tokio::test]
+async fn test_list_pages_through_jobs() {
+    let temp_dir = tempdir().unwrap();
+    let scheduler = test_scheduler(temp_dir.path().to_path_buf()).await;
+    let mut ids = Vec::new();
+    for i in 0..5 {
+        let mut job = test_job(&format!("job-{}", i));
+        job.metadata.created_at = Utc::now() - Duration::minutes(10 - i);
+        ids.push(scheduler.queue.submit_job(job).await.unwrap());
+    }
+    let args = vec!["list".to_string()];
+
+    let output = scheduler.list_jobs(&args, OutputFormat::Table, Page { limit: Some(2), page: 2 }).await.unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].starts_with(&ids[2]));
+    assert!(lines[2].starts_with(&ids[3]));
+    assert_eq!(lines[3], "showing 3–4 of 5");
+
+    let last = scheduler.list_jobs(&args, OutputFormat::Json, Page { limit: Some(2), page: 3 }).await.unwrap();
+    let json: serde_json::Value = serde_json::from_str(&last).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 1);
+    assert_eq!(json[0]["name"], "job-4");
+
+    let past = scheduler.list_jobs(&args, OutputFormat::Table, Page { limit: Some(2), page: 9 }).await.unwrap();
+    assert!(past.ends_with("page 9 is past the end (5 total)"));
+}
+
+#[tokio::test]
+async fn test_script_job_records_output() {
+    let temp_dir = tempdir().unwrap();
+    let scripts = ScriptEngine::new(temp_dir.path().join("scripts")).await.unwrap();
+    let script_id = scripts.create_script(
+        "backup".to_string(),
+        String::new(),
+        r#"print("backing up " + args[0]); 40 + 2"#.to_string(),
+        "test".to_string(),
+        Vec::new(),
+        Vec::new(),
+    ).await.unwrap();
+    let config = QueueConfig {
+        storage_path: temp_dir.path().join("state"),
+        ..Default::default()
+    };
+    let scheduler = SchedulerPlugin {
+        queue: JobQueue::new(config).await.unwrap().with_scripts(scripts),
+        plugins: Weak::new(),
+    };
+
+    let command = format!("script:{}", script_id);
+    let args: Vec<String> = ["create", "nightly-backup", &command, "@daily", "prod"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let output = scheduler.create_job(&args).await.unwrap();
+    let job_id = output.trim_start_matches("Created job with ID: ").to_string();
+    let missing: Vec<String> = ["create", "broken", "script:missing", "@daily"].iter().map(|s| s.to_string()).collect();
+    assert!(scheduler.create_job(&missing).await.is_err());
+
+    scheduler.run_job_now(&["run-now".to_string(), job_id.clone()]).await.unwrap();
+    for _ in 0..50 {
+        scheduler.queue.tick().await;
+        if scheduler.queue.get_job_result(&job_id).await.is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    let result = scheduler.queue.get_job_result(&job_id).await.unwrap();
+    assert!(result.success, "{:?}", result.error);
+    assert_eq!(result.output, "backing up prod\n42\n");
+    let details = scheduler.show_job(&["show".to_string(), job_id]).await.unwrap();
+    assert!(details.contains("    backing up prod"));
+}
+
+#[tokio::test]
+async fn test_create_verify_warns_for_unknown_command() {
+    let temp_dir = tempdir().unwrap();
+    let plugin_manager = PluginManager::new();
+    let scheduler = SchedulerPlugin {
+        plugins: Arc::downgrade(&plugin_manager),
+        ..test_scheduler(temp_dir.path().to_path_buf()).await
+    };
+    let create = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+    let output = scheduler.create_job(&create(&["create", "typo", "no-such-command-9f3a", "@daily", "--verify"])).await.unwrap();
+    assert!(output.starts_with("Warning: 'no-such-command-9f3a' is not a builtin, plugin or executable on PATH"));
+    let job_id = output.lines().last().unwrap().trim_start_matches("Created job with ID: ");
+    assert!(scheduler.queue.get_job(job_id).await.is_some());
+
+    let output = scheduler.create_job(&create(&["create", "real", "sh", "@daily", "--verify", "-c", "true"])).await.unwrap();
+    assert!(output.starts_with("Created job with ID: "));
+    let output = scheduler.create_job(&create(&["create", "builtin", "pwd", "@daily", "--verify"])).await.unwrap();
+    assert!(output.starts_with("Created job with ID: "));
+    // Commands of the shell's plugins count too
+    let output = scheduler.create_job(&create(&["create", "plugin", "net", "@daily", "--verify"])).await.unwrap();
+    assert!(output.starts_with("Created job with ID: "));
+
+    // Without --verify nothing is checked
+    let output = scheduler.create_job(&create(&["create", "unchecked", "no-such-command-9f3a", "@daily"])).await.unwrap();
+    assert!(output.starts_with("Created job with ID: "));
+}
@@ -1,14 +1,19 @@
 use std::collections::HashMap;
-use rhai::{Engine, Scope, AST, Dynamic, Map, Array};
+use rhai::{Engine, Scope, Dynamic, Map, Array};
+use rhai::packages::Package;
 use anyhow::Result;
 use tokio::fs;
-use std::path::PathBuf;
-use async_trait::async_trait;
-use super::super::super::{Command, Environment};
+use std::path::{Component, Path, PathBuf};
+use super::super::super::{util, Environment};
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use std::time::Duration;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Responses larger than this are rejected rather than buffered into a script string.
+const MAX_HTTP_BODY: usize = 10 * 1024 * 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Script {
@@ -28,6 +33,10 @@ pub struct ScriptEngine {
     engine: Arc<Engine>,
     scripts: Arc<RwLock<HashMap<String, Script>>>,
     storage_path: PathBuf,
+    /// The environment of the script being run, read and written by `get_env`/`set_env`
+    session: Arc<std::sync::Mutex<Environment>>,
+    /// Collects `print` output while `execute_captured` runs; `print` goes to stdout otherwise
+    output: Arc<std::sync::Mutex<Option<String>>>,
 }
 
 impl ScriptEngine {
@@ -40,10 +49,99 @@ impl ScriptEngine {
         engine.register_global_module(rhai::packages::BasicMapPackage::new().as_shared_module());
 
         // Custom functions
-        engine.register_fn("print", |s: &str| println!("{}", s));
-        engine.register_fn("now", || Utc::now());
+        let output: Arc<std::sync::Mutex<Option<String>>> = Arc::new(std::sync::Mutex::new(None));
+        let print_output = output.clone();
+        engine.on_print(move |s| {
+            let mut captured = print_output.lock().unwrap_or_else(|e| e.into_inner());
+            match captured.as_mut() {
+                Some(buffer) => {
+                    buffer.push_str(s);
+                    buffer.push('\n');
+                }
+                None => println!("{}", s),
+            }
+        });
+        engine.register_fn("now", Utc::now);
         engine.register_fn("sleep", |ms: i64| std::thread::sleep(std::time::Duration::from_millis(ms as u64)));
 
+        // JSON
+        engine.register_fn("parse_json", |json: &str| -> Result<Dynamic, Box<rhai::EvalAltResult>> {
+            let value: serde_json::Value = serde_json::from_str(json)
+                .map_err(|e| format!("parse_json: {}", e))?;
+            rhai::serde::to_dynamic(value)
+        });
+        engine.register_fn("to_json", |value: Dynamic| -> Result<String, Box<rhai::EvalAltResult>> {
+            let value: serde_json::Value = rhai::serde::from_dynamic(&value)?;
+            serde_json::to_string(&value).map_err(|e| format!("to_json: {}", e).into())
+        });
+
+        // HTTP, over one client shared by every script
+        let client = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()?;
+        let http = client.clone();
+        engine.register_fn("http_get", move |url: &str| -> Result<String, Box<rhai::EvalAltResult>> {
+            http_body("http_get", http_request(&http, "GET", url, None)?)
+        });
+        let http = client.clone();
+        engine.register_fn("http_post", move |url: &str, body: &str| -> Result<String, Box<rhai::EvalAltResult>> {
+            http_body("http_post", http_request(&http, "POST", url, Some(body))?)
+        });
+        let http = client.clone();
+        engine.register_fn("http_request", move |method: &str, url: &str, body: &str| -> Result<Map, Box<rhai::EvalAltResult>> {
+            let body = if body.is_empty() { None } else { Some(body) };
+            let response = http_request(&http, method, url, body)?;
+            let mut map = Map::new();
+            map.insert("status".into(), Dynamic::from(response.status as i64));
+            map.insert("headers".into(), Dynamic::from(response.headers));
+            map.insert("body".into(), Dynamic::from(response.body));
+            Ok(map)
+        });
+
+        // Session environment
+        let session = Arc::new(std::sync::Mutex::new(Environment::new()));
+        let get_session = session.clone();
+        engine.register_fn("get_env", move |name: &str| -> Dynamic {
+            get_session.lock()
+                .ok()
+                .and_then(|env| env.get_var(name).cloned())
+                .map(Dynamic::from)
+                .unwrap_or(Dynamic::UNIT)
+        });
+        let set_session = session.clone();
+        engine.register_fn("set_env", move |name: &str, value: &str| -> Result<(), Box<rhai::EvalAltResult>> {
+            if !Environment::is_settable(name) {
+                return Err(format!("set_env: '{}' is not a settable variable name", name).into());
+            }
+            let mut env = set_session.lock().map_err(|_| "set_env: environment unavailable".to_string())?;
+            env.set_var(name.to_string(), value.to_string());
+            env.export_var(name.to_string(), value.to_string());
+            Ok(())
+        });
+
+        // Files, confined to the sandbox root: `$NEXUSSHELL_SCRIPT_SANDBOX`, or `sandbox` in
+        // the script storage directory. Relative paths in scripts are resolved against it.
+        let sandbox_root = std::env::var_os("NEXUSSHELL_SCRIPT_SANDBOX")
+            .filter(|root| !root.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| storage_path.join("sandbox"));
+        let root = sandbox_root.clone();
+        engine.register_fn("read_file", move |path: &str| -> Result<String, Box<rhai::EvalAltResult>> {
+            let path = sandboxed_path(&root, "read_file", path)?;
+            std::fs::read_to_string(&path)
+                .map_err(|e| format!("read_file: {}: {}", path.display(), e).into())
+        });
+        let root = sandbox_root.clone();
+        engine.register_fn("write_file", move |path: &str, content: &str| -> Result<(), Box<rhai::EvalAltResult>> {
+            let path = sandboxed_path(&root, "write_file", path)?;
+            std::fs::write(&path, content)
+                .map_err(|e| format!("write_file: {}: {}", path.display(), e).into())
+        });
+        let root = sandbox_root.clone();
+        engine.register_fn("file_exists", move |path: &str| -> Result<bool, Box<rhai::EvalAltResult>> {
+            Ok(sandboxed_path(&root, "file_exists", path)?.exists())
+        });
+
         let engine = Arc::new(engine);
         let scripts = Arc::new(RwLock::new(HashMap::new()));
 
@@ -51,6 +149,8 @@ impl ScriptEngine {
             engine,
             scripts,
             storage_path,
+            session,
+            output,
         };
 
         script_engine.load_scripts().await?;
@@ -64,11 +164,9 @@ impl ScriptEngine {
         }
 
         let mut scripts = self.scripts.write().await;
-        let entries = fs::read_dir(&self.storage_path).await?;
-        
-        for entry in entries.await {
-            let entry = entry?;
-            if entry.file_type().await?.is_file() && entry.path().extension().map_or(false, |ext| ext == "json") {
+        let mut entries = fs::read_dir(&self.storage_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_file() && entry.path().extension().is_some_and(|ext| ext == "json") {
                 let content = fs::read_to_string(entry.path()).await?;
                 let script: Script = serde_json::from_str(&content)?;
                 scripts.insert(script.id.clone(), script);
@@ -186,7 +284,16 @@ impl ScriptEngine {
             .collect()
     }
 
-    pub async fn execute_script(&self, id: &str, args: &[String]) -> Result<Dynamic> {
+    pub fn try_list_scripts(&self) -> Vec<Script> {
+        self.scripts
+            .try_read()
+            .map(|scripts| scripts.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Runs a script against `env`. Variables it sets with `set_env` are exported to the
+    /// session that owns `env`.
+    pub async fn execute_script(&self, id: &str, args: &[String], env: &Environment) -> Result<Dynamic> {
         let scripts = self.scripts.read().await;
         let script = scripts.get(id).ok_or_else(|| anyhow::anyhow!("Script not found"))?;
 
@@ -197,12 +304,15 @@ impl ScriptEngine {
         let args_array: Array = args.iter().map(|s| Dynamic::from(s.clone())).collect();
         scope.push("args", args_array);
 
-        // Add environment variables
+        // Add a snapshot of the session's variables
         let mut env_map = Map::new();
-        for (key, value) in std::env::vars() {
-            env_map.insert(Dynamic::from(key), Dynamic::from(value));
+        for (key, value) in env.vars() {
+            env_map.insert(key.as_str().into(), Dynamic::from(value.clone()));
         }
         scope.push("env", env_map);
+        if let Ok(mut session) = self.session.lock() {
+            *session = env.clone();
+        }
 
         // Add utility functions
         scope.push_constant("SCRIPT_ID", script.id.clone());
@@ -210,30 +320,31 @@ impl ScriptEngine {
 
         // Compile and run the script
         let ast = self.engine.compile(&script.content)?;
-        let result = self.engine.run_ast_with_scope(&mut scope, &ast)?;
+        let result = self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, &ast)?;
 
         Ok(result)
     }
 
+    /// Like `execute_script`, but collects what the script prints instead of writing it to
+    /// stdout. The output ends with the script's result unless that is `()`, and is returned
+    /// even when the script fails.
+    pub async fn execute_captured(&self, id: &str, args: &[String], env: &Environment) -> (String, Result<Dynamic>) {
+        *self.output.lock().unwrap_or_else(|e| e.into_inner()) = Some(String::new());
+        let result = self.execute_script(id, args, env).await;
+        let mut output = self.output.lock().unwrap_or_else(|e| e.into_inner()).take().unwrap_or_default();
+        if let Ok(value) = &result {
+            if !value.is_unit() {
+                output.push_str(&format!("{}\n", value));
+            }
+        }
+        (output, result)
+    }
+
     pub async fn validate_script(&self, content: &str) -> Result<()> {
         self.engine.compile(content)?;
         Ok(())
     }
 
-    pub fn register_function<F>(&self, name: &str, f: F)
-    where
-        F: rhai::RegisterFn,
-    {
-        self.engine.register_fn(name, f);
-    }
-
-    pub fn register_type<T>(&self)
-    where
-        T: rhai::RegisterType,
-    {
-        self.engine.register_type::<T>();
-    }
-
     pub async fn get_script_dependencies(&self, id: &str) -> Result<Vec<Script>> {
         let scripts = self.scripts.read().await;
         let script = scripts.get(id).ok_or_else(|| anyhow::anyhow!("Script not found"))?;
@@ -261,3 +372,235 @@ impl ScriptEngine {
             .collect()
     }
 }
+
+struct HttpResponse {
+    status: u16,
+    headers: Map,
+    body: String,
+}
+
+/// Makes a request from synchronous script code by blocking on the shell's runtime, and
+/// fails rather than panics on a runtime that can't be blocked. The body is read in chunks
+/// so an oversized response fails without being buffered whole.
+fn http_request(
+    client: &reqwest::Client,
+    method: &str,
+    url: &str,
+    body: Option<&str>,
+) -> Result<HttpResponse, Box<rhai::EvalAltResult>> {
+    let method = reqwest::Method::from_bytes(method.to_uppercase().as_bytes())
+        .map_err(|_| format!("http: invalid method '{}'", method))?;
+    let mut request = client.request(method, url);
+    if let Some(body) = body {
+        request = request.body(body.to_string());
+    }
+
+    let response = util::block_on(async {
+        let mut response = request.send().await.map_err(|e| format!("http: {}: {}", url, e))?;
+        let status = response.status().as_u16();
+        let mut headers = Map::new();
+        for (name, value) in response.headers() {
+            headers.insert(name.as_str().into(), Dynamic::from(String::from_utf8_lossy(value.as_bytes()).to_string()));
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| format!("http: {}: {}", url, e))? {
+            if bytes.len() + chunk.len() > MAX_HTTP_BODY {
+                return Err(format!("http: {}: response exceeds {} bytes", url, MAX_HTTP_BODY).into());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(HttpResponse {
+            status,
+            headers,
+            body: String::from_utf8_lossy(&bytes).to_string(),
+        })
+    });
+    response.map_err(|e| format!("http: {}: {}", url, e))?
+}
+
+fn http_body(function: &str, response: HttpResponse) -> Result<String, Box<rhai::EvalAltResult>> {
+    if !(200..300).contains(&response.status) {
+        return Err(format!("{}: HTTP {}", function, response.status).into());
+    }
+    Ok(response.body)
+}
+
+/// Resolves a script-supplied path inside the sandbox, following `..` and symlinks so
+/// neither can be used to step outside it. The sandbox root is created on first use.
+fn sandboxed_path(root: &Path, function: &str, path: &str) -> Result<PathBuf, Box<rhai::EvalAltResult>> {
+    std::fs::create_dir_all(root)
+        .map_err(|e| format!("{}: sandbox {}: {}", function, root.display(), e))?;
+    let root = root.canonicalize()
+        .map_err(|e| format!("{}: sandbox {}: {}", function, root.display(), e))?;
+
+    let mut normalized = PathBuf::new();
+    for component in root.join(path).components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+
+    // The file itself may not exist yet, but its directory must
+    let resolved = match normalized.canonicalize() {
+        Ok(resolved) => resolved,
+        Err(_) => match (normalized.parent(), normalized.file_name()) {
+            (Some(parent), Some(name)) => parent.canonicalize()
+                .map(|parent| parent.join(name))
+                .map_err(|e| format!("{}: {}: {}", function, path, e))?,
+            _ => normalized.clone(),
+        },
+    };
+
+    if !resolved.starts_with(&root) {
+        return Err(format!("{}: {} is outside the script sandbox", function, path).into());
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::{Executor, PluginManager, Preprocessor};
+    use crate::shell::mock_http::{self, Reply};
+    use tempfile::tempdir;
+
+    /// Answers every request with `status` and `body`, echoing the request line in a header.
+    async fn spawn_http_mock(status: &'static str, body: &'static str) -> String {
+        mock_http::serve(move |line| Reply::new(status, body).header("X-Request", line)).await
+    }
+
+    #[tokio::test]
+    async fn test_set_env_reaches_the_session() {
+        let temp_dir = tempdir().unwrap();
+        let engine = ScriptEngine::new(temp_dir.path().to_path_buf()).await.unwrap();
+        let id = engine.create_script(
+            "configure".to_string(),
+            String::new(),
+            r#"set_env("FOO", "bar"); get_env("FOO")"#.to_string(),
+            "test".to_string(),
+            Vec::new(),
+            Vec::new(),
+        ).await.unwrap();
+
+        let mut env = Environment::new();
+        let result = engine.execute_script(&id, &[], &env).await.unwrap();
+        assert_eq!(result.into_string().unwrap(), "bar");
+        env.apply_exports();
+
+        let executor = Executor::new(PluginManager::new());
+        let line = Preprocessor::new().expand_commands("echo $FOO", &env, &executor).await.unwrap();
+        assert_eq!(line, "echo bar");
+
+        let id = engine.create_script(
+            "reserved".to_string(),
+            String::new(),
+            r#"set_env("?", "0")"#.to_string(),
+            "test".to_string(),
+            Vec::new(),
+            Vec::new(),
+        ).await.unwrap();
+        assert!(engine.execute_script(&id, &[], &env).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_functions_stay_in_sandbox() {
+        let temp_dir = tempdir().unwrap();
+        let storage = temp_dir.path().join("scripts");
+        let engine = ScriptEngine::new(storage.clone()).await.unwrap();
+        let sandbox = storage.join("sandbox");
+        let env = Environment::new();
+
+        let id = engine.create_script(
+            "notes".to_string(),
+            String::new(),
+            r#"write_file("notes.txt", "hello"); if !file_exists("notes.txt") { throw "missing" } read_file("./notes.txt")"#.to_string(),
+            "test".to_string(),
+            Vec::new(),
+            Vec::new(),
+        ).await.unwrap();
+        let result = engine.execute_script(&id, &[], &env).await.unwrap();
+        assert_eq!(result.into_string().unwrap(), "hello");
+        assert_eq!(std::fs::read_to_string(sandbox.join("notes.txt")).unwrap(), "hello");
+
+        let outside = storage.join("outside.txt");
+        std::fs::write(&outside, "secret").unwrap();
+        for script in [
+            r#"read_file("../outside.txt")"#.to_string(),
+            format!(r#"read_file("{}")"#, outside.display()),
+            r#"write_file("../escape.txt", "x")"#.to_string(),
+        ] {
+            let id = engine.create_script("escape".to_string(), String::new(), script, "test".to_string(), Vec::new(), Vec::new())
+                .await
+                .unwrap();
+            let err = engine.execute_script(&id, &[], &env).await.unwrap_err();
+            assert!(err.to_string().contains("outside the script sandbox"));
+        }
+        assert!(!storage.join("escape.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_json_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let engine = ScriptEngine::new(temp_dir.path().to_path_buf()).await.unwrap();
+        let script = r#"
+            let data = parse_json(args[0]);
+            data.service.replicas += 1;
+            data.service.tags.push("canary");
+            to_json(data)
+        "#;
+        let id = engine.create_script("scale".to_string(), String::new(), script.to_string(), "test".to_string(), Vec::new(), Vec::new())
+            .await
+            .unwrap();
+
+        let input = r#"{"service":{"name":"api","replicas":2,"ratio":0.5,"tags":["web"],"owner":null}}"#;
+        let result = engine.execute_script(&id, &[input.to_string()], &Environment::new()).await.unwrap();
+        let output: serde_json::Value = serde_json::from_str(&result.into_string().unwrap()).unwrap();
+        assert_eq!(output, serde_json::json!({
+            "service": {"name": "api", "replicas": 3, "ratio": 0.5, "tags": ["web", "canary"], "owner": null}
+        }));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_http_functions() {
+        let temp_dir = tempdir().unwrap();
+        let engine = ScriptEngine::new(temp_dir.path().to_path_buf()).await.unwrap();
+        let env = Environment::new();
+        let ok = spawn_http_mock("200 OK", "pong").await;
+        let missing = spawn_http_mock("404 Not Found", "nope").await;
+
+        let script = r#"
+            let response = http_request("POST", args[0] + "/ping", "hi");
+            if response.status != 200 { throw "bad status" }
+            http_get(args[0] + "/ping") + " " + response.headers["x-request"]
+        "#;
+        let id = engine.create_script("ping".to_string(), String::new(), script.to_string(), "test".to_string(), Vec::new(), Vec::new())
+            .await
+            .unwrap();
+        let result = engine.execute_script(&id, &[ok], &env).await.unwrap();
+        assert_eq!(result.into_string().unwrap(), "pong POST /ping HTTP/1.1");
+
+        let id = engine.create_script("missing".to_string(), String::new(), "http_get(args[0])".to_string(), "test".to_string(), Vec::new(), Vec::new())
+            .await
+            .unwrap();
+        let err = engine.execute_script(&id, &[missing], &env).await.unwrap_err();
+        assert!(err.to_string().contains("HTTP 404"));
+    }
+
+    #[tokio::test]
+    async fn test_http_on_a_single_threaded_runtime_fails_cleanly() {
+        let temp_dir = tempdir().unwrap();
+        let engine = ScriptEngine::new(temp_dir.path().to_path_buf()).await.unwrap();
+        let id = engine.create_script("get".to_string(), String::new(), r#"http_get("http://127.0.0.1:9")"#.to_string(), "test".to_string(), Vec::new(), Vec::new())
+            .await
+            .unwrap();
+
+        let err = engine.execute_script(&id, &[], &Environment::new()).await.unwrap_err();
+        assert!(err.to_string().contains("single-threaded runtime"), "{}", err);
+    }
+}
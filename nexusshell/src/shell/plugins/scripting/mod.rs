@@ -1,9 +1,10 @@
 mod engine;
 
 use async_trait::async_trait;
-use super::super::{Command, Environment, Plugin};
+use super::super::{Command, Environment, Plugin, Table};
 use anyhow::Result;
-use engine::{ScriptEngine, Script};
+use engine::Script;
+pub use engine::ScriptEngine;
 use colored::*;
 use std::path::PathBuf;
 use tokio::fs;
@@ -13,13 +14,19 @@ pub struct ScriptingPlugin {
 }
 
 impl ScriptingPlugin {
-    pub async fn new() -> Result<Self> {
+    /// `engine` is shared with the scheduler, which runs stored scripts as jobs
+    /// (`schedule create <name> script:<id> ...`).
+    pub fn new(engine: ScriptEngine) -> Self {
+        ScriptingPlugin { engine }
+    }
+
+    /// Opens the engine over the scripts stored under `~/.nexusshell/scripts`.
+    pub async fn open_engine() -> Result<ScriptEngine> {
         let mut storage_path = dirs::home_dir().unwrap_or_default();
         storage_path.push(".nexusshell");
         storage_path.push("scripts");
 
-        let engine = ScriptEngine::new(storage_path).await?;
-        Ok(ScriptingPlugin { engine })
+        ScriptEngine::new(storage_path).await
     }
 
     async fn create_script(&self, args: &[String]) -> Result<String> {
@@ -92,8 +99,8 @@ impl ScriptingPlugin {
             }
         }
 
-        if content.is_some() {
-            self.engine.validate_script(content.as_ref().unwrap()).await?;
+        if let Some(content) = &content {
+            self.engine.validate_script(content).await?;
         }
 
         self.engine.update_script(
@@ -126,23 +133,7 @@ impl ScriptingPlugin {
             return Ok("No scripts found".to_string());
         }
 
-        let mut output = String::new();
-        output.push_str(&format!("{:<36} {:<20} {:<40} {:<20}\n",
-            "ID", "NAME", "DESCRIPTION", "TAGS"));
-
-        for script in scripts {
-            output.push_str(&format!("{:<36} {:<20} {:<40} {:<20}\n",
-                script.id,
-                script.name,
-                if script.description.len() > 37 {
-                    format!("{}...", &script.description[..37])
-                } else {
-                    script.description
-                },
-                script.tags.join(", ")));
-        }
-
-        Ok(output)
+        Ok(script_table(&scripts))
     }
 
     async fn show_script(&self, args: &[String]) -> Result<String> {
@@ -150,7 +141,7 @@ impl ScriptingPlugin {
             return Ok("Usage: script show <id>".to_string());
         }
 
-        let id = &args[1];
+        let id = &self.resolve_script_id(&args[1]).await;
         if let Some(script) = self.engine.get_script(id).await {
             let mut output = String::new();
             output.push_str(&format!("Script Details for {}\n", script.id.bright_green()));
@@ -180,18 +171,30 @@ impl ScriptingPlugin {
         }
     }
 
-    async fn run_script(&self, args: &[String]) -> Result<String> {
+    async fn run_script(&self, args: &[String], env: &Environment) -> Result<String> {
         if args.len() < 2 {
             return Ok("Usage: script run <id> [args...]".to_string());
         }
 
-        let id = &args[1];
+        let id = &self.resolve_script_id(&args[1]).await;
         let script_args = args[2..].to_vec();
 
-        let result = self.engine.execute_script(id, &script_args).await?;
+        let result = self.engine.execute_script(id, &script_args, env).await?;
         Ok(format!("Script result: {:?}", result))
     }
 
+    async fn resolve_script_id(&self, id_or_name: &str) -> String {
+        if self.engine.get_script(id_or_name).await.is_some() {
+            return id_or_name.to_string();
+        }
+
+        self.engine.list_scripts(None).await
+            .into_iter()
+            .find(|script| script.name == id_or_name)
+            .map(|script| script.id)
+            .unwrap_or_else(|| id_or_name.to_string())
+    }
+
     async fn search_scripts(&self, args: &[String]) -> Result<String> {
         if args.len() < 2 {
             return Ok("Usage: script search <query>".to_string());
@@ -206,21 +209,7 @@ impl ScriptingPlugin {
 
         let mut output = String::new();
         output.push_str(&format!("Search results for '{}'\n", query));
-        output.push_str(&format!("{:<36} {:<20} {:<40} {:<20}\n",
-            "ID", "NAME", "DESCRIPTION", "TAGS"));
-
-        for script in scripts {
-            output.push_str(&format!("{:<36} {:<20} {:<40} {:<20}\n",
-                script.id,
-                script.name,
-                if script.description.len() > 37 {
-                    format!("{}...", &script.description[..37])
-                } else {
-                    script.description
-                },
-                script.tags.join(", ")));
-        }
-
+        output.push_str(&script_table(&scripts));
         Ok(output)
     }
 
@@ -249,17 +238,79 @@ impl Plugin for ScriptingPlugin {
         "Script management and execution"
     }
 
-    async fn execute(&self, command: &Command, _env: &Environment) -> Result<String> {
+    async fn execute(&self, command: &Command, env: &Environment) -> Result<String> {
         match command.args.first().map(|s| s.as_str()) {
             Some("create") => self.create_script(&command.args).await,
             Some("update") => self.update_script(&command.args).await,
             Some("delete") => self.delete_script(&command.args).await,
             Some("list") => self.list_scripts(&command.args).await,
             Some("show") => self.show_script(&command.args).await,
-            Some("run") => self.run_script(&command.args).await,
+            Some("run") => self.run_script(&command.args, env).await,
             Some("search") => self.search_scripts(&command.args).await,
             Some("validate") => self.validate_script(&command.args).await,
             _ => Ok("Available commands: create, update, delete, list, show, run, search, validate".to_string()),
         }
     }
+
+    fn complete(&self, args: &[String], partial: &str) -> Vec<String> {
+        match args.first().map(|s| s.as_str()) {
+            Some("show") | Some("run") if args.len() == 1 => {
+                let mut completions: Vec<String> = self.engine.try_list_scripts()
+                    .into_iter()
+                    .flat_map(|script| vec![script.id, script.name])
+                    .filter(|candidate| candidate.starts_with(partial))
+                    .collect();
+                completions.sort();
+                completions.dedup();
+                completions
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Descriptions longer than this are cut short in `script list` and `script search`.
+const DESCRIPTION_WIDTH: usize = 40;
+
+fn script_table(scripts: &[Script]) -> String {
+    let mut table = Table::new(["ID", "NAME", "DESCRIPTION", "TAGS"]).max_width(2, DESCRIPTION_WIDTH);
+    for script in scripts {
+        table.push_row([
+            script.id.clone(),
+            script.name.clone(),
+            script.description.clone(),
+            script.tags.join(", "),
+        ]);
+    }
+    table.render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_list_truncates_multibyte_descriptions() {
+        let temp_dir = tempdir().unwrap();
+        let engine = ScriptEngine::new(temp_dir.path().to_path_buf()).await.unwrap();
+        // Each of these chars takes several bytes, so a byte offset would land mid-character
+        let description = "データベースのバックアップを毎晩作成し、古いスナップショットを削除します。ログは三十日間保持されます";
+        assert!(description.chars().count() > DESCRIPTION_WIDTH);
+        engine.create_script(
+            "backup".to_string(),
+            description.to_string(),
+            "1 + 1".to_string(),
+            "test".to_string(),
+            vec!["ops".to_string()],
+            Vec::new(),
+        ).await.unwrap();
+        let plugin = ScriptingPlugin { engine };
+
+        let output = plugin.list_scripts(&["list".to_string()]).await.unwrap();
+        let row = output.lines().nth(1).unwrap();
+        let shown: String = description.chars().take(DESCRIPTION_WIDTH - 1).collect();
+        assert!(row.contains(&format!("{}…", shown)));
+        assert!(!row.contains(description));
+    }
 }
@@ -0,0 +1,106 @@
+//! Authenticated encryption shared by the credential and key stores, and meant for any other
+//! secrets kept at rest (such as an encrypted config file).
+//!
+//! Sealed data is `nonce || ciphertext || tag` using ChaCha20-Poly1305 with a random nonce.
+
+use anyhow::Result;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::num::NonZeroU32;
+
+pub const KEY_LEN: usize = 32;
+pub const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Stretches `passphrase` into an encryption key with PBKDF2-HMAC-SHA256.
+pub fn derive_key(passphrase: &[u8], salt: &[u8]) -> [u8; KEY_LEN] {
+    let mut key = [0u8; KEY_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        salt,
+        passphrase,
+        &mut key,
+    );
+    key
+}
+
+pub fn random_salt() -> Result<[u8; SALT_LEN]> {
+    let mut salt = [0u8; SALT_LEN];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| anyhow::anyhow!("Failed to generate salt"))?;
+    Ok(salt)
+}
+
+/// Encrypts `plaintext`, authenticating it together with `aad`.
+pub fn seal(key: &[u8; KEY_LEN], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow::anyhow!("Failed to generate nonce"))?;
+
+    let mut in_out = plaintext.to_vec();
+    aead_key(key)?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut in_out)
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt data"))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&in_out);
+    Ok(sealed)
+}
+
+/// Decrypts data produced by [`seal`]; fails if it or `aad` was tampered with.
+pub fn open(key: &[u8; KEY_LEN], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN + CHACHA20_POLY1305.tag_len() {
+        return Err(anyhow::anyhow!("Encrypted data is truncated"));
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| anyhow::anyhow!("Invalid nonce"))?;
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = aead_key(key)?
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt data"))?;
+    Ok(plaintext.to_vec())
+}
+
+fn aead_key(key: &[u8; KEY_LEN]) -> Result<LessSafeKey> {
+    let key = UnboundKey::new(&CHACHA20_POLY1305, key)
+        .map_err(|_| anyhow::anyhow!("Failed to create encryption key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_round_trip() {
+        let key = derive_key(b"correct horse battery staple", &random_salt().unwrap());
+        let sealed = seal(&key, b"credential:db", b"hunter2").unwrap();
+
+        assert_ne!(&sealed[NONCE_LEN..], b"hunter2");
+        assert_eq!(open(&key, b"credential:db", &sealed).unwrap(), b"hunter2");
+
+        // Fresh nonce per call
+        assert_ne!(seal(&key, b"", b"same").unwrap(), seal(&key, b"", b"same").unwrap());
+    }
+
+    #[test]
+    fn test_open_detects_tampering() {
+        let salt = random_salt().unwrap();
+        let key = derive_key(b"passphrase", &salt);
+        let sealed = seal(&key, b"aad", b"secret").unwrap();
+
+        let mut flipped = sealed.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 1;
+        assert!(open(&key, b"aad", &flipped).is_err());
+        assert!(open(&key, b"other aad", &sealed).is_err());
+        assert!(open(&derive_key(b"wrong", &salt), b"aad", &sealed).is_err());
+        assert!(open(&key, b"aad", &sealed[..NONCE_LEN]).is_err());
+    }
+}
@@ -1,16 +1,22 @@
+pub mod crypto;
+#[cfg(test)]
+mod tests;
+
 use async_trait::async_trait;
-use super::super::{Command, Environment, Plugin};
+use super::super::{Command, Environment, Plugin, ShellError, Table};
+use super::super::render::Page;
+use super::super::timespec::parse_time;
 use anyhow::Result;
 use serde::{Serialize, Deserialize};
 use tokio::fs;
-use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use std::collections::HashMap;
-use ring::{aead, digest, pbkdf2};
+use std::sync::Mutex;
+use ring::rand::SecureRandom;
+use ring::signature::KeyPair as _;
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
-use std::num::NonZeroU32;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
-use colored::*;
 
 const CREDENTIAL_STORE_PATH: &str = ".nexusshell/credentials";
 const KEY_STORE_PATH: &str = ".nexusshell/keys";
@@ -34,6 +40,7 @@ pub struct KeyPair {
     name: String,
     public_key: String,
     encrypted_private_key: String,
+    salt: String,
     created_at: DateTime<Utc>,
     expires_at: Option<DateTime<Utc>>,
     metadata: HashMap<String, String>,
@@ -51,8 +58,8 @@ pub struct AuditLogEntry {
 
 pub struct SecurityPlugin {
     master_key: Vec<u8>,
-    credentials: HashMap<String, Credential>,
-    keys: HashMap<String, KeyPair>,
+    credentials: Mutex<HashMap<String, Credential>>,
+    keys: Mutex<HashMap<String, KeyPair>>,
 }
 
 impl SecurityPlugin {
@@ -70,8 +77,8 @@ impl SecurityPlugin {
 
         Ok(SecurityPlugin {
             master_key,
-            credentials: HashMap::new(),
-            keys: HashMap::new(),
+            credentials: Mutex::new(HashMap::new()),
+            keys: Mutex::new(HashMap::new()),
         })
     }
 
@@ -83,69 +90,32 @@ impl SecurityPlugin {
         if master_key_path.exists() {
             Ok(fs::read(&master_key_path).await?)
         } else {
-            let key = ring::rand::SystemRandom::new()
-                .generate_vec(32)?;
+            let mut key = vec![0u8; crypto::KEY_LEN];
+            ring::rand::SystemRandom::new()
+                .fill(&mut key)
+                .map_err(|_| anyhow::anyhow!("Failed to generate master key"))?;
             fs::write(&master_key_path, &key).await?;
             Ok(key)
         }
     }
 
     fn encrypt(&self, data: &[u8]) -> Result<(String, String)> {
-        let salt = ring::rand::SystemRandom::new()
-            .generate_vec(16)?;
-
-        let mut key = [0u8; 32];
-        pbkdf2::derive(
-            pbkdf2::PBKDF2_HMAC_SHA256,
-            NonZeroU32::new(100_000).unwrap(),
-            &salt,
-            &self.master_key,
-            &mut key,
-        );
-
-        let nonce = ring::rand::SystemRandom::new()
-            .generate_vec(12)?;
-
-        let sealing_key = aead::UnboundKey::new(&aead::CHACHA20_POLY1305, &key)
-            .map_err(|_| anyhow::anyhow!("Failed to create sealing key"))?;
-        let sealed_key = aead::SealingKey::new(sealing_key, &nonce);
-
-        let mut in_out = data.to_vec();
-        let tag = sealed_key
-            .seal_in_place_append_tag(&[], &mut in_out)
-            .map_err(|_| anyhow::anyhow!("Failed to encrypt data"))?;
+        let salt = crypto::random_salt()?;
+        let key = crypto::derive_key(&self.master_key, &salt);
+        let sealed = crypto::seal(&key, &[], data)?;
 
         Ok((
-            BASE64.encode(&in_out),
-            BASE64.encode(&salt),
+            BASE64.encode(&sealed),
+            BASE64.encode(salt),
         ))
     }
 
     fn decrypt(&self, encrypted_data: &str, salt: &str) -> Result<Vec<u8>> {
-        let encrypted_bytes = BASE64.decode(encrypted_data)?;
+        let sealed = BASE64.decode(encrypted_data)?;
         let salt = BASE64.decode(salt)?;
 
-        let mut key = [0u8; 32];
-        pbkdf2::derive(
-            pbkdf2::PBKDF2_HMAC_SHA256,
-            NonZeroU32::new(100_000).unwrap(),
-            &salt,
-            &self.master_key,
-            &mut key,
-        );
-
-        let opening_key = aead::UnboundKey::new(&aead::CHACHA20_POLY1305, &key)
-            .map_err(|_| anyhow::anyhow!("Failed to create opening key"))?;
-
-        let nonce = &encrypted_bytes[..12];
-        let mut in_out = encrypted_bytes[12..].to_vec();
-
-        let opening_key = aead::OpeningKey::new(opening_key, nonce);
-        opening_key
-            .open_in_place(&[], &mut in_out)
-            .map_err(|_| anyhow::anyhow!("Failed to decrypt data"))?;
-
-        Ok(in_out)
+        let key = crypto::derive_key(&self.master_key, &salt);
+        crypto::open(&key, &[], &sealed)
     }
 
     async fn log_audit(&self, entry: AuditLogEntry) -> Result<()> {
@@ -154,20 +124,21 @@ impl SecurityPlugin {
         let log_path = home_dir.join(AUDIT_LOG_PATH);
 
         let entry_json = serde_json::to_string(&entry)?;
-        fs::write(&log_path, format!("{}\n", entry_json)).await?;
+        let mut log = fs::OpenOptions::new().create(true).append(true).open(&log_path).await?;
+        log.write_all(format!("{}\n", entry_json).as_bytes()).await?;
 
         Ok(())
     }
 
-    async fn handle_credential(&self, args: &[String]) -> Result<String> {
+    async fn handle_credential(&self, args: &[String], dry_run: bool) -> Result<String> {
         if args.len() < 2 {
-            return Ok("Usage: security credential [add|get|list|delete] [args...]".to_string());
+            return Err(ShellError::InvalidUsage("security credential [add|get|list|delete] [args...]".to_string()).into());
         }
 
         match args[1].as_str() {
             "add" => {
                 if args.len() < 5 {
-                    return Ok("Usage: security credential add <name> <username> <password>".to_string());
+                    return Err(ShellError::InvalidUsage("security credential add <name> <username> <password>".to_string()).into());
                 }
 
                 let (encrypted_password, salt) = self.encrypt(args[4].as_bytes())?;
@@ -183,7 +154,7 @@ impl SecurityPlugin {
                     metadata: HashMap::new(),
                 };
 
-                self.credentials.insert(credential.id.clone(), credential.clone());
+                self.credentials.lock().unwrap().insert(credential.id.clone(), credential.clone());
 
                 self.log_audit(AuditLogEntry {
                     timestamp: Utc::now(),
@@ -199,12 +170,13 @@ impl SecurityPlugin {
 
             "get" => {
                 if args.len() < 3 {
-                    return Ok("Usage: security credential get <name>".to_string());
+                    return Err(ShellError::InvalidUsage("security credential get <name>".to_string()).into());
                 }
 
-                if let Some(credential) = self.credentials.values()
+                let credential = self.credentials.lock().unwrap().values()
                     .find(|c| c.name == args[2])
-                {
+                    .cloned();
+                if let Some(credential) = credential {
                     let password = self.decrypt(&credential.encrypted_password, &credential.salt)?;
                     let password = String::from_utf8(password)?;
 
@@ -219,35 +191,38 @@ impl SecurityPlugin {
 
                     Ok(format!("Username: {}\nPassword: {}", credential.username, password))
                 } else {
-                    Ok(format!("Credential '{}' not found", args[2]))
+                    Err(ShellError::NotFound(format!("Credential '{}'", args[2])).into())
                 }
             }
 
             "list" => {
-                let mut output = String::new();
-                output.push_str(&format!("{:<36} {:<20} {:<20} {:<30}\n",
-                    "ID", "NAME", "USERNAME", "CREATED AT"));
-
-                for credential in self.credentials.values() {
-                    output.push_str(&format!("{:<36} {:<20} {:<20} {:<30}\n",
-                        credential.id,
-                        credential.name,
-                        credential.username,
-                        credential.created_at.to_rfc3339()));
+                let mut table = Table::new(["ID", "NAME", "USERNAME", "CREATED AT"]);
+                for credential in self.credentials.lock().unwrap().values() {
+                    table.push_row([
+                        credential.id.clone(),
+                        credential.name.clone(),
+                        credential.username.clone(),
+                        credential.created_at.to_rfc3339(),
+                    ]);
                 }
 
-                Ok(output)
+                Ok(table.render())
             }
 
             "delete" => {
                 if args.len() < 3 {
-                    return Ok("Usage: security credential delete <name>".to_string());
+                    return Err(ShellError::InvalidUsage("security credential delete <name> [--dry-run]".to_string()).into());
                 }
 
-                if let Some(credential) = self.credentials.values()
+                let credential = self.credentials.lock().unwrap().values()
                     .find(|c| c.name == args[2])
-                {
-                    self.credentials.remove(&credential.id);
+                    .cloned();
+                if let Some(credential) = credential {
+                    if dry_run {
+                        return Ok(format!("Would delete credential '{}' ({})", credential.name, credential.id));
+                    }
+
+                    self.credentials.lock().unwrap().remove(&credential.id);
 
                     self.log_audit(AuditLogEntry {
                         timestamp: Utc::now(),
@@ -260,7 +235,7 @@ impl SecurityPlugin {
 
                     Ok(format!("Credential '{}' deleted", args[2]))
                 } else {
-                    Ok(format!("Credential '{}' not found", args[2]))
+                    Err(ShellError::NotFound(format!("Credential '{}'", args[2])).into())
                 }
             }
 
@@ -268,33 +243,36 @@ impl SecurityPlugin {
         }
     }
 
-    async fn handle_key(&self, args: &[String]) -> Result<String> {
+    async fn handle_key(&self, args: &[String], dry_run: bool) -> Result<String> {
         if args.len() < 2 {
-            return Ok("Usage: security key [generate|import|export|list|delete] [args...]".to_string());
+            return Err(ShellError::InvalidUsage("security key [generate|import|export|list|delete] [args...]".to_string()).into());
         }
 
         match args[1].as_str() {
             "generate" => {
                 if args.len() < 3 {
-                    return Ok("Usage: security key generate <name>".to_string());
+                    return Err(ShellError::InvalidUsage("security key generate <name>".to_string()).into());
                 }
 
-                let key_pair = ring::signature::Ed25519KeyPair::generate(
-                    &ring::rand::SystemRandom::new())?;
+                let pkcs8 = ring::signature::Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new())
+                    .map_err(|_| anyhow::anyhow!("Failed to generate key pair"))?;
+                let key_pair = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+                    .map_err(|_| anyhow::anyhow!("Failed to generate key pair"))?;
 
-                let (encrypted_private_key, salt) = self.encrypt(key_pair.as_ref())?;
+                let (encrypted_private_key, salt) = self.encrypt(pkcs8.as_ref())?;
 
                 let key = KeyPair {
                     id: Uuid::new_v4().to_string(),
                     name: args[2].clone(),
                     public_key: BASE64.encode(key_pair.public_key().as_ref()),
                     encrypted_private_key,
+                    salt,
                     created_at: Utc::now(),
                     expires_at: None,
                     metadata: HashMap::new(),
                 };
 
-                self.keys.insert(key.id.clone(), key.clone());
+                self.keys.lock().unwrap().insert(key.id.clone(), key.clone());
 
                 self.log_audit(AuditLogEntry {
                     timestamp: Utc::now(),
@@ -310,7 +288,7 @@ impl SecurityPlugin {
 
             "import" => {
                 if args.len() < 4 {
-                    return Ok("Usage: security key import <name> <private_key_path>".to_string());
+                    return Err(ShellError::InvalidUsage("security key import <name> <private_key_path>".to_string()).into());
                 }
 
                 let private_key = fs::read(&args[3]).await?;
@@ -321,12 +299,13 @@ impl SecurityPlugin {
                     name: args[2].clone(),
                     public_key: "".to_string(), // Would need to derive public key from private key
                     encrypted_private_key,
+                    salt,
                     created_at: Utc::now(),
                     expires_at: None,
                     metadata: HashMap::new(),
                 };
 
-                self.keys.insert(key.id.clone(), key.clone());
+                self.keys.lock().unwrap().insert(key.id.clone(), key.clone());
 
                 self.log_audit(AuditLogEntry {
                     timestamp: Utc::now(),
@@ -342,12 +321,13 @@ impl SecurityPlugin {
 
             "export" => {
                 if args.len() < 4 {
-                    return Ok("Usage: security key export <name> <output_path>".to_string());
+                    return Err(ShellError::InvalidUsage("security key export <name> <output_path>".to_string()).into());
                 }
 
-                if let Some(key) = self.keys.values()
+                let key = self.keys.lock().unwrap().values()
                     .find(|k| k.name == args[2])
-                {
+                    .cloned();
+                if let Some(key) = key {
                     let private_key = self.decrypt(&key.encrypted_private_key, &key.salt)?;
                     fs::write(&args[3], private_key).await?;
 
@@ -362,35 +342,38 @@ impl SecurityPlugin {
 
                     Ok(format!("Key exported to {}", args[3]))
                 } else {
-                    Ok(format!("Key '{}' not found", args[2]))
+                    Err(ShellError::NotFound(format!("Key '{}'", args[2])).into())
                 }
             }
 
             "list" => {
-                let mut output = String::new();
-                output.push_str(&format!("{:<36} {:<20} {:<30} {:<20}\n",
-                    "ID", "NAME", "CREATED AT", "EXPIRES AT"));
-
-                for key in self.keys.values() {
-                    output.push_str(&format!("{:<36} {:<20} {:<30} {:<20}\n",
-                        key.id,
-                        key.name,
+                let mut table = Table::new(["ID", "NAME", "CREATED AT", "EXPIRES AT"]);
+                for key in self.keys.lock().unwrap().values() {
+                    table.push_row([
+                        key.id.clone(),
+                        key.name.clone(),
                         key.created_at.to_rfc3339(),
-                        key.expires_at.map_or("Never".to_string(), |dt| dt.to_rfc3339())));
+                        key.expires_at.map_or("Never".to_string(), |dt| dt.to_rfc3339()),
+                    ]);
                 }
 
-                Ok(output)
+                Ok(table.render())
             }
 
             "delete" => {
                 if args.len() < 3 {
-                    return Ok("Usage: security key delete <name>".to_string());
+                    return Err(ShellError::InvalidUsage("security key delete <name> [--dry-run]".to_string()).into());
                 }
 
-                if let Some(key) = self.keys.values()
+                let key = self.keys.lock().unwrap().values()
                     .find(|k| k.name == args[2])
-                {
-                    self.keys.remove(&key.id);
+                    .cloned();
+                if let Some(key) = key {
+                    if dry_run {
+                        return Ok(format!("Would delete key '{}' ({})", key.name, key.id));
+                    }
+
+                    self.keys.lock().unwrap().remove(&key.id);
 
                     self.log_audit(AuditLogEntry {
                         timestamp: Utc::now(),
@@ -403,7 +386,7 @@ impl SecurityPlugin {
 
                     Ok(format!("Key '{}' deleted", args[2]))
                 } else {
-                    Ok(format!("Key '{}' not found", args[2]))
+                    Err(ShellError::NotFound(format!("Key '{}'", args[2])).into())
                 }
             }
 
@@ -411,9 +394,9 @@ impl SecurityPlugin {
         }
     }
 
-    async fn handle_audit(&self, args: &[String]) -> Result<String> {
+    async fn handle_audit(&self, args: &[String], page: Page) -> Result<String> {
         if args.len() < 2 {
-            return Ok("Usage: security audit [list|export] [args...]".to_string());
+            return Err(ShellError::InvalidUsage("security audit [list|export] [args...]".to_string()).into());
         }
 
         match args[1].as_str() {
@@ -422,34 +405,28 @@ impl SecurityPlugin {
                     .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
                 let log_path = home_dir.join(AUDIT_LOG_PATH);
 
-                let content = fs::read_to_string(&log_path).await?;
-                let mut entries: Vec<AuditLogEntry> = Vec::new();
-
-                for line in content.lines() {
-                    if let Ok(entry) = serde_json::from_str(line) {
-                        entries.push(entry);
+                let mut after = None;
+                let mut before = None;
+                let mut i = 2;
+                while i < args.len() {
+                    match args[i].as_str() {
+                        "--after" | "--before" => {
+                            let value = args.get(i + 1)
+                                .ok_or_else(|| ShellError::InvalidUsage(format!("security audit list {} <time>", args[i])))?;
+                            let time = Some(parse_time(value)?);
+                            if args[i] == "--after" { after = time } else { before = time }
+                            i += 2;
+                        }
+                        _ => i += 1,
                     }
                 }
 
-                let mut output = String::new();
-                output.push_str(&format!("{:<30} {:<15} {:<15} {:<20} {:<10}\n",
-                    "TIMESTAMP", "ACTION", "USER", "RESOURCE", "STATUS"));
-
-                for entry in entries {
-                    output.push_str(&format!("{:<30} {:<15} {:<15} {:<20} {:<10}\n",
-                        entry.timestamp.to_rfc3339(),
-                        entry.action,
-                        entry.user,
-                        entry.resource,
-                        entry.status));
-                }
-
-                Ok(output)
+                audit_listing(&log_path, after, before, page).await
             }
 
             "export" => {
                 if args.len() < 3 {
-                    return Ok("Usage: security audit export <output_path>".to_string());
+                    return Err(ShellError::InvalidUsage("security audit export <output_path>".to_string()).into());
                 }
 
                 let home_dir = dirs::home_dir()
@@ -466,6 +443,108 @@ impl SecurityPlugin {
     }
 }
 
+/// The audit log as a table, optionally limited to entries between `after` and `before`.
+/// Page 1 holds the most recent entries; without a time filter only that page is read, from
+/// the end of the log.
+async fn audit_listing(
+    log_path: &std::path::Path,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    page: Page,
+) -> Result<String> {
+    let (skip, count) = match page.limit {
+        Some(limit) => (limit.saturating_mul(page.page - 1), limit),
+        None => (0, usize::MAX),
+    };
+    let parse = |lines: Vec<String>| -> Vec<AuditLogEntry> {
+        lines.iter().filter_map(|line| serde_json::from_str(line).ok()).collect()
+    };
+
+    let (entries, total) = if after.is_some() || before.is_some() {
+        let (lines, _) = tail_lines(log_path, 0, usize::MAX).await?;
+        let mut entries = parse(lines);
+        entries.retain(|entry| {
+            after.is_none_or(|after| entry.timestamp >= after)
+                && before.is_none_or(|before| entry.timestamp <= before)
+        });
+        let total = entries.len();
+        let end = total.saturating_sub(skip);
+        let start = end.saturating_sub(count);
+        (entries.drain(start..end).collect::<Vec<_>>(), total)
+    } else {
+        let (lines, total) = tail_lines(log_path, skip, count).await?;
+        (parse(lines), total)
+    };
+
+    let last = total.saturating_sub(skip);
+    let shown = entries.len();
+    let mut table = Table::new(["TIMESTAMP", "ACTION", "USER", "RESOURCE", "STATUS"]);
+    for entry in entries {
+        table.push_row([
+            entry.timestamp.to_rfc3339(),
+            entry.action,
+            entry.user,
+            entry.resource,
+            entry.status,
+        ]);
+    }
+
+    let mut output = table.render();
+    if let Some(footer) = page.footer(last + 1 - shown.min(last), last, total) {
+        output.push_str(&format!("\n{}", footer));
+    }
+    Ok(output)
+}
+
+/// Bytes read per step when scanning a log backwards.
+const TAIL_CHUNK_SIZE: u64 = 8 * 1024;
+
+/// The non-empty lines of `path`, skipping the last `skip` and keeping the `count` before
+/// them, in file order, along with the total number of non-empty lines. The file is read
+/// backwards, so only the lines kept are ever decoded. A missing file has no lines.
+async fn tail_lines(path: &std::path::Path, skip: usize, count: usize) -> Result<(Vec<String>, usize)> {
+    let mut file = match fs::File::open(path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut position = file.metadata().await?.len();
+    let mut kept = Vec::new();
+    let mut total = 0;
+    // Bytes of the line that straddles the chunk boundary, still waiting for its start
+    let mut partial: Vec<u8> = Vec::new();
+    let mut take_line = |line: &[u8], kept: &mut Vec<String>| {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return;
+        }
+        if total >= skip && total - skip < count {
+            kept.push(String::from_utf8_lossy(line).into_owned());
+        }
+        total += 1;
+    };
+
+    while position > 0 {
+        let size = TAIL_CHUNK_SIZE.min(position);
+        position -= size;
+        file.seek(std::io::SeekFrom::Start(position)).await?;
+        let mut chunk = vec![0u8; size as usize];
+        file.read_exact(&mut chunk).await?;
+
+        chunk.extend_from_slice(&partial);
+        // Newest line first; the last piece may continue in the chunk before this one
+        let mut pieces: Vec<&[u8]> = chunk.rsplit(|byte| *byte == b'\n').collect();
+        partial = pieces.pop().map(<[u8]>::to_vec).unwrap_or_default();
+        for line in pieces {
+            take_line(line, &mut kept);
+        }
+    }
+    take_line(&partial, &mut kept);
+
+    kept.reverse();
+    Ok((kept, total))
+}
+
 #[async_trait]
 impl Plugin for SecurityPlugin {
     fn name(&self) -> &str {
@@ -478,9 +557,10 @@ impl Plugin for SecurityPlugin {
 
     async fn execute(&self, command: &Command, _env: &Environment) -> Result<String> {
         match command.args.first().map(|s| s.as_str()) {
-            Some("credential") => self.handle_credential(&command.args).await,
-            Some("key") => self.handle_key(&command.args).await,
-            Some("audit") => self.handle_audit(&command.args).await,
+            Some("credential") => self.handle_credential(&command.args, command.flags.contains_key("dry-run")).await,
+            Some("key") => self.handle_key(&command.args, command.flags.contains_key("dry-run")).await,
+            // Read as typed, so `--after -2h` keeps its value
+            Some("audit") => self.handle_audit(&command.raw_args(), Page::from_command(command)?).await,
             _ => Ok("Available commands: credential, key, audit".to_string()),
         }
     }
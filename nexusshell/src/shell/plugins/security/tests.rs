@@ -1,144 +1,219 @@
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::tempdir;
-    use std::fs;
-    use std::path::Path;
-
-    #[tokio::test]
-    async fn test_credential_management() {
-        let plugin = SecurityPlugin::new().await.unwrap();
-
-        // Test adding credential
-        let result = plugin.handle_credential(&vec![
-            "credential".to_string(),
-            "add".to_string(),
-            "test-cred".to_string(),
-            "testuser".to_string(),
-            "testpass".to_string(),
-        ]).await.unwrap();
-        assert!(result.contains("successfully"));
-
-        // Test getting credential
-        let result = plugin.handle_credential(&vec![
-            "credential".to_string(),
-            "get".to_string(),
-            "test-cred".to_string(),
-        ]).await.unwrap();
-        assert!(result.contains("testuser"));
-        assert!(result.contains("testpass"));
-
-        // Test listing credentials
-        let result = plugin.handle_credential(&vec![
-            "credential".to_string(),
-            "list".to_string(),
-        ]).await.unwrap();
-        assert!(result.contains("test-cred"));
-        assert!(result.contains("testuser"));
-
-        // Test deleting credential
-        let result = plugin.handle_credential(&vec![
-            "credential".to_string(),
-            "delete".to_string(),
-            "test-cred".to_string(),
-        ]).await.unwrap();
-        assert!(result.contains("deleted"));
-    }
-
-    #[tokio::test]
-    async fn test_key_management() {
-        let plugin = SecurityPlugin::new().await.unwrap();
-        let temp_dir = tempdir().unwrap();
-
-        // Test generating key
-        let result = plugin.handle_key(&vec![
-            "key".to_string(),
-            "generate".to_string(),
-            "test-key".to_string(),
-        ]).await.unwrap();
-        assert!(result.contains("successfully"));
-
-        // Test listing keys
-        let result = plugin.handle_key(&vec![
-            "key".to_string(),
-            "list".to_string(),
-        ]).await.unwrap();
-        assert!(result.contains("test-key"));
-
-        // Test exporting key
-        let export_path = temp_dir.path().join("test-key.pem");
-        let result = plugin.handle_key(&vec![
-            "key".to_string(),
-            "export".to_string(),
-            "test-key".to_string(),
-            export_path.to_str().unwrap().to_string(),
-        ]).await.unwrap();
-        assert!(result.contains("exported"));
-        assert!(export_path.exists());
-
-        // Test importing key
-        let result = plugin.handle_key(&vec![
-            "key".to_string(),
-            "import".to_string(),
-            "imported-key".to_string(),
-            export_path.to_str().unwrap().to_string(),
-        ]).await.unwrap();
-        assert!(result.contains("imported"));
-
-        // Test deleting key
-        let result = plugin.handle_key(&vec![
-            "key".to_string(),
-            "delete".to_string(),
-            "test-key".to_string(),
-        ]).await.unwrap();
-        assert!(result.contains("deleted"));
-    }
-
-    #[tokio::test]
-    async fn test_encryption() {
-        let plugin = SecurityPlugin::new().await.unwrap();
-        let test_data = b"test data";
-
-        // Test encryption
-        let (encrypted, salt) = plugin.encrypt(test_data).unwrap();
-        assert!(!encrypted.is_empty());
-        assert!(!salt.is_empty());
-
-        // Test decryption
-        let decrypted = plugin.decrypt(&encrypted, &salt).unwrap();
-        assert_eq!(decrypted, test_data);
-    }
-
-    #[tokio::test]
-    async fn test_audit_logging() {
-        let plugin = SecurityPlugin::new().await.unwrap();
-        let temp_dir = tempdir().unwrap();
-
-        // Create some audit events
-        plugin.handle_credential(&vec![
-            "credential".to_string(),
-            "add".to_string(),
-            "test-cred".to_string(),
-            "testuser".to_string(),
-            "testpass".to_string(),
-        ]).await.unwrap();
-
-        // Test listing audit log
-        let result = plugin.handle_audit(&vec![
-            "audit".to_string(),
-            "list".to_string(),
-        ]).await.unwrap();
-        assert!(result.contains("credential_add"));
-        assert!(result.contains("test-cred"));
-
-        // Test exporting audit log
-        let export_path = temp_dir.path().join("audit.log");
-        let result = plugin.handle_audit(&vec![
-            "audit".to_string(),
-            "export".to_string(),
-            export_path.to_str().unwrap().to_string(),
-        ]).await.unwrap();
-        assert!(result.contains("exported"));
-        assert!(export_path.exists());
-    }
+use super::*;
+use crate::shell::Parser;
+use tempfile::tempdir;
+use std::fs;
+
+#[tokio::test]
+async fn test_credential_management() {
+    let plugin = SecurityPlugin::new().await.unwrap();
+
+    // Test adding credential
+    let result = plugin.handle_credential(&["credential".to_string(),
+        "add".to_string(),
+        "test-cred".to_string(),
+        "testuser".to_string(),
+        "testpass".to_string()], false).await.unwrap();
+    assert!(result.contains("successfully"));
+
+    // Test getting credential
+    let result = plugin.handle_credential(&["credential".to_string(),
+        "get".to_string(),
+        "test-cred".to_string()], false).await.unwrap();
+    assert!(result.contains("testuser"));
+    assert!(result.contains("testpass"));
+
+    // Test listing credentials
+    let result = plugin.handle_credential(&["credential".to_string(),
+        "list".to_string()], false).await.unwrap();
+    assert!(result.contains("test-cred"));
+    assert!(result.contains("testuser"));
+
+    // Test deleting credential
+    let result = plugin.handle_credential(&["credential".to_string(),
+        "delete".to_string(),
+        "test-cred".to_string()], false).await.unwrap();
+    assert!(result.contains("deleted"));
+}
+
+#[tokio::test]
+async fn test_key_management() {
+    let plugin = SecurityPlugin::new().await.unwrap();
+    let temp_dir = tempdir().unwrap();
+
+    // Test generating key
+    let result = plugin.handle_key(&["key".to_string(),
+        "generate".to_string(),
+        "test-key".to_string()], false).await.unwrap();
+    assert!(result.contains("successfully"));
+
+    // Test listing keys
+    let result = plugin.handle_key(&["key".to_string(),
+        "list".to_string()], false).await.unwrap();
+    assert!(result.contains("test-key"));
+
+    // Test exporting key
+    let export_path = temp_dir.path().join("test-key.pem");
+    let result = plugin.handle_key(&["key".to_string(),
+        "export".to_string(),
+        "test-key".to_string(),
+        export_path.to_str().unwrap().to_string()], false).await.unwrap();
+    assert!(result.contains("exported"));
+    assert!(export_path.exists());
+
+    // Test importing key
+    let result = plugin.handle_key(&["key".to_string(),
+        "import".to_string(),
+        "imported-key".to_string(),
+        export_path.to_str().unwrap().to_string()], false).await.unwrap();
+    assert!(result.contains("imported"));
+
+    // Test deleting key
+    let result = plugin.handle_key(&["key".to_string(),
+        "delete".to_string(),
+        "test-key".to_string()], false).await.unwrap();
+    assert!(result.contains("deleted"));
+}
+
+#[tokio::test]
+async fn test_encryption() {
+    let plugin = SecurityPlugin::new().await.unwrap();
+    let test_data = b"test data";
+
+    // Test encryption
+    let (encrypted, salt) = plugin.encrypt(test_data).unwrap();
+    assert!(!encrypted.is_empty());
+    assert!(!salt.is_empty());
+
+    // Test decryption
+    let decrypted = plugin.decrypt(&encrypted, &salt).unwrap();
+    assert_eq!(decrypted, test_data);
+}
+
+#[tokio::test]
+async fn test_audit_logging() {
+    let plugin = SecurityPlugin::new().await.unwrap();
+    let temp_dir = tempdir().unwrap();
+
+    // Create some audit events
+    plugin.handle_credential(&["credential".to_string(),
+        "add".to_string(),
+        "test-cred".to_string(),
+        "testuser".to_string(),
+        "testpass".to_string()], false).await.unwrap();
+
+    // Test listing audit log
+    let result = plugin.handle_audit(&["audit".to_string(),
+        "list".to_string()], Page::default()).await.unwrap();
+    assert!(result.contains("credential_add"));
+    assert!(result.contains("test-cred"));
+
+    // Test exporting audit log
+    let export_path = temp_dir.path().join("audit.log");
+    let result = plugin.handle_audit(&["audit".to_string(),
+        "export".to_string(),
+        export_path.to_str().unwrap().to_string()], Page::default()).await.unwrap();
+    assert!(result.contains("exported"));
+    assert!(export_path.exists());
+}
+
+#[tokio::test]
+async fn test_delete_dry_run() {
+    let plugin = SecurityPlugin::new().await.unwrap();
+    let env = Environment::new();
+    let run = |line: &str| {
+        let command = Parser::new().parse(line).unwrap();
+        let plugin = &plugin;
+        let env = &env;
+        async move { plugin.execute(&command, env).await }
+    };
+
+    run("security credential add dry-cred dryuser drypass").await.unwrap();
+    let result = run("security credential delete dry-cred --dry-run").await.unwrap();
+    assert!(result.contains("Would delete credential 'dry-cred'"));
+    assert!(run("security credential get dry-cred").await.unwrap().contains("dryuser"));
+
+    run("security key generate dry-key").await.unwrap();
+    let result = run("security key delete dry-key --dry-run").await.unwrap();
+    assert!(result.contains("Would delete key 'dry-key'"));
+    assert!(run("security key list").await.unwrap().contains("dry-key"));
+
+    let err = run("security key delete missing-key --dry-run").await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_error_variants() {
+    let plugin = SecurityPlugin::new().await.unwrap();
+
+    let err = plugin.handle_credential(&["credential".to_string(),
+        "get".to_string(),
+        "no-such-cred".to_string()], false).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::NotFound(_))));
+
+    let err = plugin.handle_credential(&["credential".to_string(),
+        "get".to_string()], false).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<ShellError>(), Some(ShellError::InvalidUsage(_))));
+    assert_eq!(err.downcast_ref::<ShellError>().unwrap().exit_code(), 2);
+}
+
+#[tokio::test]
+async fn test_tail_lines_pages_from_the_end() {
+    let temp_dir = tempdir().unwrap();
+    let log_path = temp_dir.path().join("audit.log");
+    // Long enough to span several read chunks
+    let content: String = (1..=2000).map(|i| format!("{{\"entry\":{}}}\n", i)).collect();
+    fs::write(&log_path, content).unwrap();
+
+    let (lines, total) = tail_lines(&log_path, 0, 3).await.unwrap();
+    assert_eq!(total, 2000);
+    assert_eq!(lines, vec!["{\"entry\":1998}", "{\"entry\":1999}", "{\"entry\":2000}"]);
+
+    // Page 3 of 10 per page, newest first: entries 1971-1980 in file order
+    let page = Page { limit: Some(10), page: 3 };
+    let (lines, _) = tail_lines(&log_path, 20, page.limit.unwrap()).await.unwrap();
+    assert_eq!(lines.first().map(String::as_str), Some("{\"entry\":1971}"));
+    assert_eq!(lines.last().map(String::as_str), Some("{\"entry\":1980}"));
+    assert_eq!(page.footer(1971, 1980, total).as_deref(), Some("showing 1971–1980 of 2000"));
+
+    let (lines, _) = tail_lines(&log_path, 1995, 10).await.unwrap();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], "{\"entry\":1}");
+
+    let (lines, total) = tail_lines(&temp_dir.path().join("missing.log"), 0, 10).await.unwrap();
+    assert!(lines.is_empty());
+    assert_eq!(total, 0);
+}
+
+#[tokio::test]
+async fn test_audit_list_filters_by_time() {
+    let temp_dir = tempdir().unwrap();
+    let log_path = temp_dir.path().join("audit.log");
+    let content: String = [("old", 72), ("recent", 3), ("latest", 0)]
+        .iter()
+        .map(|(resource, hours_ago)| {
+            let entry = AuditLogEntry {
+                timestamp: Utc::now() - chrono::Duration::hours(*hours_ago),
+                action: "credential_add".to_string(),
+                user: "ops".to_string(),
+                resource: resource.to_string(),
+                status: "success".to_string(),
+                details: None,
+            };
+            format!("{}\n", serde_json::to_string(&entry).unwrap())
+        })
+        .collect();
+    fs::write(&log_path, content).unwrap();
+
+    let output = audit_listing(&log_path, Some(parse_time("1d").unwrap()), None, Page::default()).await.unwrap();
+    assert!(output.contains("recent") && output.contains("latest"));
+    assert!(!output.contains("old"));
+
+    let output = audit_listing(&log_path, None, Some(parse_time("-1h").unwrap()), Page::default()).await.unwrap();
+    assert!(output.contains("old") && output.contains("recent"));
+    assert!(!output.contains("latest"));
+
+    let page = Page { limit: Some(1), page: 1 };
+    let output = audit_listing(&log_path, Some(parse_time("yesterday").unwrap() - chrono::Duration::days(7)), None, page).await.unwrap();
+    assert!(output.contains("latest"));
+    assert!(output.ends_with("showing 3–3 of 3"));
 }
@@ -0,0 +1,269 @@
+use super::{Command, Environment, Executor, Parser, ShellError};
+use super::parser::split_stages;
+use std::collections::{HashMap, HashSet};
+
+/// Turns a raw input line into commands. Stages run in a fixed order: history expansion
+/// (`!!`, `!n`, `!-n`, `!prefix`), alias expansion, variable and command substitution
+/// (`$NAME`, `${NAME}`, `$(...)`), then tokenization. Single-quoted text is left alone.
+pub struct Preprocessor {
+    aliases: HashMap<String, String>,
+    parser: Parser,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Preprocessor {
+            aliases: HashMap::new(),
+            parser: Parser::new(),
+        }
+    }
+
+    pub fn set_alias(&mut self, name: String, value: String) {
+        self.aliases.insert(name, value);
+    }
+
+    pub fn remove_alias(&mut self, name: &str) -> bool {
+        self.aliases.remove(name).is_some()
+    }
+
+    pub fn aliases(&self) -> Vec<(String, String)> {
+        let mut aliases: Vec<_> = self.aliases.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        aliases.sort();
+        aliases
+    }
+
+    /// Final stage: splits an expanded line into pipeline stages.
+    pub fn tokenize(&self, line: &str) -> anyhow::Result<Vec<Command>> {
+        self.parser.parse_pipeline(line)
+    }
+
+    /// First stage: resolves `!!`, `!n`, `!-n` and `!prefix`. The shell records this line.
+    pub fn expand_history(&self, input: &str, history: &[String]) -> anyhow::Result<String> {
+        expand_history(input, history)
+    }
+
+    /// The stages after history expansion: aliases, then variable and command substitution,
+    /// returning the line that will be tokenized.
+    pub async fn expand_commands(&self, line: &str, env: &Environment, executor: &Executor) -> anyhow::Result<String> {
+        let line = self.expand_aliases(line);
+        self.substitute(&line, env, Some(executor)).await
+    }
+
+    /// Replaces the first word of each pipeline stage with its alias. A word is never
+    /// expanded twice in the same chain, so `alias ls='ls -la'` and alias cycles terminate.
+    pub fn expand_aliases(&self, line: &str) -> String {
+        split_stages(line)
+            .into_iter()
+            .map(|stage| {
+                let mut stage = stage.to_string();
+                let mut seen = HashSet::new();
+                loop {
+                    let trimmed = stage.trim_start();
+                    let indent = &stage[..stage.len() - trimmed.len()];
+                    let word = trimmed.split_whitespace().next().unwrap_or("");
+                    let value = match self.aliases.get(word) {
+                        Some(value) if seen.insert(word.to_string()) => value,
+                        _ => break,
+                    };
+                    stage = format!("{}{}{}", indent, value, &trimmed[word.len()..]);
+                }
+                stage
+            })
+            .collect::<Vec<_>>()
+            .join("|")
+    }
+
+    /// Variable and command substitution. `executor` is `None` while expanding the body of a
+    /// `$(...)`, which keeps substitutions from nesting.
+    async fn substitute(
+        &self,
+        line: &str,
+        env: &Environment,
+        executor: Option<&Executor>,
+    ) -> anyhow::Result<String> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut output = String::new();
+        let mut in_single_quotes = false;
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            if c == '\'' {
+                in_single_quotes = !in_single_quotes;
+            }
+            if c != '$' || in_single_quotes || i + 1 == chars.len() {
+                output.push(c);
+                i += 1;
+                continue;
+            }
+
+            match chars[i + 1] {
+                '(' => {
+                    let end = matching_paren(&chars, i + 1).ok_or_else(|| {
+                        ShellError::InvalidUsage("command substitution: unterminated $(".to_string())
+                    })?;
+                    let body: String = chars[i + 2..end].iter().collect();
+                    let executor = executor.ok_or_else(|| {
+                        ShellError::External("command substitution: nested $(...) is not supported".to_string())
+                    })?;
+                    output.push_str(&self.run_substitution(&body, env, executor).await?);
+                    i = end + 1;
+                }
+                '{' => {
+                    let end = chars[i + 2..].iter().position(|&c| c == '}').map(|p| p + i + 2).ok_or_else(|| {
+                        ShellError::InvalidUsage("variable expansion: unterminated ${".to_string())
+                    })?;
+                    let name: String = chars[i + 2..end].iter().collect();
+                    output.push_str(env.get_var(&name).map(|s| s.as_str()).unwrap_or(""));
+                    i = end + 1;
+                }
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    let len = chars[i + 1..]
+                        .iter()
+                        .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                        .count();
+                    let name: String = chars[i + 1..i + 1 + len].iter().collect();
+                    output.push_str(env.get_var(&name).map(|s| s.as_str()).unwrap_or(""));
+                    i += 1 + len;
+                }
+                _ => {
+                    output.push(c);
+                    i += 1;
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    async fn run_substitution(&self, body: &str, env: &Environment, executor: &Executor) -> anyhow::Result<String> {
+        let body = self.expand_aliases(body);
+        let body = Box::pin(self.substitute(&body, env, None)).await?;
+        let commands = self.parser.parse_pipeline(&body)?;
+        let output = executor.execute_pipeline(&commands, env).await
+            .map_err(|e| ShellError::External(format!("command substitution: {}", e)))?;
+        Ok(output.trim_end_matches('\n').to_string())
+    }
+}
+
+/// Index of the `)` closing the `(` at `open`.
+fn matching_paren(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &c) in chars.iter().enumerate().skip(open) {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Expands `!!`, `!n`, `!-n` and `!prefix` against `history` (oldest first). Quoted text and
+/// a `!` followed by whitespace, `=`, `(` or `"` (or ending the line) are left alone.
+fn expand_history(line: &str, history: &[String]) -> anyhow::Result<String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut output = String::new();
+    let mut quote = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match quote {
+            Some(q) if c == q => quote = None,
+            None if c == '\'' || c == '"' => quote = Some(c),
+            _ => {}
+        }
+        let next = chars.get(i + 1).copied();
+        let designator = next.is_some_and(|n| !n.is_whitespace() && !matches!(n, '=' | '(' | '"'));
+        if c != '!' || quote.is_some() || !designator {
+            output.push(c);
+            i += 1;
+            continue;
+        }
+
+        let len = if next == Some('!') {
+            1
+        } else {
+            chars[i + 1..].iter().take_while(|c| !c.is_whitespace() && **c != '|').count()
+        };
+        let event: String = chars[i + 1..i + 1 + len].iter().collect();
+
+        let entry = if event == "!" {
+            history.last()
+        } else if let Some(back) = event.strip_prefix('-').and_then(|n| n.parse::<usize>().ok()) {
+            history.len().checked_sub(back).and_then(|index| history.get(index))
+        } else if let Ok(number) = event.parse::<usize>() {
+            number.checked_sub(1).and_then(|index| history.get(index))
+        } else {
+            history.iter().rev().find(|entry| entry.starts_with(&event))
+        };
+
+        let entry = entry.ok_or_else(|| ShellError::NotFound(format!("history expansion: event '!{}'", event)))?;
+        output.push_str(entry);
+        i += 1 + len;
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::PluginManager;
+
+    fn executor() -> Executor {
+        Executor::new(PluginManager::new())
+    }
+
+    #[tokio::test]
+    async fn test_alias_then_variable_expansion() {
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.set_alias("greet".to_string(), "echo hello $WHO".to_string());
+        let mut env = Environment::new();
+        env.set_var("WHO".to_string(), "world".to_string());
+
+        let line = preprocessor.expand_commands("greet and ${WHO}s", &env, &executor()).await.unwrap();
+        assert_eq!(line, "echo hello world and worlds");
+
+        let line = preprocessor.expand_commands("echo '$WHO'", &env, &executor()).await.unwrap();
+        assert_eq!(line, "echo '$WHO'");
+    }
+
+    #[test]
+    fn test_alias_recursion_guard() {
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.set_alias("ls".to_string(), "ls -la".to_string());
+        preprocessor.set_alias("a".to_string(), "b 1".to_string());
+        preprocessor.set_alias("b".to_string(), "a 2".to_string());
+
+        assert_eq!(preprocessor.expand_aliases("ls /tmp | ls"), "ls -la /tmp | ls -la");
+        assert_eq!(preprocessor.expand_aliases("a"), "a 2 1");
+    }
+
+    #[tokio::test]
+    async fn test_history_and_command_substitution() {
+        let preprocessor = Preprocessor::new();
+        let env = Environment::new();
+        let history = vec!["echo first".to_string(), "git status".to_string()];
+
+        assert_eq!(preprocessor.expand_history("!!", &history).unwrap(), "git status");
+        assert_eq!(preprocessor.expand_history("!ec", &history).unwrap(), "echo first");
+        assert_eq!(preprocessor.expand_history("!1 again", &history).unwrap(), "echo first again");
+
+        let err = preprocessor.expand_history("!nope", &history).unwrap_err();
+        assert!(err.to_string().starts_with("history expansion"));
+
+        for line in ["echo \"hi!!\"", "echo 'hi!!'", "echo hi! there", "echo \"wow!\"", "echo done!"] {
+            assert_eq!(preprocessor.expand_history(line, &history).unwrap(), line);
+        }
+
+        let line = preprocessor.expand_commands("echo $(echo inner)!", &env, &executor()).await.unwrap();
+        assert_eq!(line, "echo inner!");
+    }
+}
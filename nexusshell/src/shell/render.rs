@@ -0,0 +1,225 @@
+use super::Command;
+use super::table::Table;
+use serde::Serialize;
+
+/// How a structured command prints its results, chosen with `--output`/`-o`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "table" => Some(OutputFormat::Table),
+            "json" => Some(OutputFormat::Json),
+            "yaml" | "yml" => Some(OutputFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Reads `--output FORMAT`, `--output=FORMAT` or `-o FORMAT`; `--json` is kept as a
+    /// shorthand for `--output json`.
+    pub fn from_command(command: &Command) -> anyhow::Result<Self> {
+        let value = ["output", "o"].iter()
+            .find_map(|flag| command.flags.get(*flag).and_then(|value| value.as_deref()));
+        match value {
+            Some(value) => Self::parse(value)
+                .ok_or_else(|| anyhow::anyhow!("Unknown output format '{}' (expected table, json or yaml)", value)),
+            None if command.flags.contains_key("json") => Ok(OutputFormat::Json),
+            None => Ok(OutputFormat::Table),
+        }
+    }
+
+    pub fn render<T: Record>(self, records: &[T]) -> anyhow::Result<String> {
+        match self {
+            OutputFormat::Table => TableRenderer.render(records),
+            OutputFormat::Json => JsonRenderer.render(records),
+            OutputFormat::Yaml => YamlRenderer.render(records),
+        }
+    }
+}
+
+/// Rows per page when `--page` is given without `--limit`.
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+
+/// A `--limit N [--page P]` window over a long listing. Pages count from 1; without a limit
+/// everything is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub limit: Option<usize>,
+    pub page: usize,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Page { limit: None, page: 1 }
+    }
+}
+
+impl Page {
+    pub fn from_command(command: &Command) -> anyhow::Result<Self> {
+        let number = |flag: &str| -> anyhow::Result<Option<usize>> {
+            match command.flags.get(flag) {
+                None => Ok(None),
+                Some(value) => value.as_deref()
+                    .and_then(|value| value.parse().ok())
+                    .filter(|n: &usize| *n > 0)
+                    .map(Some)
+                    .ok_or_else(|| anyhow::anyhow!("--{} requires a positive number", flag)),
+            }
+        };
+        let limit = number("limit")?;
+        let page = number("page")?;
+        Ok(Page {
+            limit: limit.or(page.map(|_| DEFAULT_PAGE_SIZE)),
+            page: page.unwrap_or(1),
+        })
+    }
+
+    /// The indices of `total` items that fall on this page.
+    pub fn window(&self, total: usize) -> std::ops::Range<usize> {
+        match self.limit {
+            Some(limit) => {
+                let start = limit.saturating_mul(self.page - 1).min(total);
+                start..start.saturating_add(limit).min(total)
+            }
+            None => 0..total,
+        }
+    }
+
+    pub fn apply<T>(&self, items: Vec<T>) -> Vec<T> {
+        let window = self.window(items.len());
+        items.into_iter().skip(window.start).take(window.len()).collect()
+    }
+
+    /// "showing X–Y of Z" for a paged listing whose page covers 1-based positions `first..=last`.
+    pub fn footer(&self, first: usize, last: usize, total: usize) -> Option<String> {
+        self.limit?;
+        Some(if first > last {
+            format!("page {} is past the end ({} total)", self.page, total)
+        } else {
+            format!("showing {}–{} of {}", first, last, total)
+        })
+    }
+}
+
+/// A row of structured command output. The serialized form is used for json/yaml,
+/// `cells` for the table.
+pub trait Record: Serialize {
+    fn headers() -> &'static [&'static str];
+    fn cells(&self) -> Vec<String>;
+}
+
+pub trait Renderer {
+    fn render<T: Record>(&self, records: &[T]) -> anyhow::Result<String>;
+}
+
+/// Left-aligned columns sized to their widest cell, see `Table`.
+pub struct TableRenderer;
+
+impl Renderer for TableRenderer {
+    fn render<T: Record>(&self, records: &[T]) -> anyhow::Result<String> {
+        let mut table = Table::new(T::headers().iter().copied());
+        for record in records {
+            table.push_row(record.cells());
+        }
+        Ok(table.render())
+    }
+}
+
+pub struct JsonRenderer;
+
+impl Renderer for JsonRenderer {
+    fn render<T: Record>(&self, records: &[T]) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(records)?)
+    }
+}
+
+pub struct YamlRenderer;
+
+impl Renderer for YamlRenderer {
+    fn render<T: Record>(&self, records: &[T]) -> anyhow::Result<String> {
+        Ok(serde_yaml::to_string(records)?.trim_end().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::Parser;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Service {
+        name: String,
+        replicas: u32,
+    }
+
+    impl Record for Service {
+        fn headers() -> &'static [&'static str] {
+            &["NAME", "REPLICAS"]
+        }
+
+        fn cells(&self) -> Vec<String> {
+            vec![self.name.clone(), self.replicas.to_string()]
+        }
+    }
+
+    fn fixture() -> Vec<Service> {
+        vec![
+            Service { name: "web".to_string(), replicas: 3 },
+            Service { name: "scheduler".to_string(), replicas: 1 },
+        ]
+    }
+
+    #[test]
+    fn test_render_all_formats() {
+        let services = fixture();
+
+        let table = OutputFormat::Table.render(&services).unwrap();
+        assert_eq!(table, "NAME       REPLICAS\nweb        3\nscheduler  1");
+
+        let json = OutputFormat::Json.render(&services).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Service>>(&json).unwrap(), services);
+
+        let yaml = OutputFormat::Yaml.render(&services).unwrap();
+        assert_eq!(serde_yaml::from_str::<Vec<Service>>(&yaml).unwrap(), services);
+    }
+
+    #[test]
+    fn test_page_window() {
+        let parser = Parser::new();
+        let page = |input: &str| Page::from_command(&parser.parse(input).unwrap());
+
+        assert_eq!(page("schedule list").unwrap(), Page::default());
+        assert_eq!(page("schedule list --limit 10 --page 3").unwrap(), Page { limit: Some(10), page: 3 });
+        assert_eq!(page("schedule list --page 2").unwrap().limit, Some(DEFAULT_PAGE_SIZE));
+        assert!(page("schedule list --limit 0").is_err());
+        assert!(page("schedule list --page two").is_err());
+
+        let page = Page { limit: Some(10), page: 3 };
+        assert_eq!(page.window(25), 20..25);
+        assert_eq!(page.apply((1..=25).collect()), vec![21, 22, 23, 24, 25]);
+        assert_eq!(page.footer(21, 25, 25).as_deref(), Some("showing 21–25 of 25"));
+        assert_eq!(Page { limit: Some(10), page: 4 }.window(25), 25..25);
+        assert_eq!(Page::default().window(25), 0..25);
+        assert_eq!(Page::default().footer(1, 25, 25), None);
+    }
+
+    #[test]
+    fn test_output_format_from_command() {
+        let parser = Parser::new();
+        let format = |input: &str| OutputFormat::from_command(&parser.parse(input).unwrap());
+
+        assert_eq!(format("ps").unwrap(), OutputFormat::Table);
+        assert_eq!(format("ps -o json").unwrap(), OutputFormat::Json);
+        assert_eq!(format("ps --output yaml").unwrap(), OutputFormat::Yaml);
+        assert_eq!(format("ps --output=table").unwrap(), OutputFormat::Table);
+        assert_eq!(format("ps --json").unwrap(), OutputFormat::Json);
+        assert!(format("ps -o xml").is_err());
+    }
+}
@@ -1,62 +1,216 @@
 use syntect::easy::HighlightLines;
-use syntect::highlighting::{ThemeSet, Style};
-use syntect::parsing::SyntaxSet;
+use syntect::highlighting::{Theme, ThemeSet, Style};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
 use syntect::util::as_24_bit_terminal_escaped;
 use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use super::color;
 
 lazy_static! {
-    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
-    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
+    static ref SYNTAX_SET: Arc<SyntaxSet> = Arc::new(SyntaxSet::load_defaults_newlines());
+    static ref THEME_SET: Arc<ThemeSet> = Arc::new(ThemeSet::load_defaults());
 }
 
+const DEFAULT_THEME: &str = "base16-ocean.dark";
+/// How many extension -> syntax lookups are remembered.
+const SYNTAX_CACHE_SIZE: usize = 32;
+
+/// Extra `.sublime-syntax` files (under `syntaxes/`) and `.tmTheme` files (under `themes/`),
+/// relative to the home directory.
+pub const USER_SYNTAX_DIR: &str = ".nexusshell/syntax";
+
 pub struct SyntaxHighlighter {
-    syntax_set: &'static SyntaxSet,
-    theme_set: &'static ThemeSet,
+    syntax_set: Arc<SyntaxSet>,
+    theme: Theme,
+    /// Most recently used first; values index into `syntax_set.syntaxes()`
+    syntax_cache: Mutex<VecDeque<(String, usize)>>,
 }
 
 impl SyntaxHighlighter {
+    /// The bundled syntaxes and themes, plus any found in `USER_SYNTAX_DIR`.
     pub fn new() -> Self {
+        let user_dir = dirs::home_dir().map(|home| home.join(USER_SYNTAX_DIR));
+        match user_dir.filter(|dir| dir.is_dir()) {
+            Some(dir) => Self::with_user_dir(&dir).unwrap_or_else(|e| {
+                eprintln!("Warning: ignoring custom syntaxes in {}: {}", dir.display(), e);
+                Self::from_sets(SYNTAX_SET.clone(), &THEME_SET)
+            }),
+            None => Self::from_sets(SYNTAX_SET.clone(), &THEME_SET),
+        }
+    }
+
+    /// Adds the syntaxes in `dir/syntaxes` and themes in `dir/themes` to the bundled ones.
+    /// A `theme` file in `dir` containing a theme name selects that theme.
+    pub fn with_user_dir(dir: &Path) -> anyhow::Result<Self> {
+        let syntaxes = dir.join("syntaxes");
+        let syntax_set = if syntaxes.is_dir() {
+            let mut builder = SyntaxSet::clone(&SYNTAX_SET).into_builder();
+            builder.add_from_folder(&syntaxes, true)?;
+            Arc::new(builder.build())
+        } else {
+            SYNTAX_SET.clone()
+        };
+
+        let themes = dir.join("themes");
+        let mut theme_set = ThemeSet { themes: THEME_SET.themes.clone() };
+        if themes.is_dir() {
+            theme_set.add_from_folder(&themes)?;
+        }
+
+        let mut highlighter = Self::from_sets(syntax_set, &theme_set);
+        if let Ok(name) = std::fs::read_to_string(dir.join("theme")) {
+            let name = name.trim();
+            highlighter.theme = theme_set.themes.get(name).cloned()
+                .ok_or_else(|| anyhow::anyhow!("unknown theme '{}'", name))?;
+        }
+        Ok(highlighter)
+    }
+
+    fn from_sets(syntax_set: Arc<SyntaxSet>, theme_set: &ThemeSet) -> Self {
         SyntaxHighlighter {
-            syntax_set: &SYNTAX_SET,
-            theme_set: &THEME_SET,
+            syntax_set,
+            theme: theme_set.themes[DEFAULT_THEME].clone(),
+            syntax_cache: Mutex::new(VecDeque::with_capacity(SYNTAX_CACHE_SIZE)),
+        }
+    }
+
+    /// The syntax for a file extension, falling back to plain text.
+    fn syntax_for(&self, extension: &str) -> &SyntaxReference {
+        let syntaxes = self.syntax_set.syntaxes();
+        let mut cache = match self.syntax_cache.lock() {
+            Ok(cache) => cache,
+            Err(_) => {
+                return self.syntax_set.find_syntax_by_extension(extension)
+                    .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text())
+            }
+        };
+
+        if let Some(position) = cache.iter().position(|(cached, _)| cached == extension) {
+            let entry = cache.remove(position).unwrap();
+            let index = entry.1;
+            cache.push_front(entry);
+            return &syntaxes[index];
         }
+
+        let syntax = self.syntax_set.find_syntax_by_extension(extension)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        let index = syntaxes.iter().position(|candidate| std::ptr::eq(candidate, syntax)).unwrap_or_default();
+        if cache.len() == SYNTAX_CACHE_SIZE {
+            cache.pop_back();
+        }
+        cache.push_front((extension.to_string(), index));
+        syntax
     }
 
     pub fn highlight_command(&self, input: &str) -> String {
+        if !color::enabled() {
+            return input.to_string();
+        }
+
         // Use the shell script syntax for command highlighting
-        let syntax = self.syntax_set.find_syntax_by_extension("sh")
-            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-        
-        let mut highlighter = HighlightLines::new(syntax, &self.theme_set.themes["base16-ocean.dark"]);
-        let ranges: Vec<(Style, &str)> = highlighter.highlight_line(input, self.syntax_set).unwrap();
-        
+        let mut highlighter = HighlightLines::new(self.syntax_for("sh"), &self.theme);
+        let ranges: Vec<(Style, &str)> = highlighter.highlight_line(input, &self.syntax_set).unwrap();
+
         as_24_bit_terminal_escaped(&ranges[..], false)
     }
 
     pub fn highlight_file(&self, content: &str, extension: &str) -> String {
-        let syntax = self.syntax_set.find_syntax_by_extension(extension)
-            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-        
-        let mut highlighter = HighlightLines::new(syntax, &self.theme_set.themes["base16-ocean.dark"]);
+        if !color::enabled() {
+            return content.to_string();
+        }
+
+        let mut highlighter = self.line_highlighter(extension);
         let mut output = String::new();
 
         for line in content.lines() {
-            let ranges: Vec<(Style, &str)> = highlighter.highlight_line(line, self.syntax_set).unwrap();
-            let escaped = as_24_bit_terminal_escaped(&ranges[..], false);
-            output.push_str(&escaped);
+            output.push_str(&highlighter.highlight(line));
             output.push('\n');
         }
 
         output
     }
 
-    pub fn highlight_help(&self, content: &str) -> String {
-        let syntax = self.syntax_set.find_syntax_by_extension("md")
-            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-        
-        let mut highlighter = HighlightLines::new(syntax, &self.theme_set.themes["base16-ocean.dark"]);
-        let ranges: Vec<(Style, &str)> = highlighter.highlight_line(content, self.syntax_set).unwrap();
-        
-        as_24_bit_terminal_escaped(&ranges[..], false)
+    /// Highlights `reader` line by line into `out`, so large files never have to be held
+    /// in memory. Produces the same text as `highlight_file`.
+    pub fn highlight_reader(&self, reader: impl BufRead, extension: &str, mut out: impl Write) -> io::Result<()> {
+        let mut highlighter = self.line_highlighter(extension);
+        for line in reader.lines() {
+            writeln!(out, "{}", highlighter.highlight(&line?))?;
+        }
+        out.flush()
+    }
+
+    /// A highlighter for feeding a file one line at a time, in order.
+    pub fn line_highlighter(&self, extension: &str) -> LineHighlighter<'_> {
+        LineHighlighter {
+            highlighter: color::enabled().then(|| HighlightLines::new(self.syntax_for(extension), &self.theme)),
+            syntax_set: &self.syntax_set,
+        }
+    }
+}
+
+/// Highlights successive lines of one file. The parse state carries over from line to line,
+/// so multi-line constructs such as block comments stay highlighted.
+pub struct LineHighlighter<'a> {
+    /// `None` when color is off
+    highlighter: Option<HighlightLines<'a>>,
+    syntax_set: &'a SyntaxSet,
+}
+
+impl LineHighlighter<'_> {
+    pub fn highlight(&mut self, line: &str) -> String {
+        match self.highlighter.as_mut().map(|highlighter| highlighter.highlight_line(line, self.syntax_set)) {
+            Some(Ok(ranges)) => as_24_bit_terminal_escaped(&ranges[..], false),
+            _ => line.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syntax_cache_reuses_reference() {
+        let highlighter = SyntaxHighlighter::from_sets(SYNTAX_SET.clone(), &THEME_SET);
+
+        let first = highlighter.syntax_for("rs") as *const SyntaxReference;
+        let second = highlighter.syntax_for("rs") as *const SyntaxReference;
+        assert_eq!(first, second);
+        assert_eq!(highlighter.syntax_set.syntaxes()[highlighter.syntax_cache.lock().unwrap()[0].1].name, "Rust");
+        assert_eq!(highlighter.syntax_cache.lock().unwrap().len(), 1);
+
+        // Unknown extensions cache the plain text fallback; the oldest entry is evicted
+        for i in 0..SYNTAX_CACHE_SIZE {
+            highlighter.syntax_for(&format!("unknown{}", i));
+        }
+        let cache = highlighter.syntax_cache.lock().unwrap();
+        assert_eq!(cache.len(), SYNTAX_CACHE_SIZE);
+        assert!(cache.iter().all(|(extension, _)| extension != "rs"));
+    }
+
+    #[test]
+    fn test_streaming_matches_buffered() {
+        let highlighter = SyntaxHighlighter::from_sets(SYNTAX_SET.clone(), &THEME_SET);
+        let fixture = "/* a block comment\n   spanning lines */\nfn main() {\n    println!(\"hi\");\n}\n";
+
+        let mut streamed = Vec::new();
+        highlighter.highlight_reader(io::Cursor::new(fixture), "rs", &mut streamed).unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), highlighter.highlight_file(fixture, "rs"));
+    }
+
+    #[test]
+    fn test_user_dir_theme() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("theme"), "InspiredGitHub\n").unwrap();
+        let highlighter = SyntaxHighlighter::with_user_dir(temp_dir.path()).unwrap();
+        assert_eq!(highlighter.theme.settings.background, THEME_SET.themes["InspiredGitHub"].settings.background);
+        assert_ne!(highlighter.theme.settings.background, THEME_SET.themes[DEFAULT_THEME].settings.background);
+
+        std::fs::write(temp_dir.path().join("theme"), "no-such-theme").unwrap();
+        assert!(SyntaxHighlighter::with_user_dir(temp_dir.path()).is_err());
     }
 }
@@ -0,0 +1,186 @@
+use super::color;
+
+/// Cells wider than this are cut short unless the column sets its own limit.
+pub const DEFAULT_MAX_WIDTH: usize = 48;
+
+const ELLIPSIS: char = '…';
+
+/// A plain-text table whose columns are sized to their widest cell.
+///
+/// Widths count visible characters, so cells may carry color escapes; when color is off the
+/// escapes are dropped. A cell over its column's limit is cut short with an ellipsis, losing
+/// its color.
+#[derive(Debug, Clone)]
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+    max_widths: Vec<usize>,
+    color: bool,
+}
+
+impl Table {
+    pub fn new<I, S>(headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let headers: Vec<String> = headers.into_iter().map(Into::into).collect();
+        Table {
+            max_widths: vec![DEFAULT_MAX_WIDTH; headers.len()],
+            headers,
+            rows: Vec::new(),
+            color: color::enabled(),
+        }
+    }
+
+    /// Caps `column` at `width` characters, ellipsis included.
+    pub fn max_width(mut self, column: usize, width: usize) -> Self {
+        if let Some(max) = self.max_widths.get_mut(column) {
+            *max = width.max(1);
+        }
+        self
+    }
+
+    /// Adds a row; missing cells are left blank and extra cells are ignored.
+    pub fn row<I, S>(mut self, cells: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.push_row(cells);
+        self
+    }
+
+    pub fn push_row<I, S>(&mut self, cells: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut cells: Vec<String> = cells.into_iter().map(Into::into).collect();
+        cells.resize(self.headers.len(), String::new());
+        self.rows.push(cells);
+    }
+
+    pub fn render(&self) -> String {
+        let fit = |cell: &str, column: usize| self.fit(cell, self.max_widths[column]);
+        let headers: Vec<String> = self.headers.iter().enumerate().map(|(column, cell)| fit(cell, column)).collect();
+        let rows: Vec<Vec<String>> = self.rows.iter()
+            .map(|row| row.iter().enumerate().map(|(column, cell)| fit(cell, column)).collect())
+            .collect();
+
+        let widths: Vec<usize> = (0..self.headers.len())
+            .map(|column| {
+                std::iter::once(&headers)
+                    .chain(&rows)
+                    .map(|row| visible_width(&row[column]))
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        std::iter::once(&headers)
+            .chain(&rows)
+            .map(|row| {
+                row.iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - visible_width(cell))))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The cell as it will be printed: without escapes when color is off, and cut to `max`.
+    fn fit(&self, cell: &str, max: usize) -> String {
+        if visible_width(cell) <= max {
+            return if self.color { cell.to_string() } else { strip_ansi(cell) };
+        }
+        truncate(&strip_ansi(cell), max)
+    }
+}
+
+/// Shortens `text` to at most `max` characters, ending it with an ellipsis when anything was
+/// cut. Counts chars rather than bytes, so multi-byte text never splits mid-character.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let mut truncated: String = text.chars().take(max - 1).collect();
+    truncated.push(ELLIPSIS);
+    truncated
+}
+
+/// Removes SGR escape sequences (`ESC [ ... m`).
+pub fn strip_ansi(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+fn visible_width(text: &str) -> usize {
+    strip_ansi(text).chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns_align_around_long_cells() {
+        let _color = color::scoped(false);
+        let table = Table::new(["ID", "NAME"])
+            .max_width(0, 8)
+            .row(["3f2a9b1c0d4e5f60", "web"])
+            .row(["a1", "a-much-longer-container-name"])
+            .render();
+
+        assert_eq!(table, "ID        NAME\n3f2a9b1…  web\na1        a-much-longer-container-name");
+
+        let long = "x".repeat(DEFAULT_MAX_WIDTH + 10);
+        let table = Table::new(["VALUE", "NEXT"]).row([long.as_str(), "end"]).render();
+        let row = table.lines().nth(1).unwrap();
+        assert_eq!(row.chars().count(), DEFAULT_MAX_WIDTH + "  end".len());
+        assert!(row.contains('…'));
+    }
+
+    #[test]
+    fn test_truncate_counts_chars() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("ünïcödé téxt", 6), "ünïcö…");
+        assert_eq!(truncate("日本語のテキスト", 4), "日本語…");
+        assert_eq!(truncate("abc", 1), "…");
+        assert_eq!(truncate("abc", 0), "");
+    }
+
+    #[test]
+    fn test_color_escapes_do_not_count_toward_width() {
+        let status = color::paint_if(true, "32", "running");
+        let colored = {
+            let _color = color::scoped(true);
+            Table::new(["STATUS", "NAME"]).row([status.as_str(), "web"]).row(["exited", "db"]).render()
+        };
+        assert_eq!(strip_ansi(&colored), "STATUS   NAME\nrunning  web\nexited   db");
+        assert!(colored.contains("\x1b[32m"));
+
+        let _color = color::scoped(false);
+        let plain = Table::new(["STATUS", "NAME"]).row([status.as_str(), "web"]).render();
+        assert_eq!(plain, "STATUS   NAME\nrunning  web");
+    }
+}
@@ -0,0 +1,123 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+const EXPECTED: &str = "expected e.g. 30m, -2h, 1d, yesterday, 2024-01-01 or 2024-01-01T09:00:00Z";
+
+/// Parses a point in time for filters such as `--after`/`--before`.
+///
+/// Accepts `now`, `today` and `yesterday` (local midnight), offsets like `30m`, `1h30m` or
+/// `-2d` (all in the past; `+` looks ahead), and absolute times understood by `parse_absolute`.
+pub fn parse_time(expr: &str) -> anyhow::Result<DateTime<Utc>> {
+    parse_time_from(expr, Utc::now())
+}
+
+/// Like `parse_time`, with offsets and day names taken relative to `now`.
+pub fn parse_time_from(expr: &str, now: DateTime<Utc>) -> anyhow::Result<DateTime<Utc>> {
+    let expr = expr.trim();
+    let time = match expr.to_lowercase().as_str() {
+        "now" => Some(now),
+        "today" => local_midnight(now, 0),
+        "yesterday" => local_midnight(now, 1),
+        _ => parse_offset(expr).map(|offset| now + offset).or_else(|| parse_absolute(expr)),
+    };
+    time.ok_or_else(|| anyhow::anyhow!("Invalid time '{}', {}", expr, EXPECTED))
+}
+
+/// RFC 3339, or a local `YYYY-MM-DD[ T]HH:MM[:SS]` or `YYYY-MM-DD` (midnight).
+pub fn parse_absolute(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let naive = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)));
+    naive
+        .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// `[+|-]<n><unit>...` with units s, m, h, d and w. Unsigned offsets point into the past.
+fn parse_offset(expr: &str) -> Option<Duration> {
+    let (sign, rest) = match expr.strip_prefix('+') {
+        Some(rest) => (1, rest),
+        None => (-1, expr.strip_prefix('-').unwrap_or(expr)),
+    };
+    if rest.is_empty() {
+        return None;
+    }
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in rest.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let n: i64 = number.parse().ok()?;
+        number.clear();
+        total += match c {
+            's' => Duration::seconds(n),
+            'm' => Duration::minutes(n),
+            'h' => Duration::hours(n),
+            'd' => Duration::days(n),
+            'w' => Duration::weeks(n),
+            _ => return None,
+        };
+    }
+    // A trailing number without a unit is not an offset
+    if !number.is_empty() {
+        return None;
+    }
+    Some(total * sign)
+}
+
+/// Local midnight `days_back` days before `now`'s local date.
+fn local_midnight(now: DateTime<Utc>, days_back: i64) -> Option<DateTime<Utc>> {
+    let date = now.with_timezone(&Local).date_naive() - Duration::days(days_back);
+    Local.from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_offsets() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 15, 12, 0, 0).unwrap();
+        assert_eq!(parse_time_from("now", now).unwrap(), now);
+        assert_eq!(parse_time_from("1h", now).unwrap(), now - Duration::hours(1));
+        assert_eq!(parse_time_from("-30m", now).unwrap(), now - Duration::minutes(30));
+        assert_eq!(parse_time_from("2d", now).unwrap(), now - Duration::days(2));
+        assert_eq!(parse_time_from("1h30m", now).unwrap(), now - Duration::minutes(90));
+        assert_eq!(parse_time_from("+1w", now).unwrap(), now + Duration::weeks(1));
+    }
+
+    #[test]
+    fn test_dates_and_day_names() {
+        let now = Utc::now();
+        let today = parse_time_from("today", now).unwrap().with_timezone(&Local);
+        assert_eq!(today.date_naive(), now.with_timezone(&Local).date_naive());
+        assert_eq!(today.time(), chrono::NaiveTime::from_hms_opt(0, 0, 0).unwrap());
+        let yesterday = parse_time_from("Yesterday", now).unwrap().with_timezone(&Local);
+        assert_eq!(yesterday.date_naive().succ_opt(), Some(today.date_naive()));
+
+        let new_year = parse_time("2024-01-01").unwrap().with_timezone(&Local);
+        assert_eq!(new_year.date_naive(), NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        assert_eq!(new_year.time(), chrono::NaiveTime::from_hms_opt(0, 0, 0).unwrap());
+        assert_eq!(
+            parse_time("2024-01-01T09:00:00Z").unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap()
+        );
+        assert!(parse_time("2024-01-01 09:30").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        for input in ["", "-", "soon", "5", "3x", "h", "2024-13-01", "1h30"] {
+            let err = parse_time(input).unwrap_err();
+            assert!(err.to_string().contains("Invalid time"), "{}", input);
+        }
+    }
+}
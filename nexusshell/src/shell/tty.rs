@@ -0,0 +1,94 @@
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use anyhow::Result;
+use std::time::Duration;
+
+/// How long to wait for terminal input before checking the remote side for output again.
+pub const INPUT_POLL: Duration = Duration::from_millis(10);
+
+/// Something to forward from the local terminal to a remote one.
+pub enum TerminalInput {
+    Bytes(Vec<u8>),
+    Resize(u16, u16),
+}
+
+/// Keeps the local terminal in raw mode so keystrokes reach the remote PTY unprocessed,
+/// and restores it however the session ends.
+pub struct RawMode;
+
+impl RawMode {
+    pub fn enable() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Reads the next key press or window-size change from the local terminal, if any.
+pub fn poll_terminal() -> Result<Option<TerminalInput>> {
+    if !event::poll(INPUT_POLL)? {
+        return Ok(None);
+    }
+    Ok(match event::read()? {
+        Event::Key(key) => key_bytes(key).map(TerminalInput::Bytes),
+        Event::Paste(text) => Some(TerminalInput::Bytes(text.into_bytes())),
+        Event::Resize(cols, rows) => Some(TerminalInput::Resize(cols, rows)),
+        _ => None,
+    })
+}
+
+/// The bytes a terminal would send for `key`.
+pub fn key_bytes(key: KeyEvent) -> Option<Vec<u8>> {
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+    let bytes: &[u8] = match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) && c.is_ascii_alphabetic() => {
+            return Some(vec![c.to_ascii_lowercase() as u8 - b'a' + 1]);
+        }
+        KeyCode::Char(c) => {
+            let mut bytes = c.to_string().into_bytes();
+            if key.modifiers.contains(KeyModifiers::ALT) {
+                bytes.insert(0, 0x1b);
+            }
+            return Some(bytes);
+        }
+        KeyCode::Enter => b"\r",
+        KeyCode::Tab => b"\t",
+        KeyCode::BackTab => b"\x1b[Z",
+        KeyCode::Backspace => b"\x7f",
+        KeyCode::Esc => b"\x1b",
+        KeyCode::Up => b"\x1b[A",
+        KeyCode::Down => b"\x1b[B",
+        KeyCode::Right => b"\x1b[C",
+        KeyCode::Left => b"\x1b[D",
+        KeyCode::Home => b"\x1b[H",
+        KeyCode::End => b"\x1b[F",
+        KeyCode::Insert => b"\x1b[2~",
+        KeyCode::Delete => b"\x1b[3~",
+        KeyCode::PageUp => b"\x1b[5~",
+        KeyCode::PageDown => b"\x1b[6~",
+        _ => return None,
+    };
+    Some(bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_bytes() {
+        let key = |code, modifiers| KeyEvent::new(code, modifiers);
+        assert_eq!(key_bytes(key(KeyCode::Char('c'), KeyModifiers::CONTROL)), Some(vec![3]));
+        assert_eq!(key_bytes(key(KeyCode::Char('é'), KeyModifiers::NONE)), Some("é".as_bytes().to_vec()));
+        assert_eq!(key_bytes(key(KeyCode::Enter, KeyModifiers::NONE)), Some(b"\r".to_vec()));
+        assert_eq!(key_bytes(key(KeyCode::Up, KeyModifiers::NONE)), Some(b"\x1b[A".to_vec()));
+        assert_eq!(key_bytes(key(KeyCode::F(1), KeyModifiers::NONE)), None);
+    }
+}
@@ -0,0 +1,158 @@
+use std::future::Future;
+use std::time::Duration;
+
+/// How often and how patiently `retry` re-runs a failing operation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total tries, including the first one
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait before retry number `retry` (0 for the first retry).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.saturating_pow(retry);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// Runs `op` until it succeeds, fails with an error `is_retryable` rejects, or the policy's
+/// attempts run out. Only use it for idempotent operations.
+pub async fn retry<T, E, F, Fut>(policy: &RetryPolicy, is_retryable: impl Fn(&E) -> bool, mut op: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Err(e) if attempt < policy.max_attempts && is_retryable(&e) => {
+                tokio::time::sleep(policy.backoff(attempt - 1)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Runs `future` to completion from synchronous code, such as completion, on the shell's
+/// runtime. Fails outside a multi-threaded runtime, which can't be blocked from inside.
+pub fn block_on<F: Future>(future: F) -> anyhow::Result<F::Output> {
+    let handle = tokio::runtime::Handle::try_current()
+        .map_err(|_| anyhow::anyhow!("not running on the shell's runtime"))?;
+    if handle.runtime_flavor() != tokio::runtime::RuntimeFlavor::MultiThread {
+        return Err(anyhow::anyhow!("a single-threaded runtime can't be blocked on"));
+    }
+    Ok(tokio::task::block_in_place(|| handle.block_on(future)))
+}
+
+fn is_transient_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+/// Timeouts, connection failures, throttling and 5xx responses from the AWS SDK.
+pub fn aws_retryable<E: aws_sdk_s3::error::ProvideErrorMetadata>(err: &aws_sdk_s3::error::SdkError<E>) -> bool {
+    use aws_sdk_s3::error::SdkError;
+    match err {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => true,
+        SdkError::ServiceError(service) => {
+            matches!(
+                service.err().code(),
+                Some("Throttling" | "ThrottlingException" | "TooManyRequestsException"
+                    | "RequestLimitExceeded" | "SlowDown" | "InternalError" | "ServiceUnavailable")
+            ) || is_transient_status(service.raw().status().as_u16())
+        }
+        _ => false,
+    }
+}
+
+/// Daemon 5xx/429 responses, timeouts and transport errors from the Docker API.
+pub fn docker_retryable(err: &bollard::errors::Error) -> bool {
+    use bollard::errors::Error;
+    match err {
+        Error::DockerResponseServerError { status_code, .. } => is_transient_status(*status_code),
+        Error::RequestTimeoutError | Error::HyperResponseError { .. } | Error::IOError { .. } => true,
+        _ => false,
+    }
+}
+
+/// API server 5xx/429 responses and transport errors from Kubernetes.
+pub fn kube_retryable(err: &kube::Error) -> bool {
+    match err {
+        kube::Error::Api(response) => is_transient_status(response.code),
+        kube::Error::HyperError(_) | kube::Error::Service(_) => true,
+        _ => false,
+    }
+}
+
+/// Timeouts, connection failures and 5xx/429 responses from HTTP APIs (Azure, GCP, registries).
+pub fn http_retryable(err: &reqwest::Error) -> bool {
+    err.is_timeout()
+        || err.is_connect()
+        || err.status().is_some_and(|status| is_transient_status(status.as_u16()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Fails with a retryable error `failures` times, then succeeds.
+    async fn flaky(calls: &AtomicU32, failures: u32) -> Result<&'static str, &'static str> {
+        if calls.fetch_add(1, Ordering::SeqCst) < failures {
+            Err("transient")
+        } else {
+            Ok("done")
+        }
+    }
+
+    fn quick(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy { max_attempts, initial_backoff: Duration::ZERO, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn test_retry_policies() {
+        let retryable = |e: &&str| *e == "transient";
+
+        let calls = AtomicU32::new(0);
+        assert_eq!(retry(&quick(3), retryable, || flaky(&calls, 2)).await, Ok("done"));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let calls = AtomicU32::new(0);
+        assert_eq!(retry(&quick(3), retryable, || flaky(&calls, 3)).await, Err("transient"));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let calls = AtomicU32::new(0);
+        assert_eq!(retry(&quick(1), retryable, || flaky(&calls, 1)).await, Err("transient"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Errors the classifier rejects are returned straight away
+        let calls = AtomicU32::new(0);
+        assert_eq!(retry(&quick(5), |_: &&str| false, || flaky(&calls, 1)).await, Err("transient"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(200));
+        assert_eq!(policy.backoff(1), Duration::from_millis(400));
+        assert_eq!(policy.backoff(2), Duration::from_millis(800));
+        assert_eq!(policy.backoff(10), Duration::from_secs(5));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,65 @@
+use std::process::Command;
+
+fn nexusshell() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_nexusshell"))
+}
+
+#[test]
+fn test_inline_commands() {
+    let output = nexusshell()
+        .args(["-c", "echo hello; echo world"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("hello"));
+    assert!(stdout.contains("world"));
+}
+
+#[test]
+fn test_inline_exit_status_follows_last_command() {
+    let output = nexusshell()
+        .args(["-c", "echo ok; cd /nexusshell/does/not/exist"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = nexusshell()
+        .args(["-c", "cd /nexusshell/does/not/exist; echo recovered"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_script_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script.nsh");
+    std::fs::write(&script, "# greeting\necho from-script\n").unwrap();
+
+    let output = nexusshell().arg(&script).output().unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("from-script"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_variable_assignments() {
+    let output = nexusshell()
+        .env("NO_COLOR", "1")
+        .args(["-c", "NEXUS_X=scoped NEXUS_W=both env"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.lines().any(|line| line == "NEXUS_X=scoped"));
+    assert!(stdout.lines().any(|line| line == "NEXUS_W=both"));
+
+    let output = nexusshell()
+        .env("NO_COLOR", "1")
+        .args(["-c", "NEXUS_Y=persisted; printenv NEXUS_Y"])
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).lines().any(|line| line == "persisted"));
+}